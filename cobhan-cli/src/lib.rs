@@ -0,0 +1,252 @@
+//! # cobhan-cli
+//!
+//! Decodes a raw dump of a Cobhan Buffer, as copied out of a host process, and describes it: the
+//! header fields, whether the payload is inline or backed by a temp file, and the payload itself
+//! as a string, JSON or a hex dump. Format v2 dumps, whose reserved field holds
+//! [`BufferFlags`], are decoded with [`inspect_as`].
+
+use std::fmt::Write;
+use std::fs;
+use std::str;
+
+use cobhan::{decode_header_length, BufferFlags, BufferFormat, BUFFER_HEADER_SIZE};
+use serde_json::Value;
+
+const HEADER_SIZE: usize = BUFFER_HEADER_SIZE as usize;
+
+/// How the payload is printed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// JSON if the payload parses as JSON, else a string if it is utf-8, else a hex dump
+    Auto,
+    String,
+    Json,
+    Hex,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(Format::Auto),
+            "string" => Some(Format::String),
+            "json" => Some(Format::Json),
+            "hex" => Some(Format::Hex),
+            _ => None,
+        }
+    }
+}
+
+/// Where the payload of a buffer lives.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Payload {
+    /// The payload follows the header.
+    Inline(Vec<u8>),
+    /// The payload was spilled; the buffer holds the temp file path.
+    TempFile {
+        path: String,
+        /// The file contents, or why they could not be read.
+        contents: Result<Vec<u8>, String>,
+    },
+}
+
+/// A decoded Cobhan Buffer.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Inspection {
+    /// The raw i32 length field.
+    pub length: i32,
+    /// The raw reserved field, the flags in format v2.
+    pub reserved: u32,
+    pub payload: Payload,
+    /// Bytes in the dump past the end of the payload, i.e. unused capacity.
+    pub trailing: usize,
+}
+
+/// Decodes hex text, ignoring whitespace, commas and `0x` prefixes.
+pub fn parse_hex(text: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<char> = text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .flat_map(|word| word.trim_start_matches("0x").trim_start_matches("0X").chars())
+        .collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("odd number of hex digits ({})", digits.len()));
+    }
+    digits
+        .chunks(2)
+        .enumerate()
+        .map(|(i, pair)| match (pair[0].to_digit(16), pair[1].to_digit(16)) {
+            (Some(high), Some(low)) => Ok((high << 4 | low) as u8),
+            _ => Err(format!(
+                "invalid hex digits {:?} at {}",
+                pair.iter().collect::<String>(),
+                i * 2
+            )),
+        })
+        .collect()
+}
+
+/// Decodes the format v1 buffer at the start of `dump`, reading the temp file if the payload was
+/// spilled.
+///
+/// Fails if the dump is shorter than the header or than the length field says, or if a temp
+/// file path is not utf-8.
+pub fn inspect(dump: &[u8]) -> Result<Inspection, String> {
+    inspect_as(dump, BufferFormat::V1)
+}
+
+/// Decodes the buffer at the start of `dump` like [`inspect`], in the given header format: in
+/// format v2 a payload flagged `TEMP_FILE` is also a temp file path.
+pub fn inspect_as(dump: &[u8], format: BufferFormat) -> Result<Inspection, String> {
+    if dump.len() < HEADER_SIZE {
+        return Err(format!(
+            "dump is {} bytes, shorter than the {} byte header",
+            dump.len(),
+            HEADER_SIZE
+        ));
+    }
+    let length = decode_header_length([dump[0], dump[1], dump[2], dump[3]]);
+    let reserved = decode_header_length([dump[4], dump[5], dump[6], dump[7]]) as u32;
+    let spilled = length < 0
+        || (format == BufferFormat::V2
+            && BufferFlags::from_bits(reserved).contains(BufferFlags::TEMP_FILE));
+    if length == i32::MIN {
+        return Err("length field is i32::MIN, which no buffer can have".to_string());
+    }
+    let span = length.unsigned_abs() as usize;
+    let available = dump.len() - HEADER_SIZE;
+    if span > available {
+        return Err(format!(
+            "length field says {} bytes but the dump holds {} bytes after the header",
+            span, available
+        ));
+    }
+    let bytes = dump[HEADER_SIZE..HEADER_SIZE + span].to_vec();
+    let payload = if !spilled {
+        Payload::Inline(bytes)
+    } else {
+        let path = String::from_utf8(bytes)
+            .map_err(|e| format!("temp file path is not utf-8: {}", e.utf8_error()))?;
+        let contents = fs::read(&path).map_err(|e| e.to_string());
+        Payload::TempFile { path, contents }
+    };
+    Ok(Inspection {
+        length,
+        reserved,
+        payload,
+        trailing: available - span,
+    })
+}
+
+/// Describes `inspection`, printing at most `limit` payload bytes as a string or hex dump.
+pub fn render(inspection: &Inspection, format: Format, limit: usize) -> String {
+    let mut out = String::new();
+    let payload = match &inspection.payload {
+        Payload::Inline(bytes) => {
+            let _ = writeln!(out, "length field: {} (inline)", inspection.length);
+            Some(bytes)
+        }
+        Payload::TempFile { path, contents } => {
+            let _ = writeln!(
+                out,
+                "length field: {} (temp file, {} byte path)",
+                inspection.length,
+                path.len()
+            );
+            let _ = writeln!(out, "temp file:    {}", path);
+            match contents {
+                Ok(bytes) => Some(bytes),
+                Err(e) => {
+                    let _ = writeln!(out, "unreadable:   {}", e);
+                    None
+                }
+            }
+        }
+    };
+    let _ = write!(out, "reserved:     0x{:08x}", inspection.reserved);
+    if inspection.reserved != 0 {
+        let flags = BufferFlags::from_bits(inspection.reserved);
+        let _ = write!(out, " (as v2 flags: {:?})", flags);
+    }
+    out.push('\n');
+    if inspection.trailing > 0 {
+        let _ = writeln!(out, "unused:       {} bytes", inspection.trailing);
+    }
+    if let Some(bytes) = payload {
+        let _ = writeln!(out, "payload:      {} bytes", bytes.len());
+        out.push('\n');
+        out.push_str(&render_payload(bytes, format, limit));
+    }
+    out
+}
+
+fn render_payload(bytes: &[u8], format: Format, limit: usize) -> String {
+    let json = || serde_json::from_slice::<Value>(bytes);
+    match format {
+        Format::Auto => match json() {
+            Ok(value) => render_json(&value),
+            Err(_) if str::from_utf8(bytes).is_ok() => render_string(bytes, limit),
+            Err(_) => render_hex(bytes, limit),
+        },
+        Format::Json => match json() {
+            Ok(value) => render_json(&value),
+            Err(e) => format!("not JSON: {}\n", e),
+        },
+        Format::String => render_string(bytes, limit),
+        Format::Hex => render_hex(bytes, limit),
+    }
+}
+
+fn render_json(value: &Value) -> String {
+    let mut out = serde_json::to_string_pretty(value).unwrap_or_default();
+    out.push('\n');
+    out
+}
+
+fn render_string(bytes: &[u8], limit: usize) -> String {
+    let shown = &bytes[..bytes.len().min(limit)];
+    let mut out = match str::from_utf8(shown) {
+        Ok(text) => format!("{:?}\n", text),
+        Err(e) if e.error_len().is_none() => {
+            // The limit split a character
+            format!("{:?}\n", str::from_utf8(&shown[..e.valid_up_to()]).unwrap())
+        }
+        Err(e) => format!(
+            "not utf-8: invalid byte at {}\n{:?}\n",
+            e.valid_up_to(),
+            String::from_utf8_lossy(shown)
+        ),
+    };
+    push_truncated(&mut out, bytes.len(), limit);
+    out
+}
+
+fn render_hex(bytes: &[u8], limit: usize) -> String {
+    let mut out = String::new();
+    for (line, chunk) in bytes[..bytes.len().min(limit)].chunks(16).enumerate() {
+        let _ = write!(out, "{:08x} ", line * 16);
+        for i in 0..16 {
+            match chunk.get(i) {
+                Some(byte) => {
+                    let _ = write!(out, " {:02x}", byte);
+                }
+                None => out.push_str("   "),
+            }
+        }
+        out.push_str("  |");
+        out.extend(chunk.iter().map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        }));
+        out.push_str("|\n");
+    }
+    push_truncated(&mut out, bytes.len(), limit);
+    out
+}
+
+fn push_truncated(out: &mut String, len: usize, limit: usize) {
+    if len > limit {
+        let _ = writeln!(out, "... {} more bytes", len - limit);
+    }
+}
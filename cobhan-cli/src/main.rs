@@ -0,0 +1,107 @@
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::process;
+
+use cobhan::BufferFormat;
+use cobhan_cli::{inspect_as, parse_hex, render, Format};
+
+const USAGE: &str = "\
+Usage: cobhan-cli [OPTIONS] [FILE]
+
+Describes the Cobhan Buffer in FILE, a raw dump of the buffer memory. Without FILE, or with
+FILE `-`, reads the dump from stdin as hex text.
+
+Options:
+  --hex          FILE holds hex text rather than raw bytes
+  --raw          stdin holds raw bytes rather than hex text
+  --as FORMAT    print the payload as auto (default), string, json or hex
+  --limit BYTES  print at most BYTES payload bytes as a string or hex dump (default 4096)
+  --v2           the buffer uses header format v2, with flags in the reserved field
+  -h, --help     print this help
+
+Header fields are little-endian. Exits with 1 if the buffer is malformed.";
+
+struct Options {
+    path: Option<String>,
+    hex: Option<bool>,
+    format: Format,
+    limit: usize,
+    buffer_format: BufferFormat,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        path: None,
+        hex: None,
+        format: Format::Auto,
+        limit: 4096,
+        buffer_format: BufferFormat::V1,
+    };
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "--hex" => options.hex = Some(true),
+            "--raw" => options.hex = Some(false),
+            "--v2" => options.buffer_format = BufferFormat::V2,
+            "--as" => {
+                let name = args.next().ok_or("--as needs a format")?;
+                options.format =
+                    Format::parse(&name).ok_or_else(|| format!("unknown format {}", name))?;
+            }
+            "--limit" => {
+                let limit = args.next().ok_or("--limit needs a byte count")?;
+                options.limit = limit
+                    .parse()
+                    .map_err(|_| format!("invalid byte count {}", limit))?;
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ if options.path.is_none() => options.path = Some(arg),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+    Ok(options)
+}
+
+fn read_dump(options: &Options) -> Result<Vec<u8>, String> {
+    let (bytes, hex) = match options.path.as_deref() {
+        None | Some("-") => {
+            let mut bytes = Vec::new();
+            io::stdin()
+                .read_to_end(&mut bytes)
+                .map_err(|e| format!("failed to read stdin: {}", e))?;
+            (bytes, options.hex.unwrap_or(true))
+        }
+        Some(path) => {
+            let bytes = fs::read(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+            (bytes, options.hex.unwrap_or(false))
+        }
+    };
+    if !hex {
+        return Ok(bytes);
+    }
+    let text = String::from_utf8(bytes).map_err(|_| "hex input is not text".to_string())?;
+    parse_hex(&text)
+}
+
+fn main() {
+    let options = parse_args().unwrap_or_else(|e| {
+        eprintln!("cobhan-cli: {}\n\n{}", e, USAGE);
+        process::exit(2);
+    });
+    let dump = read_dump(&options).unwrap_or_else(|e| {
+        eprintln!("cobhan-cli: {}", e);
+        process::exit(2);
+    });
+    match inspect_as(&dump, options.buffer_format) {
+        Ok(inspection) => print!("{}", render(&inspection, options.format, options.limit)),
+        Err(e) => {
+            eprintln!("cobhan-cli: malformed buffer: {}", e);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use cobhan::{BufferFlags, BufferFormat};
+use cobhan_cli::{inspect, inspect_as, parse_hex, render, Format, Payload};
+
+fn dump(length: i32, payload: &[u8]) -> Vec<u8> {
+    let mut dump = cobhan::encode_header_length(length).to_vec();
+    dump.extend_from_slice(&[0; 4]);
+    dump.extend_from_slice(payload);
+    dump
+}
+
+#[test]
+fn inline_payloads_print_as_json_string_or_hex() {
+    let inspection = inspect(&dump(13, br#"{"a":[1,2,3]}"#)).unwrap();
+    assert_eq!(
+        inspection.payload,
+        Payload::Inline(br#"{"a":[1,2,3]}"#.to_vec())
+    );
+    let report = render(&inspection, Format::Auto, 4096);
+    assert!(report.starts_with("length field: 13 (inline)\n"));
+    assert!(report.contains("\"a\": [\n"));
+
+    let report = render(&inspect(&dump(5, b"hello")).unwrap(), Format::Auto, 4096);
+    assert!(report.ends_with("\"hello\"\n"));
+
+    let report = render(
+        &inspect(&dump(3, b"\x00\xff!")).unwrap(),
+        Format::Auto,
+        4096,
+    );
+    assert!(report.contains("00000000  00 ff 21"));
+    assert!(report.ends_with("|..!|\n"));
+}
+
+#[test]
+fn unused_capacity_and_limits_are_reported() {
+    let inspection = inspect(&dump(4, b"abcdefgh")).unwrap();
+    assert_eq!(inspection.trailing, 4);
+    let report = render(&inspection, Format::String, 2);
+    assert!(report.contains("unused:       4 bytes\n"));
+    assert!(report.ends_with("\"ab\"\n... 2 more bytes\n"));
+}
+
+#[test]
+fn v2_flags_are_decoded() {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(b"spilled").unwrap();
+    let path = file.path().to_str().unwrap().to_string();
+    let mut v2 = dump(path.len() as i32, path.as_bytes());
+    let flags = BufferFlags::TEMP_FILE.with_app_bits(0x1234).bits();
+    v2[4..8].copy_from_slice(&cobhan::encode_header_length(flags as i32));
+
+    // Format v1 ignores the reserved field
+    assert!(matches!(inspect(&v2).unwrap().payload, Payload::Inline(_)));
+
+    let inspection = inspect_as(&v2, BufferFormat::V2).unwrap();
+    assert_eq!(inspection.reserved, 0x1234_0001);
+    assert_eq!(
+        inspection.payload,
+        Payload::TempFile {
+            path,
+            contents: Ok(b"spilled".to_vec()),
+        }
+    );
+    let report = render(&inspection, Format::Auto, 4096);
+    assert!(report.contains("reserved:     0x12340001 (as v2 flags: {TEMP_FILE, app 0x1234})\n"));
+}
+
+#[test]
+fn temp_file_payloads_are_followed() {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(b"spilled").unwrap();
+    let path = file.path().to_str().unwrap().to_string();
+
+    let inspection = inspect(&dump(-(path.len() as i32), path.as_bytes())).unwrap();
+    assert_eq!(
+        inspection.payload,
+        Payload::TempFile {
+            path: path.clone(),
+            contents: Ok(b"spilled".to_vec()),
+        }
+    );
+    let report = render(&inspection, Format::Auto, 4096);
+    assert!(report.contains(&format!("temp file:    {}\n", path)));
+    assert!(report.ends_with("\"spilled\"\n"));
+
+    drop(file);
+    let report = render(
+        &inspect(&dump(-(path.len() as i32), path.as_bytes())).unwrap(),
+        Format::Auto,
+        4096,
+    );
+    assert!(report.contains("unreadable:"));
+}
+
+#[test]
+fn malformed_dumps_are_rejected() {
+    assert!(inspect(&[0; 7]).is_err());
+    assert!(inspect(&dump(9, b"too short")[..12]).is_err());
+    assert!(inspect(&dump(i32::MIN, b"")).is_err());
+    assert!(inspect(&dump(-2, b"\xff\xfe")).is_err());
+}
+
+#[test]
+fn hex_text_is_parsed() {
+    assert_eq!(parse_hex("0a 0B,0x0c\n0d"), Ok(vec![10, 11, 12, 13]));
+    assert!(parse_hex("abc").is_err());
+    assert!(parse_hex("zz").is_err());
+    // Non-ASCII text is rejected, not sliced mid-character
+    assert_eq!(
+        parse_hex("aé0a"),
+        Err("invalid hex digits \"aé\" at 0".to_string())
+    );
+    assert!(parse_hex("aéa").is_err());
+    assert!(parse_hex("éé").is_err());
+}
+
+#[test]
+fn reads_hex_from_stdin() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_cobhan-cli"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"02000000 00000000 6869")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert!(report.starts_with("length field: 2 (inline)\n"));
+    assert!(report.ends_with("\"hi\"\n"));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_cobhan-cli"))
+        .args(["--as", "yaml"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
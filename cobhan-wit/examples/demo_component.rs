@@ -0,0 +1,10 @@
+//! Exports a few libcobhandemo functions as a component:
+//!
+//! `cargo build -p cobhan-wit --example demo_component --target wasm32-wasip2`
+
+cobhan_wit::export_cobhan! {
+    "toUpper" => cobhandemo::toUpper,
+    "base64Encode" => cobhandemo::base64Encode,
+    "compress" => cobhandemo::compress,
+    "decompress" => cobhandemo::decompress,
+}
@@ -0,0 +1,164 @@
+//! # cobhan-wit
+//!
+//! Exports cobhan-shaped functions, `unsafe extern "C" fn(input, output) -> i32`, as a
+//! WebAssembly component. The `cobhan:adapter/dispatch` interface in `wit/cobhan.wit` carries
+//! payloads as WIT `list<u8>` or `string` values and errors as the cobhan `ERR_*` codes, so an
+//! existing Rust core is exported unchanged:
+//!
+//! ```ignore
+//! cobhan_wit::export_cobhan! {
+//!     "toUpper" => cobhandemo::toUpper,
+//!     "base64Encode" => cobhandemo::base64Encode,
+//! }
+//! ```
+//!
+//! Build the library with `cargo build --target wasm32-wasip2` to produce the component.
+//!
+//! Each call packs the input into a Cobhan Buffer, and output that does not fit the output buffer
+//! is spilled into memory rather than a temp file, so components never touch the file system.
+
+use std::collections::HashMap;
+use std::os::raw::c_char;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use cobhan::{
+    cbuffer_to_vector, with_thread_spill_transport, SpillTransport, BUFFER_HEADER_SIZE,
+    ERR_INVALID_UTF8, ERR_LENGTH_OVERFLOW, ERR_NONE, ERR_READ_TEMP_FILE_FAILED,
+};
+
+#[doc(hidden)]
+pub mod bindings {
+    wit_bindgen::generate!({
+        world: "cobhan",
+        path: "wit",
+        pub_export_macro: true,
+        export_macro_name: "export",
+        default_bindings_module: "cobhan_wit::bindings",
+    });
+}
+
+/// The function name passed to `call` or `call-string` is not exported
+///
+/// Outside the range used by cobhan itself and by libraries for their own codes.
+pub const ERR_UNKNOWN_FUNCTION: i32 = -2001;
+
+/// Output buffer capacity used when the input is smaller
+pub const DEFAULT_OUTPUT_CAPACITY: usize = 4096;
+
+const HEADER_SIZE: usize = BUFFER_HEADER_SIZE as usize;
+
+/// A cobhan-shaped function: reads the input buffer and writes the output buffer.
+pub type CobhanFn = unsafe extern "C" fn(*const c_char, *mut c_char) -> i32;
+
+/// An 8 byte aligned Cobhan Buffer.
+struct Buffer {
+    words: Vec<u64>,
+}
+
+impl Buffer {
+    fn new(payload: &[u8], length: usize) -> Result<Self, i32> {
+        if length > i32::MAX as usize {
+            return Err(ERR_LENGTH_OVERFLOW);
+        }
+        let mut words = vec![0u64; (HEADER_SIZE + length).div_ceil(8)];
+        // SAFETY: `words` holds at least HEADER_SIZE + length bytes
+        let bytes = unsafe {
+            std::slice::from_raw_parts_mut(words.as_mut_ptr() as *mut u8, HEADER_SIZE + length)
+        };
+        bytes[..4].copy_from_slice(&cobhan::encode_header_length(length as i32));
+        bytes[HEADER_SIZE..HEADER_SIZE + payload.len()].copy_from_slice(payload);
+        Ok(Buffer { words })
+    }
+}
+
+/// Keeps spilled output in memory until the adapter reads it back.
+#[derive(Default)]
+struct MemoryTransport {
+    next_id: AtomicU64,
+    payloads: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl MemoryTransport {
+    fn payloads(&self) -> std::sync::MutexGuard<'_, HashMap<String, Vec<u8>>> {
+        self.payloads.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl SpillTransport for MemoryTransport {
+    fn spill(&self, bytes: &[u8]) -> Result<String, i32> {
+        let reference = format!(
+            "cobhan-wit:{}",
+            self.next_id.fetch_add(1, Ordering::Relaxed)
+        );
+        self.payloads().insert(reference.clone(), bytes.to_vec());
+        Ok(reference)
+    }
+
+    fn read(&self, reference: &str) -> Result<Vec<u8>, i32> {
+        self.payloads()
+            .remove(reference)
+            .ok_or(ERR_READ_TEMP_FILE_FAILED)
+    }
+
+    fn discard(&self, reference: &str) {
+        self.payloads().remove(reference);
+    }
+}
+
+/// Calls `function` with `input` and returns its output, or its error code.
+pub fn call_bytes(function: CobhanFn, input: &[u8]) -> Result<Vec<u8>, i32> {
+    let input_buffer = Buffer::new(input, input.len())?;
+    let mut output_buffer = Buffer::new(&[], input.len().max(DEFAULT_OUTPUT_CAPACITY))?;
+    let input_ptr = input_buffer.words.as_ptr() as *const c_char;
+    let output_ptr = output_buffer.words.as_mut_ptr() as *mut c_char;
+
+    with_thread_spill_transport(Arc::new(MemoryTransport::default()), || {
+        let result = unsafe { function(input_ptr, output_ptr) };
+        if result != ERR_NONE {
+            return Err(result);
+        }
+        unsafe { cbuffer_to_vector(output_ptr) }
+    })
+}
+
+/// Calls `function` with utf-8 `input` and returns its utf-8 output, or its error code.
+pub fn call_string(function: CobhanFn, input: &str) -> Result<String, i32> {
+    let output = call_bytes(function, input.as_bytes())?;
+    String::from_utf8(output).map_err(|_| ERR_INVALID_UTF8)
+}
+
+/// Exports the listed cobhan-shaped functions through the `cobhan:adapter/dispatch` interface.
+///
+/// Invoke once, at the root of the crate that is built as the component. The component exports
+/// are only emitted on wasm32, so the same crate still builds as a native library.
+#[macro_export]
+macro_rules! export_cobhan {
+    ($($name:literal => $function:path),* $(,)?) => {
+        #[allow(dead_code)]
+        struct CobhanComponent;
+
+        impl $crate::bindings::exports::cobhan::adapter::dispatch::Guest for CobhanComponent {
+            fn call(function: String, input: Vec<u8>) -> Result<Vec<u8>, i32> {
+                match function.as_str() {
+                    $($name => $crate::call_bytes($function, &input),)*
+                    _ => Err($crate::ERR_UNKNOWN_FUNCTION),
+                }
+            }
+
+            fn call_string(function: String, input: String) -> Result<String, i32> {
+                match function.as_str() {
+                    $($name => $crate::call_string($function, &input),)*
+                    _ => Err($crate::ERR_UNKNOWN_FUNCTION),
+                }
+            }
+
+            fn functions() -> Vec<String> {
+                vec![$(String::from($name)),*]
+            }
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        $crate::bindings::export!(CobhanComponent);
+    };
+}
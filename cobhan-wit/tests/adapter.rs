@@ -0,0 +1,55 @@
+use std::os::raw::c_char;
+
+use cobhan_wit::{call_bytes, call_string, DEFAULT_OUTPUT_CAPACITY, ERR_UNKNOWN_FUNCTION};
+
+unsafe extern "C" fn repeat_twice(input: *const c_char, output: *mut c_char) -> i32 {
+    let bytes = match cobhan::cbuffer_to_vector(input) {
+        Ok(b) => b,
+        Err(e) => return e,
+    };
+    cobhan::bytes_to_cbuffer(bytes.repeat(2), output)
+}
+
+unsafe extern "C" fn fail(_input: *const c_char, _output: *mut c_char) -> i32 {
+    ERR_UNKNOWN_FUNCTION
+}
+
+#[test]
+fn strings_round_trip() {
+    assert_eq!(
+        call_string(cobhandemo::toUpper, "Initial value"),
+        Ok("INITIAL VALUE".to_string())
+    );
+    assert_eq!(
+        call_string(cobhandemo::base64Encode, "Test"),
+        Ok("VGVzdA==".to_string())
+    );
+}
+
+#[test]
+fn large_output_is_spilled_in_memory() {
+    let input = vec![7u8; DEFAULT_OUTPUT_CAPACITY];
+    let output = call_bytes(repeat_twice, &input).unwrap();
+    assert_eq!(output.len(), 2 * DEFAULT_OUTPUT_CAPACITY);
+    assert!(output.iter().all(|b| *b == 7));
+
+    let original = b"cobhan ".repeat(100_000);
+    let compressed = call_bytes(cobhandemo::compress, &original).unwrap();
+    assert_eq!(
+        call_bytes(cobhandemo::decompress, &compressed),
+        Ok(original)
+    );
+}
+
+#[test]
+fn errors_are_returned() {
+    assert_eq!(call_bytes(fail, b""), Err(ERR_UNKNOWN_FUNCTION));
+    assert_eq!(
+        call_string(cobhandemo::toUpper, "\u{0}"),
+        Ok("\u{0}".to_string())
+    );
+    assert_eq!(
+        call_bytes(cobhandemo::toUpper, &[0x80, 0xFE]),
+        Err(cobhan::ERR_INVALID_UTF8)
+    );
+}
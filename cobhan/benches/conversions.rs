@@ -0,0 +1,127 @@
+//! Conversion costs across payload sizes: `cargo bench -p cobhan --features testing`.
+//!
+//! * `read`: copying a payload out of a buffer, against the zero-copy floor of borrowing it in
+//!   place, and reading a spilled payload back from its temp file
+//! * `write`: copying a payload into a buffer that fits it, and spilling one that does not
+//! * `json`: encoding and decoding JSON objects
+
+use std::collections::HashMap;
+use std::fs;
+use std::hint::black_box;
+
+use cobhan::testing::OwnedCBuffer;
+use cobhan::*;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use serde_json::Value;
+
+const HEADER_SIZE: usize = BUFFER_HEADER_SIZE as usize;
+
+const SIZES: [usize; 4] = [64, 4 * 1024, 64 * 1024, 1024 * 1024];
+
+/// Output capacity for spilled writes: room for the temp file path, not the payload
+const SPILL_CAPACITY: usize = 256;
+
+fn payload(size: usize) -> Vec<u8> {
+    (0..size).map(|i| b'a' + (i % 26) as u8).collect()
+}
+
+fn read(c: &mut Criterion) {
+    let mut group = c.benchmark_group("read");
+    for size in SIZES {
+        let bytes = payload(size);
+        let input = OwnedCBuffer::from_bytes(&bytes);
+        group.throughput(Throughput::Bytes(size as u64));
+
+        group.bench_with_input(BenchmarkId::new("copy", size), &input, |b, input| {
+            b.iter(|| unsafe { cbuffer_to_vector(black_box(input.as_ptr())) })
+        });
+        group.bench_with_input(BenchmarkId::new("copy_utf8", size), &input, |b, input| {
+            b.iter(|| unsafe { cbuffer_to_string(black_box(input.as_ptr())) })
+        });
+        group.bench_with_input(BenchmarkId::new("zero_copy", size), &input, |b, input| {
+            b.iter(|| unsafe {
+                let buffer = black_box(input.as_ptr());
+                let length = decode_header_length(*(buffer as *const [u8; 4]));
+                let payload = buffer.add(HEADER_SIZE).cast::<u8>();
+                black_box(std::slice::from_raw_parts(payload, length as usize))
+            })
+        });
+
+        let file = tempfile::NamedTempFile::new_in(temp_dir()).unwrap();
+        fs::write(file.path(), &bytes).unwrap();
+        let spilled = OwnedCBuffer::spilled(file.path().to_str().unwrap());
+        group.bench_with_input(BenchmarkId::new("spilled", size), &spilled, |b, spilled| {
+            b.iter(|| unsafe { cbuffer_to_vector(black_box(spilled.as_ptr())) })
+        });
+    }
+    group.finish();
+}
+
+fn write(c: &mut Criterion) {
+    let mut group = c.benchmark_group("write");
+    for size in SIZES {
+        let bytes = payload(size);
+        group.throughput(Throughput::Bytes(size as u64));
+
+        let mut output = OwnedCBuffer::with_capacity(size);
+        group.bench_with_input(BenchmarkId::new("copy", size), &bytes, |b, bytes| {
+            b.iter(|| {
+                output.reset();
+                unsafe { bytes_to_cbuffer(black_box(bytes), output.as_mut_ptr()) }
+            })
+        });
+
+        if size <= SPILL_CAPACITY {
+            continue;
+        }
+        // Includes deleting the temp file, so that runs do not fill the temp directory
+        let mut output = OwnedCBuffer::with_capacity(SPILL_CAPACITY);
+        group.bench_with_input(BenchmarkId::new("spill", size), &bytes, |b, bytes| {
+            b.iter(|| {
+                output.reset();
+                let result = unsafe { bytes_to_cbuffer(black_box(bytes), output.as_mut_ptr()) };
+                if let Some(path) = output.temp_file_path() {
+                    let _ = fs::remove_file(path);
+                }
+                result
+            })
+        });
+    }
+    group.finish();
+}
+
+fn json_object(entries: usize) -> HashMap<String, Value> {
+    (0..entries)
+        .map(|i| {
+            let value =
+                serde_json::json!({"id": i, "name": format!("entry {}", i), "ok": i % 2 == 0});
+            (format!("key{}", i), value)
+        })
+        .collect()
+}
+
+fn json(c: &mut Criterion) {
+    let mut group = c.benchmark_group("json");
+    for entries in [8, 256, 4096] {
+        let object = json_object(entries);
+        let encoded = serde_json::to_vec(&object).unwrap();
+        group.throughput(Throughput::Bytes(encoded.len() as u64));
+
+        let mut output = OwnedCBuffer::with_capacity(encoded.len());
+        group.bench_with_input(BenchmarkId::new("encode", entries), &object, |b, object| {
+            b.iter(|| {
+                output.reset();
+                unsafe { hashmap_json_to_cbuffer(black_box(object), output.as_mut_ptr()) }
+            })
+        });
+
+        let input = OwnedCBuffer::from_bytes(&encoded);
+        group.bench_with_input(BenchmarkId::new("decode", entries), &input, |b, input| {
+            b.iter(|| unsafe { cbuffer_to_hashmap_json(black_box(input.as_ptr())) })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, read, write, json);
+criterion_main!(benches);
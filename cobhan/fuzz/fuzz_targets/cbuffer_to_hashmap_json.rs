@@ -0,0 +1,10 @@
+#![no_main]
+
+use cobhan_fuzz::FuzzBuffer;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: FuzzBuffer| {
+    input.with_buffer(|buffer| {
+        let _ = unsafe { cobhan::cbuffer_to_hashmap_json(buffer) };
+    });
+});
@@ -0,0 +1,72 @@
+//! # cobhan-fuzz
+//!
+//! [`FuzzBuffer`] turns fuzzer input into a Cobhan Buffer with an adversarial header: the length
+//! field may be shorter than the allocation, zero, or negative (a spill reference made of
+//! arbitrary bytes, or occasionally a valid one), and the reserved field is arbitrary. The length
+//! never exceeds the allocation, since that breaks the documented safety contract rather than
+//! exercising the parser.
+//!
+//! Spill references resolve through an in-memory [`MockTransport`], so fuzzing never reads or
+//! writes files on disk.
+
+use std::os::raw::c_char;
+use std::sync::Arc;
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+use cobhan::testing::MockTransport;
+use cobhan::with_thread_spill_transport;
+
+const HEADER_SIZE: usize = cobhan::BUFFER_HEADER_SIZE as usize;
+
+#[derive(Debug)]
+pub struct FuzzBuffer {
+    payload: Vec<u8>,
+    length: i32,
+    reserved: i32,
+    spilled: Option<Vec<u8>>,
+}
+
+impl<'a> Arbitrary<'a> for FuzzBuffer {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let spilled: Option<Vec<u8>> = u.arbitrary()?;
+        let reserved: i32 = u.arbitrary()?;
+        let payload: Vec<u8> = u.arbitrary()?;
+        let capacity = payload.len().min(i32::MAX as usize) as i32;
+        let length = u.int_in_range(-capacity..=capacity)?;
+        Ok(FuzzBuffer {
+            payload,
+            length,
+            reserved,
+            spilled,
+        })
+    }
+}
+
+impl FuzzBuffer {
+    /// Runs `f` with a pointer to the packed buffer and the mock transport installed.
+    ///
+    /// When the input carries a spilled payload and the length is negative, the payload is
+    /// rewritten to hold a valid reference to it if the reference fits.
+    pub fn with_buffer<R>(&self, f: impl FnOnce(*const c_char) -> R) -> R {
+        let mock = Arc::new(MockTransport::new());
+        let mut payload = self.payload.clone();
+        let mut length = self.length;
+        if let (Some(spilled), true) = (&self.spilled, length < 0) {
+            let reference = mock.insert(spilled);
+            if reference.len() <= payload.len() {
+                payload[..reference.len()].copy_from_slice(reference.as_bytes());
+                length = -(reference.len() as i32);
+            }
+        }
+
+        let mut words = vec![0u64; (HEADER_SIZE + payload.len()).div_ceil(8)];
+        let bytes = unsafe {
+            std::slice::from_raw_parts_mut(words.as_mut_ptr() as *mut u8, words.len() * 8)
+        };
+        bytes[..4].copy_from_slice(&cobhan::encode_header_length(length));
+        bytes[4..8].copy_from_slice(&self.reserved.to_le_bytes());
+        bytes[HEADER_SIZE..HEADER_SIZE + payload.len()].copy_from_slice(&payload);
+
+        with_thread_spill_transport(mock, || f(words.as_ptr() as *const c_char))
+    }
+}
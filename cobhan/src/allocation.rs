@@ -0,0 +1,182 @@
+//! # Rust-allocated buffers
+//!
+//! Libraries export [`cobhan_alloc`] and [`cobhan_free`], so hosts without an allocator of
+//! their own handy can have the library allocate Cobhan Buffers. Rust code can use them too,
+//! and consume an output buffer with [`cbuffer_into_vec`], which hands its allocation over to
+//! the returned `Vec` instead of copying the payload into a new one:
+//!
+//! ```ignore
+//! let output = cobhan::cobhan_alloc(64 * 1024);
+//! let result = unsafe { render(input, output) };
+//! // ... on success, the payload becomes the Vec, with no second allocation
+//! let rendered = unsafe { cobhan::cbuffer_into_vec(output) }?;
+//! ```
+//!
+//! The allocations are kept in a registry, so freeing a buffer that was not allocated here fails
+//! with `ERR_INVALID_HANDLE` rather than corrupting the heap.
+
+use std::collections::BTreeMap;
+use std::mem::ManuallyDrop;
+use std::os::raw::c_char;
+use std::ptr;
+use std::slice;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::buffer::read_header;
+use crate::{
+    capture, cbuffer_to_vector, encode_header_length, write_header_length, BUFFER_HEADER_SIZE,
+};
+use crate::{ERR_INVALID_HANDLE, ERR_INVALID_LENGTH, ERR_NONE, ERR_NULL_PTR};
+
+const HEADER_SIZE: usize = BUFFER_HEADER_SIZE as usize;
+
+/// Alignment of the buffers, as host allocators guarantee.
+const ALIGN: usize = 8;
+
+/// Where a buffer lies in the `Vec<u8>` allocated for it.
+struct Allocation {
+    /// Bytes from the start of the allocation to the buffer header, to align it.
+    offset: usize,
+    /// Payload capacity the buffer was allocated with.
+    capacity: usize,
+    /// Capacity of the `Vec`.
+    allocated: usize,
+}
+
+static ALLOCATIONS: Mutex<BTreeMap<usize, Allocation>> = Mutex::new(BTreeMap::new());
+
+fn allocations() -> MutexGuard<'static, BTreeMap<usize, Allocation>> {
+    ALLOCATIONS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Allocates a zeroed Cobhan Buffer with room for `capacity` payload bytes, to be released with
+/// [`cobhan_free`] or [`cbuffer_into_vec`].
+///
+/// The length field is set to `capacity`, ready for use as an output buffer. Returns NULL if
+/// `capacity` is negative or the allocation fails.
+#[no_mangle]
+pub extern "C" fn cobhan_alloc(capacity: i32) -> *mut c_char {
+    if capacity < 0 {
+        debug_print!("cobhan_alloc: capacity {} is negative", capacity);
+        return ptr::null_mut();
+    }
+    let len = HEADER_SIZE + capacity as usize + ALIGN - 1;
+    let mut bytes = Vec::new();
+    if bytes.try_reserve_exact(len).is_err() {
+        error_print!("cobhan_alloc: failed to allocate {} bytes", len);
+        return ptr::null_mut();
+    }
+    bytes.resize(len, 0u8);
+    let mut bytes = ManuallyDrop::new(bytes);
+    let offset = bytes.as_ptr().align_offset(ALIGN);
+    // SAFETY: the allocation has ALIGN - 1 bytes to spare for the offset
+    let buffer = unsafe { bytes.as_mut_ptr().add(offset) } as *mut c_char;
+    unsafe { write_header_length(buffer, capacity) };
+    let allocation = Allocation {
+        offset,
+        capacity: capacity as usize,
+        allocated: bytes.capacity(),
+    };
+    allocations().insert(buffer as usize, allocation);
+    buffer
+}
+
+/// Takes `buffer` out of the registry, failing with `ERR_INVALID_HANDLE` if it is not there.
+fn take_allocation(buffer: *mut c_char) -> Result<Allocation, i32> {
+    match allocations().remove(&(buffer as usize)) {
+        Some(allocation) => Ok(allocation),
+        None => {
+            debug_print!("take_allocation: buffer was not allocated by cobhan_alloc");
+            Err(ERR_INVALID_HANDLE)
+        }
+    }
+}
+
+/// Rebuilds the `Vec` a buffer was allocated as, holding no bytes.
+unsafe fn into_allocation_vec(buffer: *mut c_char, allocation: &Allocation) -> Vec<u8> {
+    let start = buffer.cast::<u8>().sub(allocation.offset);
+    Vec::from_raw_parts(start, 0, allocation.allocated)
+}
+
+/// Releases a Cobhan Buffer returned by [`cobhan_alloc`].
+///
+/// Returns `ERR_NONE`, or `ERR_INVALID_HANDLE`, releasing nothing, if `buffer` was not
+/// allocated by `cobhan_alloc` or was already released.
+///
+/// ## Safety
+///
+/// The buffer must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn cobhan_free(buffer: *mut c_char) -> i32 {
+    if buffer.is_null() {
+        debug_print!("cobhan_free: buffer is NULL");
+        return ERR_NULL_PTR;
+    }
+    match take_allocation(buffer) {
+        Ok(allocation) => {
+            drop(into_allocation_vec(buffer, &allocation));
+            ERR_NONE
+        }
+        Err(e) => e,
+    }
+}
+
+/// Takes a Cobhan Buffer returned by [`cobhan_alloc`] and turns it into a `Vec<u8>` holding its
+/// payload, releasing the buffer.
+///
+/// The payload is moved to the start of the buffer's own allocation, which the `Vec` takes over
+/// with its full capacity, so nothing is allocated or copied into new memory. A spilled payload
+/// is read back like [`cbuffer_to_vector`] reads it instead.
+///
+/// Fails with `ERR_INVALID_HANDLE`, releasing nothing, if `buffer` was not allocated by
+/// `cobhan_alloc`. On other failures, such as `ERR_INVALID_LENGTH` for a length field over the
+/// capacity, the buffer is released all the same.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header is not correctly formatted.
+/// - The buffer is used afterwards.
+pub unsafe fn cbuffer_into_vec(buffer: *mut c_char) -> Result<Vec<u8>, i32> {
+    if buffer.is_null() {
+        debug_print!("cbuffer_into_vec: buffer is NULL");
+        return Err(ERR_NULL_PTR);
+    }
+    let allocation = take_allocation(buffer)?;
+    let (length, spilled) = read_header(buffer);
+    if spilled {
+        let result = cbuffer_to_vector(buffer);
+        drop(into_allocation_vec(buffer, &allocation));
+        return result;
+    }
+    let mut bytes = into_allocation_vec(buffer, &allocation);
+    let len = length as usize;
+    if len > allocation.capacity {
+        debug_print!(
+            "cbuffer_into_vec: length field {} is over the capacity of {}",
+            length,
+            allocation.capacity
+        );
+        return Err(ERR_INVALID_LENGTH);
+    }
+    let payload = buffer.offset(BUFFER_HEADER_SIZE).cast::<u8>();
+    ptr::copy(payload, bytes.as_mut_ptr(), len);
+    bytes.set_len(len);
+    capture::record_input(&bytes);
+    Ok(bytes)
+}
+
+/// A zeroed Cobhan Buffer holding `payload`, with room for `capacity` payload bytes and its
+/// length field set to `capacity`, in storage the crate owns rather than a registered
+/// allocation. The storage is 8 byte aligned, as host allocators guarantee.
+///
+/// `capacity` must fit the length field and hold `payload`.
+pub(crate) fn aligned_buffer(payload: &[u8], capacity: usize) -> Vec<u64> {
+    let mut words = vec![0u64; (HEADER_SIZE + capacity).div_ceil(ALIGN)];
+    // SAFETY: `words` holds at least HEADER_SIZE + capacity bytes
+    let bytes =
+        unsafe { slice::from_raw_parts_mut(words.as_mut_ptr() as *mut u8, HEADER_SIZE + capacity) };
+    bytes[..4].copy_from_slice(&encode_header_length(capacity as i32));
+    bytes[HEADER_SIZE..HEADER_SIZE + payload.len()].copy_from_slice(payload);
+    words
+}
@@ -0,0 +1,95 @@
+//! # Per-call arenas
+//!
+//! Functions that read many buffers per call can copy them into an [`Arena`] instead of
+//! allocating each one, and free them all at once when the call returns:
+//!
+//! ```ignore
+//! cobhan::with_arena(|arena| unsafe {
+//!     let key = cobhan::cbuffer_to_str_in(arena, key)?;
+//!     let value = cobhan::cbuffer_to_bytes_in(arena, value)?;
+//!     // ...
+//! })
+//! ```
+//!
+//! Each thread keeps its arena's memory between calls, so after the first call a conversion is a
+//! pointer bump rather than an allocation. Values borrowed from the arena cannot outlive the
+//! `with_arena` closure.
+
+use std::cell::Cell;
+use std::os::raw::c_char;
+use std::str;
+
+use bumpalo::Bump;
+
+use crate::{cbuffer_payload, ERR_INVALID_UTF8};
+
+/// A bump allocator freed wholesale at the end of [`with_arena`].
+#[derive(Debug)]
+pub struct Arena {
+    bump: Bump,
+}
+
+impl Arena {
+    /// Copies `bytes` into the arena.
+    pub fn alloc_bytes(&self, bytes: &[u8]) -> &[u8] {
+        self.bump.alloc_slice_copy(bytes)
+    }
+
+    /// Copies `string` into the arena.
+    pub fn alloc_str(&self, string: &str) -> &str {
+        self.bump.alloc_str(string)
+    }
+
+    /// Bytes the arena has allocated from the heap, including unused space.
+    pub fn allocated_bytes(&self) -> usize {
+        self.bump.allocated_bytes()
+    }
+}
+
+thread_local! {
+    static ARENA: Cell<Option<Bump>> = const { Cell::new(None) };
+}
+
+/// Runs `f` with this thread's arena, then frees everything allocated from it.
+///
+/// Nested calls get an arena of their own.
+pub fn with_arena<R>(f: impl FnOnce(&Arena) -> R) -> R {
+    let arena = Arena {
+        bump: ARENA.with(Cell::take).unwrap_or_default(),
+    };
+    let result = f(&arena);
+    let mut bump = arena.bump;
+    bump.reset();
+    ARENA.with(|cell| cell.set(Some(bump)));
+    result
+}
+
+/// Takes a pointer to an external Cobhan Buffer and fallibly copies its payload into `arena`.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn cbuffer_to_bytes_in(arena: &Arena, buffer: *const c_char) -> Result<&[u8], i32> {
+    let bytes = cbuffer_payload(buffer)?;
+    Ok(arena.alloc_bytes(&bytes))
+}
+
+/// Takes a pointer to an external Cobhan Buffer and fallibly copies it into `arena` as a `str`.
+///
+/// The str is fallibly checked to ensure UTF-8 formatting.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn cbuffer_to_str_in(arena: &Arena, buffer: *const c_char) -> Result<&str, i32> {
+    let bytes = cbuffer_payload(buffer)?;
+    let string = str::from_utf8(&bytes).map_err(|_| {
+        debug_print!("cbuffer_to_str_in: payload is invalid utf-8 string");
+        ERR_INVALID_UTF8
+    })?;
+    Ok(arena.alloc_str(string))
+}
@@ -0,0 +1,180 @@
+//! # N-dimensional array buffers
+//!
+//! An array payload is laid out as (all values little-endian):
+//!
+//! * u32 dtype - one of the `DTYPE_*` constants
+//! * u32 rank - number of dimensions
+//! * rank x u64 - length of each dimension, outermost first
+//! * packed element data in row-major (C) order
+//!
+//! This matches the memory of a C-contiguous NumPy array with a little-endian dtype, so hosts can
+//! wrap the data section directly once the header has been read.
+
+use std::convert::TryFrom;
+use std::os::raw::c_char;
+
+use ndarray::{ArrayD, IxDyn};
+
+use crate::{
+    bytes_to_cbuffer, cbuffer_payload, PayloadReader, ERR_BUFFER_TOO_LARGE, ERR_MALFORMED_PAYLOAD,
+};
+
+/// NumPy `uint8`
+pub const DTYPE_U8: u32 = 1;
+/// NumPy `int8`
+pub const DTYPE_I8: u32 = 2;
+/// NumPy `uint16`
+pub const DTYPE_U16: u32 = 3;
+/// NumPy `int16`
+pub const DTYPE_I16: u32 = 4;
+/// NumPy `uint32`
+pub const DTYPE_U32: u32 = 5;
+/// NumPy `int32`
+pub const DTYPE_I32: u32 = 6;
+/// NumPy `uint64`
+pub const DTYPE_U64: u32 = 7;
+/// NumPy `int64`
+pub const DTYPE_I64: u32 = 8;
+/// NumPy `float32`
+pub const DTYPE_F32: u32 = 9;
+/// NumPy `float64`
+pub const DTYPE_F64: u32 = 10;
+
+/// Largest rank accepted when decoding, matching NumPy's own limit.
+pub const MAX_RANK: u32 = 32;
+
+/// Element types that can be marshaled in an array buffer.
+pub trait ArrayElement: Copy {
+    /// The `DTYPE_*` code written in the header
+    const DTYPE: u32;
+    /// Size of one element in bytes
+    const SIZE: usize;
+
+    fn write_le(self, out: &mut Vec<u8>);
+    fn read_le(bytes: &[u8]) -> Self;
+}
+
+macro_rules! array_element {
+    ($t:ty, $dtype:expr) => {
+        impl ArrayElement for $t {
+            const DTYPE: u32 = $dtype;
+            const SIZE: usize = std::mem::size_of::<$t>();
+
+            fn write_le(self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+
+            fn read_le(bytes: &[u8]) -> Self {
+                let mut raw = [0u8; std::mem::size_of::<$t>()];
+                raw.copy_from_slice(bytes);
+                <$t>::from_le_bytes(raw)
+            }
+        }
+    };
+}
+
+array_element!(u8, DTYPE_U8);
+array_element!(i8, DTYPE_I8);
+array_element!(u16, DTYPE_U16);
+array_element!(i16, DTYPE_I16);
+array_element!(u32, DTYPE_U32);
+array_element!(i32, DTYPE_I32);
+array_element!(u64, DTYPE_U64);
+array_element!(i64, DTYPE_I64);
+array_element!(f32, DTYPE_F32);
+array_element!(f64, DTYPE_F64);
+
+/// Takes a pointer to an external Cobhan Buffer and fallibly attempts to interpret it as an `ArrayD<T>`.
+///
+/// The dtype in the header must match `T`, and the data section must hold exactly the number of
+/// elements described by the shape.
+///
+/// ## Notes
+///
+/// This function copies from the provided Cobhan Buffer into Rust owned data.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn cbuffer_to_array<T: ArrayElement>(buffer: *const c_char) -> Result<ArrayD<T>, i32> {
+    let bytes = cbuffer_payload(buffer)?;
+    let mut reader = PayloadReader::new(&bytes);
+
+    let dtype = reader.read_u32()?;
+    if dtype != T::DTYPE {
+        debug_print!(
+            "cbuffer_to_array: dtype {} does not match expected {}",
+            dtype,
+            T::DTYPE
+        );
+        return Err(ERR_MALFORMED_PAYLOAD);
+    }
+
+    let rank = reader.read_u32()?;
+    if rank > MAX_RANK {
+        debug_print!("cbuffer_to_array: rank {} exceeds {}", rank, MAX_RANK);
+        return Err(ERR_MALFORMED_PAYLOAD);
+    }
+
+    let mut shape = Vec::with_capacity(rank as usize);
+    let mut element_count: usize = 1;
+    for _ in 0..rank {
+        let dim = reader.read_bytes(8)?;
+        let dim = u64::from_le_bytes([
+            dim[0], dim[1], dim[2], dim[3], dim[4], dim[5], dim[6], dim[7],
+        ]);
+        let dim = usize::try_from(dim).map_err(|_| ERR_MALFORMED_PAYLOAD)?;
+        element_count = element_count
+            .checked_mul(dim)
+            .ok_or(ERR_MALFORMED_PAYLOAD)?;
+        shape.push(dim);
+    }
+
+    let data_len = element_count
+        .checked_mul(T::SIZE)
+        .ok_or(ERR_MALFORMED_PAYLOAD)?;
+    let data = reader.read_bytes(data_len)?;
+    if !reader.is_empty() {
+        debug_print!("cbuffer_to_array: trailing bytes after array data");
+        return Err(ERR_MALFORMED_PAYLOAD);
+    }
+
+    let elements = data.chunks_exact(T::SIZE).map(T::read_le).collect();
+    ArrayD::from_shape_vec(IxDyn(&shape), elements).map_err(|_| ERR_MALFORMED_PAYLOAD)
+}
+
+/// Takes an `ArrayD<T>` and fallibly encodes its dtype, shape and elements into a provided external Cobhan Buffer.
+///
+/// Arrays in any memory layout are written in row-major order.
+///
+/// Will cause an error code if the provided Cobhan Buffer is too small.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn array_to_cbuffer<T: ArrayElement>(array: &ArrayD<T>, buffer: *mut c_char) -> i32 {
+    if array.ndim() > MAX_RANK as usize {
+        debug_print!(
+            "array_to_cbuffer: rank {} exceeds {}",
+            array.ndim(),
+            MAX_RANK
+        );
+        return ERR_BUFFER_TOO_LARGE;
+    }
+
+    let mut bytes = Vec::with_capacity(8 + 8 * array.ndim() + T::SIZE * array.len());
+    bytes.extend_from_slice(&T::DTYPE.to_le_bytes());
+    bytes.extend_from_slice(&(array.ndim() as u32).to_le_bytes());
+    for dim in array.shape() {
+        bytes.extend_from_slice(&(*dim as u64).to_le_bytes());
+    }
+    for element in array.iter() {
+        element.write_le(&mut bytes);
+    }
+
+    bytes_to_cbuffer(&bytes, buffer)
+}
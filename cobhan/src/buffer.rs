@@ -0,0 +1,1473 @@
+//! # Cobhan Buffers
+//!
+//! The header layout and the byte and string conversions the other codecs are built on. A buffer
+//! is an 8 byte header, an i32 length and an i32 reserved field, followed by the payload; a
+//! negative length means the payload is a spill reference, see [`transport`](crate::transport).
+//! Format v2 uses the reserved field for [`BufferFlags`](crate::BufferFlags).
+//!
+//! ## Writing
+//!
+//! [`string_to_cbuffer`] and [`bytes_to_cbuffer`] take any `AsRef<str>` or `AsRef<[u8]>`, so a
+//! `String`, `Vec<u8>` or `Cow` is written without converting it first, and spill what does not
+//! fit. Functions whose hosts would rather not read spills have other writers:
+//!
+//! * [`bytes_to_cbuffer_or_size`] leaves a buffer that is too small, even a zero-capacity one,
+//!   alone and returns the required size as a positive value, so the host can allocate an
+//!   exactly sized buffer and call again; [`query_required_size`] reports it up front
+//! * [`write_with_retry`](crate::write_with_retry) fails with `ERR_BUFFER_TOO_SMALL` and leaves
+//!   the size to retry with in the reserved field (format v1 only)
+//! * [`bytes_to_cbuffers_vectored`] splits a payload across a list of buffers, filling each in
+//!   order
+//! * [`bytes_to_cbuffer_truncating`] writes what fits, sets the truncated flag and reports the
+//!   full length
+//!
+//! [`chunks_to_cbuffer`] writes a payload produced in chunks straight into the buffer, gathering
+//! it only if it has to spill.
+//!
+//! ## Reading
+//!
+//! [`cbuffer_for_each_chunk`] passes a payload to a callback in fixed-size chunks,
+//! [`cbuffers_chain_reader`] reads several payloads as one `Read` stream, and [`cbuffer_lines`]
+//! and [`cbuffer_split`] iterate over lines or delimited records. All of them borrow inline
+//! payloads in place and stream spilled ones, so arbitrarily large inputs are read in constant
+//! memory. [`cbuffer_transform_in_place`] hands a payload to a closure as a mutable slice, so
+//! in-place codecs (XOR, masking, case-folding) copy nothing.
+//!
+//! ## Host helpers
+//!
+//! Hosts that cannot pack the header themselves (shell scripts calling through dlcall,
+//! constrained embedded runtimes) can allocate raw memory and call [`cobhan_buffer_init`] to make
+//! it an output buffer, or [`cobhan_buffer_reset`] to make it an empty input.
+//! [`cobhan_buffer_copy`] and [`cobhan_buffer_move`] pass one call's result to another without
+//! reading it into host memory, and [`cobhan_buffer_release`] discards a spill once it is read.
+
+use alloc::borrow::{Cow, ToOwned};
+#[cfg(feature = "std")]
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ffi::c_char;
+use core::ptr::copy_nonoverlapping;
+use core::slice::{from_raw_parts, from_raw_parts_mut};
+use core::str;
+#[cfg(feature = "std")]
+use std::io::{self, BufRead, BufReader, Read};
+
+use crate::flags::{BufferFlags, BufferFormat};
+use crate::memory::Charge;
+use crate::stats::CallTimer;
+#[cfg(feature = "std")]
+use crate::temp::open_spill;
+#[cfg(all(feature = "std", feature = "json"))]
+use crate::temp::read_spill;
+use crate::temp::{bytes_to_temp, temp_to_string, temp_to_vector, write_spill_reference};
+#[cfg(feature = "std")]
+use crate::ERR_READ_TEMP_FILE_FAILED;
+use crate::{capture, config, stats, transport};
+use crate::{
+    ERR_BUFFER_TOO_LARGE, ERR_BUFFER_TOO_SMALL, ERR_INVALID_LENGTH, ERR_INVALID_UTF8, ERR_NONE,
+    ERR_NULL_PTR,
+};
+
+/// 64 bit buffer header provides 8 byte alignment for data pointers
+pub const BUFFER_HEADER_SIZE: isize = 64 / 8;
+
+pub(crate) const SIZEOF_INT32: isize = 32 / 8;
+
+/// Encodes a Cobhan Buffer header length field.
+///
+/// Header fields are little-endian, so a buffer can be passed between hosts of different
+/// endianness. With the `native_endian` feature they use the host's byte order instead, for
+/// consumers that still write native-endian headers.
+pub fn encode_header_length(length: i32) -> [u8; 4] {
+    #[cfg(not(feature = "native_endian"))]
+    return length.to_le_bytes();
+    #[cfg(feature = "native_endian")]
+    return length.to_ne_bytes();
+}
+
+/// Decodes a Cobhan Buffer header length field, see [`encode_header_length`].
+pub fn decode_header_length(bytes: [u8; 4]) -> i32 {
+    #[cfg(not(feature = "native_endian"))]
+    return i32::from_le_bytes(bytes);
+    #[cfg(feature = "native_endian")]
+    return i32::from_ne_bytes(bytes);
+}
+
+/// Writes the header of an empty output buffer with room for `capacity` payload bytes into raw
+/// memory at `buffer`, for hosts that cannot pack the header themselves.
+///
+/// Returns `ERR_NONE`, `ERR_NULL_PTR`, or `ERR_INVALID_LENGTH` if `capacity` is negative.
+///
+/// ## Safety
+///
+/// Behavior is undefined if `buffer` is not valid for writes of the 8 byte header.
+#[no_mangle]
+pub unsafe extern "C" fn cobhan_buffer_init(buffer: *mut c_char, capacity: i32) -> i32 {
+    if buffer.is_null() {
+        debug_print!("cobhan_buffer_init: buffer is NULL");
+        return ERR_NULL_PTR;
+    }
+    if capacity < 0 {
+        debug_print!("cobhan_buffer_init: capacity {} is negative", capacity);
+        return ERR_INVALID_LENGTH;
+    }
+    write_header_length(buffer, capacity);
+    write_header_reserved(buffer, 0);
+    ERR_NONE
+}
+
+/// Rewrites the header at `buffer` as an empty payload with no flags, e.g. to pass a buffer
+/// as an empty input. Output buffers are reused by calling [`cobhan_buffer_init`] again, since
+/// the header no longer holds their capacity once written.
+///
+/// Returns `ERR_NONE` or `ERR_NULL_PTR`.
+///
+/// ## Safety
+///
+/// Behavior is undefined if `buffer` is not valid for writes of the 8 byte header.
+#[no_mangle]
+pub unsafe extern "C" fn cobhan_buffer_reset(buffer: *mut c_char) -> i32 {
+    cobhan_buffer_init(buffer, 0)
+}
+
+/// Releases the spilled payload of the output buffer at `buffer` once the host has read it, and
+/// rewrites the header as an empty payload like [`cobhan_buffer_reset`].
+///
+/// The current transport discards the spill: a temporary file is removed, and a thread's spill
+/// file, with [`reuse_spill_files`](crate::CobhanConfig::reuse_spill_files), is kept for the
+/// thread's next spill. A reference to a file that was not spilled is ignored. A buffer that is not spilled is only reset. Returns `ERR_NONE`,
+/// `ERR_NULL_PTR`, or `ERR_INVALID_UTF8` if the spill reference is not valid UTF-8.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[no_mangle]
+pub unsafe extern "C" fn cobhan_buffer_release(buffer: *mut c_char) -> i32 {
+    if buffer.is_null() {
+        debug_print!("cobhan_buffer_release: buffer is NULL");
+        return ERR_NULL_PTR;
+    }
+    let (length, spilled) = read_header(buffer);
+    if spilled {
+        let payload = buffer.offset(BUFFER_HEADER_SIZE).cast::<u8>();
+        let reference_len = match payload_len(payload, length, spilled) {
+            Ok(len) => len,
+            Err(e) => return e,
+        };
+        match str::from_utf8(from_raw_parts(payload, reference_len)) {
+            Ok(reference) => transport::with_current(|t| t.discard(reference)),
+            Err(_) => {
+                debug_print!("cobhan_buffer_release: spill reference is invalid utf-8");
+                return ERR_INVALID_UTF8;
+            }
+        }
+    }
+    cobhan_buffer_reset(buffer)
+}
+
+/// Copies the payload of the Cobhan Buffer at `source` into the output buffer at `destination`,
+/// so a host can pass one call's result to another without reading it out first.
+///
+/// A spilled source is read back and the copy is written like any payload, spilling again if it
+/// does not fit `destination`; the source keeps its spill. Returns `ERR_NONE` or the error
+/// reading or writing the payload, e.g. `ERR_BUFFER_TOO_SMALL`.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size of either buffer is not correctly reserved or formatted.
+/// - The buffers overlap without being the same buffer.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[no_mangle]
+pub unsafe extern "C" fn cobhan_buffer_copy(
+    source: *const c_char,
+    destination: *mut c_char,
+) -> i32 {
+    if source.is_null() || destination.is_null() {
+        debug_print!("cobhan_buffer_copy: buffer is NULL");
+        return ERR_NULL_PTR;
+    }
+    if core::ptr::eq(source, destination) {
+        return ERR_NONE;
+    }
+    match read_payload(source) {
+        Ok(bytes) => bytes_to_cbuffer(&bytes, destination),
+        Err(e) => e,
+    }
+}
+
+/// Moves the payload of the Cobhan Buffer at `source` into the output buffer at `destination`,
+/// then resets `source` to an empty payload.
+///
+/// A spilled source hands its spill reference over as it is, so the spill now belongs to
+/// `destination` and is not copied; fails with `ERR_BUFFER_TOO_SMALL` if the reference does not
+/// fit. An inline source is copied like [`cobhan_buffer_copy`]. On failure `source` is left as
+/// it was.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size of either buffer is not correctly reserved or formatted.
+/// - The buffers overlap without being the same buffer.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[no_mangle]
+pub unsafe extern "C" fn cobhan_buffer_move(source: *mut c_char, destination: *mut c_char) -> i32 {
+    if source.is_null() || destination.is_null() {
+        debug_print!("cobhan_buffer_move: buffer is NULL");
+        return ERR_NULL_PTR;
+    }
+    if core::ptr::eq(source, destination) {
+        return ERR_NONE;
+    }
+    let (length, spilled) = read_header(source);
+    let result = if spilled {
+        let payload = source.offset(BUFFER_HEADER_SIZE).cast::<u8>();
+        match payload_len(payload, length, spilled) {
+            Ok(len) => write_spill_reference(from_raw_parts(payload, len), destination),
+            Err(e) => e,
+        }
+    } else {
+        cobhan_buffer_copy(source, destination)
+    };
+    if result == ERR_NONE {
+        cobhan_buffer_reset(source);
+    }
+    result
+}
+
+/// Takes a pointer to an external Cobhan Buffer and passes its payload to `f` to be changed in
+/// place, then sets the payload length to the length `f` returns, e.g. to XOR, mask or
+/// case-fold a payload without copying it.
+///
+/// `f` may shorten the payload but not lengthen it: a returned length over the payload length
+/// fails with `ERR_INVALID_LENGTH`, and an error `f` returns is returned; either way the length
+/// field is left as it was, though `f` may have changed the bytes.
+///
+/// ## Notes
+///
+/// Inline payloads are changed in place. Spilled payloads are read back, changed and written
+/// over the spill reference like [`bytes_to_cbuffer`], inline if they now fit where the reference
+/// was, and the old spill is discarded. In `defensive_copy_mode` inline payloads are copied out
+/// and the result copied back, so `f` does not see the host change them.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts_mut`][] is violated.
+pub unsafe fn cbuffer_transform_in_place(
+    buffer: *mut c_char,
+    mut f: impl FnMut(&mut [u8]) -> Result<usize, i32>,
+) -> i32 {
+    if buffer.is_null() {
+        debug_print!("cbuffer_transform_in_place: buffer is NULL");
+        return ERR_NULL_PTR;
+    }
+    let (length, spilled) = read_header(buffer);
+    let payload = buffer.offset(BUFFER_HEADER_SIZE).cast::<u8>();
+    let payload_len = match payload_len(payload, length, spilled) {
+        Ok(len) => len,
+        Err(e) => return e,
+    };
+    let mut transform = |bytes: &mut [u8]| match f(bytes) {
+        Ok(len) if len > bytes.len() => {
+            debug_print!(
+                "cbuffer_transform_in_place: returned length {} is over the payload length {}",
+                len,
+                bytes.len()
+            );
+            Err(ERR_INVALID_LENGTH)
+        }
+        result => result,
+    };
+
+    if spilled {
+        let (mut bytes, _charge) = match temp_to_vector(payload, payload_len) {
+            Ok(read) => read,
+            Err(e) => return e,
+        };
+        let len = match transform(&mut bytes) {
+            Ok(len) => len,
+            Err(e) => return e,
+        };
+        let reference = from_raw_parts(payload, payload_len).to_vec();
+        let reserved = read_header_reserved(buffer);
+        write_header_length(buffer, payload_len as i32);
+        let result = write_cbuffer(&bytes[..len], buffer);
+        if result == ERR_NONE {
+            if let Ok(reference) = str::from_utf8(&reference) {
+                transport::with_current(|t| t.discard(reference));
+            }
+        } else {
+            copy_nonoverlapping(reference.as_ptr(), payload, payload_len);
+            write_header_length(buffer, length);
+            write_header_reserved(buffer, reserved);
+        }
+        return result;
+    }
+
+    let result = if config::defensive_copy_mode() {
+        let mut bytes = from_raw_parts(payload, payload_len).to_vec();
+        transform(&mut bytes).inspect(|len| copy_nonoverlapping(bytes.as_ptr(), payload, *len))
+    } else {
+        transform(from_raw_parts_mut(payload, payload_len))
+    };
+    match result {
+        Ok(len) => {
+            write_header_length(buffer, len as i32);
+            ERR_NONE
+        }
+        Err(e) => e,
+    }
+}
+
+/// Reads the length field of the Cobhan Buffer at `buffer`.
+pub(crate) unsafe fn read_header_length(buffer: *const c_char) -> i32 {
+    decode_header_length((buffer as *const [u8; 4]).read())
+}
+
+/// Writes the length field of the Cobhan Buffer at `buffer`.
+pub(crate) unsafe fn write_header_length(buffer: *mut c_char, length: i32) {
+    (buffer as *mut [u8; 4]).write(encode_header_length(length))
+}
+
+/// Reads the reserved field of the Cobhan Buffer at `buffer`, encoded like the length field.
+pub(crate) unsafe fn read_header_reserved(buffer: *const c_char) -> u32 {
+    decode_header_length((buffer.offset(SIZEOF_INT32) as *const [u8; 4]).read()) as u32
+}
+
+/// Writes the reserved field of the Cobhan Buffer at `buffer`, encoded like the length field.
+pub(crate) unsafe fn write_header_reserved(buffer: *mut c_char, value: u32) {
+    (buffer.offset(SIZEOF_INT32) as *mut [u8; 4]).write(encode_header_length(value as i32))
+}
+
+/// Reads the reserved field of the Cobhan Buffer at `buffer` as flags.
+pub(crate) unsafe fn read_header_flags(buffer: *const c_char) -> BufferFlags {
+    BufferFlags::from_bits(read_header_reserved(buffer))
+}
+
+/// Writes the reserved field of the Cobhan Buffer at `buffer` as flags.
+pub(crate) unsafe fn write_header_flags(buffer: *mut c_char, flags: BufferFlags) {
+    write_header_reserved(buffer, flags.bits())
+}
+
+/// Reads the length field of the Cobhan Buffer at `buffer`, and whether the payload is a spill
+/// reference: a negative length, or in format v2 the `TEMP_FILE` flag.
+pub(crate) unsafe fn read_header(buffer: *const c_char) -> (i32, bool) {
+    let length = read_header_length(buffer);
+    let spilled = length < 0
+        || (config::buffer_format() == BufferFormat::V2
+            && read_header_flags(buffer).contains(BufferFlags::TEMP_FILE));
+    (length, spilled)
+}
+
+/// Marks the Cobhan Buffer at `buffer` as holding a new payload: in format v2, clears the format
+/// flags and sets `flags`, keeping the application-defined bits. Format v1 leaves the reserved
+/// field alone.
+pub(crate) unsafe fn mark_payload(buffer: *mut c_char, flags: BufferFlags) {
+    if config::buffer_format() == BufferFormat::V2 {
+        write_header_flags(buffer, read_header_flags(buffer).app_only() | flags);
+    }
+}
+
+/// Converts a length field into the number of payload bytes (or spill reference bytes, if
+/// `spilled`) at `payload`. Payload bytes must not exceed the configured `max_buffer_len`.
+pub(crate) fn payload_len(payload: *const u8, length: i32, spilled: bool) -> Result<usize, i32> {
+    let len = span_len(payload, length)?;
+    if !spilled {
+        check_max_buffer_len(len)?;
+    }
+    Ok(len)
+}
+
+/// Converts a length field into the number of bytes at `payload` it covers.
+///
+/// Fails with `ERR_INVALID_LENGTH` for `i32::MIN`, which has no positive counterpart, and for
+/// lengths that would run past the end of the address space, which a corrupt header can produce
+/// on 32 bit hosts.
+pub(crate) fn span_len(payload: *const u8, length: i32) -> Result<usize, i32> {
+    use core::convert::TryFrom;
+
+    let len = length
+        .checked_abs()
+        .and_then(|len| usize::try_from(len).ok())
+        .filter(|len| (payload as usize).checked_add(*len).is_some());
+    match len {
+        Some(len) => Ok(len),
+        None => {
+            debug_print!("span_len: length field {} is not representable", length);
+            Err(ERR_INVALID_LENGTH)
+        }
+    }
+}
+
+/// Fails with `ERR_BUFFER_TOO_LARGE` if `len` is over the configured `max_buffer_len`.
+pub(crate) fn check_max_buffer_len(len: usize) -> Result<(), i32> {
+    match config::max_buffer_len() {
+        Some(max) if len > max => {
+            warn_print!(
+                "check_max_buffer_len: {} bytes is over the limit of {}",
+                len,
+                max
+            );
+            Err(ERR_BUFFER_TOO_LARGE)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Takes a pointer to an external Cobhan Buffer and fallibly attempts to interpret it as a `Vec<u8>`.
+///
+/// ## Notes
+///
+/// This function does a memcopy from the provided Cobhan Buffer into Rust owned data.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[cfg_attr(feature = "copy_analysis", track_caller)]
+pub unsafe fn cbuffer_to_vector(buffer: *const c_char) -> Result<Vec<u8>, i32> {
+    let bytes = read_vector(buffer)?;
+    capture::record_input(&bytes);
+    stats::record_copy("cbuffer_to_vector", buffer, bytes.len());
+    Ok(bytes)
+}
+
+/// Reads a payload like [`cbuffer_to_vector`], without capturing it.
+pub(crate) unsafe fn read_vector(buffer: *const c_char) -> Result<Vec<u8>, i32> {
+    if buffer.is_null() {
+        debug_print!("cbuffer_to_vector: buffer is NULL");
+        return Err(ERR_NULL_PTR);
+    }
+    let timer = CallTimer::start();
+    let (length, spilled) = read_header(buffer);
+    let payload = buffer.offset(BUFFER_HEADER_SIZE).cast::<u8>();
+    trace_print!("cbuffer_to_vector: raw length field is {}", length);
+    let payload_len = payload_len(payload, length, spilled)?;
+
+    let (bytes, _charge) = if spilled {
+        trace_print!("cbuffer_to_vector: calling temp_to_vector");
+        temp_to_vector(payload, payload_len)?
+    } else {
+        //Allocation: to_vec() is a clone/copy
+        let charge = Charge::reserve(payload_len)?;
+        (from_raw_parts(payload, payload_len).to_vec(), charge)
+    };
+    timer.finish("cbuffer_to_vector", bytes.len());
+    Ok(bytes)
+}
+
+/// Takes a pointer to an external Cobhan Buffer and fallibly attempts to interpret it as a `String`.
+///
+/// The String is fallibly checked to ensure UTF-8 formatting.
+///
+/// ## Notes
+///
+/// This function does a memcopy from the provided Cobhan Buffer into Rust owned data.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[cfg_attr(feature = "copy_analysis", track_caller)]
+pub unsafe fn cbuffer_to_string(buffer: *const c_char) -> Result<String, i32> {
+    if buffer.is_null() {
+        debug_print!("cbuffer_to_string: buffer is NULL");
+        return Err(ERR_NULL_PTR);
+    }
+    let timer = CallTimer::start();
+    let (length, spilled) = read_header(buffer);
+    let payload = buffer.offset(BUFFER_HEADER_SIZE).cast::<u8>();
+    trace_print!("cbuffer_to_string: raw length field is {}", length);
+    let payload_len = payload_len(payload, length, spilled)?;
+
+    let (string, _charge) = if spilled {
+        trace_print!("cbuffer_to_string: calling temp_to_string");
+        temp_to_string(payload, payload_len)?
+    } else {
+        let charge = Charge::reserve(payload_len)?;
+        let string = str::from_utf8(from_raw_parts(payload, payload_len))
+            .map(|s| s.to_owned())
+            .map_err(|_| {
+                debug_print!(
+                    "cbuffer_to_string: payload is invalid utf-8 string (length = {})",
+                    length
+                );
+                ERR_INVALID_UTF8
+            })?;
+        (string, charge)
+    };
+    timer.finish("cbuffer_to_string", string.len());
+    capture::record_input(string.as_bytes());
+    stats::record_copy("cbuffer_to_string", buffer, string.len());
+    Ok(string)
+}
+
+/// A payload borrowed in place or read from the tempfile, holding the charge for the bytes read
+/// until it is dropped.
+pub(crate) struct Payload<'a> {
+    bytes: Cow<'a, [u8]>,
+    _charge: Charge,
+}
+
+impl<'a> Payload<'a> {
+    /// A payload that was not read from the tempfile, which nothing is charged for.
+    pub(crate) fn uncharged(bytes: Cow<'a, [u8]>) -> Self {
+        Payload {
+            bytes,
+            _charge: Charge::empty(),
+        }
+    }
+
+    /// A payload read from the tempfile, with the charge taken while reading it.
+    pub(crate) fn charged(bytes: Vec<u8>, charge: Charge) -> Self {
+        Payload {
+            bytes: Cow::Owned(bytes),
+            _charge: charge,
+        }
+    }
+
+    /// Hands the payload over to a caller, which no longer counts it.
+    pub(crate) fn into_cow(self) -> Cow<'a, [u8]> {
+        self.bytes
+    }
+
+    /// Hands the payload over to a caller as a `Vec`, see [`into_cow`](Self::into_cow).
+    pub(crate) fn into_owned(self) -> Vec<u8> {
+        self.bytes.into_owned()
+    }
+}
+
+impl core::ops::Deref for Payload<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl AsRef<[u8]> for Payload<'_> {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+/// Gets the payload of a Cobhan Buffer, borrowing it in place or reading it from the tempfile.
+pub(crate) unsafe fn cbuffer_payload<'a>(buffer: *const c_char) -> Result<Payload<'a>, i32> {
+    let bytes = read_payload(buffer)?;
+    capture::record_input(&bytes);
+    Ok(bytes)
+}
+
+/// Gets a payload like `cbuffer_payload`, without capturing it.
+pub(crate) unsafe fn read_payload<'a>(buffer: *const c_char) -> Result<Payload<'a>, i32> {
+    if buffer.is_null() {
+        debug_print!("cbuffer_payload: buffer is NULL");
+        return Err(ERR_NULL_PTR);
+    }
+    let timer = CallTimer::start();
+    let (length, spilled) = read_header(buffer);
+    let payload = buffer.offset(BUFFER_HEADER_SIZE).cast::<u8>();
+    trace_print!("cbuffer_payload: raw length field is {}", length);
+    let payload_len = payload_len(payload, length, spilled)?;
+
+    let bytes = if !spilled {
+        Payload::uncharged(inline_payload(payload, payload_len))
+    } else {
+        trace_print!("cbuffer_payload: calling temp_to_vector");
+        let (bytes, charge) = temp_to_vector(payload, payload_len)?;
+        Payload::charged(bytes, charge)
+    };
+    timer.finish("cbuffer_payload", bytes.len());
+    Ok(bytes)
+}
+
+/// Borrows an inline payload in place, or copies it right away in `defensive_copy_mode`.
+pub(crate) unsafe fn inline_payload<'a>(payload: *const u8, len: usize) -> Cow<'a, [u8]> {
+    let bytes = from_raw_parts(payload, len);
+    if config::defensive_copy_mode() {
+        Cow::Owned(bytes.to_vec())
+    } else {
+        Cow::Borrowed(bytes)
+    }
+}
+
+/// Gets a reader over the payload of a Cobhan Buffer, streaming spilled payloads.
+#[cfg(feature = "std")]
+pub(crate) unsafe fn open_payload<'a>(buffer: *const c_char) -> Result<Box<dyn BufRead + 'a>, i32> {
+    if buffer.is_null() {
+        debug_print!("open_payload: buffer is NULL");
+        return Err(ERR_NULL_PTR);
+    }
+    let (length, spilled) = read_header(buffer);
+    let payload = buffer.offset(BUFFER_HEADER_SIZE).cast::<u8>();
+    trace_print!("open_payload: raw length field is {}", length);
+    let payload_len = payload_len(payload, length, spilled)?;
+
+    if !spilled {
+        let bytes = inline_payload(payload, payload_len);
+        capture::record_input(&bytes);
+        match bytes {
+            Cow::Borrowed(bytes) => Ok(Box::new(bytes)),
+            Cow::Owned(bytes) => Ok(Box::new(io::Cursor::new(bytes))),
+        }
+    } else {
+        trace_print!("open_payload: calling open_spill");
+        Ok(Box::new(BufReader::new(open_spill(payload, payload_len)?)))
+    }
+}
+
+/// Maps a failure reading a payload from `open_payload`.
+#[cfg(feature = "std")]
+pub(crate) fn read_failed(_e: io::Error) -> i32 {
+    error_print!("open_payload: failed to read payload: {}", _e);
+    ERR_READ_TEMP_FILE_FAILED
+}
+
+/// Takes a pointer to an external Cobhan Buffer and passes its payload to `f` in chunks of
+/// `chunk_size` bytes, the last chunk holding the remainder, without loading it all.
+///
+/// Inline payloads are passed in place and spilled payloads are streamed from the transport, so
+/// memory use does not grow with the payload and the configured `max_buffer_len` does not apply
+/// to spilled payloads. `f` is not called for an empty payload, and the first error it returns
+/// stops the iteration and is returned. A `chunk_size` of 0 fails with `ERR_INVALID_LENGTH`.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[cfg(feature = "std")]
+pub unsafe fn cbuffer_for_each_chunk(
+    buffer: *const c_char,
+    chunk_size: usize,
+    mut f: impl FnMut(&[u8]) -> Result<(), i32>,
+) -> Result<(), i32> {
+    if chunk_size == 0 {
+        debug_print!("cbuffer_for_each_chunk: chunk size is 0");
+        return Err(ERR_INVALID_LENGTH);
+    }
+    if buffer.is_null() {
+        debug_print!("cbuffer_for_each_chunk: buffer is NULL");
+        return Err(ERR_NULL_PTR);
+    }
+    let (_, spilled) = read_header(buffer);
+    if !spilled {
+        return cbuffer_payload(buffer)?.chunks(chunk_size).try_for_each(f);
+    }
+
+    let mut reader = open_payload(buffer)?;
+    // Chunks that straddle the reader's buffer are assembled here
+    let mut pending = Vec::new();
+    loop {
+        let available = reader.fill_buf().map_err(read_failed)?;
+        if available.is_empty() {
+            break;
+        }
+        let mut taken = 0;
+        if !pending.is_empty() {
+            taken = (chunk_size - pending.len()).min(available.len());
+            pending.extend_from_slice(&available[..taken]);
+            if pending.len() == chunk_size {
+                f(&pending)?;
+                pending.clear();
+            }
+        }
+        while pending.is_empty() && available.len() - taken >= chunk_size {
+            f(&available[taken..taken + chunk_size])?;
+            taken += chunk_size;
+        }
+        pending.extend_from_slice(&available[taken..]);
+        let len = available.len();
+        reader.consume(len);
+    }
+    if !pending.is_empty() {
+        f(&pending)?;
+    }
+    Ok(())
+}
+
+/// Takes pointers to external Cobhan Buffers and returns a reader over their payloads, one after
+/// the other, as one stream, e.g. for a parser consuming a multi-part upload.
+///
+/// Inline payloads are read in place and spilled payloads are streamed from the transport, each
+/// opened once the reader reaches it, so the payloads are never concatenated. Fails with
+/// `ERR_NULL_PTR` or `ERR_INVALID_LENGTH` if a buffer is NULL or its length field is not
+/// representable; a payload that then fails to be read ends the stream with an I/O error, and
+/// [`ChainReader::error`] tells its error code.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size of any buffer is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+/// - A buffer is modified or freed before the reader is dropped.
+#[cfg(feature = "std")]
+pub unsafe fn cbuffers_chain_reader<'a>(
+    buffers: &'a [*const c_char],
+) -> Result<ChainReader<'a>, i32> {
+    for buffer in buffers {
+        if buffer.is_null() {
+            debug_print!("cbuffers_chain_reader: buffer is NULL");
+            return Err(ERR_NULL_PTR);
+        }
+        let (length, _) = read_header(*buffer);
+        span_len(buffer.offset(BUFFER_HEADER_SIZE).cast::<u8>(), length)?;
+    }
+    Ok(ChainReader {
+        buffers: buffers.iter(),
+        current: None,
+        failed: None,
+    })
+}
+
+/// The payloads of several buffers read as one stream, see [`cbuffers_chain_reader`].
+#[cfg(feature = "std")]
+pub struct ChainReader<'a> {
+    buffers: core::slice::Iter<'a, *const c_char>,
+    current: Option<Box<dyn BufRead + 'a>>,
+    failed: Option<i32>,
+}
+
+#[cfg(feature = "std")]
+impl ChainReader<'_> {
+    /// The error code of the payload that failed to be read, if one did.
+    pub fn error(&self) -> Option<i32> {
+        self.failed
+    }
+
+    fn fail(&mut self, e: i32) -> io::Error {
+        self.failed = Some(e);
+        self.current = None;
+        io::Error::other(format!("failed to read payload: error {}", e))
+    }
+}
+
+#[cfg(feature = "std")]
+impl BufRead for ChainReader<'_> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        loop {
+            if let Some(e) = self.failed {
+                return Err(self.fail(e));
+            }
+            let reader = match &mut self.current {
+                Some(reader) => reader,
+                None => match self.buffers.next() {
+                    // SAFETY: the caller of `cbuffers_chain_reader` keeps the buffers valid
+                    Some(buffer) => match unsafe { open_payload(*buffer) } {
+                        Ok(reader) => self.current.insert(reader),
+                        Err(e) => return Err(self.fail(e)),
+                    },
+                    None => return Ok(&[]),
+                },
+            };
+            match reader.fill_buf() {
+                Ok([]) => self.current = None,
+                Ok(_) => break,
+                Err(e) => return Err(self.fail(read_failed(e))),
+            }
+        }
+        // Filled just above, so this returns the same bytes
+        match &mut self.current {
+            Some(reader) => reader.fill_buf(),
+            None => Ok(&[]),
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if let Some(reader) = &mut self.current {
+            reader.consume(amt);
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Read for ChainReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+/// Takes a pointer to an external Cobhan Buffer and returns an iterator over the segments of its
+/// payload separated by `delimiter`, without the delimiters.
+///
+/// Like `[u8]::split`, a payload ending with the delimiter ends with an empty segment and an
+/// empty payload is one empty segment. Segments of inline payloads are borrowed in place, and
+/// spilled payloads are streamed from the transport, one owned segment at a time, so neither is
+/// copied whole. An error ends the iteration.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+/// - The buffer is modified or freed before the iterator is dropped.
+#[cfg(feature = "std")]
+pub unsafe fn cbuffer_split<'a>(buffer: *const c_char, delimiter: u8) -> SplitIter<'a> {
+    let (source, failed) = match split_source(buffer) {
+        Ok(source) => (source, None),
+        Err(e) => (
+            SplitSource::Inline(Payload::uncharged(Cow::Borrowed(&[])), 0),
+            Some(e),
+        ),
+    };
+    SplitIter {
+        source,
+        delimiter,
+        failed,
+        done: false,
+    }
+}
+
+#[cfg(feature = "std")]
+unsafe fn split_source<'a>(buffer: *const c_char) -> Result<SplitSource<'a>, i32> {
+    if buffer.is_null() {
+        debug_print!("cbuffer_split: buffer is NULL");
+        return Err(ERR_NULL_PTR);
+    }
+    let (_, spilled) = read_header(buffer);
+    if spilled {
+        Ok(SplitSource::Spilled(open_payload(buffer)?))
+    } else {
+        Ok(SplitSource::Inline(cbuffer_payload(buffer)?, 0))
+    }
+}
+
+/// The delimited segments of a payload, see [`cbuffer_split`].
+#[cfg(feature = "std")]
+pub struct SplitIter<'a> {
+    source: SplitSource<'a>,
+    delimiter: u8,
+    failed: Option<i32>,
+    done: bool,
+}
+
+#[cfg(feature = "std")]
+enum SplitSource<'a> {
+    /// The payload and the offset of the next segment.
+    Inline(Payload<'a>, usize),
+    Spilled(Box<dyn BufRead + 'a>),
+}
+
+#[cfg(feature = "std")]
+impl<'a> SplitIter<'a> {
+    fn next_segment(&mut self) -> Result<Cow<'a, [u8]>, i32> {
+        if let Some(e) = self.failed {
+            return Err(e);
+        }
+        let delimiter = self.delimiter;
+        match &mut self.source {
+            SplitSource::Inline(bytes, start) => {
+                let rest = &bytes[*start..];
+                let end = match rest.iter().position(|b| *b == delimiter) {
+                    Some(i) => *start + i,
+                    None => {
+                        self.done = true;
+                        bytes.len()
+                    }
+                };
+                let segment = match &bytes.bytes {
+                    Cow::Borrowed(bytes) => Cow::Borrowed(&bytes[*start..end]),
+                    Cow::Owned(bytes) => Cow::Owned(bytes[*start..end].to_vec()),
+                };
+                *start = end + 1;
+                Ok(segment)
+            }
+            SplitSource::Spilled(reader) => {
+                let mut segment = Vec::new();
+                loop {
+                    let available = reader.fill_buf().map_err(read_failed)?;
+                    if available.is_empty() {
+                        self.done = true;
+                        return Ok(Cow::Owned(segment));
+                    }
+                    let found = available.iter().position(|b| *b == delimiter);
+                    let taken = found.unwrap_or(available.len());
+                    check_max_buffer_len(segment.len() + taken)?;
+                    segment.extend_from_slice(&available[..taken]);
+                    reader.consume(taken + found.map_or(0, |_| 1));
+                    if found.is_some() {
+                        return Ok(Cow::Owned(segment));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a> Iterator for SplitIter<'a> {
+    type Item = Result<Cow<'a, [u8]>, i32>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.next_segment();
+        if result.is_err() {
+            self.done = true;
+        }
+        Some(result)
+    }
+}
+
+/// Takes a pointer to an external Cobhan Buffer and returns an iterator over the lines of its
+/// payload as strings, like [`cbuffer_split`] on `\n`.
+///
+/// Like `str::lines`, lines end with `\n` or `\r\n`, which are not included, and a final line
+/// ending is optional, so it does not add an empty line. Each line is fallibly checked to ensure
+/// UTF-8 formatting, and an invalid one ends the iteration with `ERR_INVALID_UTF8`.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+/// - The buffer is modified or freed before the iterator is dropped.
+#[cfg(feature = "std")]
+pub unsafe fn cbuffer_lines<'a>(buffer: *const c_char) -> LinesIter<'a> {
+    LinesIter {
+        split: cbuffer_split(buffer, b'\n'),
+    }
+}
+
+/// The lines of a payload, see [`cbuffer_lines`].
+#[cfg(feature = "std")]
+pub struct LinesIter<'a> {
+    split: SplitIter<'a>,
+}
+
+#[cfg(feature = "std")]
+impl<'a> Iterator for LinesIter<'a> {
+    type Item = Result<Cow<'a, str>, i32>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = match self.split.next()? {
+            Ok(line) => line,
+            Err(e) => return Some(Err(e)),
+        };
+        if line.is_empty() && self.split.done {
+            return None;
+        }
+        let line = match line {
+            Cow::Borrowed(line) => str::from_utf8(line.strip_suffix(b"\r").unwrap_or(line))
+                .map(Cow::Borrowed)
+                .map_err(|_| ()),
+            Cow::Owned(mut line) => {
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                String::from_utf8(line).map(Cow::Owned).map_err(|_| ())
+            }
+        };
+        Some(line.map_err(|_| {
+            debug_print!("cbuffer_lines: line is invalid utf-8 string");
+            self.split.done = true;
+            ERR_INVALID_UTF8
+        }))
+    }
+}
+
+/// Gets the payload of a Cobhan Buffer like `cbuffer_payload`, ignoring the configured
+/// `max_buffer_len` so that settings can always be changed.
+#[cfg(all(feature = "std", feature = "json"))]
+pub(crate) unsafe fn cbuffer_payload_unlimited<'a>(
+    buffer: *const c_char,
+) -> Result<Cow<'a, [u8]>, i32> {
+    if buffer.is_null() {
+        debug_print!("cbuffer_payload_unlimited: buffer is NULL");
+        return Err(ERR_NULL_PTR);
+    }
+    let (length, spilled) = read_header(buffer);
+    let payload = buffer.offset(BUFFER_HEADER_SIZE).cast::<u8>();
+    let span_len = span_len(payload, length)?;
+
+    if !spilled {
+        Ok(Cow::Borrowed(from_raw_parts(payload, span_len)))
+    } else {
+        Ok(Cow::Owned(read_spill(payload, span_len)?))
+    }
+}
+
+/// Takes a string, e.g. a `&str`, `String` or `Cow<str>`, and fallibly encodes it into a provided
+/// external Cobhan Buffer.
+///
+/// Will cause an error code if the provided Cobhan Buffer is too small.
+///
+/// ## Notes
+///
+/// This function does a memcopy from the Rust data into the provided Cobhan Buffer.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn string_to_cbuffer(string: impl AsRef<str>, buffer: *mut c_char) -> i32 {
+    bytes_to_cbuffer(string.as_ref().as_bytes(), buffer)
+}
+
+/// Takes bytes, e.g. a `&[u8]`, `Vec<u8>` or `Cow<[u8]>`, and fallibly encodes them into a
+/// provided external Cobhan Buffer.
+///
+/// Will cause an error code if the provided Cobhan Buffer is too small.
+///
+/// ## Notes
+///
+/// This function does a memcopy from the Rust data into the provided Cobhan Buffer.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn bytes_to_cbuffer(bytes: impl AsRef<[u8]>, buffer: *mut c_char) -> i32 {
+    let bytes = bytes.as_ref();
+    capture::record_output(bytes);
+    write_cbuffer(bytes, buffer)
+}
+
+/// Returns the buffer capacity `bytes` needs, or `ERR_BUFFER_TOO_LARGE` if it is over the
+/// configured `max_buffer_len` or does not fit the i32 length field.
+///
+/// Functions can export this as the first phase of a size query, so hosts allocate an exactly
+/// sized buffer before calling the function that writes it.
+pub fn query_required_size(bytes: &[u8]) -> i32 {
+    if let Err(e) = check_max_buffer_len(bytes.len()) {
+        return e;
+    }
+    if bytes.len() > i32::MAX as usize {
+        debug_print!(
+            "query_required_size: {} bytes does not fit the length field",
+            bytes.len()
+        );
+        return ERR_BUFFER_TOO_LARGE;
+    }
+    bytes.len() as i32
+}
+
+/// Takes a string and encodes it into a provided external Cobhan Buffer if it fits, see
+/// [`bytes_to_cbuffer_or_size`].
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn string_to_cbuffer_or_size(string: impl AsRef<str>, buffer: *mut c_char) -> i32 {
+    bytes_to_cbuffer_or_size(string.as_ref().as_bytes(), buffer)
+}
+
+/// Takes bytes and encodes them into a provided external Cobhan Buffer if they fit, never
+/// spilling.
+///
+/// Returns `ERR_NONE` once written. If the payload is longer than the buffer capacity, which may
+/// be zero, nothing is written and the required capacity is returned as a positive value, so the
+/// host can allocate a buffer of that size and call again. Other failures are negative error
+/// codes, as from [`query_required_size`].
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn bytes_to_cbuffer_or_size(bytes: impl AsRef<[u8]>, buffer: *mut c_char) -> i32 {
+    let bytes = bytes.as_ref();
+    if buffer.is_null() {
+        debug_print!("bytes_to_cbuffer_or_size: buffer is NULL");
+        return ERR_NULL_PTR;
+    }
+    let payload = buffer.offset(BUFFER_HEADER_SIZE).cast::<u8>();
+
+    let buffer_cap = read_header_length(buffer);
+    trace_print!(
+        "bytes_to_cbuffer_or_size: buffer capacity is {}",
+        buffer_cap
+    );
+    if buffer_cap < 0 {
+        debug_print!("bytes_to_cbuffer_or_size: Invalid buffer capacity");
+        return ERR_BUFFER_TOO_SMALL;
+    }
+    let buffer_cap = match span_len(payload, buffer_cap) {
+        Ok(cap) => cap,
+        Err(e) => return e,
+    };
+
+    let required = query_required_size(bytes);
+    if required < 0 || bytes.len() > buffer_cap {
+        debug_print!(
+            "bytes_to_cbuffer_or_size: {} bytes required, capacity is {}",
+            required,
+            buffer_cap
+        );
+        return required;
+    }
+
+    capture::record_output(bytes);
+    copy_nonoverlapping(bytes.as_ptr(), payload, bytes.len());
+    write_header_length(buffer, required);
+    mark_payload(buffer, BufferFlags::empty());
+    ERR_NONE
+}
+
+/// What [`bytes_to_cbuffer_truncating`] wrote.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WriteOutcome {
+    /// Bytes written to the buffer.
+    pub written: usize,
+    /// Length of the whole payload.
+    pub total: usize,
+}
+
+impl WriteOutcome {
+    /// Returns `true` if only the start of the payload fit the buffer.
+    pub fn is_truncated(&self) -> bool {
+        self.written < self.total
+    }
+}
+
+/// Takes bytes and writes as much of them as fits into a provided external Cobhan Buffer,
+/// never spilling.
+///
+/// A payload longer than the buffer capacity is cut to the capacity and the `TRUNCATED` flag is
+/// set in the reserved field, whatever the configured [`BufferFormat`]; the other format flags
+/// are cleared and the application-defined bits are kept. The returned [`WriteOutcome`] has the
+/// full length, for the caller to report to the host.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn bytes_to_cbuffer_truncating(
+    bytes: impl AsRef<[u8]>,
+    buffer: *mut c_char,
+) -> Result<WriteOutcome, i32> {
+    let bytes = bytes.as_ref();
+    if buffer.is_null() {
+        debug_print!("bytes_to_cbuffer_truncating: buffer is NULL");
+        return Err(ERR_NULL_PTR);
+    }
+    let payload = buffer.offset(BUFFER_HEADER_SIZE).cast::<u8>();
+
+    let buffer_cap = read_header_length(buffer);
+    trace_print!(
+        "bytes_to_cbuffer_truncating: buffer capacity is {}",
+        buffer_cap
+    );
+    if buffer_cap <= 0 {
+        debug_print!("bytes_to_cbuffer_truncating: Invalid buffer capacity");
+        return Err(ERR_BUFFER_TOO_SMALL);
+    }
+    let written = bytes.len().min(span_len(payload, buffer_cap)?);
+    check_max_buffer_len(written)?;
+
+    capture::record_output(&bytes[..written]);
+    copy_nonoverlapping(bytes.as_ptr(), payload, written);
+    write_header_length(buffer, written as i32);
+
+    let outcome = WriteOutcome {
+        written,
+        total: bytes.len(),
+    };
+    let mut flags = read_header_flags(buffer).app_only();
+    if outcome.is_truncated() {
+        debug_print!(
+            "bytes_to_cbuffer_truncating: truncated {} bytes to {}",
+            outcome.total,
+            written
+        );
+        flags.insert(BufferFlags::TRUNCATED);
+    }
+    write_header_flags(buffer, flags);
+    Ok(outcome)
+}
+
+/// Takes bytes and splits them across several provided external Cobhan Buffers in order,
+/// never spilling.
+///
+/// Each buffer is filled to its capacity before the next one is used, so the last buffer used
+/// holds the remainder and any buffers after it are left with an empty payload. The host
+/// concatenates the payloads to get the value back. Fails with `ERR_BUFFER_TOO_SMALL`, writing
+/// nothing, if the capacities add up to less than the payload length.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size of any buffer is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn bytes_to_cbuffers_vectored(bytes: impl AsRef<[u8]>, buffers: &[*mut c_char]) -> i32 {
+    let bytes = bytes.as_ref();
+    if let Err(e) = check_max_buffer_len(bytes.len()) {
+        return e;
+    }
+
+    let mut capacities = Vec::with_capacity(buffers.len());
+    for &buffer in buffers {
+        if buffer.is_null() {
+            debug_print!("bytes_to_cbuffers_vectored: buffer is NULL");
+            return ERR_NULL_PTR;
+        }
+        let buffer_cap = read_header_length(buffer);
+        if buffer_cap < 0 {
+            debug_print!(
+                "bytes_to_cbuffers_vectored: Invalid buffer capacity {}",
+                buffer_cap
+            );
+            return ERR_BUFFER_TOO_SMALL;
+        }
+        match span_len(buffer.offset(BUFFER_HEADER_SIZE).cast::<u8>(), buffer_cap) {
+            Ok(cap) => capacities.push(cap),
+            Err(e) => return e,
+        }
+    }
+    let total_cap = capacities
+        .iter()
+        .fold(0usize, |total, cap| total.saturating_add(*cap));
+    debug_print!(
+        "bytes_to_cbuffers_vectored: {} bytes into {} buffers of {} bytes",
+        bytes.len(),
+        buffers.len(),
+        total_cap
+    );
+    if bytes.len() > total_cap {
+        return ERR_BUFFER_TOO_SMALL;
+    }
+
+    capture::record_output(bytes);
+    let mut rest = bytes;
+    for (&buffer, &cap) in buffers.iter().zip(&capacities) {
+        let (chunk, remainder) = rest.split_at(rest.len().min(cap));
+        copy_nonoverlapping(
+            chunk.as_ptr(),
+            buffer.offset(BUFFER_HEADER_SIZE).cast::<u8>(),
+            chunk.len(),
+        );
+        write_header_length(buffer, chunk.len() as i32);
+        mark_payload(buffer, BufferFlags::empty());
+        rest = remainder;
+    }
+    ERR_NONE
+}
+
+/// Takes chunks of a payload, e.g. from a compressor or encoder producing it incrementally, and
+/// fallibly writes them one after another into a provided external Cobhan Buffer.
+///
+/// Chunks are copied straight into the buffer while they fit its capacity. Once one does not,
+/// the chunks written so far and the remaining ones are gathered and spilled like
+/// [`bytes_to_cbuffer`] would, so producers only hold the whole payload when it spills. Fails
+/// with `ERR_BUFFER_TOO_LARGE` as soon as the chunks add up to more than the configured
+/// `max_buffer_len`.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn chunks_to_cbuffer(
+    chunks: impl IntoIterator<Item = impl AsRef<[u8]>>,
+    buffer: *mut c_char,
+) -> i32 {
+    if buffer.is_null() {
+        debug_print!("chunks_to_cbuffer: buffer is NULL");
+        return ERR_NULL_PTR;
+    }
+    let payload = buffer.offset(BUFFER_HEADER_SIZE).cast::<u8>();
+
+    let buffer_cap = read_header_length(buffer);
+    trace_print!("chunks_to_cbuffer: buffer capacity is {}", buffer_cap);
+    if buffer_cap <= 0 {
+        debug_print!("chunks_to_cbuffer: Invalid buffer capacity");
+        return ERR_BUFFER_TOO_SMALL;
+    }
+    let buffer_cap = match span_len(payload, buffer_cap) {
+        Ok(cap) => cap,
+        Err(e) => return e,
+    };
+
+    let timer = CallTimer::start();
+    let mut chunks = chunks.into_iter();
+    let mut written = 0usize;
+    for chunk in chunks.by_ref() {
+        let chunk = chunk.as_ref();
+        let total = written.saturating_add(chunk.len());
+        if let Err(e) = check_max_buffer_len(total) {
+            return e;
+        }
+        if total > buffer_cap {
+            debug_print!(
+                "chunks_to_cbuffer: {} bytes outgrow capacity {}, spilling",
+                total,
+                buffer_cap
+            );
+            let mut charge = match Charge::reserve(total) {
+                Ok(charge) => charge,
+                Err(e) => return e,
+            };
+            let mut bytes = Vec::with_capacity(total);
+            bytes.extend_from_slice(from_raw_parts(payload, written));
+            bytes.extend_from_slice(chunk);
+            for chunk in chunks {
+                let chunk = chunk.as_ref();
+                let grown = check_max_buffer_len(bytes.len().saturating_add(chunk.len()))
+                    .and_then(|_| charge.grow(chunk.len()));
+                if let Err(e) = grown {
+                    return e;
+                }
+                bytes.extend_from_slice(chunk);
+            }
+            return bytes_to_cbuffer(&bytes, buffer);
+        }
+        copy_nonoverlapping(chunk.as_ptr(), payload.add(written), chunk.len());
+        written = total;
+    }
+
+    capture::record_output(from_raw_parts(payload, written));
+    write_header_length(buffer, written as i32);
+    mark_payload(buffer, BufferFlags::empty());
+    timer.finish("chunks_to_cbuffer", written);
+    ERR_NONE
+}
+
+/// Writes a payload like [`bytes_to_cbuffer`], without capturing it.
+pub(crate) unsafe fn write_cbuffer(bytes: &[u8], buffer: *mut c_char) -> i32 {
+    let timer = CallTimer::start();
+    let result = write_payload(bytes, buffer);
+    if result == ERR_NONE {
+        timer.finish("bytes_to_cbuffer", bytes.len());
+    }
+    result
+}
+
+/// Writes a payload like `write_cbuffer`, without timing it.
+pub(crate) unsafe fn write_payload(bytes: &[u8], buffer: *mut c_char) -> i32 {
+    if buffer.is_null() {
+        debug_print!("bytes_to_cbuffer: buffer is NULL");
+        return ERR_NULL_PTR;
+    }
+
+    let payload = buffer.offset(BUFFER_HEADER_SIZE).cast::<u8>();
+
+    let buffer_cap = read_header_length(buffer);
+    trace_print!("bytes_to_cbuffer: buffer capacity is {}", buffer_cap);
+
+    if buffer_cap <= 0 {
+        debug_print!("bytes_to_cbuffer: Invalid buffer capacity");
+        return ERR_BUFFER_TOO_SMALL;
+    }
+    if let Err(e) = span_len(payload, buffer_cap) {
+        return e;
+    }
+
+    let bytes_len = bytes.len();
+    trace_print!("bytes_to_cbuffer: bytes.len() is {}", bytes_len);
+    if let Err(e) = check_max_buffer_len(bytes_len) {
+        return e;
+    }
+
+    // Compared as usize: a payload longer than i32::MAX must spill, not wrap
+    if bytes_len > buffer_cap as usize {
+        trace_print!("bytes_to_cbuffer: calling bytes_to_temp");
+        return bytes_to_temp(bytes, buffer);
+    }
+
+    copy_nonoverlapping(bytes.as_ptr(), payload, bytes_len);
+
+    write_header_length(buffer, bytes_len as i32);
+    mark_payload(buffer, BufferFlags::empty());
+
+    ERR_NONE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An 8 byte aligned buffer with room for 24 payload bytes and a length field of `length`.
+    fn buffer(length: i32) -> [u64; 4] {
+        let mut words = [0u64; 4];
+        unsafe { write_header_length(words.as_mut_ptr().cast(), length) };
+        words
+    }
+
+    #[test]
+    fn header_fields_are_little_endian() {
+        let mut words = buffer(0);
+        let ptr = words.as_mut_ptr().cast::<c_char>();
+        unsafe {
+            write_header_length(ptr, -2);
+            write_header_reserved(ptr, 0x0102_0304);
+            assert_eq!(read_header_length(ptr), -2);
+            assert_eq!(read_header_reserved(ptr), 0x0102_0304);
+            let header = from_raw_parts(ptr.cast::<u8>(), BUFFER_HEADER_SIZE as usize);
+            assert_eq!(header, [0xfe, 0xff, 0xff, 0xff, 4, 3, 2, 1]);
+
+            write_header_flags(ptr, BufferFlags::TRUNCATED);
+            assert_eq!(read_header_flags(ptr), BufferFlags::TRUNCATED);
+        }
+    }
+
+    #[test]
+    fn negative_lengths_are_spill_references() {
+        let words = buffer(-5);
+        assert_eq!(unsafe { read_header(words.as_ptr().cast()) }, (-5, true));
+        let words = buffer(5);
+        assert_eq!(unsafe { read_header(words.as_ptr().cast()) }, (5, false));
+    }
+
+    #[test]
+    fn v1_payloads_keep_the_reserved_field() {
+        let mut words = buffer(3);
+        let ptr = words.as_mut_ptr().cast::<c_char>();
+        unsafe {
+            write_header_reserved(ptr, 0xdead);
+            mark_payload(ptr, BufferFlags::TRUNCATED);
+            assert_eq!(read_header_reserved(ptr), 0xdead);
+        }
+    }
+
+    #[test]
+    fn length_fields_convert_to_spans() {
+        let payload = [0u8; 8];
+        let ptr = payload.as_ptr();
+        assert_eq!(span_len(ptr, 8), Ok(8));
+        assert_eq!(span_len(ptr, -8), Ok(8));
+        assert_eq!(span_len(ptr, i32::MIN), Err(ERR_INVALID_LENGTH));
+        assert_eq!(
+            span_len(usize::MAX as *const u8, 1),
+            Err(ERR_INVALID_LENGTH)
+        );
+        assert_eq!(payload_len(ptr, -8, true), Ok(8));
+        assert_eq!(payload_len(ptr, 0, false), Ok(0));
+    }
+
+    #[test]
+    fn payloads_are_written_in_place() {
+        let mut words = buffer(24);
+        let ptr = words.as_mut_ptr().cast::<c_char>();
+        unsafe {
+            assert_eq!(write_payload(b"payload", ptr), ERR_NONE);
+            assert_eq!(read_header(ptr), (7, false));
+            assert_eq!(read_vector(ptr), Ok(b"payload".to_vec()));
+            assert_eq!(write_payload(b"", ptr), ERR_NONE);
+            assert_eq!(read_vector(ptr), Ok(Vec::new()));
+        }
+        let mut words = buffer(0);
+        let ptr = words.as_mut_ptr().cast::<c_char>();
+        assert_eq!(unsafe { write_payload(b"x", ptr) }, ERR_BUFFER_TOO_SMALL);
+        assert_eq!(
+            unsafe { write_payload(b"x", core::ptr::null_mut()) },
+            ERR_NULL_PTR
+        );
+    }
+}
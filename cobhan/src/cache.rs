@@ -0,0 +1,166 @@
+//! # Payload deduplication
+//!
+//! Some hosts pass the same config or policy document on every call. A [`PayloadCache`] keeps
+//! one shared copy of each recent payload, keyed by its hash, so [`cbuffer_to_vector_cached`]
+//! hands back an `Arc<Vec<u8>>` clone instead of copying the payload again:
+//!
+//! ```ignore
+//! static POLICIES: OnceLock<cobhan::PayloadCache> = OnceLock::new();
+//!
+//! let policy = cobhan::cbuffer_to_vector_cached(POLICIES.get_or_init(Default::default), input)?;
+//! ```
+//!
+//! The cache holds at most [`PayloadCache::max_bytes`] of payloads, dropping the least recently
+//! used ones to make room; payloads larger than that are returned in a fresh `Arc` and not kept.
+//! A hash match is only a hit if the bytes are equal too.
+
+use std::borrow::Cow;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::os::raw::c_char;
+use std::sync::{Arc, Mutex};
+
+use crate::{cbuffer_payload, stats};
+
+#[derive(Debug)]
+struct Entry {
+    bytes: Arc<Vec<u8>>,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    by_hash: HashMap<u64, Entry>,
+    bytes: usize,
+    clock: u64,
+}
+
+/// A size-bounded cache of shared payloads, see the [module documentation](self).
+#[derive(Debug)]
+pub struct PayloadCache {
+    entries: Mutex<Entries>,
+    hasher: RandomState,
+    max_bytes: usize,
+}
+
+impl Default for PayloadCache {
+    /// Keeps up to 16 MiB of payloads.
+    fn default() -> Self {
+        PayloadCache::with_max_bytes(16 * 1024 * 1024)
+    }
+}
+
+impl PayloadCache {
+    /// A cache keeping up to `max_bytes` of payloads.
+    pub fn with_max_bytes(max_bytes: usize) -> Self {
+        PayloadCache {
+            entries: Mutex::new(Entries::default()),
+            hasher: RandomState::new(),
+            max_bytes,
+        }
+    }
+
+    /// Most payload bytes kept at once.
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+
+    /// Returns the shared copy of `bytes`, adding it if it fits.
+    pub fn get_or_insert(&self, bytes: &[u8]) -> Arc<Vec<u8>> {
+        self.share(Cow::Borrowed(bytes)).0
+    }
+
+    /// Returns the shared copy of `bytes` and whether it was already kept.
+    fn share(&self, bytes: Cow<'_, [u8]>) -> (Arc<Vec<u8>>, bool) {
+        let len = bytes.len();
+        if len > self.max_bytes {
+            return (Arc::new(bytes.into_owned()), false);
+        }
+        let hash = self.hasher.hash_one(&*bytes);
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.clock += 1;
+        let clock = entries.clock;
+        if let Some(entry) = entries.by_hash.get_mut(&hash) {
+            if *entry.bytes == *bytes {
+                entry.last_used = clock;
+                return (Arc::clone(&entry.bytes), true);
+            }
+        }
+
+        if let Some(replaced) = entries.by_hash.remove(&hash) {
+            entries.bytes -= replaced.bytes.len();
+        }
+        while entries.bytes + len > self.max_bytes {
+            let oldest = entries
+                .by_hash
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(hash, _)| *hash);
+            match oldest.and_then(|oldest| entries.by_hash.remove(&oldest)) {
+                Some(evicted) => entries.bytes -= evicted.bytes.len(),
+                None => break,
+            }
+        }
+        let shared = Arc::new(bytes.into_owned());
+        entries.bytes += len;
+        entries.by_hash.insert(
+            hash,
+            Entry {
+                bytes: Arc::clone(&shared),
+                last_used: clock,
+            },
+        );
+        (shared, false)
+    }
+
+    /// Number of payloads kept.
+    pub fn len(&self) -> usize {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .by_hash
+            .len()
+    }
+
+    /// Returns `true` if no payloads are kept.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Total bytes of the payloads kept.
+    pub fn size_bytes(&self) -> usize {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).bytes
+    }
+
+    /// Drops every kept payload. Copies already handed out stay valid.
+    pub fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.by_hash.clear();
+        entries.bytes = 0;
+    }
+}
+
+/// Takes a pointer to an external Cobhan Buffer and fallibly copies its payload into a `Vec<u8>`
+/// shared through `cache`.
+///
+/// A payload already in the cache is returned without copying; inline payloads are hashed and
+/// compared in place.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[cfg_attr(feature = "copy_analysis", track_caller)]
+pub unsafe fn cbuffer_to_vector_cached(
+    cache: &PayloadCache,
+    buffer: *const c_char,
+) -> Result<Arc<Vec<u8>>, i32> {
+    let bytes = cbuffer_payload(buffer)?;
+    let (shared, hit) = cache.share(bytes.into_cow());
+    if !hit {
+        stats::record_copy("cbuffer_to_vector_cached", buffer, shared.len());
+    }
+    Ok(shared)
+}
@@ -0,0 +1,301 @@
+//! # Capture and replay
+//!
+//! Records the payloads crossing the boundary to a capture file, so a bug seen by a host can be
+//! reproduced from the buffers it actually sent:
+//!
+//! ```ignore
+//! cobhan::capture::start_capture("/tmp/calls.cbncap", Default::default())?;
+//! // ... the host calls into the library ...
+//! cobhan::capture::stop_capture();
+//!
+//! // Later, in a test
+//! let outputs = unsafe { cobhan::capture::replay("/tmp/calls.cbncap", toUpper, 4096)? };
+//! ```
+//!
+//! While capturing, every payload this crate reads from an input buffer and every payload it
+//! writes to an output buffer is appended to the file, resolving spilled payloads. Capture is off
+//! until [`start_capture`] is called, and costs one atomic load per buffer while off.
+//!
+//! Independently of capture files, [`set_tee_sink`] registers a function that also receives every
+//! payload written to an output buffer, e.g. to forward it to a channel or sample it for metrics:
+//!
+//! ```ignore
+//! let (sender, receiver) = std::sync::mpsc::channel();
+//! let sender = std::sync::Mutex::new(sender);
+//! cobhan::capture::set_tee_sink(Arc::new(move |bytes: &[u8]| {
+//!     let _ = sender.lock().unwrap().send(bytes.to_vec());
+//! }));
+//! ```
+//!
+//! The file is an 8 byte magic (`CBNCAP`, 0, 1) followed by one record per payload: u8 direction
+//! (0 input, 1 output), u32 payload length, u32 captured length, captured bytes (lengths
+//! little-endian). The captured length is shorter than the payload length when the payload was
+//! truncated to [`CaptureOptions::max_payload_len`].
+
+use std::cell::Cell;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Write;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::allocation::aligned_buffer;
+use crate::buffer::read_vector;
+use crate::convert::PayloadReader;
+use crate::redact::redact;
+use crate::{ERR_BUFFER_TOO_LARGE, ERR_CAPTURE_FAILED, ERR_MALFORMED_PAYLOAD};
+
+const MAGIC: [u8; 8] = *b"CBNCAP\x00\x01";
+
+/// A cobhan-shaped function: reads the input buffer and writes the output buffer.
+pub type CobhanFn = unsafe extern "C" fn(*const c_char, *mut c_char) -> i32;
+
+/// Rewrites a payload before it is captured, e.g. to mask credentials.
+pub type Redactor = Arc<dyn Fn(Direction, &[u8]) -> Vec<u8> + Send + Sync>;
+
+/// Receives a copy of every payload written to an output buffer while registered.
+pub type TeeSink = Arc<dyn Fn(&[u8]) + Send + Sync>;
+
+/// Whether a payload was read from an input buffer or written to an output buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Input,
+    Output,
+}
+
+/// Limits applied to captured payloads.
+#[derive(Clone)]
+pub struct CaptureOptions {
+    /// Payloads longer than this are truncated; the record keeps the full length.
+    pub max_payload_len: usize,
+    /// Applied to every payload before it is truncated and written.
+    pub redact: Option<Redactor>,
+}
+
+impl Default for CaptureOptions {
+    fn default() -> Self {
+        CaptureOptions {
+            max_payload_len: 64 * 1024,
+            redact: None,
+        }
+    }
+}
+
+impl fmt::Debug for CaptureOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CaptureOptions")
+            .field("max_payload_len", &self.max_payload_len)
+            .field("redact", &self.redact.as_ref().map(|_| ".."))
+            .finish()
+    }
+}
+
+/// One payload read back from a capture file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CapturedBuffer {
+    pub direction: Direction,
+    /// Length of the payload when it was captured.
+    pub len: usize,
+    /// The captured bytes, after redaction and truncation.
+    pub bytes: Vec<u8>,
+}
+
+impl CapturedBuffer {
+    /// Returns `true` if the payload was longer than `max_payload_len`.
+    pub fn is_truncated(&self) -> bool {
+        self.bytes.len() < self.len
+    }
+}
+
+struct Capture {
+    file: File,
+    options: CaptureOptions,
+}
+
+static CAPTURING: AtomicBool = AtomicBool::new(false);
+
+static CAPTURE: Mutex<Option<Capture>> = Mutex::new(None);
+
+static TEEING: AtomicBool = AtomicBool::new(false);
+
+static TEE_SINK: RwLock<Option<TeeSink>> = RwLock::new(None);
+
+/// Starts appending payloads to a new capture file at `path`, replacing any capture in progress.
+///
+/// Fails with `ERR_CAPTURE_FAILED` if the file cannot be created.
+pub fn start_capture(path: impl AsRef<Path>, options: CaptureOptions) -> Result<(), i32> {
+    let mut file = File::create(path.as_ref()).map_err(|_e| {
+        error_print!("start_capture: failed to create capture file: {}", _e);
+        ERR_CAPTURE_FAILED
+    })?;
+    file.write_all(&MAGIC).map_err(|_| ERR_CAPTURE_FAILED)?;
+    *CAPTURE.lock().unwrap_or_else(|e| e.into_inner()) = Some(Capture { file, options });
+    CAPTURING.store(true, Ordering::Release);
+    Ok(())
+}
+
+/// Stops capturing and closes the capture file.
+pub fn stop_capture() {
+    CAPTURING.store(false, Ordering::Release);
+    CAPTURE.lock().unwrap_or_else(|e| e.into_inner()).take();
+}
+
+/// Returns `true` while a capture is in progress.
+pub fn is_capturing() -> bool {
+    CAPTURING.load(Ordering::Acquire)
+}
+
+/// Passes every payload written to an output buffer to `sink` as well, replacing any sink
+/// already registered.
+///
+/// The sink is called on the writing thread before the payload is copied into the buffer, so it
+/// sees payloads that then fail to be written, e.g. with `ERR_BUFFER_TOO_SMALL`, and it should
+/// not block. It may write to output buffers itself, which does not call it again. A panic in
+/// the sink is caught and the payload written regardless.
+pub fn set_tee_sink(sink: TeeSink) {
+    *TEE_SINK.write().unwrap_or_else(|e| e.into_inner()) = Some(sink);
+    TEEING.store(true, Ordering::Release);
+}
+
+/// Unregisters the sink set with [`set_tee_sink`].
+pub fn clear_tee_sink() {
+    TEEING.store(false, Ordering::Release);
+    TEE_SINK.write().unwrap_or_else(|e| e.into_inner()).take();
+}
+
+/// Returns `true` while a tee sink is registered.
+pub fn is_teeing() -> bool {
+    TEEING.load(Ordering::Acquire)
+}
+
+thread_local! {
+    static IN_TEE_SINK: Cell<bool> = const { Cell::new(false) };
+}
+
+fn tee(bytes: &[u8]) {
+    if !is_teeing() || IN_TEE_SINK.with(|entered| entered.replace(true)) {
+        return;
+    }
+    // Cloned out so the sink can replace itself
+    let sink = TEE_SINK.read().unwrap_or_else(|e| e.into_inner()).clone();
+    if let Some(sink) = sink {
+        // Keep the library working; a panic in the sink must not unwind into the host
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| sink(bytes)));
+    }
+    IN_TEE_SINK.with(|entered| entered.set(false));
+}
+
+fn record(direction: Direction, bytes: &[u8]) {
+    if !is_capturing() {
+        return;
+    }
+    let mut capture = CAPTURE.lock().unwrap_or_else(|e| e.into_inner());
+    let capture = match capture.as_mut() {
+        Some(capture) => capture,
+        None => return,
+    };
+    let bytes = redact(bytes);
+    let redacted = capture
+        .options
+        .redact
+        .as_ref()
+        .map(|f| f(direction, &bytes));
+    let bytes = redacted.as_deref().unwrap_or(&bytes);
+    let captured = &bytes[..bytes.len().min(capture.options.max_payload_len)];
+
+    // Records are written whole, so a crash leaves at most the last one incomplete. Payloads
+    // are at most i32::MAX bytes, so the lengths fit
+    let mut record = Vec::with_capacity(9 + captured.len());
+    record.push(direction as u8);
+    record.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    record.extend_from_slice(&(captured.len() as u32).to_le_bytes());
+    record.extend_from_slice(captured);
+    if capture.file.write_all(&record).is_err() {
+        // Keep the library working; the capture is abandoned
+        CAPTURING.store(false, Ordering::Release);
+    }
+}
+
+/// Records a payload read from an input buffer.
+pub(crate) fn record_input(bytes: &[u8]) {
+    record(Direction::Input, bytes)
+}
+
+/// Records a payload about to be written to an output buffer.
+pub(crate) fn record_output(bytes: &[u8]) {
+    tee(bytes);
+    record(Direction::Output, bytes)
+}
+
+/// Reads every record of the capture file at `path`.
+///
+/// Fails with `ERR_CAPTURE_FAILED` if the file cannot be read, and with `ERR_MALFORMED_PAYLOAD`
+/// if it is not a capture file. An incomplete last record is ignored.
+pub fn read_capture(path: impl AsRef<Path>) -> Result<Vec<CapturedBuffer>, i32> {
+    let bytes = fs::read(path.as_ref()).map_err(|_| ERR_CAPTURE_FAILED)?;
+    if !bytes.starts_with(&MAGIC) {
+        debug_print!("read_capture: not a capture file");
+        return Err(ERR_MALFORMED_PAYLOAD);
+    }
+    let mut reader = PayloadReader::new(&bytes[MAGIC.len()..]);
+    let mut records = Vec::new();
+    while !reader.is_empty() {
+        let direction = match reader.read_u8()? {
+            0 => Direction::Input,
+            1 => Direction::Output,
+            _ => return Err(ERR_MALFORMED_PAYLOAD),
+        };
+        // Only running out of bytes fails past the direction: the capture was cut short
+        let (len, bytes) = match (reader.read_u32(), reader.read_length_prefixed()) {
+            (Ok(len), Ok(bytes)) => (len as usize, bytes.to_vec()),
+            _ => break,
+        };
+        if bytes.len() > len {
+            return Err(ERR_MALFORMED_PAYLOAD);
+        }
+        records.push(CapturedBuffer {
+            direction,
+            len,
+            bytes,
+        });
+    }
+    Ok(records)
+}
+
+/// Calls `function` once for each input captured in the file at `path`, in order, with an
+/// output buffer of `output_capacity` bytes, and returns each output or error code.
+///
+/// Suits functions that take one input buffer and one output buffer. Inputs that were truncated
+/// when they were captured are not replayed and yield `ERR_BUFFER_TOO_LARGE`.
+///
+/// ## Safety
+///
+/// `function` must be safe to call with a valid input buffer and output buffer.
+pub unsafe fn replay(
+    path: impl AsRef<Path>,
+    function: CobhanFn,
+    output_capacity: usize,
+) -> Result<Vec<Result<Vec<u8>, i32>>, i32> {
+    let records = read_capture(path)?;
+    let inputs = records.iter().filter(|r| r.direction == Direction::Input);
+    Ok(inputs
+        .map(|input| {
+            if input.is_truncated() {
+                return Err(ERR_BUFFER_TOO_LARGE);
+            }
+            if output_capacity > i32::MAX as usize {
+                return Err(ERR_BUFFER_TOO_LARGE);
+            }
+            let input = aligned_buffer(&input.bytes, input.bytes.len());
+            let mut output = aligned_buffer(&[], output_capacity);
+            let output_ptr = output.as_mut_ptr() as *mut c_char;
+            match function(input.as_ptr() as *const c_char, output_ptr) {
+                0 => read_vector(output_ptr),
+                e => Err(e),
+            }
+        })
+        .collect())
+}
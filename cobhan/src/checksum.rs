@@ -0,0 +1,96 @@
+//! # Checksums
+//!
+//! [`bytes_to_cbuffer_with_crc`] stores a CRC-32 of the payload in the reserved header field and
+//! [`cbuffer_verify_crc`] checks it, so hosts that pass buffers through shared memory or other
+//! processes can detect corruption without exchanging a separate digest. The checksum is the
+//! common IEEE CRC-32 (as in zlib and PNG), encoded like the length field, and covers the
+//! payload itself, read back from the spill for spilled payloads.
+//!
+//! The checksum takes the whole reserved field, so it cannot be combined with the
+//! [`BufferFlags`](crate::BufferFlags) of format v2: writing one with format v2 configured fails
+//! with `ERR_INVALID_CONFIG`.
+
+use core::ffi::c_char;
+
+use crate::buffer::{read_header_reserved, read_payload, write_header_reserved};
+use crate::flags::BufferFormat;
+use crate::{bytes_to_cbuffer, config};
+use crate::{ERR_CHECKSUM_MISMATCH, ERR_INVALID_CONFIG, ERR_NONE};
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// The IEEE CRC-32 of `bytes`.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, byte| {
+        CRC32_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Takes bytes and encodes them into a provided external Cobhan Buffer like
+/// [`bytes_to_cbuffer`], then stores its CRC-32 in the reserved field.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn bytes_to_cbuffer_with_crc(bytes: impl AsRef<[u8]>, buffer: *mut c_char) -> i32 {
+    let bytes = bytes.as_ref();
+    if config::buffer_format() == BufferFormat::V2 {
+        debug_print!("bytes_to_cbuffer_with_crc: the reserved field holds format v2 flags");
+        return ERR_INVALID_CONFIG;
+    }
+    let result = bytes_to_cbuffer(bytes, buffer);
+    if result == ERR_NONE {
+        write_header_reserved(buffer, crc32(bytes));
+    }
+    result
+}
+
+/// Checks the payload of a Cobhan Buffer against the CRC-32 in its reserved field, see
+/// [`bytes_to_cbuffer_with_crc`].
+///
+/// Returns `ERR_NONE` if they match and `ERR_CHECKSUM_MISMATCH` if they do not, or the error
+/// reading the payload.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn cbuffer_verify_crc(buffer: *const c_char) -> i32 {
+    let payload = match read_payload(buffer) {
+        Ok(payload) => payload,
+        Err(e) => return e,
+    };
+    let (expected, actual) = (read_header_reserved(buffer), crc32(&payload));
+    if expected != actual {
+        debug_print!(
+            "cbuffer_verify_crc: checksum is {:#010x}, header says {:#010x}",
+            actual,
+            expected
+        );
+        return ERR_CHECKSUM_MISMATCH;
+    }
+    ERR_NONE
+}
@@ -0,0 +1,177 @@
+//! # Compressed payloads
+//!
+//! With the `gzip` or `zstd` feature, [`compressed_bytes_to_cbuffer`] compresses a payload before
+//! writing it and sets the `COMPRESSED` [`BufferFlags`] bit, and
+//! [`cbuffer_to_decompressed_vector`] decompresses payloads that have the bit set, so hosts on
+//! constrained links can pass compressed data and leave the codec to Rust. The algorithm is
+//! detected from the gzip or zstd frame magic, so readers need no other header field.
+//!
+//! Format v1 hosts may leave the reserved field uninitialized, so with format v1 the bit is only
+//! honored on payloads that start with a gzip or zstd magic; other payloads are read as they are.
+//!
+//! Decompressed payloads are held to the configured `max_buffer_len`, so a small payload cannot
+//! expand without bound.
+
+use std::io::{self, Read};
+use std::os::raw::c_char;
+
+use crate::buffer::{read_header_flags, read_payload, write_header_flags};
+use crate::flags::{BufferFlags, BufferFormat};
+use crate::memory::{read_charged, Charge};
+use crate::{bytes_to_cbuffer, capture, config, ERR_COMPRESSION_FAILED, ERR_NONE};
+
+/// A compression algorithm for [`compressed_bytes_to_cbuffer`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    /// gzip (RFC 1952) at the default level
+    #[cfg(feature = "gzip")]
+    Gzip,
+    /// Zstandard at the default level
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+fn compress(bytes: &[u8], compression: Compression) -> io::Result<Vec<u8>> {
+    match compression {
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => {
+            use std::io::Write;
+
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(bytes)?;
+            encoder.finish()
+        }
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => zstd::encode_all(bytes, zstd::DEFAULT_COMPRESSION_LEVEL),
+    }
+}
+
+/// The decoder for `compressed` that its magic names.
+fn decoder(compressed: &[u8]) -> io::Result<Box<dyn Read + '_>> {
+    if compressed.starts_with(GZIP_MAGIC) {
+        #[cfg(feature = "gzip")]
+        {
+            Ok(Box::new(flate2::read::GzDecoder::new(compressed)))
+        }
+        #[cfg(not(feature = "gzip"))]
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "gzip payload without the gzip feature",
+        ));
+    } else if compressed.starts_with(ZSTD_MAGIC) {
+        #[cfg(feature = "zstd")]
+        {
+            Ok(Box::new(zstd::Decoder::new(compressed)?))
+        }
+        #[cfg(not(feature = "zstd"))]
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "zstd payload without the zstd feature",
+        ));
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "payload is neither gzip nor zstd",
+        ))
+    }
+}
+
+/// Maps a failure decompressing a payload.
+fn decompress_failed(_e: io::Error) -> i32 {
+    debug_print!(
+        "cbuffer_to_decompressed_vector: decompression failed {}",
+        _e
+    );
+    ERR_COMPRESSION_FAILED
+}
+
+/// Whether the payload of `buffer` is to be decompressed.
+unsafe fn is_compressed(buffer: *const c_char, payload: &[u8]) -> bool {
+    if !read_header_flags(buffer).contains(BufferFlags::COMPRESSED) {
+        return false;
+    }
+    match config::buffer_format() {
+        BufferFormat::V1 => payload.starts_with(GZIP_MAGIC) || payload.starts_with(ZSTD_MAGIC),
+        BufferFormat::V2 => true,
+    }
+}
+
+/// Takes bytes, e.g. a `&[u8]` or `Vec<u8>`, compresses them with `compression` and encodes
+/// them into a provided external Cobhan Buffer like [`bytes_to_cbuffer`], then sets the
+/// `COMPRESSED` flag.
+///
+/// The flag is set whatever the configured [`BufferFormat`]; with format v1 the other format
+/// flags are cleared and the application-defined bits are kept.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn compressed_bytes_to_cbuffer(
+    bytes: impl AsRef<[u8]>,
+    compression: Compression,
+    buffer: *mut c_char,
+) -> i32 {
+    let bytes = bytes.as_ref();
+    let compressed = match compress(bytes, compression) {
+        Ok(compressed) => compressed,
+        Err(_e) => {
+            debug_print!(
+                "compressed_bytes_to_cbuffer: {:?} failed {}",
+                compression,
+                _e
+            );
+            return ERR_COMPRESSION_FAILED;
+        }
+    };
+    debug_print!(
+        "compressed_bytes_to_cbuffer: {:?} compressed {} bytes to {}",
+        compression,
+        bytes.len(),
+        compressed.len()
+    );
+    let result = bytes_to_cbuffer(&compressed, buffer);
+    if result == ERR_NONE {
+        let mut flags = read_header_flags(buffer);
+        if config::buffer_format() == BufferFormat::V1 {
+            flags = flags.app_only();
+        }
+        write_header_flags(buffer, flags | BufferFlags::COMPRESSED);
+    }
+    result
+}
+
+/// Takes a pointer to an external Cobhan Buffer and fallibly copies its payload into a
+/// `Vec<u8>`, decompressing it if the `COMPRESSED` flag is set. With format v1 the flag is only
+/// honored if the payload starts with a gzip or zstd magic, see the [module documentation](self).
+///
+/// Fails with `ERR_COMPRESSION_FAILED` if a flagged payload is corrupt, or is compressed with an
+/// algorithm whose feature is not enabled, and with `ERR_BUFFER_TOO_LARGE` if it decompresses to
+/// more than the configured `max_buffer_len`.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn cbuffer_to_decompressed_vector(buffer: *const c_char) -> Result<Vec<u8>, i32> {
+    let payload = read_payload(buffer)?;
+    if !is_compressed(buffer, &payload) {
+        capture::record_input(&payload);
+        return Ok(payload.into_owned());
+    }
+    // Charged and held to the limit as it is decompressed, as its size is not known up front
+    let mut charge = Charge::empty();
+    let bytes = read_charged(
+        decoder(&payload).map_err(decompress_failed)?,
+        &mut charge,
+        decompress_failed,
+    )?;
+    capture::record_input(&bytes);
+    Ok(bytes)
+}
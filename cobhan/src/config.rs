@@ -0,0 +1,644 @@
+//! # Configuration
+//!
+//! Process-wide settings, applied with [`configure`]:
+//!
+//! ```ignore
+//! cobhan::configure(cobhan::CobhanConfig {
+//!     max_buffer_len: Some(16 * 1024 * 1024),
+//!     spill_policy: cobhan::SpillPolicy::Reject,
+//!     ..Default::default()
+//! });
+//! ```
+//!
+//! Until `configure` is called, the settings are read once, at first use, from `COBHAN_*`
+//! environment variables (see [`CobhanConfig::from_env`]), so a host can adjust them without
+//! code changes.
+//!
+//! With the `json` feature, every library built on this crate also exports `cobhan_configure`,
+//! which takes the settings as a JSON object, so the host can apply its own deployment
+//! configuration at startup:
+//!
+//! ```text
+//! {"temp_dir": "/var/cache/app", "max_buffer_len": 16777216, "spill_policy": "reject"}
+//! ```
+
+#[cfg(all(feature = "std", feature = "json"))]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::env;
+#[cfg(feature = "std")]
+use std::fmt;
+#[cfg(feature = "std")]
+use std::path::PathBuf;
+#[cfg(feature = "std")]
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+#[cfg(feature = "std")]
+use std::sync::{Arc, RwLock};
+
+#[cfg(feature = "std")]
+use core::ffi::c_char;
+#[cfg(all(feature = "std", feature = "json"))]
+use serde_json::Value;
+#[cfg(feature = "std")]
+use std::ffi::CString;
+
+use crate::BufferFormat;
+#[cfg(all(feature = "std", feature = "json"))]
+use crate::{cbuffer_payload_unlimited, ERR_JSON_DECODE_FAILED};
+#[cfg(feature = "std")]
+use crate::{ERR_INVALID_CONFIG, ERR_NONE};
+
+/// What happens to payloads that do not fit the caller's buffer.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SpillPolicy {
+    /// Hand the payload to the current [`SpillTransport`](crate::SpillTransport).
+    #[default]
+    Spill,
+    /// Fail with `ERR_BUFFER_TOO_SMALL`.
+    Reject,
+}
+
+/// Severity of a line of `cobhan_debug` output, and as a setting the most verbose level written.
+///
+/// The library writes failures of the system rather than of the call's input, such as a spill
+/// file that could not be written or a caught panic, at `Error`; calls refused by a configured
+/// limit or policy at `Warn`; process-wide events such as shutdown at `Info`; why other calls
+/// failed at `Debug`; and the steps of every call at `Trace`. `Off` is only meaningful as a
+/// setting.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    Off = 0,
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    #[default]
+    Debug = 4,
+    Trace = 5,
+}
+
+#[cfg(feature = "std")]
+impl LogLevel {
+    /// The level with the number passed over FFI, `0` (off) to `5` (trace).
+    pub fn from_i32(level: i32) -> Option<Self> {
+        match level {
+            0 => Some(LogLevel::Off),
+            1 => Some(LogLevel::Error),
+            2 => Some(LogLevel::Warn),
+            3 => Some(LogLevel::Info),
+            4 => Some(LogLevel::Debug),
+            5 => Some(LogLevel::Trace),
+            _ => None,
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(LogLevel::Off),
+            "error" => Some(LogLevel::Error),
+            "warn" => Some(LogLevel::Warn),
+            "info" => Some(LogLevel::Info),
+            "debug" => Some(LogLevel::Debug),
+            "trace" => Some(LogLevel::Trace),
+            _ => None,
+        }
+    }
+}
+
+/// Where `cobhan_debug` output goes.
+///
+/// Output is only produced when the crate is built with the `cobhan_debug` feature.
+#[cfg(feature = "std")]
+#[derive(Clone, Default)]
+pub enum DebugSink {
+    /// Standard output, logcat on Android, os_log on iOS.
+    #[default]
+    Platform,
+    /// Standard error.
+    Stderr,
+    /// Discard debug output.
+    Off,
+    /// Pass each line to a callback, e.g. to forward it to the host's logger.
+    Callback(Arc<dyn Fn(&str) + Send + Sync>),
+    /// Pass each line and its level to a callback, see also [`cobhan_set_log_callback`].
+    Leveled(Arc<LevelSink>),
+}
+
+/// A callback receiving each line of `cobhan_debug` output with its level.
+#[cfg(feature = "std")]
+pub type LevelSink = dyn Fn(LogLevel, &str) + Send + Sync;
+
+#[cfg(feature = "std")]
+impl fmt::Debug for DebugSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DebugSink::Platform => f.write_str("Platform"),
+            DebugSink::Stderr => f.write_str("Stderr"),
+            DebugSink::Off => f.write_str("Off"),
+            DebugSink::Callback(_) => f.write_str("Callback(..)"),
+            DebugSink::Leveled(_) => f.write_str("Leveled(..)"),
+        }
+    }
+}
+
+/// Process-wide settings, see the [module documentation](self).
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, Default)]
+pub struct CobhanConfig {
+    /// Directory spill files are created in; the platform temp directory if `None`.
+    pub temp_dir: Option<PathBuf>,
+    /// Largest payload accepted from or written to a buffer, spilled or not. Larger payloads fail
+    /// with `ERR_BUFFER_TOO_LARGE`. Unlimited if `None`.
+    pub max_buffer_len: Option<usize>,
+    /// Largest number of bytes the conversions may hold at once, across all threads, see
+    /// [`current_marshaling_bytes`](crate::current_marshaling_bytes). Conversions that would
+    /// exceed it fail with `ERR_OUT_OF_MEMORY`. Unlimited if `None`.
+    pub max_marshaling_bytes: Option<usize>,
+    /// Largest number of payload bytes in output buffers leased at once, see
+    /// [`lease_output_buffer`](crate::lease_output_buffer). Unlimited if `None`.
+    pub max_leased_bytes: Option<usize>,
+    /// Largest JSON document decoded whole, such as by
+    /// [`cbuffer_to_hashmap_json`](crate::cbuffer_to_hashmap_json), in bytes. Larger documents
+    /// fail with `ERR_JSON_LIMITS_EXCEEDED`. The JSON limits do not apply to the iterators that
+    /// decode one element or line at a time. Unlimited if `None`.
+    pub max_json_bytes: Option<usize>,
+    /// Deepest nesting of arrays and objects in a decoded JSON document, the top-level object
+    /// being depth 1. Deeper documents fail with `ERR_JSON_LIMITS_EXCEEDED`. Unlimited if `None`,
+    /// though `serde_json` stops at a depth of 128.
+    pub max_json_depth: Option<usize>,
+    /// Most object keys in a decoded JSON document, counted across all its objects. Documents
+    /// with more fail with `ERR_JSON_LIMITS_EXCEEDED`. Unlimited if `None`.
+    pub max_json_keys: Option<usize>,
+    /// What happens to payloads that do not fit the caller's buffer.
+    pub spill_policy: SpillPolicy,
+    /// Header layout written to buffers, see [`BufferFormat`]. Hosts must read it too.
+    pub buffer_format: BufferFormat,
+    /// Where `cobhan_debug` output goes.
+    pub debug_sink: DebugSink,
+    /// Most verbose `cobhan_debug` output written; more verbose lines are not formatted at all.
+    pub log_level: LogLevel,
+    /// Copy inline payloads out of the host's buffers as soon as they are read instead of
+    /// borrowing them in place, for hosts whose garbage collector may move or free the buffers
+    /// during a call, see [`PinnedInput`](crate::PinnedInput).
+    pub defensive_copy_mode: bool,
+    /// Spill each thread's payloads to one file of its own, truncated and rewritten by every
+    /// spill, instead of a new temporary file each time, saving the file creation for services
+    /// that spill often. Hosts release each spill with `cobhan_buffer_release` once they have
+    /// read it, without removing the file; until then the thread's spills go to new files.
+    pub reuse_spill_files: bool,
+}
+
+#[cfg(feature = "std")]
+impl CobhanConfig {
+    /// Reads the settings from environment variables, using the default for any that is unset or
+    /// not valid:
+    ///
+    /// * `COBHAN_TEMP_DIR`: directory spill files are created in
+    /// * `COBHAN_MAX_BUFFER_LEN`: largest payload in bytes
+    /// * `COBHAN_MAX_MARSHALING_BYTES`: largest number of bytes held by conversions at once
+    /// * `COBHAN_MAX_LEASED_BYTES`: largest number of bytes in leased output buffers at once
+    /// * `COBHAN_MAX_JSON_BYTES`, `COBHAN_MAX_JSON_DEPTH`, `COBHAN_MAX_JSON_KEYS`: limits of
+    ///   decoded JSON documents
+    /// * `COBHAN_SPILL_POLICY`: `spill` or `reject`
+    /// * `COBHAN_BUFFER_FORMAT`: `1` or `2`
+    /// * `COBHAN_DEBUG_SINK`: `platform`, `stderr` or `off`
+    /// * `COBHAN_LOG_LEVEL`: `off`, `error`, `warn`, `info`, `debug` or `trace`
+    /// * `COBHAN_DEFENSIVE_COPY_MODE`: `1` or `0`
+    /// * `COBHAN_REUSE_SPILL_FILES`: `1` or `0`
+    pub fn from_env() -> Self {
+        let var = |name| env::var(name).ok().filter(|value| !value.is_empty());
+        let mut config = CobhanConfig::default();
+        if let Some(dir) = var("COBHAN_TEMP_DIR") {
+            config.temp_dir = Some(PathBuf::from(dir));
+        }
+        if let Some(len) = var("COBHAN_MAX_BUFFER_LEN").and_then(|len| len.parse().ok()) {
+            config.max_buffer_len = Some(len);
+        }
+        if let Some(len) = var("COBHAN_MAX_MARSHALING_BYTES").and_then(|len| len.parse().ok()) {
+            config.max_marshaling_bytes = Some(len);
+        }
+        if let Some(len) = var("COBHAN_MAX_LEASED_BYTES").and_then(|len| len.parse().ok()) {
+            config.max_leased_bytes = Some(len);
+        }
+        if let Some(len) = var("COBHAN_MAX_JSON_BYTES").and_then(|len| len.parse().ok()) {
+            config.max_json_bytes = Some(len);
+        }
+        if let Some(depth) = var("COBHAN_MAX_JSON_DEPTH").and_then(|depth| depth.parse().ok()) {
+            config.max_json_depth = Some(depth);
+        }
+        if let Some(keys) = var("COBHAN_MAX_JSON_KEYS").and_then(|keys| keys.parse().ok()) {
+            config.max_json_keys = Some(keys);
+        }
+        if let Some(policy) = var("COBHAN_SPILL_POLICY").and_then(|p| SpillPolicy::parse(&p)) {
+            config.spill_policy = policy;
+        }
+        if let Some(format) = var("COBHAN_BUFFER_FORMAT").and_then(|f| BufferFormat::parse(&f)) {
+            config.buffer_format = format;
+        }
+        if let Some(sink) = var("COBHAN_DEBUG_SINK").and_then(|s| DebugSink::parse(&s)) {
+            config.debug_sink = sink;
+        }
+        if let Some(level) = var("COBHAN_LOG_LEVEL").and_then(|l| LogLevel::parse(&l)) {
+            config.log_level = level;
+        }
+        match var("COBHAN_DEFENSIVE_COPY_MODE").as_deref() {
+            Some("1") => config.defensive_copy_mode = true,
+            Some("0") => config.defensive_copy_mode = false,
+            _ => {}
+        }
+        match var("COBHAN_REUSE_SPILL_FILES").as_deref() {
+            Some("1") => config.reuse_spill_files = true,
+            Some("0") => config.reuse_spill_files = false,
+            _ => {}
+        }
+        config
+    }
+
+    /// Overrides the settings present in `json`, see [`cobhan_configure`].
+    #[cfg(feature = "json")]
+    fn apply_json(&mut self, json: &HashMap<String, Value>) -> Result<(), i32> {
+        use std::convert::TryFrom;
+
+        for (key, value) in json {
+            match (key.as_str(), value) {
+                ("temp_dir", Value::Null) => self.temp_dir = None,
+                ("temp_dir", Value::String(dir)) => self.temp_dir = Some(PathBuf::from(dir)),
+                ("max_buffer_len", Value::Null) => self.max_buffer_len = None,
+                ("max_buffer_len", Value::Number(len)) => {
+                    let len = len.as_u64().and_then(|len| usize::try_from(len).ok());
+                    self.max_buffer_len = Some(len.ok_or(ERR_INVALID_CONFIG)?);
+                }
+                ("max_marshaling_bytes", Value::Null) => self.max_marshaling_bytes = None,
+                ("max_marshaling_bytes", Value::Number(len)) => {
+                    let len = len.as_u64().and_then(|len| usize::try_from(len).ok());
+                    self.max_marshaling_bytes = Some(len.ok_or(ERR_INVALID_CONFIG)?);
+                }
+                ("max_leased_bytes", Value::Null) => self.max_leased_bytes = None,
+                ("max_leased_bytes", Value::Number(len)) => {
+                    let len = len.as_u64().and_then(|len| usize::try_from(len).ok());
+                    self.max_leased_bytes = Some(len.ok_or(ERR_INVALID_CONFIG)?);
+                }
+                ("max_json_bytes", Value::Null) => self.max_json_bytes = None,
+                ("max_json_bytes", Value::Number(len)) => {
+                    let len = len.as_u64().and_then(|len| usize::try_from(len).ok());
+                    self.max_json_bytes = Some(len.ok_or(ERR_INVALID_CONFIG)?);
+                }
+                ("max_json_depth", Value::Null) => self.max_json_depth = None,
+                ("max_json_depth", Value::Number(depth)) => {
+                    let depth = depth.as_u64().and_then(|depth| usize::try_from(depth).ok());
+                    self.max_json_depth = Some(depth.ok_or(ERR_INVALID_CONFIG)?);
+                }
+                ("max_json_keys", Value::Null) => self.max_json_keys = None,
+                ("max_json_keys", Value::Number(keys)) => {
+                    let keys = keys.as_u64().and_then(|keys| usize::try_from(keys).ok());
+                    self.max_json_keys = Some(keys.ok_or(ERR_INVALID_CONFIG)?);
+                }
+                ("spill_policy", Value::String(policy)) => {
+                    self.spill_policy = SpillPolicy::parse(policy).ok_or(ERR_INVALID_CONFIG)?;
+                }
+                ("buffer_format", Value::Number(format)) => {
+                    let format = BufferFormat::parse(&format.to_string());
+                    self.buffer_format = format.ok_or(ERR_INVALID_CONFIG)?;
+                }
+                ("debug_sink", Value::String(sink)) => {
+                    self.debug_sink = DebugSink::parse(sink).ok_or(ERR_INVALID_CONFIG)?;
+                }
+                ("log_level", Value::String(level)) => {
+                    self.log_level = LogLevel::parse(level).ok_or(ERR_INVALID_CONFIG)?;
+                }
+                ("defensive_copy_mode", Value::Bool(enabled)) => {
+                    self.defensive_copy_mode = *enabled
+                }
+                ("reuse_spill_files", Value::Bool(enabled)) => self.reuse_spill_files = *enabled,
+                _ => return Err(ERR_INVALID_CONFIG),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl SpillPolicy {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "spill" => Some(SpillPolicy::Spill),
+            "reject" => Some(SpillPolicy::Reject),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl BufferFormat {
+    fn parse(version: &str) -> Option<Self> {
+        match version {
+            "1" => Some(BufferFormat::V1),
+            "2" => Some(BufferFormat::V2),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl DebugSink {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "platform" => Some(DebugSink::Platform),
+            "stderr" => Some(DebugSink::Stderr),
+            "off" => Some(DebugSink::Off),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+static CONFIG: RwLock<Option<CobhanConfig>> = RwLock::new(None);
+
+/// Copies of the settings read by every conversion and log line, so that those do not take the
+/// `CONFIG` lock. They are published under its write lock whenever the settings change.
+#[cfg(feature = "std")]
+struct Published {
+    /// Whether the copies are of the settings in effect, false until they are first read.
+    current: AtomicBool,
+    /// `usize::MAX` if unlimited, like the limits themselves.
+    max_buffer_len: AtomicUsize,
+    max_marshaling_bytes: AtomicUsize,
+    spill_allowed: AtomicBool,
+    v2: AtomicBool,
+    defensive_copy_mode: AtomicBool,
+    reuse_spill_files: AtomicBool,
+    log_level: AtomicU8,
+}
+
+#[cfg(feature = "std")]
+static PUBLISHED: Published = Published {
+    current: AtomicBool::new(false),
+    max_buffer_len: AtomicUsize::new(usize::MAX),
+    max_marshaling_bytes: AtomicUsize::new(usize::MAX),
+    spill_allowed: AtomicBool::new(true),
+    v2: AtomicBool::new(false),
+    defensive_copy_mode: AtomicBool::new(false),
+    reuse_spill_files: AtomicBool::new(false),
+    log_level: AtomicU8::new(LogLevel::Debug as u8),
+};
+
+#[cfg(feature = "std")]
+impl Published {
+    fn publish(&self, config: &CobhanConfig) {
+        let unlimited = usize::MAX;
+        let max_buffer_len = config.max_buffer_len.unwrap_or(unlimited);
+        let max_marshaling_bytes = config.max_marshaling_bytes.unwrap_or(unlimited);
+        let spill_allowed = config.spill_policy == SpillPolicy::Spill;
+        let v2 = config.buffer_format == BufferFormat::V2;
+        self.max_buffer_len.store(max_buffer_len, Ordering::Relaxed);
+        self.max_marshaling_bytes
+            .store(max_marshaling_bytes, Ordering::Relaxed);
+        self.spill_allowed.store(spill_allowed, Ordering::Relaxed);
+        self.v2.store(v2, Ordering::Relaxed);
+        self.defensive_copy_mode
+            .store(config.defensive_copy_mode, Ordering::Relaxed);
+        self.reuse_spill_files
+            .store(config.reuse_spill_files, Ordering::Relaxed);
+        self.log_level
+            .store(config.log_level as u8, Ordering::Relaxed);
+        self.current.store(true, Ordering::Release);
+    }
+
+    /// The copies, reading the settings from the environment first if they were never read.
+    fn get(&self) -> &Self {
+        if !self.current.load(Ordering::Acquire) {
+            with_config(|_| ());
+        }
+        self
+    }
+}
+
+/// Replaces the process-wide settings. Environment variables are not consulted afterwards.
+#[cfg(feature = "std")]
+pub fn configure(config: CobhanConfig) {
+    let mut current = CONFIG.write().unwrap_or_else(|e| e.into_inner());
+    PUBLISHED.publish(&config);
+    *current = Some(config);
+}
+
+/// Takes a Cobhan Buffer holding a JSON object and applies the settings it contains.
+///
+/// Keys are the [`CobhanConfig`] field names, and settings that are not present keep their
+/// current value: `temp_dir` (string or null), `max_buffer_len`, `max_marshaling_bytes`,
+/// `max_leased_bytes`, `max_json_bytes`, `max_json_depth` and `max_json_keys` (number or null),
+/// `spill_policy` (`"spill"` or `"reject"`), `buffer_format` (1 or 2),
+/// `debug_sink` (`"platform"`, `"stderr"` or `"off"`), `log_level` (`"off"` to `"trace"`),
+/// `reuse_spill_files` and `defensive_copy_mode` (boolean).
+/// Fails with `ERR_INVALID_CONFIG`, changing nothing, if any key or value is not recognized. The
+/// JSON buffer itself is exempt from `max_buffer_len`.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[cfg(all(feature = "std", feature = "json"))]
+#[no_mangle]
+pub unsafe extern "C" fn cobhan_configure(json: *const c_char) -> i32 {
+    let json = match cbuffer_payload_unlimited(json) {
+        Ok(json) => json,
+        Err(e) => return e,
+    };
+    let json: HashMap<String, Value> = match serde_json::from_slice(&json) {
+        Ok(json) => json,
+        Err(_) => return ERR_JSON_DECODE_FAILED,
+    };
+    // The lock is released before any debug output, which reads the configuration
+    let result = {
+        let mut config = CONFIG.write().unwrap_or_else(|e| e.into_inner());
+        let mut updated = config.get_or_insert_with(CobhanConfig::from_env).clone();
+        updated.apply_json(&json).map(|_| {
+            PUBLISHED.publish(&updated);
+            *config = Some(updated);
+        })
+    };
+    match result {
+        Ok(()) => ERR_NONE,
+        Err(e) => {
+            warn_print!(
+                "cobhan_configure: invalid settings {}",
+                crate::redact::redact_str(&format!("{:?}", json))
+            );
+            e
+        }
+    }
+}
+
+/// A host function receiving `cobhan_debug` output: the [`LogLevel`] number and one
+/// NUL-terminated utf-8 line, valid only for the duration of the call.
+#[cfg(feature = "std")]
+pub type LogCallback = unsafe extern "C" fn(level: i32, message: *const c_char);
+
+/// Sends `cobhan_debug` output to `callback`, with its level, or back to the platform sink if
+/// `callback` is null.
+///
+/// Lines are passed without interior NUL bytes. Shorthand for setting
+/// [`CobhanConfig::debug_sink`] to [`DebugSink::Leveled`].
+///
+/// ## Safety
+///
+/// `callback` must be safe to call from any thread, at any time until it is replaced.
+#[cfg(feature = "std")]
+#[no_mangle]
+pub unsafe extern "C" fn cobhan_set_log_callback(callback: Option<LogCallback>) {
+    let sink = match callback {
+        Some(callback) => DebugSink::Leveled(Arc::new(move |level, line| {
+            // Interior NULs would truncate the line, so they are dropped
+            let line = CString::new(line.replace('\0', "")).unwrap_or_default();
+            // SAFETY: the host guaranteed the callback is callable when registering it
+            unsafe { callback(level as i32, line.as_ptr()) }
+        })),
+        None => DebugSink::Platform,
+    };
+    update(|config| config.debug_sink = sink);
+}
+
+/// Sets the most verbose `cobhan_debug` output written, from `0` (off) to `5` (trace), see
+/// [`LogLevel`]. Fails with `ERR_INVALID_CONFIG` for any other number.
+#[cfg(feature = "std")]
+#[no_mangle]
+pub extern "C" fn cobhan_set_log_level(level: i32) -> i32 {
+    match LogLevel::from_i32(level) {
+        Some(level) => {
+            update(|config| config.log_level = level);
+            ERR_NONE
+        }
+        None => ERR_INVALID_CONFIG,
+    }
+}
+
+/// Forgets the settings, so they are read from the environment again on next use.
+#[cfg(feature = "std")]
+pub(crate) fn reset() {
+    let mut current = CONFIG.write().unwrap_or_else(|e| e.into_inner());
+    PUBLISHED.current.store(false, Ordering::Release);
+    *current = None;
+}
+
+/// The settings in effect.
+#[cfg(feature = "std")]
+pub fn current_config() -> CobhanConfig {
+    with_config(CobhanConfig::clone)
+}
+
+/// Changes one setting, keeping the others.
+#[cfg(feature = "std")]
+pub(crate) fn update(f: impl FnOnce(&mut CobhanConfig)) {
+    let mut config = CONFIG.write().unwrap_or_else(|e| e.into_inner());
+    let config = config.get_or_insert_with(CobhanConfig::from_env);
+    f(config);
+    PUBLISHED.publish(config);
+}
+
+/// Runs `f` against the settings in effect, reading them from the environment on first use.
+#[cfg(feature = "std")]
+pub(crate) fn with_config<R>(f: impl FnOnce(&CobhanConfig) -> R) -> R {
+    if let Some(config) = &*CONFIG.read().unwrap_or_else(|e| e.into_inner()) {
+        return f(config);
+    }
+    let mut config = CONFIG.write().unwrap_or_else(|e| e.into_inner());
+    let config = config.get_or_insert_with(|| {
+        let config = CobhanConfig::from_env();
+        PUBLISHED.publish(&config);
+        config
+    });
+    f(config)
+}
+
+/// `usize::MAX` as unlimited, the way the limits are published.
+#[cfg(feature = "std")]
+fn limit(published: &AtomicUsize) -> Option<usize> {
+    match published.load(Ordering::Relaxed) {
+        usize::MAX => None,
+        limit => Some(limit),
+    }
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn max_buffer_len() -> Option<usize> {
+    limit(&PUBLISHED.get().max_buffer_len)
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn max_marshaling_bytes() -> Option<usize> {
+    limit(&PUBLISHED.get().max_marshaling_bytes)
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn max_leased_bytes() -> Option<usize> {
+    with_config(|config| config.max_leased_bytes)
+}
+
+/// `max_json_bytes`, `max_json_depth` and `max_json_keys`.
+#[cfg(all(feature = "std", feature = "json"))]
+pub(crate) fn json_limits() -> (Option<usize>, Option<usize>, Option<usize>) {
+    with_config(|config| {
+        (
+            config.max_json_bytes,
+            config.max_json_depth,
+            config.max_json_keys,
+        )
+    })
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn spill_allowed() -> bool {
+    PUBLISHED.get().spill_allowed.load(Ordering::Relaxed)
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn buffer_format() -> BufferFormat {
+    match PUBLISHED.get().v2.load(Ordering::Relaxed) {
+        true => BufferFormat::V2,
+        false => BufferFormat::V1,
+    }
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn defensive_copy_mode() -> bool {
+    PUBLISHED.get().defensive_copy_mode.load(Ordering::Relaxed)
+}
+
+#[cfg(feature = "tempfile")]
+pub(crate) fn reuse_spill_files() -> bool {
+    PUBLISHED.get().reuse_spill_files.load(Ordering::Relaxed)
+}
+
+/// Whether `cobhan_debug` output at `level` is written.
+#[cfg(feature = "cobhan_debug")]
+pub(crate) fn log_enabled(level: LogLevel) -> bool {
+    level as u8 <= PUBLISHED.get().log_level.load(Ordering::Relaxed)
+}
+
+// Without `std` there is no configuration; the defaults apply
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn max_buffer_len() -> Option<usize> {
+    None
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn max_marshaling_bytes() -> Option<usize> {
+    None
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn spill_allowed() -> bool {
+    true
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn buffer_format() -> BufferFormat {
+    BufferFormat::V1
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn defensive_copy_mode() -> bool {
+    false
+}
@@ -0,0 +1,284 @@
+//! # Conformance vectors
+//!
+//! A new language binding can certify that it lays out buffers like this library does. The
+//! canonical [`vectors`] cover the edge cases bindings get wrong: an empty payload, a payload
+//! filling the buffer's capacity exactly, a spilled payload, invalid UTF-8 and a length field
+//! with no positive counterpart. [`write_vectors`], exported as
+//! [`cobhan_conformance_write_vectors`], writes them to a directory:
+//!
+//! * `manifest.json` - one entry per vector: `name`, `capacity`, `length_field`, `spilled`, and
+//!   the codes reading the buffer as bytes (`read_result`) and as a string (`string_result`)
+//!   return, `0` for success
+//! * `<name>.bin` - the buffer memory, the 8 byte header followed by `capacity` bytes
+//! * `<name>.payload` - the payload the buffer holds
+//! * `<name>.spill` - for spilled vectors, the spill file `<name>.bin` references by absolute path
+//!
+//! A binding's tests read each `.bin` back and check they get the payload or error the manifest
+//! lists, then produce a buffer of each vector themselves and pass it, with the vector name, to
+//! the exported [`cobhan_conformance_verify`], which writes a JSON report of the first mismatch,
+//! such as `{"vector": "utf8", "field": "payload", "offset": 3, ...}`, or `null`.
+//!
+//! Vectors use header format v1, with little-endian header fields.
+
+use std::fs;
+use std::os::raw::c_char;
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::buffer::{read_header, read_payload};
+use crate::diff::{diff_payloads, report_json, DiffReport};
+use crate::{
+    bytes_to_cbuffer, cbuffer_to_string, decode_header_length, encode_header_length,
+    BUFFER_HEADER_SIZE, ERR_INVALID_LENGTH, ERR_INVALID_UTF8, ERR_JSON_ENCODE_FAILED, ERR_NONE,
+    ERR_NULL_PTR, ERR_UNKNOWN_TEST_VECTOR, ERR_WRITE_TEMP_FILE_FAILED,
+};
+
+/// A canonical buffer, see the [module documentation](self).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestVector {
+    pub name: &'static str,
+    /// Bytes after the header in the buffer memory.
+    pub capacity: usize,
+    /// The payload the buffer holds, inline or in its spill file.
+    pub payload: Vec<u8>,
+    pub spilled: bool,
+    /// Replaces the length field, for vectors of malformed headers.
+    pub length_field: Option<i32>,
+    /// What reading the buffer as bytes returns: `ERR_NONE` or an error code.
+    pub read_result: i32,
+    /// What reading the buffer as a string returns: `ERR_NONE` or an error code.
+    pub string_result: i32,
+}
+
+impl TestVector {
+    fn new(name: &'static str, capacity: usize, payload: Vec<u8>) -> Self {
+        TestVector {
+            name,
+            capacity,
+            payload,
+            spilled: false,
+            length_field: None,
+            read_result: ERR_NONE,
+            string_result: ERR_NONE,
+        }
+    }
+
+    /// The buffer memory, header and `capacity` bytes. Spilled vectors hold `spill_reference`.
+    pub fn to_buffer(&self, spill_reference: &str) -> Vec<u8> {
+        let content = if self.spilled {
+            spill_reference.as_bytes()
+        } else {
+            &self.payload[..]
+        };
+        let length = match self.length_field {
+            Some(length) => length,
+            None if self.spilled => -(content.len() as i32),
+            None => content.len() as i32,
+        };
+        let header_size = BUFFER_HEADER_SIZE as usize;
+        let mut buffer = vec![0u8; header_size + self.capacity.max(content.len())];
+        buffer[..4].copy_from_slice(&encode_header_length(length));
+        buffer[header_size..header_size + content.len()].copy_from_slice(content);
+        buffer
+    }
+}
+
+/// The canonical test vectors, see the [module documentation](self).
+pub fn vectors() -> Vec<TestVector> {
+    let pattern = |len: usize| (0..len).map(|i| b'a' + (i % 26) as u8).collect::<Vec<u8>>();
+    vec![
+        TestVector::new("empty", 16, Vec::new()),
+        TestVector::new("utf8", 64, "héllo, wörld ✓ 🌍".as_bytes().to_vec()),
+        TestVector {
+            string_result: ERR_INVALID_UTF8,
+            ..TestVector::new("binary", 256, (0..=255).collect())
+        },
+        TestVector {
+            string_result: ERR_INVALID_UTF8,
+            ..TestVector::new("invalid_utf8", 16, b"ok \x80 \xe2\x82".to_vec())
+        },
+        TestVector::new("max_length", 4096, pattern(4096)),
+        TestVector {
+            spilled: true,
+            ..TestVector::new("temp_spill", 4096, pattern(65536))
+        },
+        TestVector {
+            length_field: Some(i32::MIN),
+            read_result: ERR_INVALID_LENGTH,
+            string_result: ERR_INVALID_LENGTH,
+            ..TestVector::new("invalid_length", 0, Vec::new())
+        },
+    ]
+}
+
+/// The canonical test vector called `name`.
+pub fn vector(name: &str) -> Option<TestVector> {
+    vectors().into_iter().find(|vector| vector.name == name)
+}
+
+/// Writes the test vectors and their manifest to `dir`, creating it if needed, see the
+/// [module documentation](self).
+///
+/// Fails with `ERR_WRITE_TEMP_FILE_FAILED` if a file cannot be written.
+pub fn write_vectors(dir: impl AsRef<Path>) -> Result<(), i32> {
+    let write_failed = |_e: std::io::Error| {
+        error_print!("write_vectors: failed to write a vector: {}", _e);
+        ERR_WRITE_TEMP_FILE_FAILED
+    };
+    fs::create_dir_all(dir.as_ref()).map_err(write_failed)?;
+    let dir = dir.as_ref().canonicalize().map_err(write_failed)?;
+    let mut manifest = Vec::new();
+    for vector in vectors() {
+        let spill_path = dir.join(format!("{}.spill", vector.name));
+        let spill_reference = spill_path.to_string_lossy();
+        if vector.spilled {
+            fs::write(&spill_path, &vector.payload).map_err(write_failed)?;
+        }
+        let buffer = vector.to_buffer(&spill_reference);
+        fs::write(dir.join(format!("{}.bin", vector.name)), &buffer).map_err(write_failed)?;
+        fs::write(
+            dir.join(format!("{}.payload", vector.name)),
+            &vector.payload,
+        )
+        .map_err(write_failed)?;
+        manifest.push(json!({
+            "name": vector.name,
+            "capacity": vector.capacity,
+            "length_field": decode_header_length([buffer[0], buffer[1], buffer[2], buffer[3]]),
+            "spilled": vector.spilled,
+            "read_result": vector.read_result,
+            "string_result": vector.string_result,
+        }));
+    }
+    let manifest = serde_json::to_vec_pretty(&json!({ "vectors": manifest }))
+        .map_err(|_| ERR_JSON_ENCODE_FAILED)?;
+    fs::write(dir.join("manifest.json"), manifest).map_err(write_failed)
+}
+
+/// How a buffer produced for a test vector differs from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mismatch {
+    /// The buffer is spilled and the vector is not, or the other way round.
+    Spilled { expected: bool, actual: bool },
+    /// Reading the buffer returned a different code than reading the vector.
+    Read { expected: i32, actual: i32 },
+    /// The payloads differ.
+    Payload(DiffReport),
+}
+
+/// Takes a pointer to an external Cobhan Buffer a host produced for `vector` and compares it
+/// with the vector, returning the first mismatch.
+///
+/// Errors reading the buffer are mismatches unless the vector expects them; only a NULL buffer
+/// fails, with `ERR_NULL_PTR`.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn verify_vector(
+    vector: &TestVector,
+    buffer: *const c_char,
+) -> Result<Option<Mismatch>, i32> {
+    if buffer.is_null() {
+        debug_print!("verify_vector: buffer is NULL");
+        return Err(ERR_NULL_PTR);
+    }
+    let bytes = match read_payload(buffer) {
+        Ok(bytes) => bytes,
+        Err(e) if e == vector.read_result => return Ok(None),
+        Err(e) => {
+            return Ok(Some(Mismatch::Read {
+                expected: vector.read_result,
+                actual: e,
+            }))
+        }
+    };
+    if vector.read_result != ERR_NONE {
+        return Ok(Some(Mismatch::Read {
+            expected: vector.read_result,
+            actual: ERR_NONE,
+        }));
+    }
+    let (_, spilled) = read_header(buffer);
+    if spilled != vector.spilled {
+        return Ok(Some(Mismatch::Spilled {
+            expected: vector.spilled,
+            actual: spilled,
+        }));
+    }
+    Ok(diff_payloads(&vector.payload, &bytes).map(Mismatch::Payload))
+}
+
+fn mismatch_json(name: &str, mismatch: Option<Mismatch>) -> Value {
+    let mut report = match mismatch {
+        None => return Value::Null,
+        Some(Mismatch::Spilled { expected, actual }) => {
+            json!({ "field": "spilled", "expected": expected, "actual": actual })
+        }
+        Some(Mismatch::Read { expected, actual }) => {
+            json!({ "field": "read_result", "expected": expected, "actual": actual })
+        }
+        Some(Mismatch::Payload(diff)) => report_json(Some(diff)),
+    };
+    report["vector"] = json!(name);
+    report
+}
+
+/// Writes the test vectors to the directory named by the string in the Cobhan Buffer at `dir`,
+/// see [`write_vectors`].
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[no_mangle]
+pub unsafe extern "C" fn cobhan_conformance_write_vectors(dir: *const c_char) -> i32 {
+    match cbuffer_to_string(dir).and_then(write_vectors) {
+        Ok(()) => ERR_NONE,
+        Err(e) => e,
+    }
+}
+
+/// Compares the Cobhan Buffer at `buffer`, produced by the host for the test vector named by the
+/// string in the Cobhan Buffer at `name`, with the vector, and writes the first mismatch into the
+/// output buffer at `report` as JSON, or `null` if there is none.
+///
+/// Returns `ERR_NONE` once the report is written, whether or not the buffer conforms,
+/// `ERR_UNKNOWN_TEST_VECTOR` if there is no vector called `name`, or the error reading the
+/// buffer or writing the report.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size of any buffer is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[no_mangle]
+pub unsafe extern "C" fn cobhan_conformance_verify(
+    name: *const c_char,
+    buffer: *const c_char,
+    report: *mut c_char,
+) -> i32 {
+    let name = match cbuffer_to_string(name) {
+        Ok(name) => name,
+        Err(e) => return e,
+    };
+    let vector = match vector(&name) {
+        Some(vector) => vector,
+        None => {
+            debug_print!("cobhan_conformance_verify: no test vector called {}", name);
+            return ERR_UNKNOWN_TEST_VECTOR;
+        }
+    };
+    let mismatch = match verify_vector(&vector, buffer) {
+        Ok(mismatch) => mismatch,
+        Err(e) => return e,
+    };
+    match serde_json::to_vec(&mismatch_json(&name, mismatch)) {
+        Ok(json_bytes) => bytes_to_cbuffer(&json_bytes, report),
+        Err(_) => ERR_JSON_ENCODE_FAILED,
+    }
+}
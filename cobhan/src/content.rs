@@ -0,0 +1,185 @@
+//! # Content types
+//!
+//! Format v2 buffers can carry a hint of what their payload holds in bits 8-15 of the flags
+//! field, so generic functions, e.g. a proxy forwarding payloads between libraries, can handle
+//! payloads they were not written for. [`set_cbuffer_content_type`] sets the hint on a written
+//! payload and [`decode_any`] decodes a payload according to it:
+//!
+//! ```ignore
+//! match unsafe { cobhan::decode_any(input) }? {
+//!     DecodedValue::Text(text) => forward_text(&text),
+//!     DecodedValue::Json(json) => forward_json(&json),
+//!     DecodedValue::Bytes(content_type, bytes) => forward_bytes(content_type, &bytes),
+//! }
+//! ```
+//!
+//! Like the other flags, the hint is cleared when a new payload is written, and is only honored
+//! with [`BufferFormat::V2`](crate::BufferFormat::V2), since v1 hosts may leave the reserved field
+//! uninitialized.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ffi::c_char;
+
+use crate::buffer::{read_header_flags, write_header_flags};
+use crate::flags::BufferFormat;
+#[cfg(all(feature = "std", feature = "json"))]
+use crate::json::check_json_limits;
+use crate::memory::Charge;
+#[cfg(feature = "json")]
+use crate::ERR_JSON_DECODE_FAILED;
+use crate::{cbuffer_payload, config};
+use crate::{ERR_INVALID_UTF8, ERR_NONE, ERR_NULL_PTR};
+
+/// What a payload holds, as hinted in its flags field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ContentType {
+    /// No hint, code 0.
+    Unspecified,
+    /// UTF-8 text, code 1.
+    Utf8,
+    /// A JSON document, code 2.
+    Json,
+    /// Opaque binary data, code 3.
+    Binary,
+    /// A MessagePack document, code 4.
+    MsgPack,
+    /// A CBOR document, code 5.
+    Cbor,
+    /// A Protocol Buffers message, code 6.
+    Protobuf,
+    /// A code this library does not define; codes from 128 up are left to applications.
+    Other(u8),
+}
+
+impl ContentType {
+    /// The content type with hint code `code`.
+    pub const fn from_code(code: u8) -> Self {
+        match code {
+            0 => ContentType::Unspecified,
+            1 => ContentType::Utf8,
+            2 => ContentType::Json,
+            3 => ContentType::Binary,
+            4 => ContentType::MsgPack,
+            5 => ContentType::Cbor,
+            6 => ContentType::Protobuf,
+            code => ContentType::Other(code),
+        }
+    }
+
+    /// The hint code of this content type.
+    pub const fn code(self) -> u8 {
+        match self {
+            ContentType::Unspecified => 0,
+            ContentType::Utf8 => 1,
+            ContentType::Json => 2,
+            ContentType::Binary => 3,
+            ContentType::MsgPack => 4,
+            ContentType::Cbor => 5,
+            ContentType::Protobuf => 6,
+            ContentType::Other(code) => code,
+        }
+    }
+}
+
+/// Reads the content type hint of a Cobhan Buffer, whatever the configured format.
+///
+/// ## Safety
+///
+/// Behavior is undefined if the Cobhan Buffer Header size is not correctly reserved.
+pub unsafe fn cbuffer_content_type(buffer: *const c_char) -> Result<ContentType, i32> {
+    if buffer.is_null() {
+        debug_print!("cbuffer_content_type: buffer is NULL");
+        return Err(ERR_NULL_PTR);
+    }
+    Ok(read_header_flags(buffer).content_type())
+}
+
+/// Sets the content type hint of a Cobhan Buffer after its payload is written, keeping the
+/// other flags.
+///
+/// ## Safety
+///
+/// Behavior is undefined if the Cobhan Buffer Header size is not correctly reserved.
+pub unsafe fn set_cbuffer_content_type(buffer: *mut c_char, content_type: ContentType) -> i32 {
+    if buffer.is_null() {
+        debug_print!("set_cbuffer_content_type: buffer is NULL");
+        return ERR_NULL_PTR;
+    }
+    write_header_flags(
+        buffer,
+        read_header_flags(buffer).with_content_type(content_type),
+    );
+    ERR_NONE
+}
+
+/// A payload decoded by [`decode_any`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum DecodedValue {
+    /// A payload hinted [`ContentType::Utf8`].
+    Text(String),
+    /// A payload hinted [`ContentType::Json`], with the `json` feature.
+    #[cfg(feature = "json")]
+    Json(serde_json::Value),
+    /// A payload hinted with a content type that has a [registered format](crate::register_format),
+    /// with the `formats` feature.
+    #[cfg(feature = "formats")]
+    Structured(ContentType, serde_json::Value),
+    /// Any other payload, with its hint: binary data, unhinted payloads, and content types
+    /// this library has no decoder for, such as MessagePack.
+    Bytes(ContentType, Vec<u8>),
+}
+
+/// Takes a pointer to an external Cobhan Buffer and fallibly decodes it according to its content
+/// type hint.
+///
+/// Fails with `ERR_INVALID_UTF8`, `ERR_JSON_DECODE_FAILED` or a registered format's error code if
+/// the payload is not what its hint says, and with `ERR_JSON_LIMITS_EXCEEDED` if a JSON payload
+/// is over the configured JSON limits. In format v1 the hint is ignored and every payload is
+/// returned as `Bytes(ContentType::Unspecified, ..)`.
+///
+/// ## Notes
+///
+/// This function does a memcopy from the provided Cobhan Buffer into Rust owned data.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn decode_any(buffer: *const c_char) -> Result<DecodedValue, i32> {
+    let bytes = cbuffer_payload(buffer)?;
+    let content_type = match config::buffer_format() {
+        BufferFormat::V1 => ContentType::Unspecified,
+        BufferFormat::V2 => read_header_flags(buffer).content_type(),
+    };
+    let _charge = Charge::reserve(bytes.len())?;
+    match content_type {
+        ContentType::Utf8 => String::from_utf8(bytes.into_owned())
+            .map(DecodedValue::Text)
+            .map_err(|_| {
+                debug_print!("decode_any: payload hinted utf8 is invalid utf-8 string");
+                ERR_INVALID_UTF8
+            }),
+        #[cfg(feature = "json")]
+        ContentType::Json => {
+            // Without `std` there are no limits configured
+            #[cfg(feature = "std")]
+            check_json_limits(&bytes)?;
+            serde_json::from_slice(&bytes)
+                .map(DecodedValue::Json)
+                .map_err(|_e| {
+                    debug_print!("decode_any: payload hinted json failed to decode: {}", _e);
+                    ERR_JSON_DECODE_FAILED
+                })
+        }
+        content_type => {
+            #[cfg(feature = "formats")]
+            if let Some(format) = crate::format::registered_format(content_type) {
+                return crate::format::decode_with(&*format, &bytes)
+                    .map(|value| DecodedValue::Structured(content_type, value));
+            }
+            Ok(DecodedValue::Bytes(content_type, bytes.into_owned()))
+        }
+    }
+}
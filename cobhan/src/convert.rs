@@ -0,0 +1,1365 @@
+//! # Typed conversions
+//!
+//! Codecs for the payload conventions in the [crate documentation](crate): text encodings,
+//! base64 and hex, timestamps, decimals, big integers, bitsets, f32 arrays, matrices, versioned
+//! payloads, string maps, packed buffers, C strings, paths and wide scalars.
+//!
+//! [`cbuffer_to_string_trimmed`] and [`cbuffer_to_string_lowercase`] trim or lowercase a string
+//! while decoding it, borrowing inline payloads that need no change instead of allocating, and
+//! with the `smallvec` feature `cbuffer_to_smallvec` copies short payloads (IDs, tokens) to the
+//! stack instead of the heap.
+
+use alloc::borrow::Cow;
+#[cfg(feature = "std")]
+use alloc::borrow::ToOwned;
+use alloc::ffi::CString;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ffi::{c_char, CStr};
+use core::ops::RangeInclusive;
+use core::slice::from_raw_parts;
+use core::{mem, str};
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::ffi::{OsStr, OsString};
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+
+#[cfg(any(feature = "time", feature = "decimal"))]
+use crate::cbuffer_to_string;
+use crate::memory::Charge;
+use crate::stats;
+#[cfg(feature = "decimal")]
+use crate::ERR_INVALID_DECIMAL;
+#[cfg(all(feature = "std", not(any(unix, windows))))]
+use crate::ERR_INVALID_PATH;
+#[cfg(feature = "time")]
+use crate::ERR_INVALID_TIMESTAMP;
+use crate::{bytes_to_cbuffer, cbuffer_payload, string_to_cbuffer};
+use crate::{
+    ERR_ARRAY_LENGTH_MISMATCH, ERR_BASE64_DECODE_FAILED, ERR_BUFFER_TOO_LARGE,
+    ERR_HEX_DECODE_FAILED, ERR_INTERIOR_NUL, ERR_INVALID_UTF16, ERR_INVALID_UTF8,
+    ERR_MALFORMED_PAYLOAD, ERR_MISALIGNED_PAYLOAD, ERR_NULL_PTR, ERR_SCALAR_OUT_OF_RANGE,
+    ERR_UNSUPPORTED_VERSION,
+};
+#[cfg(feature = "encodings")]
+use crate::{ERR_INVALID_ENCODING, ERR_UNKNOWN_ENCODING};
+
+/// Sequential reader over the little-endian binary layouts used by the structured codecs.
+pub(crate) struct PayloadReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> PayloadReader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        PayloadReader { bytes }
+    }
+
+    pub(crate) fn read_bytes(&mut self, count: usize) -> Result<&'a [u8], i32> {
+        if self.bytes.len() < count {
+            debug_print!(
+                "PayloadReader: needed {} bytes but only {} remain",
+                count,
+                self.bytes.len()
+            );
+            return Err(ERR_MALFORMED_PAYLOAD);
+        }
+        let (head, tail) = self.bytes.split_at(count);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn read_u8(&mut self) -> Result<u8, i32> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub(crate) fn read_u32(&mut self) -> Result<u32, i32> {
+        let b = self.read_bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub(crate) fn read_length_prefixed(&mut self) -> Result<&'a [u8], i32> {
+        let length = self.read_u32()? as usize;
+        self.read_bytes(length)
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn read_length_prefixed_str(&mut self) -> Result<&'a str, i32> {
+        str::from_utf8(self.read_length_prefixed()?).map_err(|_| {
+            debug_print!("PayloadReader: string is invalid utf-8");
+            ERR_INVALID_UTF8
+        })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+/// Appends a u32 little-endian length prefix and the bytes, failing if the length does not fit.
+pub(crate) fn push_length_prefixed(out: &mut Vec<u8>, bytes: &[u8]) -> Result<(), i32> {
+    push_u32(out, bytes.len())?;
+    out.extend_from_slice(bytes);
+    Ok(())
+}
+
+/// Appends a count or length as u32 little-endian, failing if it does not fit.
+pub(crate) fn push_u32(out: &mut Vec<u8>, value: usize) -> Result<(), i32> {
+    if value > u32::MAX as usize {
+        debug_print!("push_u32: {} does not fit in a u32", value);
+        return Err(ERR_BUFFER_TOO_LARGE);
+    }
+    out.extend_from_slice(&(value as u32).to_le_bytes());
+    Ok(())
+}
+
+/// Takes a pointer to an external Cobhan Buffer holding UTF-16LE code units and fallibly attempts to interpret it as a `String`.
+///
+/// The payload is fallibly checked to ensure an even length and correctly paired surrogates.
+///
+/// ## Notes
+///
+/// This function transcodes from the provided Cobhan Buffer into Rust owned data.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn cbuffer_utf16le_to_string(buffer: *const c_char) -> Result<String, i32> {
+    let bytes = cbuffer_payload(buffer)?;
+
+    if bytes.len() % 2 != 0 {
+        debug_print!(
+            "cbuffer_utf16le_to_string: payload length {} is not a multiple of 2",
+            bytes.len()
+        );
+        return Err(ERR_INVALID_UTF16);
+    }
+    // Each 2 byte unit decodes to at most 3 bytes of UTF-8
+    let _charge = Charge::reserve(bytes.len() / 2 * 3)?;
+
+    let units = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]));
+
+    char::decode_utf16(units)
+        .collect::<Result<String, _>>()
+        .map_err(|_e| {
+            debug_print!("cbuffer_utf16le_to_string: invalid utf-16 payload: {}", _e);
+            ERR_INVALID_UTF16
+        })
+}
+
+/// Takes a pointer to an external Cobhan Buffer and fallibly attempts to decode it as a `String` from the
+/// character encoding named by `encoding_label` (a WHATWG label such as `"shift_jis"` or `"windows-1252"`).
+///
+/// Malformed byte sequences are reported as an error rather than replaced.
+///
+/// ## Notes
+///
+/// This function transcodes from the provided Cobhan Buffer into Rust owned data.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[cfg(feature = "encodings")]
+pub unsafe fn cbuffer_decode(buffer: *const c_char, encoding_label: &str) -> Result<String, i32> {
+    let encoding =
+        encoding_rs::Encoding::for_label(encoding_label.as_bytes()).ok_or_else(|| {
+            debug_print!("cbuffer_decode: unknown encoding label {}", encoding_label);
+            ERR_UNKNOWN_ENCODING
+        })?;
+
+    let bytes = cbuffer_payload(buffer)?;
+    let _charge = Charge::reserve(
+        encoding
+            .new_decoder_without_bom_handling()
+            .max_utf8_buffer_length_without_replacement(bytes.len())
+            .unwrap_or(usize::MAX),
+    )?;
+
+    encoding
+        .decode_without_bom_handling_and_without_replacement(&bytes)
+        .map(|s| s.into_owned())
+        .ok_or_else(|| {
+            debug_print!(
+                "cbuffer_decode: payload is invalid {} (length = {})",
+                encoding.name(),
+                bytes.len()
+            );
+            ERR_INVALID_ENCODING
+        })
+}
+
+/// Takes a pointer to an external Cobhan Buffer and fallibly attempts to interpret it as a string with leading and
+/// trailing whitespace removed.
+///
+/// The string is fallibly checked to ensure UTF-8 formatting.
+///
+/// ## Notes
+///
+/// Inline payloads are borrowed in place and trimmed without copying; spilled payloads are
+/// trimmed in the `String` they are read into.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn cbuffer_to_string_trimmed<'a>(buffer: *const c_char) -> Result<Cow<'a, str>, i32> {
+    Ok(
+        match cbuffer_to_cow_str(buffer, "cbuffer_to_string_trimmed")? {
+            Cow::Borrowed(string) => Cow::Borrowed(string.trim()),
+            Cow::Owned(mut string) => {
+                string.truncate(string.trim_end().len());
+                let leading = string.len() - string.trim_start().len();
+                string.drain(..leading);
+                Cow::Owned(string)
+            }
+        },
+    )
+}
+
+/// Takes a pointer to an external Cobhan Buffer and fallibly attempts to interpret it as a lowercase string.
+///
+/// The string is fallibly checked to ensure UTF-8 formatting, and lowercased like
+/// `str::to_lowercase`.
+///
+/// ## Notes
+///
+/// Inline payloads that are already lowercase, as most identifiers are, are borrowed in place;
+/// others are lowercased into a new `String`.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn cbuffer_to_string_lowercase<'a>(buffer: *const c_char) -> Result<Cow<'a, str>, i32> {
+    let string = cbuffer_to_cow_str(buffer, "cbuffer_to_string_lowercase")?;
+    if string
+        .chars()
+        .flat_map(char::to_lowercase)
+        .eq(string.chars())
+    {
+        return Ok(string);
+    }
+    let _charge = Charge::reserve(string.len())?;
+    Ok(Cow::Owned(string.to_lowercase()))
+}
+
+/// Gets the payload of a Cobhan Buffer as a string, borrowed if the payload is.
+unsafe fn cbuffer_to_cow_str<'a>(
+    buffer: *const c_char,
+    _caller: &str,
+) -> Result<Cow<'a, str>, i32> {
+    let string = match cbuffer_payload(buffer)?.into_cow() {
+        Cow::Borrowed(bytes) => str::from_utf8(bytes).map(Cow::Borrowed).ok(),
+        Cow::Owned(bytes) => String::from_utf8(bytes).map(Cow::Owned).ok(),
+    };
+    match string {
+        Some(string) => Ok(string),
+        None => {
+            debug_print!("{}: payload is invalid utf-8 string", _caller);
+            Err(ERR_INVALID_UTF8)
+        }
+    }
+}
+
+/// Takes a pointer to an external Cobhan Buffer holding standard (padded) base64 text and fallibly decodes it into a `Vec<u8>`.
+///
+/// ## Notes
+///
+/// This function decodes from the provided Cobhan Buffer into Rust owned data.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn cbuffer_base64_decode_to_vec(buffer: *const c_char) -> Result<Vec<u8>, i32> {
+    let text = cbuffer_payload(buffer)?;
+    let _charge = Charge::reserve(text.len() / 4 * 3)?;
+
+    base64::decode(&text).map_err(|_e| {
+        debug_print!("cbuffer_base64_decode_to_vec: base64 decode failed {}", _e);
+        ERR_BASE64_DECODE_FAILED
+    })
+}
+
+/// Takes a pointer to an external Cobhan Buffer holding hex text and fallibly decodes it into a `Vec<u8>`.
+///
+/// Both upper and lower case hex digits are accepted.
+///
+/// ## Notes
+///
+/// This function decodes from the provided Cobhan Buffer into Rust owned data.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn cbuffer_hex_decode_to_vec(buffer: *const c_char) -> Result<Vec<u8>, i32> {
+    let text = cbuffer_payload(buffer)?;
+    let _charge = Charge::reserve(text.len() / 2)?;
+
+    hex::decode(&text).map_err(|_e| {
+        debug_print!("cbuffer_hex_decode_to_vec: hex decode failed {}", _e);
+        ERR_HEX_DECODE_FAILED
+    })
+}
+
+/// Takes a pointer to an external Cobhan Buffer holding an RFC 3339 timestamp and fallibly parses it as a `DateTime<Utc>`.
+///
+/// Timestamps with a non-UTC offset are converted to UTC.
+///
+/// ## Notes
+///
+/// This function parses from the provided Cobhan Buffer into Rust owned data.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[cfg(feature = "time")]
+pub unsafe fn cbuffer_to_datetime_rfc3339(
+    buffer: *const c_char,
+) -> Result<chrono::DateTime<chrono::Utc>, i32> {
+    let text = cbuffer_to_string(buffer)?;
+
+    chrono::DateTime::parse_from_rfc3339(&text)
+        .map(|dt| dt.with_timezone(&chrono::Utc))
+        .map_err(|_e| {
+            debug_print!(
+                "cbuffer_to_datetime_rfc3339: invalid timestamp {}: {}",
+                crate::redact::redact_str(&text),
+                _e
+            );
+            ERR_INVALID_TIMESTAMP
+        })
+}
+
+/// Takes a pointer to an external Cobhan Buffer holding a canonical decimal string and fallibly parses it as a `Decimal`.
+///
+/// Values that cannot be represented exactly are rejected rather than rounded.
+///
+/// ## Notes
+///
+/// This function parses from the provided Cobhan Buffer into Rust owned data.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[cfg(feature = "decimal")]
+pub unsafe fn cbuffer_to_decimal(buffer: *const c_char) -> Result<rust_decimal::Decimal, i32> {
+    let text = cbuffer_to_string(buffer)?;
+
+    rust_decimal::Decimal::from_str_exact(&text).map_err(|_e| {
+        debug_print!(
+            "cbuffer_to_decimal: invalid decimal {}: {}",
+            crate::redact::redact_str(&text),
+            _e
+        );
+        ERR_INVALID_DECIMAL
+    })
+}
+
+/// Takes a pointer to an external Cobhan Buffer holding a 16 byte binary decimal and fallibly interprets it as a `Decimal`.
+///
+/// ## Notes
+///
+/// This function copies from the provided Cobhan Buffer into Rust owned data.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[cfg(feature = "decimal")]
+pub unsafe fn cbuffer_to_decimal_binary(
+    buffer: *const c_char,
+) -> Result<rust_decimal::Decimal, i32> {
+    let bytes = cbuffer_payload(buffer)?;
+
+    let mut layout = [0u8; 16];
+    if bytes.len() != layout.len() {
+        debug_print!(
+            "cbuffer_to_decimal_binary: payload length {} is not 16",
+            bytes.len()
+        );
+        return Err(ERR_INVALID_DECIMAL);
+    }
+    layout.copy_from_slice(&bytes);
+
+    // Only the scale and sign bits of the flags word are defined
+    let flags = u32::from_le_bytes([layout[0], layout[1], layout[2], layout[3]]);
+    let scale = (flags >> 16) & 0xFF;
+    if flags & 0x7F00_FFFF != 0 || scale > rust_decimal::Decimal::MAX_SCALE {
+        debug_print!("cbuffer_to_decimal_binary: invalid flags {:#x}", flags);
+        return Err(ERR_INVALID_DECIMAL);
+    }
+
+    Ok(rust_decimal::Decimal::deserialize(layout))
+}
+
+/// Takes a pointer to an external Cobhan Buffer holding big-endian two's-complement bytes and fallibly interprets it as a `BigInt`.
+///
+/// An empty payload is interpreted as zero.
+///
+/// ## Notes
+///
+/// This function copies from the provided Cobhan Buffer into Rust owned data.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[cfg(feature = "bigint")]
+pub unsafe fn cbuffer_to_bigint(buffer: *const c_char) -> Result<num_bigint::BigInt, i32> {
+    let bytes = cbuffer_payload(buffer)?;
+    let _charge = Charge::reserve(bytes.len())?;
+    Ok(num_bigint::BigInt::from_signed_bytes_be(&bytes))
+}
+
+/// Takes a pointer to an external Cobhan Buffer and fallibly copies its payload into a `SmallVec`.
+///
+/// Payloads of up to `N` bytes, such as IDs and tokens, are held on the stack without a heap
+/// allocation; longer payloads are moved to the heap.
+///
+/// ## Notes
+///
+/// This function does a memcopy from the provided Cobhan Buffer into Rust owned data.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[cfg(feature = "smallvec")]
+pub unsafe fn cbuffer_to_smallvec<const N: usize>(
+    buffer: *const c_char,
+) -> Result<smallvec::SmallVec<[u8; N]>, i32> {
+    Ok(match cbuffer_payload(buffer)?.into_cow() {
+        Cow::Borrowed(bytes) => smallvec::SmallVec::from_slice(bytes),
+        Cow::Owned(bytes) => smallvec::SmallVec::from_vec(bytes),
+    })
+}
+
+/// Takes a pointer to an external Cobhan Buffer holding a packed bitset and fallibly unpacks it into a `Vec<bool>`.
+///
+/// ## Notes
+///
+/// This function unpacks from the provided Cobhan Buffer into Rust owned data.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn cbuffer_to_bitvec(buffer: *const c_char) -> Result<Vec<bool>, i32> {
+    let bytes = cbuffer_payload(buffer)?;
+
+    if bytes.len() < 4 {
+        debug_print!("cbuffer_to_bitvec: payload is missing bit count");
+        return Err(ERR_MALFORMED_PAYLOAD);
+    }
+    let (count, packed) = bytes.split_at(4);
+    let bit_count = u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize;
+
+    if packed.len() != bit_count.div_ceil(8) {
+        debug_print!(
+            "cbuffer_to_bitvec: {} packed bytes cannot hold {} bits",
+            packed.len(),
+            bit_count
+        );
+        return Err(ERR_MALFORMED_PAYLOAD);
+    }
+    // Unpacked, each bit takes a byte
+    let _charge = Charge::reserve(bit_count)?;
+
+    Ok((0..bit_count)
+        .map(|i| packed[i / 8] & (1 << (i % 8)) != 0)
+        .collect())
+}
+
+/// Checks that a payload of `len` bytes holds whole f32 values.
+fn check_f32_len(len: usize) -> Result<usize, i32> {
+    if !len.is_multiple_of(mem::size_of::<f32>()) {
+        debug_print!(
+            "f32 array: {} bytes is not a whole number of f32 values",
+            len
+        );
+        return Err(ERR_ARRAY_LENGTH_MISMATCH);
+    }
+    Ok(len / mem::size_of::<f32>())
+}
+
+/// Takes a pointer to an external Cobhan Buffer holding packed little-endian f32 values and fallibly copies them into a `Vec<f32>`.
+///
+/// Fails with `ERR_ARRAY_LENGTH_MISMATCH` if the payload length is not a multiple of 4.
+///
+/// ## Notes
+///
+/// This function copies from the provided Cobhan Buffer into Rust owned data, so the payload may have any alignment.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn cbuffer_to_f32_vec(buffer: *const c_char) -> Result<Vec<f32>, i32> {
+    let bytes = cbuffer_payload(buffer)?;
+    check_f32_len(bytes.len())?;
+    let _charge = Charge::reserve(bytes.len())?;
+    Ok(bytes
+        .chunks_exact(mem::size_of::<f32>())
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect())
+}
+
+/// Takes a pointer to an external Cobhan Buffer holding packed little-endian f32 values and fallibly borrows them in place.
+///
+/// Fails with `ERR_ARRAY_LENGTH_MISMATCH` if the payload length is not a multiple of 4, and with `ERR_MISALIGNED_PAYLOAD` if an inline payload is not 4 byte aligned, i.e. the buffer itself is not.
+///
+/// ## Notes
+///
+/// Inline payloads are borrowed on little-endian targets. Spilled payloads, payloads read in `defensive_copy_mode` and payloads on big-endian targets are copied.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn cbuffer_as_f32_slice<'a>(buffer: *const c_char) -> Result<Cow<'a, [f32]>, i32> {
+    let bytes = cbuffer_payload(buffer)?;
+    let count = check_f32_len(bytes.len())?;
+    match bytes.into_cow() {
+        Cow::Borrowed(bytes) if cfg!(target_endian = "little") => {
+            if !(bytes.as_ptr() as usize).is_multiple_of(mem::align_of::<f32>()) {
+                debug_print!("cbuffer_as_f32_slice: payload is not 4 byte aligned");
+                return Err(ERR_MISALIGNED_PAYLOAD);
+            }
+            Ok(Cow::Borrowed(from_raw_parts(
+                bytes.as_ptr().cast::<f32>(),
+                count,
+            )))
+        }
+        bytes => {
+            let _charge = Charge::reserve(bytes.len())?;
+            Ok(Cow::Owned(
+                bytes
+                    .chunks_exact(mem::size_of::<f32>())
+                    .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                    .collect(),
+            ))
+        }
+    }
+}
+
+/// A 2D grid of fixed-size elements, such as image pixels, in row-major order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ByteMatrix {
+    pub rows: usize,
+    pub cols: usize,
+    /// Size of one element in bytes, e.g. 3 for RGB8 pixels.
+    pub elem_size: usize,
+    /// `rows * cols * elem_size` bytes.
+    pub data: Vec<u8>,
+}
+
+impl ByteMatrix {
+    /// The bytes of row `index`, if there is one.
+    pub fn row(&self, index: usize) -> Option<&[u8]> {
+        let row_len = self.cols * self.elem_size;
+        if index >= self.rows {
+            return None;
+        }
+        self.data.get(index * row_len..(index + 1) * row_len)
+    }
+}
+
+/// The number of data bytes in a matrix of the given shape, if it does not overflow.
+fn matrix_len(rows: usize, cols: usize, elem_size: usize) -> Option<usize> {
+    rows.checked_mul(cols)?.checked_mul(elem_size)
+}
+
+/// Takes a pointer to an external Cobhan Buffer holding a matrix and fallibly copies it into a [`ByteMatrix`].
+///
+/// Fails with `ERR_MALFORMED_PAYLOAD` if the element size is 0 or the data length does not match `rows * cols * elem_size`.
+///
+/// ## Notes
+///
+/// This function copies from the provided Cobhan Buffer into Rust owned data.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn cbuffer_to_matrix(buffer: *const c_char) -> Result<ByteMatrix, i32> {
+    let bytes = cbuffer_payload(buffer)?;
+    let mut reader = PayloadReader::new(&bytes);
+    let rows = reader.read_u32()? as usize;
+    let cols = reader.read_u32()? as usize;
+    let elem_size = reader.read_u32()? as usize;
+    let data = &bytes[12..];
+
+    if elem_size == 0 || matrix_len(rows, cols, elem_size) != Some(data.len()) {
+        debug_print!(
+            "cbuffer_to_matrix: {} data bytes do not hold {}x{} elements of {} bytes",
+            data.len(),
+            rows,
+            cols,
+            elem_size
+        );
+        return Err(ERR_MALFORMED_PAYLOAD);
+    }
+    let _charge = Charge::reserve(data.len())?;
+
+    Ok(ByteMatrix {
+        rows,
+        cols,
+        elem_size,
+        data: data.to_vec(),
+    })
+}
+
+/// Takes a pointer to an external Cobhan Buffer holding a versioned payload and fallibly splits it into its format version and body.
+///
+/// Fails with `ERR_MALFORMED_PAYLOAD` if the payload is empty, so has no version byte.
+///
+/// ## Notes
+///
+/// Inline bodies are borrowed in place, spilled bodies are copied.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn read_versioned<'a>(buffer: *const c_char) -> Result<(u8, Cow<'a, [u8]>), i32> {
+    let bytes = cbuffer_payload(buffer)?;
+    let version = match bytes.first() {
+        Some(version) => *version,
+        None => {
+            debug_print!("read_versioned: payload is missing its version byte");
+            return Err(ERR_MALFORMED_PAYLOAD);
+        }
+    };
+    let body = match bytes.into_cow() {
+        Cow::Borrowed(bytes) => Cow::Borrowed(&bytes[1..]),
+        Cow::Owned(mut bytes) => {
+            bytes.remove(0);
+            Cow::Owned(bytes)
+        }
+    };
+    Ok((version, body))
+}
+
+/// Checks a version read by [`read_versioned`] against the versions a reader understands,
+/// failing with `ERR_UNSUPPORTED_VERSION` for any other.
+pub fn require_version(version: u8, supported: RangeInclusive<u8>) -> Result<u8, i32> {
+    if !supported.contains(&version) {
+        debug_print!(
+            "require_version: version {} is not in {:?}",
+            version,
+            supported
+        );
+        return Err(ERR_UNSUPPORTED_VERSION);
+    }
+    Ok(version)
+}
+
+/// Takes a pointer to an external Cobhan Buffer holding a length-prefixed string map and fallibly attempts to interpret it as a `HashMap<String, String>`.
+///
+/// Keys and values are fallibly checked to ensure UTF-8 formatting. If a key repeats, the last value wins.
+///
+/// ## Notes
+///
+/// This function copies from the provided Cobhan Buffer into Rust owned data.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[cfg(feature = "std")]
+pub unsafe fn cbuffer_to_string_map(buffer: *const c_char) -> Result<HashMap<String, String>, i32> {
+    let bytes = cbuffer_payload(buffer)?;
+    // Keys and values together are no longer than the payload
+    let _charge = Charge::reserve(bytes.len())?;
+    let mut reader = PayloadReader::new(&bytes);
+
+    let count = reader.read_u32()? as usize;
+    // Every entry needs at least its two length prefixes, so cap the reservation accordingly
+    let mut map = HashMap::with_capacity(count.min(bytes.len() / 8));
+    for _ in 0..count {
+        let key = reader.read_length_prefixed_str()?;
+        let value = reader.read_length_prefixed_str()?;
+        map.insert(key.to_owned(), value.to_owned());
+    }
+
+    if !reader.is_empty() {
+        debug_print!(
+            "cbuffer_to_string_map: trailing bytes after {} entries",
+            count
+        );
+        return Err(ERR_MALFORMED_PAYLOAD);
+    }
+
+    Ok(map)
+}
+
+/// Takes a pointer to an external Cobhan Buffer holding packed payloads and fallibly unpacks them into a `Vec<Vec<u8>>`.
+///
+/// ## Notes
+///
+/// This function copies from the provided Cobhan Buffer into Rust owned data.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn unpack_buffers(buffer: *const c_char) -> Result<Vec<Vec<u8>>, i32> {
+    let bytes = cbuffer_payload(buffer)?;
+    let _charge = Charge::reserve(bytes.len())?;
+    let mut reader = PayloadReader::new(&bytes);
+
+    let count = reader.read_u32()? as usize;
+    // Every payload needs at least its length prefix, so cap the reservation accordingly
+    let mut payloads = Vec::with_capacity(count.min(bytes.len() / 4));
+    for _ in 0..count {
+        payloads.push(reader.read_length_prefixed()?.to_vec());
+    }
+
+    if !reader.is_empty() {
+        debug_print!("unpack_buffers: trailing bytes after {} payloads", count);
+        return Err(ERR_MALFORMED_PAYLOAD);
+    }
+
+    Ok(payloads)
+}
+
+/// Takes a pointer to an external Cobhan Buffer and fallibly attempts to interpret it as a NUL-terminated `CString`.
+///
+/// The payload is not required to be UTF-8, but must not contain NUL bytes.
+///
+/// ## Notes
+///
+/// This function does a memcopy from the provided Cobhan Buffer into Rust owned data.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[cfg_attr(feature = "copy_analysis", track_caller)]
+pub unsafe fn cbuffer_to_cstring(buffer: *const c_char) -> Result<CString, i32> {
+    let bytes = cbuffer_payload(buffer)?;
+    stats::record_copy("cbuffer_to_cstring", buffer, bytes.len());
+    let _charge = Charge::reserve(bytes.len())?;
+
+    CString::new(bytes.into_owned()).map_err(|_e| {
+        debug_print!(
+            "cbuffer_to_cstring: payload has a NUL byte at {}",
+            _e.nul_position()
+        );
+        ERR_INTERIOR_NUL
+    })
+}
+
+/// Takes a pointer to an external Cobhan Buffer and fallibly attempts to interpret it as a `PathBuf`.
+///
+/// On Unix the payload is taken as raw path bytes. On Windows the payload is fallibly checked to be WTF-8.
+///
+/// ## Notes
+///
+/// This function copies from the provided Cobhan Buffer into Rust owned data.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[cfg(feature = "std")]
+pub unsafe fn cbuffer_to_pathbuf(buffer: *const c_char) -> Result<PathBuf, i32> {
+    let bytes = cbuffer_payload(buffer)?;
+    let _charge = Charge::reserve(bytes.len())?;
+    bytes_to_os_string(&bytes).map(PathBuf::from)
+}
+
+#[cfg(all(feature = "std", unix))]
+fn bytes_to_os_string(bytes: &[u8]) -> Result<OsString, i32> {
+    use std::os::unix::ffi::OsStrExt;
+
+    Ok(OsStr::from_bytes(bytes).to_os_string())
+}
+
+#[cfg(all(feature = "std", windows))]
+fn bytes_to_os_string(bytes: &[u8]) -> Result<OsString, i32> {
+    use std::os::windows::ffi::OsStringExt;
+
+    // WTF-8 is UTF-8 that also permits unpaired surrogates, decoded here straight to UTF-16 units
+    let mut units = Vec::with_capacity(bytes.len());
+    let mut after_lead_surrogate = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        let first = bytes[i];
+        let (len, init) = match first {
+            0x00..=0x7F => (1, first as u32),
+            0xC2..=0xDF => (2, (first & 0x1F) as u32),
+            0xE0..=0xEF => (3, (first & 0x0F) as u32),
+            0xF0..=0xF4 => (4, (first & 0x07) as u32),
+            _ => return Err(ERR_INVALID_PATH),
+        };
+        let tail = bytes.get(i + 1..i + len).ok_or(ERR_INVALID_PATH)?;
+        let mut code_point = init;
+        for &b in tail {
+            if b & 0xC0 != 0x80 {
+                return Err(ERR_INVALID_PATH);
+            }
+            code_point = (code_point << 6) | (b & 0x3F) as u32;
+        }
+        let min = [0, 0, 0x80, 0x800, 0x10000][len];
+        if code_point < min || code_point > 0x10FFFF {
+            return Err(ERR_INVALID_PATH);
+        }
+
+        // A lead surrogate directly followed by a trail surrogate must be encoded as one 4 byte sequence
+        let is_trail = (0xDC00..=0xDFFF).contains(&code_point);
+        if is_trail && after_lead_surrogate {
+            debug_print!("bytes_to_os_string: surrogate pair encoded as two sequences");
+            return Err(ERR_INVALID_PATH);
+        }
+        after_lead_surrogate = (0xD800..=0xDBFF).contains(&code_point);
+
+        if code_point >= 0x10000 {
+            let offset = code_point - 0x10000;
+            units.push(0xD800 | (offset >> 10) as u16);
+            units.push(0xDC00 | (offset & 0x3FF) as u16);
+        } else {
+            units.push(code_point as u16);
+        }
+        i += len;
+    }
+
+    Ok(OsString::from_wide(&units))
+}
+
+#[cfg(all(feature = "std", not(any(unix, windows))))]
+fn bytes_to_os_string(bytes: &[u8]) -> Result<OsString, i32> {
+    str::from_utf8(bytes)
+        .map(OsString::from)
+        .map_err(|_| ERR_INVALID_PATH)
+}
+
+/// Takes a `String` and fallibly encodes it as UTF-16LE into a provided external Cobhan Buffer.
+///
+/// Will cause an error code if the provided Cobhan Buffer is too small.
+///
+/// ## Notes
+///
+/// This function transcodes the Rust data into the provided Cobhan Buffer.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn string_to_cbuffer_utf16le(string: impl AsRef<str>, buffer: *mut c_char) -> i32 {
+    let bytes: Vec<u8> = string
+        .as_ref()
+        .encode_utf16()
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    bytes_to_cbuffer(&bytes, buffer)
+}
+
+/// Takes a pointer to a NUL-terminated C string and fallibly copies it (without the terminator) into a provided external Cobhan Buffer.
+///
+/// Will cause an error code if the provided Cobhan Buffer is too small.
+///
+/// ## Notes
+///
+/// This function does a memcopy from the C string into the provided Cobhan Buffer.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::ffi::CStr::from_ptr`][] is violated.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn cstr_to_cbuffer(cstr: *const c_char, buffer: *mut c_char) -> i32 {
+    if cstr.is_null() {
+        debug_print!("cstr_to_cbuffer: cstr is NULL");
+        return ERR_NULL_PTR;
+    }
+    bytes_to_cbuffer(CStr::from_ptr(cstr).to_bytes(), buffer)
+}
+
+/// Takes a `Path` and fallibly encodes it into a provided external Cobhan Buffer.
+///
+/// On Unix the raw path bytes are written. On Windows the path is written as WTF-8.
+///
+/// Will cause an error code if the provided Cobhan Buffer is too small, or on other platforms if the path is not UTF-8.
+///
+/// ## Notes
+///
+/// This function does a memcopy from the Rust data into the provided Cobhan Buffer.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[cfg(feature = "std")]
+pub unsafe fn path_to_cbuffer(path: &Path, buffer: *mut c_char) -> i32 {
+    match os_str_to_bytes(path.as_os_str()) {
+        Ok(bytes) => bytes_to_cbuffer(&bytes, buffer),
+        Err(e) => e,
+    }
+}
+
+#[cfg(all(feature = "std", unix))]
+fn os_str_to_bytes(os_str: &OsStr) -> Result<Cow<'_, [u8]>, i32> {
+    use std::os::unix::ffi::OsStrExt;
+
+    Ok(Cow::Borrowed(os_str.as_bytes()))
+}
+
+#[cfg(all(feature = "std", windows))]
+fn os_str_to_bytes(os_str: &OsStr) -> Result<Cow<'_, [u8]>, i32> {
+    use std::os::windows::ffi::OsStrExt;
+
+    let mut bytes = Vec::with_capacity(os_str.len());
+    for unit in char::decode_utf16(os_str.encode_wide()) {
+        match unit {
+            Ok(c) => bytes.extend_from_slice(c.encode_utf8(&mut [0u8; 4]).as_bytes()),
+            Err(e) => {
+                // Unpaired surrogates use the generalized 3 byte UTF-8 form
+                let u = e.unpaired_surrogate();
+                bytes.extend_from_slice(&[
+                    0xE0 | (u >> 12) as u8,
+                    0x80 | ((u >> 6) & 0x3F) as u8,
+                    0x80 | (u & 0x3F) as u8,
+                ]);
+            }
+        }
+    }
+    Ok(Cow::Owned(bytes))
+}
+
+#[cfg(all(feature = "std", not(any(unix, windows))))]
+fn os_str_to_bytes(os_str: &OsStr) -> Result<Cow<'_, [u8]>, i32> {
+    os_str
+        .to_str()
+        .map(|s| Cow::Borrowed(s.as_bytes()))
+        .ok_or(ERR_INVALID_PATH)
+}
+
+/// Takes a `Vec<u8>` and fallibly encodes it as standard (padded) base64 text into a provided external Cobhan Buffer.
+///
+/// Will cause an error code if the provided Cobhan Buffer is too small.
+///
+/// ## Notes
+///
+/// This function encodes the Rust data into the provided Cobhan Buffer.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn bytes_to_cbuffer_base64(bytes: impl AsRef<[u8]>, buffer: *mut c_char) -> i32 {
+    string_to_cbuffer(base64::encode(bytes), buffer)
+}
+
+/// Takes a `Vec<u8>` and fallibly encodes it as lower case hex text into a provided external Cobhan Buffer.
+///
+/// Will cause an error code if the provided Cobhan Buffer is too small.
+///
+/// ## Notes
+///
+/// This function encodes the Rust data into the provided Cobhan Buffer.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn bytes_to_cbuffer_hex(bytes: impl AsRef<[u8]>, buffer: *mut c_char) -> i32 {
+    string_to_cbuffer(hex::encode(bytes), buffer)
+}
+
+/// Takes a `DateTime<Utc>` and fallibly encodes it as an RFC 3339 timestamp with millisecond precision into a provided external Cobhan Buffer.
+///
+/// Will cause an error code if the provided Cobhan Buffer is too small.
+///
+/// ## Notes
+///
+/// This function encodes the Rust data into the provided Cobhan Buffer.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[cfg(feature = "time")]
+pub unsafe fn datetime_rfc3339_to_cbuffer(
+    datetime: &chrono::DateTime<chrono::Utc>,
+    buffer: *mut c_char,
+) -> i32 {
+    let text = datetime.to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+    string_to_cbuffer(&text, buffer)
+}
+
+/// Takes a `Decimal` and fallibly encodes it as a canonical decimal string into a provided external Cobhan Buffer.
+///
+/// Will cause an error code if the provided Cobhan Buffer is too small.
+///
+/// ## Notes
+///
+/// This function encodes the Rust data into the provided Cobhan Buffer.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[cfg(feature = "decimal")]
+pub unsafe fn decimal_to_cbuffer(decimal: &rust_decimal::Decimal, buffer: *mut c_char) -> i32 {
+    string_to_cbuffer(decimal.to_string(), buffer)
+}
+
+/// Takes a `Decimal` and fallibly encodes it in the 16 byte binary layout into a provided external Cobhan Buffer.
+///
+/// Will cause an error code if the provided Cobhan Buffer is too small.
+///
+/// ## Notes
+///
+/// This function does a memcopy from the Rust data into the provided Cobhan Buffer.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[cfg(feature = "decimal")]
+pub unsafe fn decimal_binary_to_cbuffer(
+    decimal: &rust_decimal::Decimal,
+    buffer: *mut c_char,
+) -> i32 {
+    bytes_to_cbuffer(decimal.serialize(), buffer)
+}
+
+/// Takes a `BigInt` and fallibly encodes it as minimal big-endian two's-complement bytes into a provided external Cobhan Buffer.
+///
+/// Will cause an error code if the provided Cobhan Buffer is too small.
+///
+/// ## Notes
+///
+/// This function encodes the Rust data into the provided Cobhan Buffer.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[cfg(feature = "bigint")]
+pub unsafe fn bigint_to_cbuffer(bigint: &num_bigint::BigInt, buffer: *mut c_char) -> i32 {
+    let bytes = if bigint.sign() == num_bigint::Sign::NoSign {
+        Vec::new()
+    } else {
+        bigint.to_signed_bytes_be()
+    };
+    bytes_to_cbuffer(&bytes, buffer)
+}
+
+/// Splits a `u64` into an i64 (hi, lo) pair, each holding 32 bits of the value.
+pub fn u64_to_i64_pair(value: u64) -> (i64, i64) {
+    ((value >> 32) as i64, (value & 0xFFFF_FFFF) as i64)
+}
+
+/// Joins an i64 (hi, lo) pair produced by [`u64_to_i64_pair`] back into a `u64`.
+///
+/// Returns `ERR_SCALAR_OUT_OF_RANGE` if either half is outside `0..=u32::MAX`.
+pub fn i64_pair_to_u64(hi: i64, lo: i64) -> Result<u64, i32> {
+    let half_range = 0..=(u32::MAX as i64);
+    if !half_range.contains(&hi) || !half_range.contains(&lo) {
+        debug_print!("i64_pair_to_u64: hi {} or lo {} is out of range", hi, lo);
+        return Err(ERR_SCALAR_OUT_OF_RANGE);
+    }
+    Ok(((hi as u64) << 32) | lo as u64)
+}
+
+/// Splits an `i128` into an i64 (hi, lo) pair holding the upper and lower 64 bits.
+///
+/// The upper half carries the sign; the lower half is the raw bits reinterpreted as i64.
+pub fn i128_to_i64_pair(value: i128) -> (i64, i64) {
+    ((value >> 64) as i64, value as u64 as i64)
+}
+
+/// Joins an i64 (hi, lo) pair produced by [`i128_to_i64_pair`] back into an `i128`.
+///
+/// Every pair is a valid `i128`, so this function cannot fail.
+pub fn i64_pair_to_i128(hi: i64, lo: i64) -> i128 {
+    ((hi as i128) << 64) | (lo as u64 as i128)
+}
+
+/// Splits a `u128` into an i64 (hi, lo) pair holding the upper and lower 64 bits, each reinterpreted as i64.
+pub fn u128_to_i64_pair(value: u128) -> (i64, i64) {
+    ((value >> 64) as u64 as i64, value as u64 as i64)
+}
+
+/// Joins an i64 (hi, lo) pair produced by [`u128_to_i64_pair`] back into a `u128`.
+///
+/// Every pair is a valid `u128`, so this function cannot fail.
+pub fn i64_pair_to_u128(hi: i64, lo: i64) -> u128 {
+    ((hi as u64 as u128) << 64) | (lo as u64 as u128)
+}
+
+/// Takes a `HashMap<String, String>` and fallibly encodes it as a length-prefixed string map into a provided external Cobhan Buffer.
+///
+/// Will cause an error code if the provided Cobhan Buffer is too small.
+///
+/// ## Notes
+///
+/// This function encodes the Rust data into the provided Cobhan Buffer.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[cfg(feature = "std")]
+pub unsafe fn string_map_to_cbuffer(map: &HashMap<String, String>, buffer: *mut c_char) -> i32 {
+    let mut bytes = Vec::new();
+    let encoded = push_u32(&mut bytes, map.len()).and_then(|_| {
+        map.iter().try_for_each(|(key, value)| {
+            push_length_prefixed(&mut bytes, key.as_bytes())?;
+            push_length_prefixed(&mut bytes, value.as_bytes())
+        })
+    });
+    if let Err(e) = encoded {
+        return e;
+    }
+
+    bytes_to_cbuffer(&bytes, buffer)
+}
+
+/// Takes several payloads and fallibly packs them into a single provided external Cobhan Buffer.
+///
+/// Will cause an error code if the provided Cobhan Buffer is too small.
+///
+/// ## Notes
+///
+/// This function does a memcopy from the Rust data into the provided Cobhan Buffer.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn pack_buffers(payloads: &[&[u8]], buffer: *mut c_char) -> i32 {
+    let total: usize = payloads.iter().map(|p| 4 + p.len()).sum();
+    let mut bytes = Vec::with_capacity(4 + total);
+    let encoded = push_u32(&mut bytes, payloads.len()).and_then(|_| {
+        payloads
+            .iter()
+            .try_for_each(|payload| push_length_prefixed(&mut bytes, payload))
+    });
+    if let Err(e) = encoded {
+        return e;
+    }
+
+    bytes_to_cbuffer(&bytes, buffer)
+}
+
+/// Takes a slice of `bool` and fallibly packs it as a bitset into a provided external Cobhan Buffer.
+///
+/// Will cause an error code if the provided Cobhan Buffer is too small or there are more than `u32::MAX` bits.
+///
+/// ## Notes
+///
+/// This function packs the Rust data into the provided Cobhan Buffer.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn bitvec_to_cbuffer(bits: &[bool], buffer: *mut c_char) -> i32 {
+    if bits.len() > u32::MAX as usize {
+        debug_print!("bitvec_to_cbuffer: {} bits is too many", bits.len());
+        return ERR_BUFFER_TOO_LARGE;
+    }
+
+    let mut bytes = vec![0u8; 4 + bits.len().div_ceil(8)];
+    bytes[..4].copy_from_slice(&(bits.len() as u32).to_le_bytes());
+    for (i, _) in bits.iter().enumerate().filter(|(_, bit)| **bit) {
+        bytes[4 + i / 8] |= 1 << (i % 8);
+    }
+
+    bytes_to_cbuffer(&bytes, buffer)
+}
+
+/// Takes a `&[f32]` and encodes it into a provided external Cobhan Buffer as packed little-endian values.
+///
+/// Will cause an error code if the provided Cobhan Buffer is too small.
+///
+/// ## Notes
+///
+/// This function does a memcopy from the Rust data into the provided Cobhan Buffer.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn f32_slice_to_cbuffer(values: &[f32], buffer: *mut c_char) -> i32 {
+    if cfg!(target_endian = "little") {
+        let bytes = from_raw_parts(values.as_ptr().cast::<u8>(), mem::size_of_val(values));
+        return bytes_to_cbuffer(bytes, buffer);
+    }
+    let bytes: Vec<u8> = values
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect();
+    bytes_to_cbuffer(&bytes, buffer)
+}
+
+/// Takes the shape and row-major data of a matrix and encodes them into a provided external Cobhan Buffer.
+///
+/// Will cause an error code if the provided Cobhan Buffer is too small, if `elem_size` is 0 or `data` is not `rows * cols * elem_size` bytes (`ERR_MALFORMED_PAYLOAD`), or if a dimension does not fit in a u32.
+///
+/// ## Notes
+///
+/// This function packs the Rust data into the provided Cobhan Buffer.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn matrix_to_cbuffer(
+    rows: usize,
+    cols: usize,
+    elem_size: usize,
+    data: &[u8],
+    buffer: *mut c_char,
+) -> i32 {
+    if elem_size == 0 || matrix_len(rows, cols, elem_size) != Some(data.len()) {
+        debug_print!(
+            "matrix_to_cbuffer: {} data bytes do not hold {}x{} elements of {} bytes",
+            data.len(),
+            rows,
+            cols,
+            elem_size
+        );
+        return ERR_MALFORMED_PAYLOAD;
+    }
+
+    let mut bytes = Vec::with_capacity(12 + data.len());
+    for value in [rows, cols, elem_size] {
+        if let Err(e) = push_u32(&mut bytes, value) {
+            return e;
+        }
+    }
+    bytes.extend_from_slice(data);
+    bytes_to_cbuffer(&bytes, buffer)
+}
+
+/// Takes a format version and a payload body and encodes them into a provided external Cobhan Buffer as a versioned payload.
+///
+/// Will cause an error code if the provided Cobhan Buffer is too small.
+///
+/// ## Notes
+///
+/// This function packs the Rust data into the provided Cobhan Buffer.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn write_versioned(version: u8, bytes: impl AsRef<[u8]>, buffer: *mut c_char) -> i32 {
+    let bytes = bytes.as_ref();
+    let mut versioned = Vec::with_capacity(1 + bytes.len());
+    versioned.push(version);
+    versioned.extend_from_slice(bytes);
+    bytes_to_cbuffer(&versioned, buffer)
+}
+
+/// Converts a `bool` into a scalar boolean (1 for true, 0 for false).
+pub fn bool_to_i32(value: bool) -> i32 {
+    value as i32
+}
+
+/// Converts a scalar boolean into a `bool`; any nonzero value is true.
+pub fn i32_to_bool(value: i32) -> bool {
+    value != 0
+}
+
+/// Converts a scalar timestamp (i64 milliseconds since the Unix epoch) into a `DateTime<Utc>`.
+///
+/// Returns `ERR_INVALID_TIMESTAMP` if the value is outside the range chrono can represent.
+#[cfg(feature = "time")]
+pub fn epoch_millis_to_datetime(millis: i64) -> Result<chrono::DateTime<chrono::Utc>, i32> {
+    chrono::DateTime::from_timestamp_millis(millis).ok_or_else(|| {
+        debug_print!("epoch_millis_to_datetime: {} is out of range", millis);
+        ERR_INVALID_TIMESTAMP
+    })
+}
+
+/// Converts a `DateTime<Utc>` into a scalar timestamp (i64 milliseconds since the Unix epoch).
+///
+/// Sub-millisecond precision is truncated towards negative infinity.
+#[cfg(feature = "time")]
+pub fn datetime_to_epoch_millis(datetime: &chrono::DateTime<chrono::Utc>) -> i64 {
+    datetime.timestamp_millis()
+}
+
+/// Converts a scalar timestamp (i64 milliseconds since the Unix epoch) into a `SystemTime`.
+///
+/// Returns `ERR_INVALID_TIMESTAMP` if the value is outside the range `SystemTime` can represent.
+#[cfg(feature = "time")]
+pub fn epoch_millis_to_system_time(millis: i64) -> Result<std::time::SystemTime, i32> {
+    let offset = std::time::Duration::from_millis(millis.unsigned_abs());
+    let result = if millis >= 0 {
+        std::time::UNIX_EPOCH.checked_add(offset)
+    } else {
+        std::time::UNIX_EPOCH.checked_sub(offset)
+    };
+    result.ok_or_else(|| {
+        debug_print!("epoch_millis_to_system_time: {} is out of range", millis);
+        ERR_INVALID_TIMESTAMP
+    })
+}
+
+/// Converts a `SystemTime` into a scalar timestamp (i64 milliseconds since the Unix epoch).
+///
+/// Returns `ERR_INVALID_TIMESTAMP` if the value does not fit in an i64.
+#[cfg(feature = "time")]
+pub fn system_time_to_epoch_millis(time: std::time::SystemTime) -> Result<i64, i32> {
+    use std::convert::TryFrom;
+
+    let millis = match time.duration_since(std::time::UNIX_EPOCH) {
+        Ok(after) => i64::try_from(after.as_millis()),
+        Err(before) => i64::try_from(before.duration().as_millis()).map(|m| -m),
+    };
+    millis.map_err(|_| {
+        debug_print!("system_time_to_epoch_millis: time is out of range");
+        ERR_INVALID_TIMESTAMP
+    })
+}
@@ -0,0 +1,145 @@
+//! # Deadlines
+//!
+//! Hosts pass call deadlines as an i64 of milliseconds since the Unix epoch, the scalar timestamp
+//! convention, with zero or a negative value meaning no deadline. A [`Deadline`] turns one into
+//! a point on the monotonic clock, so later changes to the wall clock do not move it:
+//!
+//! ```ignore
+//! #[no_mangle]
+//! pub unsafe extern "C" fn lookup(key: *const c_char, deadline: i64, output: *mut c_char) -> i32 {
+//!     let deadline = cobhan::Deadline::from_epoch_millis(deadline);
+//!     let key = match cobhan::cbuffer_to_string(key) {
+//!         Ok(key) => key,
+//!         Err(e) => return e,
+//!     };
+//!     match deadline.run(move || fetch(&key)) {
+//!         Ok(value) => cobhan::bytes_to_cbuffer(&value, output),
+//!         Err(e) => e,
+//!     }
+//! }
+//! ```
+//!
+//! Long running work can also poll [`Deadline::check`] between steps instead.
+
+use std::convert::TryFrom;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::ERR_DEADLINE_EXCEEDED;
+
+/// Helper threads started by [`Deadline::run`] that have not finished.
+static HELPERS: Mutex<usize> = Mutex::new(0);
+static HELPER_DONE: Condvar = Condvar::new();
+
+/// Waits up to `timeout` for the helper threads of [`Deadline::run`] to finish, returning
+/// `true` if none is left running.
+pub(crate) fn wait_for_helpers(timeout: Duration) -> bool {
+    let helpers = HELPERS.lock().unwrap_or_else(|e| e.into_inner());
+    let (helpers, _) = HELPER_DONE
+        .wait_timeout_while(helpers, timeout, |helpers| *helpers > 0)
+        .unwrap_or_else(|e| e.into_inner());
+    *helpers == 0
+}
+
+/// The point in time by which a call should finish, if any.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Deadline {
+    at: Option<Instant>,
+}
+
+impl Deadline {
+    /// A deadline that never passes.
+    pub fn none() -> Self {
+        Deadline { at: None }
+    }
+
+    /// A deadline `timeout` from now.
+    pub fn after(timeout: Duration) -> Self {
+        Deadline {
+            at: Instant::now().checked_add(timeout),
+        }
+    }
+
+    /// The deadline `millis` milliseconds after the Unix epoch, or none if `millis` is zero or
+    /// negative.
+    pub fn from_epoch_millis(millis: i64) -> Self {
+        if millis <= 0 {
+            return Deadline::none();
+        }
+        let at = UNIX_EPOCH + Duration::from_millis(millis as u64);
+        let now = SystemTime::now();
+        let at = match at.duration_since(now) {
+            Ok(remaining) => Instant::now().checked_add(remaining),
+            // Already passed
+            Err(_) => Some(Instant::now()),
+        };
+        Deadline { at }
+    }
+
+    /// The deadline as milliseconds since the Unix epoch, or 0 if there is none, to pass on to
+    /// other libraries.
+    pub fn to_epoch_millis(&self) -> i64 {
+        let remaining = match self.remaining() {
+            Some(remaining) => remaining,
+            None => return 0,
+        };
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        i64::try_from((since_epoch + remaining).as_millis()).unwrap_or(i64::MAX)
+    }
+
+    /// Time left until the deadline, zero once it has passed, or `None` if there is no deadline.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.at
+            .map(|at| at.saturating_duration_since(Instant::now()))
+    }
+
+    /// Returns `true` once the deadline has passed.
+    pub fn is_expired(&self) -> bool {
+        self.remaining() == Some(Duration::ZERO)
+    }
+
+    /// Fails with `ERR_DEADLINE_EXCEEDED` once the deadline has passed.
+    pub fn check(&self) -> Result<(), i32> {
+        if self.is_expired() {
+            warn_print!("Deadline::check: deadline exceeded");
+            return Err(ERR_DEADLINE_EXCEEDED);
+        }
+        Ok(())
+    }
+
+    /// Runs `f` on a helper thread and returns its result, or `ERR_DEADLINE_EXCEEDED` if the
+    /// deadline passes first.
+    ///
+    /// Without a deadline `f` runs on the calling thread. A thread that misses the deadline is
+    /// left to finish in the background and its result is dropped, so `f` should not hold locks
+    /// or resources the host expects to be released when the call returns. A panic in `f` is
+    /// resumed on the calling thread.
+    pub fn run<T: Send + 'static>(&self, f: impl FnOnce() -> T + Send + 'static) -> Result<T, i32> {
+        let remaining = match self.remaining() {
+            Some(remaining) => remaining,
+            None => return Ok(f()),
+        };
+        self.check()?;
+        let (sender, receiver) = mpsc::sync_channel(1);
+        *HELPERS.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+        thread::spawn(move || {
+            // The receiver is gone if the deadline passed
+            let _ = sender.send(panic::catch_unwind(AssertUnwindSafe(f)));
+            *HELPERS.lock().unwrap_or_else(|e| e.into_inner()) -= 1;
+            HELPER_DONE.notify_all();
+        });
+        match receiver.recv_timeout(remaining) {
+            Ok(Ok(result)) => Ok(result),
+            // Re-raised on the calling thread, where ffi_guard can catch it
+            Ok(Err(payload)) => panic::resume_unwind(payload),
+            Err(_) => {
+                warn_print!("Deadline::run: deadline exceeded after {:?}", remaining);
+                Err(ERR_DEADLINE_EXCEEDED)
+            }
+        }
+    }
+}
@@ -0,0 +1,277 @@
+//! # Key dictionaries
+//!
+//! Telemetry-style payloads send the same JSON object keys on every call. A [`KeyDictionary`]
+//! numbers each key the first time it is sent, so later payloads carry the number instead:
+//!
+//! ```text
+//! first call:  {"keys": ["service", "latency_ms"], "value": {"0": "api", "1": 12}}
+//! later calls: {"keys": [], "value": {"0": "api", "1": 9}}
+//! ```
+//!
+//! `keys` lists the keys new to this payload, which take the next numbers in order, and `value`
+//! is the JSON value with every object key, nested ones included, replaced by its number. The
+//! encoding and decoding sides each keep a dictionary and stay in step as long as every payload
+//! is decoded, in the order it was encoded. A dictionary encodes one direction only, so a
+//! library sending and receiving such payloads needs two.
+//!
+//! Dictionaries are kept on the Rust side behind handles: the host creates one with
+//! [`cobhan_dictionary_new`], passes the handle with each payload and releases it with
+//! [`cobhan_dictionary_free`]. Keys are never forgotten, so dictionaries suit fixed key sets
+//! rather than keys taken from the data.
+
+use std::collections::{BTreeMap, HashMap};
+use std::os::raw::c_char;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde_json::{Map, Value};
+
+use crate::bytes_to_cbuffer;
+use crate::json::{check_json_limits, json_payload};
+use crate::memory::Charge;
+use crate::{ERR_INVALID_HANDLE, ERR_JSON_DECODE_FAILED, ERR_JSON_ENCODE_FAILED, ERR_NONE};
+
+/// The keys numbered so far, see the [module documentation](self).
+#[derive(Clone, Debug, Default)]
+pub struct KeyDictionary {
+    keys: Vec<String>,
+    ids: HashMap<String, usize>,
+}
+
+impl KeyDictionary {
+    /// A dictionary with no keys.
+    pub fn new() -> Self {
+        KeyDictionary::default()
+    }
+
+    /// Number of keys numbered.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns `true` if no keys are numbered.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Encodes `value`, numbering the keys not seen before.
+    pub fn encode(&mut self, value: &Value) -> Value {
+        let mut new_keys = Vec::new();
+        let value = self.encode_value(value, &mut new_keys);
+        let mut envelope = Map::new();
+        envelope.insert("keys".to_owned(), Value::Array(new_keys));
+        envelope.insert("value".to_owned(), value);
+        Value::Object(envelope)
+    }
+
+    fn encode_value(&mut self, value: &Value, new_keys: &mut Vec<Value>) -> Value {
+        match value {
+            Value::Object(object) => {
+                let mut encoded = Map::new();
+                for (key, value) in object {
+                    let id = match self.ids.get(key) {
+                        Some(id) => *id,
+                        None => {
+                            new_keys.push(Value::String(key.clone()));
+                            self.push(key.clone())
+                        }
+                    };
+                    encoded.insert(id.to_string(), self.encode_value(value, new_keys));
+                }
+                Value::Object(encoded)
+            }
+            Value::Array(values) => Value::Array(
+                values
+                    .iter()
+                    .map(|value| self.encode_value(value, new_keys))
+                    .collect(),
+            ),
+            _ => value.clone(),
+        }
+    }
+
+    /// Decodes an encoded payload, numbering its new keys.
+    ///
+    /// Fails with `ERR_JSON_DECODE_FAILED` if `encoded` is not an envelope as described in the
+    /// [module documentation](self) or uses a number not yet given to a key. The new keys are
+    /// numbered even then, as long as `keys` itself is well formed, to stay in step with the
+    /// encoder.
+    pub fn decode(&mut self, encoded: &Value) -> Result<Value, i32> {
+        let (new_keys, value) = match encoded {
+            Value::Object(envelope) => match (envelope.get("keys"), envelope.get("value")) {
+                (Some(Value::Array(new_keys)), Some(value)) => (new_keys, value),
+                _ => {
+                    debug_print!("KeyDictionary::decode: missing \"keys\" array or \"value\"");
+                    return Err(ERR_JSON_DECODE_FAILED);
+                }
+            },
+            _ => {
+                debug_print!("KeyDictionary::decode: not an object");
+                return Err(ERR_JSON_DECODE_FAILED);
+            }
+        };
+        if !new_keys.iter().all(Value::is_string) {
+            debug_print!("KeyDictionary::decode: \"keys\" holds a non-string");
+            return Err(ERR_JSON_DECODE_FAILED);
+        }
+        for key in new_keys.iter().filter_map(Value::as_str) {
+            self.push(key.to_owned());
+        }
+        self.decode_value(value)
+    }
+
+    fn decode_value(&self, value: &Value) -> Result<Value, i32> {
+        match value {
+            Value::Object(object) => {
+                let mut decoded = Map::new();
+                for (id, value) in object {
+                    let key = match id.parse::<usize>().ok().and_then(|id| self.keys.get(id)) {
+                        Some(key) => key,
+                        None => {
+                            debug_print!("KeyDictionary::decode: unknown key number");
+                            return Err(ERR_JSON_DECODE_FAILED);
+                        }
+                    };
+                    decoded.insert(key.clone(), self.decode_value(value)?);
+                }
+                Ok(Value::Object(decoded))
+            }
+            Value::Array(values) => values
+                .iter()
+                .map(|value| self.decode_value(value))
+                .collect::<Result<_, _>>()
+                .map(Value::Array),
+            _ => Ok(value.clone()),
+        }
+    }
+
+    /// Forgets the keys numbered `len` and up.
+    fn truncate(&mut self, len: usize) {
+        self.keys.truncate(len);
+        self.ids.retain(|_, id| *id < len);
+    }
+
+    fn push(&mut self, key: String) -> usize {
+        let id = self.keys.len();
+        self.ids.entry(key.clone()).or_insert(id);
+        self.keys.push(key);
+        id
+    }
+}
+
+static NEXT_HANDLE: AtomicI64 = AtomicI64::new(1);
+static DICTIONARIES: Mutex<BTreeMap<i64, Arc<Mutex<KeyDictionary>>>> = Mutex::new(BTreeMap::new());
+
+/// Runs `f` with the dictionary behind `handle`; fails with `ERR_INVALID_HANDLE` if there is none.
+///
+/// Calls for the same handle take turns.
+pub fn with_dictionary<R>(handle: i64, f: impl FnOnce(&mut KeyDictionary) -> R) -> Result<R, i32> {
+    let dictionary = DICTIONARIES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(&handle)
+        .cloned();
+    match dictionary {
+        Some(dictionary) => Ok(f(&mut dictionary.lock().unwrap_or_else(|e| e.into_inner()))),
+        None => {
+            debug_print!("with_dictionary: no dictionary for handle {}", handle);
+            Err(ERR_INVALID_HANDLE)
+        }
+    }
+}
+
+/// Creates an empty key dictionary and returns its handle, which is always positive.
+#[no_mangle]
+pub extern "C" fn cobhan_dictionary_new() -> i64 {
+    let handle = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+    DICTIONARIES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(handle, Arc::default());
+    handle
+}
+
+/// Releases the key dictionary behind `handle`.
+///
+/// Returns `ERR_NONE`, or `ERR_INVALID_HANDLE` if there is no dictionary behind it.
+#[no_mangle]
+pub extern "C" fn cobhan_dictionary_free(handle: i64) -> i32 {
+    let removed = DICTIONARIES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .remove(&handle);
+    match removed {
+        Some(_) => ERR_NONE,
+        None => {
+            debug_print!(
+                "cobhan_dictionary_free: no dictionary for handle {}",
+                handle
+            );
+            ERR_INVALID_HANDLE
+        }
+    }
+}
+
+/// Releases every key dictionary, for a forked child that must not use the parent's handles.
+pub(crate) fn forget_dictionaries() {
+    DICTIONARIES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clear();
+}
+
+/// Takes a pointer to an external Cobhan Buffer holding a payload encoded with a key dictionary
+/// and fallibly decodes it with the dictionary behind `handle`. The configured JSON limits apply
+/// to the encoded payload.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn cbuffer_to_json_with_dictionary(
+    handle: i64,
+    buffer: *const c_char,
+) -> Result<Value, i32> {
+    let json_bytes = json_payload(buffer)?;
+    let _charge = Charge::reserve(json_bytes.len())?;
+    check_json_limits(&json_bytes)?;
+    let encoded: Value = serde_json::from_slice(&json_bytes).map_err(|_| {
+        debug_print!("cbuffer_to_json_with_dictionary: JSON decode failed");
+        ERR_JSON_DECODE_FAILED
+    })?;
+    with_dictionary(handle, |dictionary| dictionary.decode(&encoded))?
+}
+
+/// Takes a JSON value, encodes it with the key dictionary behind `handle` and writes it into a
+/// provided external Cobhan Buffer.
+///
+/// Will cause an error code if the provided Cobhan Buffer is too small. The keys new to a payload
+/// that was not written are not numbered, so a retry sends them again.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn json_to_cbuffer_with_dictionary(
+    handle: i64,
+    json: &Value,
+    buffer: *mut c_char,
+) -> i32 {
+    let written = with_dictionary(handle, |dictionary| {
+        let len = dictionary.len();
+        let result = match serde_json::to_vec(&dictionary.encode(json)) {
+            Ok(json_bytes) => match Charge::reserve(json_bytes.len()) {
+                Ok(_charge) => bytes_to_cbuffer(&json_bytes, buffer),
+                Err(e) => e,
+            },
+            Err(_) => ERR_JSON_ENCODE_FAILED,
+        };
+        if result != ERR_NONE {
+            dictionary.truncate(len);
+        }
+        result
+    });
+    written.unwrap_or_else(|e| e)
+}
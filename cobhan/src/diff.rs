@@ -0,0 +1,180 @@
+//! # Buffer diffs
+//!
+//! Conformance tests for new bindings compare the buffers a host produces with the ones it
+//! should have produced. [`cbuffer_diff`] reports where two buffers first differ instead of just
+//! whether they do, and libraries export [`cobhan_buffer_diff`] (with the `json` feature) so the
+//! host side of a test can print the same report, a JSON object such as:
+//!
+//! ```text
+//! {"field": "payload", "offset": 12, "expected": 65, "actual": 66,
+//!  "expected_len": 20, "actual_len": 20}
+//! ```
+//!
+//! or `null` for equal buffers. Payloads are compared after reading spilled ones back, so a
+//! buffer that spilled equals one that did not. With [`BufferFormat::V2`] the flags are
+//! compared first, apart from `TEMP_FILE`, and reported as
+//! `{"field": "flags", "expected": 256, "actual": 512}`.
+
+use core::ffi::c_char;
+use core::fmt;
+
+use crate::buffer::{read_header_flags, read_payload};
+#[cfg(all(feature = "std", feature = "json"))]
+use crate::{bytes_to_cbuffer, ERR_JSON_ENCODE_FAILED};
+use crate::{config, BufferFlags, BufferFormat};
+
+/// Where two buffers first differ, see the [module documentation](self).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffReport {
+    /// The flags fields differ.
+    Flags {
+        expected: BufferFlags,
+        actual: BufferFlags,
+    },
+    /// The payloads differ at `offset`. A byte past the end of its payload is `None`.
+    Payload {
+        offset: usize,
+        expected: Option<u8>,
+        actual: Option<u8>,
+        expected_len: usize,
+        actual_len: usize,
+    },
+}
+
+impl fmt::Display for DiffReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiffReport::Flags { expected, actual } => write!(
+                f,
+                "flags differ: expected {:#010x} {:?}, got {:#010x} {:?}",
+                expected.bits(),
+                expected,
+                actual.bits(),
+                actual
+            ),
+            DiffReport::Payload {
+                offset,
+                expected,
+                actual,
+                expected_len,
+                actual_len,
+            } => {
+                write!(f, "payloads differ at byte {}: expected ", offset)?;
+                match expected {
+                    Some(byte) => write!(f, "{:#04x}", byte)?,
+                    None => write!(f, "end of payload")?,
+                }
+                f.write_str(", got ")?;
+                match actual {
+                    Some(byte) => write!(f, "{:#04x}", byte)?,
+                    None => write!(f, "end of payload")?,
+                }
+                write!(f, " (lengths {} and {})", expected_len, actual_len)
+            }
+        }
+    }
+}
+
+/// Compares two payloads, returning where they first differ.
+pub fn diff_payloads(expected: &[u8], actual: &[u8]) -> Option<DiffReport> {
+    let offset = expected
+        .iter()
+        .zip(actual)
+        .position(|(e, a)| e != a)
+        .unwrap_or_else(|| expected.len().min(actual.len()));
+    if offset == expected.len() && offset == actual.len() {
+        return None;
+    }
+    Some(DiffReport::Payload {
+        offset,
+        expected: expected.get(offset).copied(),
+        actual: actual.get(offset).copied(),
+        expected_len: expected.len(),
+        actual_len: actual.len(),
+    })
+}
+
+/// Takes pointers to two external Cobhan Buffers and fallibly compares them, returning where
+/// they first differ, see the [module documentation](self).
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size of either buffer is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn cbuffer_diff(
+    expected: *const c_char,
+    actual: *const c_char,
+) -> Result<Option<DiffReport>, i32> {
+    let expected_bytes = read_payload(expected)?;
+    let actual_bytes = read_payload(actual)?;
+    if config::buffer_format() == BufferFormat::V2 {
+        let mut expected_flags = read_header_flags(expected);
+        let mut actual_flags = read_header_flags(actual);
+        expected_flags.remove(BufferFlags::TEMP_FILE);
+        actual_flags.remove(BufferFlags::TEMP_FILE);
+        if expected_flags != actual_flags {
+            return Ok(Some(DiffReport::Flags {
+                expected: expected_flags,
+                actual: actual_flags,
+            }));
+        }
+    }
+    Ok(diff_payloads(&expected_bytes, &actual_bytes))
+}
+
+#[cfg(all(feature = "std", feature = "json"))]
+pub(crate) fn report_json(report: Option<DiffReport>) -> serde_json::Value {
+    use serde_json::{json, Value};
+
+    match report {
+        None => Value::Null,
+        Some(DiffReport::Flags { expected, actual }) => json!({
+            "field": "flags",
+            "expected": expected.bits(),
+            "actual": actual.bits(),
+        }),
+        Some(DiffReport::Payload {
+            offset,
+            expected,
+            actual,
+            expected_len,
+            actual_len,
+        }) => json!({
+            "field": "payload",
+            "offset": offset,
+            "expected": expected,
+            "actual": actual,
+            "expected_len": expected_len,
+            "actual_len": actual_len,
+        }),
+    }
+}
+
+/// Compares the Cobhan Buffers at `expected` and `actual` and writes where they first differ
+/// into the output buffer at `report` as JSON, see the [module documentation](self).
+///
+/// Returns `ERR_NONE` once the report is written, whether or not the buffers differ, or the
+/// error reading either buffer or writing the report.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size of any buffer is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[cfg(all(feature = "std", feature = "json"))]
+#[no_mangle]
+pub unsafe extern "C" fn cobhan_buffer_diff(
+    expected: *const c_char,
+    actual: *const c_char,
+    report: *mut c_char,
+) -> i32 {
+    let diff = match cbuffer_diff(expected, actual) {
+        Ok(diff) => diff,
+        Err(e) => return e,
+    };
+    match serde_json::to_vec(&report_json(diff)) {
+        Ok(json_bytes) => bytes_to_cbuffer(&json_bytes, report),
+        Err(_) => ERR_JSON_ENCODE_FAILED,
+    }
+}
@@ -0,0 +1,108 @@
+//! # Digests
+//!
+//! With the `sha2` or `blake3` feature, [`cbuffer_digest`] hashes a payload where it lies, so
+//! wrappers that only need a content hash (cache keys, deduplication, integrity checks) do not
+//! copy the payload into Rust first. Spilled payloads are streamed from the transport rather than
+//! read whole.
+
+use std::io::BufRead;
+use std::os::raw::c_char;
+
+use crate::buffer::{open_payload, read_failed};
+
+/// A hash algorithm for [`cbuffer_digest`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DigestAlgorithm {
+    /// SHA-256, a 32 byte digest
+    #[cfg(feature = "sha2")]
+    Sha256,
+    /// SHA-512, a 64 byte digest
+    #[cfg(feature = "sha2")]
+    Sha512,
+    /// BLAKE3, a 32 byte digest
+    #[cfg(feature = "blake3")]
+    Blake3,
+}
+
+enum Hasher {
+    #[cfg(feature = "sha2")]
+    Sha256(sha2::Sha256),
+    #[cfg(feature = "sha2")]
+    Sha512(sha2::Sha512),
+    #[cfg(feature = "blake3")]
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    fn new(algorithm: DigestAlgorithm) -> Self {
+        #[cfg(feature = "sha2")]
+        use sha2::Digest;
+
+        match algorithm {
+            #[cfg(feature = "sha2")]
+            DigestAlgorithm::Sha256 => Hasher::Sha256(sha2::Sha256::new()),
+            #[cfg(feature = "sha2")]
+            DigestAlgorithm::Sha512 => Hasher::Sha512(sha2::Sha512::new()),
+            #[cfg(feature = "blake3")]
+            DigestAlgorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        #[cfg(feature = "sha2")]
+        use sha2::Digest;
+
+        match self {
+            #[cfg(feature = "sha2")]
+            Hasher::Sha256(hasher) => hasher.update(bytes),
+            #[cfg(feature = "sha2")]
+            Hasher::Sha512(hasher) => hasher.update(bytes),
+            #[cfg(feature = "blake3")]
+            Hasher::Blake3(hasher) => {
+                hasher.update(bytes);
+            }
+        }
+    }
+
+    fn finalize(self) -> Vec<u8> {
+        #[cfg(feature = "sha2")]
+        use sha2::Digest;
+
+        match self {
+            #[cfg(feature = "sha2")]
+            Hasher::Sha256(hasher) => hasher.finalize().to_vec(),
+            #[cfg(feature = "sha2")]
+            Hasher::Sha512(hasher) => hasher.finalize().to_vec(),
+            #[cfg(feature = "blake3")]
+            Hasher::Blake3(hasher) => hasher.finalize().as_bytes().to_vec(),
+        }
+    }
+}
+
+/// Takes a pointer to an external Cobhan Buffer and hashes its payload with `algorithm`,
+/// without copying it.
+///
+/// Spilled payloads are hashed as they are read from the transport, so the configured
+/// `max_buffer_len` does not apply to them.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn cbuffer_digest(
+    buffer: *const c_char,
+    algorithm: DigestAlgorithm,
+) -> Result<Vec<u8>, i32> {
+    let mut reader = open_payload(buffer)?;
+    let mut hasher = Hasher::new(algorithm);
+    loop {
+        let available = reader.fill_buf().map_err(read_failed)?;
+        if available.is_empty() {
+            return Ok(hasher.finalize());
+        }
+        let len = available.len();
+        hasher.update(available);
+        reader.consume(len);
+    }
+}
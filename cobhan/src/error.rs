@@ -0,0 +1,189 @@
+//! # Error codes
+//!
+//! Fallible functions return `ERR_NONE` or one of these negative codes. The codes are the same in
+//! every build, whatever features are enabled.
+
+/// No Error
+pub const ERR_NONE: i32 = 0;
+
+/// One of the provided pointers is NULL / nil / 0
+pub const ERR_NULL_PTR: i32 = -1;
+
+/// One of the provided buffer lengths is too large
+pub const ERR_BUFFER_TOO_LARGE: i32 = -2;
+
+/// One of the provided buffers was too small
+pub const ERR_BUFFER_TOO_SMALL: i32 = -3;
+
+/// Failed to copy a buffer (copy length != expected length)
+pub const ERR_COPY_FAILED: i32 = -4;
+
+/// Failed to decode a JSON buffer
+pub const ERR_JSON_DECODE_FAILED: i32 = -5;
+
+/// Failed to encode to JSON buffer
+pub const ERR_JSON_ENCODE_FAILED: i32 = -6;
+
+/// UTF8 in a String or JSON is invalid.
+pub const ERR_INVALID_UTF8: i32 = -7;
+
+/// TempFile for large partial data failed to read.
+pub const ERR_READ_TEMP_FILE_FAILED: i32 = -8;
+
+/// TempFile for large partial data failed to write.
+pub const ERR_WRITE_TEMP_FILE_FAILED: i32 = -9;
+
+/// UTF16 in a String is invalid (odd payload length or unpaired surrogate).
+pub const ERR_INVALID_UTF16: i32 = -10;
+
+/// The requested character encoding label is not recognized.
+pub const ERR_UNKNOWN_ENCODING: i32 = -11;
+
+/// Payload is not valid in the requested character encoding.
+pub const ERR_INVALID_ENCODING: i32 = -12;
+
+/// Failed to decode a base64 buffer
+pub const ERR_BASE64_DECODE_FAILED: i32 = -13;
+
+/// Failed to decode a hex buffer
+pub const ERR_HEX_DECODE_FAILED: i32 = -14;
+
+/// Timestamp is out of the representable range or is not valid RFC 3339.
+pub const ERR_INVALID_TIMESTAMP: i32 = -15;
+
+/// Decimal string is not valid or binary decimal layout is malformed.
+pub const ERR_INVALID_DECIMAL: i32 = -16;
+
+/// A scalar value is outside the range allowed by its convention.
+pub const ERR_SCALAR_OUT_OF_RANGE: i32 = -17;
+
+/// Payload does not match the expected binary layout.
+pub const ERR_MALFORMED_PAYLOAD: i32 = -18;
+
+/// Payload contains a NUL byte and cannot be represented as a C string.
+pub const ERR_INTERIOR_NUL: i32 = -19;
+
+/// Payload is not a valid path in this platform's path encoding.
+pub const ERR_INVALID_PATH: i32 = -20;
+
+/// A buffer length field cannot be represented on this platform (`i32::MIN`, or a payload that
+/// would extend past the end of the address space).
+pub const ERR_INVALID_LENGTH: i32 = -21;
+
+/// A length does not fit the i32 buffer length field.
+pub const ERR_LENGTH_OVERFLOW: i32 = -22;
+
+/// A configuration setting is not recognized or has an invalid value.
+pub const ERR_INVALID_CONFIG: i32 = -23;
+
+/// A capture file could not be created, written or read.
+pub const ERR_CAPTURE_FAILED: i32 = -24;
+
+/// A conversion would take the memory held by conversions over the configured
+/// `max_marshaling_bytes`.
+pub const ERR_OUT_OF_MEMORY: i32 = -25;
+
+/// A payload does not match the checksum in its header.
+pub const ERR_CHECKSUM_MISMATCH: i32 = -26;
+
+/// A JSON object contains the same key more than once.
+pub const ERR_JSON_DUPLICATE_KEY: i32 = -27;
+
+/// Failed to decode a CSV buffer
+pub const ERR_CSV_DECODE_FAILED: i32 = -28;
+
+/// Failed to encode to CSV buffer
+pub const ERR_CSV_ENCODE_FAILED: i32 = -29;
+
+/// Failed to compress or decompress a payload.
+pub const ERR_COMPRESSION_FAILED: i32 = -30;
+
+/// A panic was caught by `ffi_guard` before it could unwind into the host.
+pub const ERR_PANIC: i32 = -31;
+
+/// A thread-bound callback was invoked off the thread that registered it.
+pub const ERR_WRONG_THREAD: i32 = -32;
+
+/// The deadline the host passed for the call has passed.
+pub const ERR_DEADLINE_EXCEEDED: i32 = -33;
+
+/// A scalar array payload's length is not a multiple of its element size.
+pub const ERR_ARRAY_LENGTH_MISMATCH: i32 = -34;
+
+/// A payload borrowed as a scalar array is not aligned for its element type.
+pub const ERR_MISALIGNED_PAYLOAD: i32 = -35;
+
+/// An RPC request named a method with no registered handler.
+pub const ERR_UNKNOWN_METHOD: i32 = -36;
+
+/// A versioned payload declares a format version the reader does not support.
+pub const ERR_UNSUPPORTED_VERSION: i32 = -37;
+
+/// A handle does not name a live object, e.g. it was already freed.
+pub const ERR_INVALID_HANDLE: i32 = -38;
+
+/// A conformance check named a test vector that does not exist.
+pub const ERR_UNKNOWN_TEST_VECTOR: i32 = -39;
+
+/// No format is registered for the requested content type.
+pub const ERR_UNKNOWN_FORMAT: i32 = -40;
+
+/// A JSON document is over the configured size, nesting depth or key count limits.
+pub const ERR_JSON_LIMITS_EXCEEDED: i32 = -41;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_are_numbered_in_sequence() {
+        let codes = [
+            ERR_NULL_PTR,
+            ERR_BUFFER_TOO_LARGE,
+            ERR_BUFFER_TOO_SMALL,
+            ERR_COPY_FAILED,
+            ERR_JSON_DECODE_FAILED,
+            ERR_JSON_ENCODE_FAILED,
+            ERR_INVALID_UTF8,
+            ERR_READ_TEMP_FILE_FAILED,
+            ERR_WRITE_TEMP_FILE_FAILED,
+            ERR_INVALID_UTF16,
+            ERR_UNKNOWN_ENCODING,
+            ERR_INVALID_ENCODING,
+            ERR_BASE64_DECODE_FAILED,
+            ERR_HEX_DECODE_FAILED,
+            ERR_INVALID_TIMESTAMP,
+            ERR_INVALID_DECIMAL,
+            ERR_SCALAR_OUT_OF_RANGE,
+            ERR_MALFORMED_PAYLOAD,
+            ERR_INTERIOR_NUL,
+            ERR_INVALID_PATH,
+            ERR_INVALID_LENGTH,
+            ERR_LENGTH_OVERFLOW,
+            ERR_INVALID_CONFIG,
+            ERR_CAPTURE_FAILED,
+            ERR_OUT_OF_MEMORY,
+            ERR_CHECKSUM_MISMATCH,
+            ERR_JSON_DUPLICATE_KEY,
+            ERR_CSV_DECODE_FAILED,
+            ERR_CSV_ENCODE_FAILED,
+            ERR_COMPRESSION_FAILED,
+            ERR_PANIC,
+            ERR_WRONG_THREAD,
+            ERR_DEADLINE_EXCEEDED,
+            ERR_ARRAY_LENGTH_MISMATCH,
+            ERR_MISALIGNED_PAYLOAD,
+            ERR_UNKNOWN_METHOD,
+            ERR_UNSUPPORTED_VERSION,
+            ERR_INVALID_HANDLE,
+            ERR_UNKNOWN_TEST_VECTOR,
+            ERR_UNKNOWN_FORMAT,
+            ERR_JSON_LIMITS_EXCEEDED,
+        ];
+        // Appended as they are added, so hosts can switch on them across versions
+        assert_eq!(ERR_NONE, 0);
+        for (i, code) in codes.iter().enumerate() {
+            assert_eq!(*code, -(i as i32) - 1);
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! # Buffer flags
+//!
+//! Format v2 turns the reserved header field into a u32 flags field, encoded like the length
+//! field:
+//!
+//! * bit 0, [`BufferFlags::TEMP_FILE`]: the payload is a spill reference
+//! * bit 1, [`BufferFlags::COMPRESSED`]: the payload is compressed
+//! * bit 2, [`BufferFlags::TRUNCATED`]: the payload is the start of a longer value
+//! * bits 3-7 are reserved for future format use and must be zero
+//! * bits 8-15, [`BufferFlags::content_type`]: a hint of what the payload holds, see
+//!   [`ContentType`](crate::ContentType)
+//! * bits 16-31 are application-defined, see [`BufferFlags::app_bits`]
+//!
+//! Format v1 hosts may leave the reserved field uninitialized, so flags are only honored with
+//! [`BufferFormat::V2`], which hosts and libraries opt into together through
+//! [`CobhanConfig::buffer_format`](crate::CobhanConfig::buffer_format). The one exception is
+//! `COMPRESSED`, which `cbuffer_to_decompressed_vector` also honors in v1 on payloads that start
+//! with a gzip or zstd magic. In v2 a spilled payload has a positive length field, the reference
+//! length, and the `TEMP_FILE` flag. Readers accept v1 buffers in either format: a negative
+//! length field is always a spill reference.
+
+use core::ffi::c_char;
+use core::fmt;
+use core::ops::{BitAnd, BitOr, BitOrAssign};
+
+use crate::buffer::{read_header_flags, write_header_flags};
+use crate::ContentType;
+use crate::{ERR_NONE, ERR_NULL_PTR};
+
+/// Header layout written by this library.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BufferFormat {
+    /// The reserved field is ignored; spilled payloads have a negative length field.
+    #[default]
+    V1,
+    /// The reserved field holds [`BufferFlags`]; spilled payloads are flagged `TEMP_FILE`.
+    V2,
+}
+
+/// The flags field of a format v2 header.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct BufferFlags(u32);
+
+impl BufferFlags {
+    /// The payload is a spill reference.
+    pub const TEMP_FILE: BufferFlags = BufferFlags(1 << 0);
+    /// The payload is compressed.
+    pub const COMPRESSED: BufferFlags = BufferFlags(1 << 1);
+    /// The payload is the start of a longer value.
+    pub const TRUNCATED: BufferFlags = BufferFlags(1 << 2);
+
+    /// Bits defined or reserved by the format; the others are application-defined.
+    pub const FORMAT_MASK: u32 = 0xFFFF;
+
+    const CONTENT_TYPE_SHIFT: u32 = 8;
+    const CONTENT_TYPE_MASK: u32 = 0xFF << Self::CONTENT_TYPE_SHIFT;
+
+    pub const fn empty() -> Self {
+        BufferFlags(0)
+    }
+
+    pub const fn from_bits(bits: u32) -> Self {
+        BufferFlags(bits)
+    }
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn contains(self, other: BufferFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: BufferFlags) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: BufferFlags) {
+        self.0 &= !other.0;
+    }
+
+    /// The application-defined bits 16-31.
+    pub const fn app_bits(self) -> u16 {
+        (self.0 >> 16) as u16
+    }
+
+    /// These flags with the application-defined bits replaced by `bits`.
+    pub const fn with_app_bits(self, bits: u16) -> Self {
+        BufferFlags((self.0 & Self::FORMAT_MASK) | (bits as u32) << 16)
+    }
+
+    /// The content type hint in bits 8-15.
+    pub const fn content_type(self) -> ContentType {
+        ContentType::from_code(
+            ((self.0 & Self::CONTENT_TYPE_MASK) >> Self::CONTENT_TYPE_SHIFT) as u8,
+        )
+    }
+
+    /// These flags with the content type hint replaced by `content_type`.
+    pub const fn with_content_type(self, content_type: ContentType) -> Self {
+        BufferFlags(
+            (self.0 & !Self::CONTENT_TYPE_MASK)
+                | (content_type.code() as u32) << Self::CONTENT_TYPE_SHIFT,
+        )
+    }
+
+    /// These flags without the format bits, as a writer starts a new payload.
+    pub(crate) const fn app_only(self) -> Self {
+        BufferFlags(self.0 & !Self::FORMAT_MASK)
+    }
+}
+
+impl BitOr for BufferFlags {
+    type Output = BufferFlags;
+
+    fn bitor(self, rhs: BufferFlags) -> BufferFlags {
+        BufferFlags(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for BufferFlags {
+    fn bitor_assign(&mut self, rhs: BufferFlags) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl BitAnd for BufferFlags {
+    type Output = BufferFlags;
+
+    fn bitand(self, rhs: BufferFlags) -> BufferFlags {
+        BufferFlags(self.0 & rhs.0)
+    }
+}
+
+impl fmt::Debug for BufferFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names = f.debug_set();
+        for (flag, name) in [
+            (BufferFlags::TEMP_FILE, "TEMP_FILE"),
+            (BufferFlags::COMPRESSED, "COMPRESSED"),
+            (BufferFlags::TRUNCATED, "TRUNCATED"),
+        ] {
+            if self.contains(flag) {
+                names.entry(&format_args!("{}", name));
+            }
+        }
+        if self.content_type() != ContentType::Unspecified {
+            names.entry(&format_args!("{:?}", self.content_type()));
+        }
+        let reserved = self.0 & Self::FORMAT_MASK & !Self::CONTENT_TYPE_MASK & !0b111;
+        if reserved != 0 {
+            names.entry(&format_args!("reserved {:#x}", reserved));
+        }
+        if self.app_bits() != 0 {
+            names.entry(&format_args!("app {:#06x}", self.app_bits()));
+        }
+        names.finish()
+    }
+}
+
+/// Reads the flags field of a Cobhan Buffer, whatever the configured format.
+///
+/// ## Safety
+///
+/// Behavior is undefined if the Cobhan Buffer Header size is not correctly reserved.
+pub unsafe fn cbuffer_flags(buffer: *const c_char) -> Result<BufferFlags, i32> {
+    if buffer.is_null() {
+        debug_print!("cbuffer_flags: buffer is NULL");
+        return Err(ERR_NULL_PTR);
+    }
+    Ok(read_header_flags(buffer))
+}
+
+/// Writes the flags field of a Cobhan Buffer, e.g. to set application-defined bits after
+/// writing the payload.
+///
+/// ## Safety
+///
+/// Behavior is undefined if the Cobhan Buffer Header size is not correctly reserved.
+pub unsafe fn set_cbuffer_flags(buffer: *mut c_char, flags: BufferFlags) -> i32 {
+    if buffer.is_null() {
+        debug_print!("set_cbuffer_flags: buffer is NULL");
+        return ERR_NULL_PTR;
+    }
+    write_header_flags(buffer, flags);
+    ERR_NONE
+}
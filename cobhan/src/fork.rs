@@ -0,0 +1,53 @@
+//! # Forking
+//!
+//! Python and Ruby hosts may fork after loading a library. The child gets a copy of the
+//! library's process-wide state but only the forking thread, so state kept for the other
+//! threads is stale. Libraries export [`cobhan_after_fork`] for the host to call in the child,
+//! e.g. with Python's `os.register_at_fork(after_in_child=lib.cobhan_after_fork)`. It:
+//!
+//! * forgets the bytes charged by conversions in flight, and the output buffer leases handed
+//!   out, so their limits apply to the child's own calls only
+//! * releases every key dictionary, whose handles belonged to the parent
+//! * forgets the spill files written so far without removing them, so `cobhan_shutdown` in the
+//!   child only removes the child's own and not those the parent's host has yet to read
+//! * forgets the spill scopes not ended yet, so the parent's scope ids are unknown to
+//!   `cobhan_end_call` in the child and do not discard spills the parent's host has yet to read
+//! * forgets the thread spill files of
+//!   [`reuse_spill_files`](crate::CobhanConfig::reuse_spill_files) without removing them, so
+//!   the child spills to files of its own instead of rewriting the ones the parent's host reads
+//! * keeps the parallel conversions on the calling thread, since the parent's worker pool did
+//!   not survive the fork
+//!
+//! Spill files are opened by path on each call, so the temp directory needs no reopening, and
+//! the configuration, spill transport and capture settings carry over. Locks held by other
+//! threads at the moment of the fork stay held in the child, so hosts should fork while no other
+//! thread is inside the library.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{lease, memory};
+
+static FORKED: AtomicBool = AtomicBool::new(false);
+
+/// Returns `true` once [`cobhan_after_fork`] has run in this process.
+#[cfg(feature = "rayon")]
+pub(crate) fn is_forked_child() -> bool {
+    FORKED.load(Ordering::Relaxed)
+}
+
+/// Resets the state a forked child inherits from its parent, see the
+/// [module documentation](self).
+#[no_mangle]
+pub extern "C" fn cobhan_after_fork() {
+    FORKED.store(true, Ordering::Relaxed);
+    memory::forget_charges();
+    lease::forget_leases();
+    crate::scope::forget_scopes();
+    #[cfg(feature = "json")]
+    crate::dictionary::forget_dictionaries();
+    #[cfg(feature = "tempfile")]
+    {
+        crate::transport::forget_live_spills();
+        crate::temp::forget_scratch_files();
+    }
+}
@@ -0,0 +1,230 @@
+//! # Pluggable formats
+//!
+//! Downstream crates can teach the content type machinery a serialization format of their own,
+//! e.g. a proprietary binary encoding, without forking cobhan. A [`CobhanFormat`] encodes any
+//! `Serialize` value and decodes a payload through a type-erased deserializer, and
+//! [`register_format`] installs one for a content type code:
+//!
+//! ```ignore
+//! cobhan::register_format(ContentType::Other(200), Arc::new(MyFormat));
+//!
+//! let config: MyConfig = unsafe { cobhan::cbuffer_to_value_as(input, ContentType::Other(200)) }?;
+//! ```
+//!
+//! [`value_to_cbuffer_as`] and [`cbuffer_to_value_as`] encode and decode values with the format
+//! of a content type, [`decode_any`](crate::decode_any) decodes payloads hinted with a registered
+//! content type into `DecodedValue::Structured`, and [`transcode_cbuffer`] re-encodes a payload
+//! from the format of its hint into another. [`ContentType::Json`] is built in; registering a
+//! format for it replaces the built-in one here, but not in `decode_any`.
+
+use std::collections::BTreeMap;
+use std::os::raw::c_char;
+use std::sync::{Arc, RwLock};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::buffer::read_header_flags;
+use crate::memory::Charge;
+use crate::{bytes_to_cbuffer, cbuffer_payload, config, set_cbuffer_content_type};
+use crate::{BufferFormat, ContentType};
+use crate::{ERR_JSON_DECODE_FAILED, ERR_JSON_ENCODE_FAILED, ERR_NONE, ERR_UNKNOWN_FORMAT};
+
+/// Receives the type-erased deserializer over a payload, see [`CobhanFormat::decode`].
+pub type DecodeSeed<'a> =
+    dyn FnMut(&mut dyn erased_serde::Deserializer<'_>) -> Result<(), erased_serde::Error> + 'a;
+
+/// A serialization format for payloads of one content type, see the
+/// [module documentation](self).
+pub trait CobhanFormat: Send + Sync {
+    /// Appends the encoding of `value` to `out`.
+    fn encode(&self, value: &dyn erased_serde::Serialize, out: &mut Vec<u8>) -> Result<(), i32>;
+
+    /// Runs `seed` with a deserializer over `bytes`, checking that it consumed the whole payload.
+    ///
+    /// Formats return their own error code when `bytes` is malformed or `seed` fails.
+    fn decode(&self, bytes: &[u8], seed: &mut DecodeSeed<'_>) -> Result<(), i32>;
+}
+
+/// The built-in format of [`ContentType::Json`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonFormat;
+
+impl CobhanFormat for JsonFormat {
+    fn encode(&self, value: &dyn erased_serde::Serialize, out: &mut Vec<u8>) -> Result<(), i32> {
+        serde_json::to_writer(out, value).map_err(|_e| {
+            debug_print!("JsonFormat: failed to encode: {}", _e);
+            ERR_JSON_ENCODE_FAILED
+        })
+    }
+
+    fn decode(&self, bytes: &[u8], seed: &mut DecodeSeed<'_>) -> Result<(), i32> {
+        let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+        seed(&mut <dyn erased_serde::Deserializer>::erase(
+            &mut deserializer,
+        ))
+        .map_err(|_e| {
+            debug_print!("JsonFormat: failed to decode: {}", _e);
+            ERR_JSON_DECODE_FAILED
+        })?;
+        deserializer.end().map_err(|_| ERR_JSON_DECODE_FAILED)
+    }
+}
+
+static FORMATS: RwLock<BTreeMap<u8, Arc<dyn CobhanFormat>>> = RwLock::new(BTreeMap::new());
+
+/// Uses `format` for payloads of `content_type` from now on, replacing any format registered
+/// for it.
+pub fn register_format(content_type: ContentType, format: Arc<dyn CobhanFormat>) {
+    FORMATS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(content_type.code(), format);
+}
+
+/// Removes the format registered for `content_type` with [`register_format`].
+pub fn unregister_format(content_type: ContentType) {
+    FORMATS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .remove(&content_type.code());
+}
+
+/// The format registered for `content_type`, or the built-in one of [`ContentType::Json`].
+pub fn format_for(content_type: ContentType) -> Option<Arc<dyn CobhanFormat>> {
+    match registered_format(content_type) {
+        Some(format) => Some(format),
+        None if content_type == ContentType::Json => Some(Arc::new(JsonFormat)),
+        None => None,
+    }
+}
+
+/// Drops every registered format, for `cobhan_deinit`.
+pub(crate) fn forget_formats() {
+    FORMATS.write().unwrap_or_else(|e| e.into_inner()).clear();
+}
+
+/// The registered format only, leaving the built-in content types to their own decoders.
+pub(crate) fn registered_format(content_type: ContentType) -> Option<Arc<dyn CobhanFormat>> {
+    FORMATS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(&content_type.code())
+        .cloned()
+}
+
+fn require_format(content_type: ContentType) -> Result<Arc<dyn CobhanFormat>, i32> {
+    format_for(content_type).ok_or_else(|| {
+        debug_print!("require_format: no format for {:?}", content_type);
+        ERR_UNKNOWN_FORMAT
+    })
+}
+
+/// Decodes `bytes` into a `T` with `format`.
+pub fn decode_with<T: DeserializeOwned>(format: &dyn CobhanFormat, bytes: &[u8]) -> Result<T, i32> {
+    let mut value = None;
+    format.decode(bytes, &mut |deserializer| {
+        value = Some(erased_serde::deserialize::<T>(deserializer)?);
+        Ok(())
+    })?;
+    value.ok_or(ERR_JSON_DECODE_FAILED)
+}
+
+/// Takes a pointer to an external Cobhan Buffer and fallibly decodes it into a `T` with the
+/// format of `content_type`.
+///
+/// Fails with `ERR_UNKNOWN_FORMAT` if no format is registered for `content_type`, or with the
+/// format's error code if the payload does not decode.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn cbuffer_to_value_as<T: DeserializeOwned>(
+    buffer: *const c_char,
+    content_type: ContentType,
+) -> Result<T, i32> {
+    let format = require_format(content_type)?;
+    let bytes = cbuffer_payload(buffer)?;
+    let _charge = Charge::reserve(bytes.len())?;
+    decode_with(&*format, &bytes)
+}
+
+/// Takes a value and fallibly encodes it with the format of `content_type` into a provided
+/// external Cobhan Buffer, hinting the content type in format v2.
+///
+/// Will cause an error code if no format is registered for `content_type`, the format fails to
+/// encode the value, or the provided Cobhan Buffer is too small.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn value_to_cbuffer_as<T: Serialize>(
+    value: &T,
+    content_type: ContentType,
+    buffer: *mut c_char,
+) -> i32 {
+    let format = match require_format(content_type) {
+        Ok(format) => format,
+        Err(e) => return e,
+    };
+    let mut bytes = Vec::new();
+    if let Err(e) = format.encode(value, &mut bytes) {
+        return e;
+    }
+    write_hinted(&bytes, content_type, buffer)
+}
+
+unsafe fn write_hinted(bytes: &[u8], content_type: ContentType, buffer: *mut c_char) -> i32 {
+    let _charge = match Charge::reserve(bytes.len()) {
+        Ok(charge) => charge,
+        Err(e) => return e,
+    };
+    let result = bytes_to_cbuffer(bytes, buffer);
+    if result != ERR_NONE || config::buffer_format() == BufferFormat::V1 {
+        return result;
+    }
+    set_cbuffer_content_type(buffer, content_type)
+}
+
+/// Takes a pointer to an external Cobhan Buffer, decodes it with the format of its content type
+/// hint and re-encodes it with the format of `to` into a provided external Cobhan Buffer, e.g. to
+/// forward a MessagePack payload to a library that takes JSON.
+///
+/// The value passes through a `serde_json::Value`, so values JSON has no type for, such as byte
+/// strings, arrive as JSON would represent them. Fails with `ERR_UNKNOWN_FORMAT` if either
+/// content type has no format, which in format v1, without hints, is always the case for the
+/// input.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size of either buffer is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn transcode_cbuffer(input: *const c_char, output: *mut c_char, to: ContentType) -> i32 {
+    let bytes = match cbuffer_payload(input) {
+        Ok(bytes) => bytes,
+        Err(e) => return e,
+    };
+    let from = match config::buffer_format() {
+        BufferFormat::V1 => ContentType::Unspecified,
+        BufferFormat::V2 => read_header_flags(input).content_type(),
+    };
+    let (from_format, to_format) = match (require_format(from), require_format(to)) {
+        (Ok(from_format), Ok(to_format)) => (from_format, to_format),
+        (Err(e), _) | (_, Err(e)) => return e,
+    };
+    let value: Value = match decode_with(&*from_format, &bytes) {
+        Ok(value) => value,
+        Err(e) => return e,
+    };
+    let mut encoded = Vec::new();
+    if let Err(e) = to_format.encode(&value, &mut encoded) {
+        return e;
+    }
+    write_hinted(&encoded, to, output)
+}
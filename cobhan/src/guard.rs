@@ -0,0 +1,221 @@
+//! # Panics and the last error
+//!
+//! A panic unwinding out of an `extern "C"` function aborts the host process. Exported functions
+//! can run their body in [`ffi_guard`], which catches the panic and returns `ERR_PANIC` instead:
+//!
+//! ```ignore
+//! #[no_mangle]
+//! pub unsafe extern "C" fn process(input: *const c_char, output: *mut c_char) -> i32 {
+//!     cobhan::ffi_guard(|| unsafe {
+//!         // ...
+//!     })
+//! }
+//! ```
+//!
+//! The panic message and location, and with the `backtrace` feature a backtrace, are kept in a
+//! thread-local last error, which hosts read with [`cobhan_get_last_error`] after a call fails.
+//! Libraries can record their own diagnostics there with [`set_last_error`].
+//!
+//! Some hosts, such as Node or Python holding the GIL, require their callbacks to be run on the
+//! thread that registered them, and deadlock or crash otherwise. Wrapping such a callback in a
+//! [`MainThreadCallback`] turns a call from any other thread into `ERR_WRONG_THREAD`.
+
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::mem::ManuallyDrop;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Once;
+use std::thread::{self, ThreadId};
+
+use crate::redact::redact_str;
+use crate::stats::CallTimer;
+use crate::{string_to_cbuffer, ERR_PANIC, ERR_WRONG_THREAD};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+    /// Whether `LAST_ERROR` may hold an error. Threads only touch `LAST_ERROR`, which registers
+    /// a destructor, once an error is set.
+    static HAS_LAST_ERROR: Cell<bool> = const { Cell::new(false) };
+    /// Number of `ffi_guard` calls running on this thread.
+    static GUARDED: Cell<usize> = const { Cell::new(0) };
+    /// What the panic hook saw of the panic being caught.
+    static PANIC: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+static HOOK: Once = Once::new();
+
+/// Installs, once, a panic hook that describes panics inside `ffi_guard` before running the
+/// previous hook.
+fn install_hook() {
+    HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if GUARDED.with(Cell::get) > 0 {
+                let mut message = String::from("panicked");
+                if let Some(location) = info.location() {
+                    message = format!(
+                        "panicked at {}:{}:{}",
+                        location.file(),
+                        location.line(),
+                        location.column()
+                    );
+                }
+                message = format!("{}: {}", message, payload_message(info.payload()));
+                #[cfg(feature = "backtrace")]
+                {
+                    let backtrace = std::backtrace::Backtrace::force_capture();
+                    message = format!("{}\nstack backtrace:\n{}", message, backtrace);
+                }
+                PANIC.with(|panic| *panic.borrow_mut() = Some(message));
+            }
+            previous(info)
+        }));
+    });
+}
+
+fn payload_message(payload: &(dyn std::any::Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "non-string panic payload"
+    }
+}
+
+/// Runs `f` and returns its result, or `ERR_PANIC` if it panics.
+///
+/// The last error is cleared first, and after a panic holds its message and location (and with
+/// the `backtrace` feature a backtrace). State that `f` was modifying when it panicked may be left
+/// inconsistent.
+pub fn ffi_guard(f: impl FnOnce() -> i32) -> i32 {
+    install_hook();
+    clear_last_error();
+    GUARDED.with(|guarded| guarded.set(guarded.get() + 1));
+    let timer = CallTimer::start();
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    timer.finish("ffi_guard", 0);
+    GUARDED.with(|guarded| guarded.set(guarded.get() - 1));
+    match result {
+        Ok(result) => result,
+        Err(payload) => {
+            // Another panic hook may have been installed over ours since
+            let message = PANIC
+                .with(|panic| panic.borrow_mut().take())
+                .unwrap_or_else(|| format!("panicked: {}", payload_message(payload.as_ref())));
+            error_print!("ffi_guard: caught panic: {}", redact_str(&message));
+            set_last_error(message);
+            ERR_PANIC
+        }
+    }
+}
+
+/// Records `message` as this thread's last error, for [`cobhan_get_last_error`].
+///
+/// The message is passed through the [redaction hook](crate::set_redaction_hook), if any.
+pub fn set_last_error(message: impl Into<String>) {
+    let message = redact_str(&message.into()).into_owned();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    HAS_LAST_ERROR.with(|has| has.set(true));
+}
+
+/// This thread's last error, if any.
+pub fn last_error() -> Option<String> {
+    if !HAS_LAST_ERROR.with(Cell::get) {
+        return None;
+    }
+    LAST_ERROR.with(|last| last.borrow().clone())
+}
+
+/// Forgets this thread's last error.
+pub fn clear_last_error() {
+    if HAS_LAST_ERROR.with(|has| has.replace(false)) {
+        LAST_ERROR.with(|last| *last.borrow_mut() = None);
+    }
+}
+
+/// Takes a Cobhan Buffer and writes the calling thread's last error into it, or an empty payload
+/// if there is none.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[no_mangle]
+pub unsafe extern "C" fn cobhan_get_last_error(buffer: *mut c_char) -> i32 {
+    string_to_cbuffer(last_error().unwrap_or_default(), buffer)
+}
+
+/// A host callback that may only be used on the thread that created the wrapper.
+///
+/// The wrapper is `Send` and `Sync` whatever the callback is, so it can be kept in a static or
+/// moved into worker threads, but [`get`](Self::get) and [`call`](Self::call) fail with
+/// `ERR_WRONG_THREAD` anywhere but the registering thread. A wrapper dropped on another thread
+/// leaks its callback rather than dropping it there.
+pub struct MainThreadCallback<F> {
+    callback: ManuallyDrop<F>,
+    thread: ThreadId,
+}
+
+// SAFETY: the callback is only reached, and only dropped, on the thread that created it
+unsafe impl<F> Send for MainThreadCallback<F> {}
+unsafe impl<F> Sync for MainThreadCallback<F> {}
+
+impl<F> MainThreadCallback<F> {
+    /// Wraps `callback`, binding it to the current thread.
+    pub fn new(callback: F) -> Self {
+        MainThreadCallback {
+            callback: ManuallyDrop::new(callback),
+            thread: thread::current().id(),
+        }
+    }
+
+    /// The thread the callback is bound to.
+    pub fn thread(&self) -> ThreadId {
+        self.thread
+    }
+
+    /// Returns `true` if called on the thread the callback is bound to.
+    pub fn is_current_thread(&self) -> bool {
+        thread::current().id() == self.thread
+    }
+
+    /// The callback, or `ERR_WRONG_THREAD` off its thread.
+    pub fn get(&self) -> Result<&F, i32> {
+        if !self.is_current_thread() {
+            warn_print!(
+                "MainThreadCallback: bound to {:?}, called on {:?}",
+                self.thread,
+                thread::current().id()
+            );
+            return Err(ERR_WRONG_THREAD);
+        }
+        Ok(&self.callback)
+    }
+
+    /// Runs `f` with the callback, or returns `ERR_WRONG_THREAD` off its thread.
+    pub fn call<R>(&self, f: impl FnOnce(&F) -> R) -> Result<R, i32> {
+        self.get().map(f)
+    }
+}
+
+impl<F> fmt::Debug for MainThreadCallback<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MainThreadCallback")
+            .field("thread", &self.thread)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<F> Drop for MainThreadCallback<F> {
+    fn drop(&mut self) {
+        if self.is_current_thread() {
+            // SAFETY: the callback is not used again
+            unsafe { ManuallyDrop::drop(&mut self.callback) }
+        } else {
+            warn_print!("MainThreadCallback: dropped off its thread, leaking the callback");
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! # Health checks
+//!
+//! Libraries export [`cobhan_health`], so an orchestrator that has loaded one can probe it before
+//! routing traffic to it. It writes a JSON object such as:
+//!
+//! ```text
+//! {"version": "0.1.1", "features": ["std", "json", "tempfile"],
+//!  "temp_dir": {"path": "/tmp", "writable": true},
+//!  "worker_pool": null, "leases": {"leased_bytes": 0, "max_leased_bytes": null}}
+//! ```
+//!
+//! `features` lists the cargo features the library was built with, and `temp_dir` whether a
+//! file can be created in the directory spill files go to, checked by writing and removing one.
+//! `worker_pool` holds the `threads` of the pool the parallel conversions run on with the
+//! `rayon` feature, and is null without it. `leases` tells how full the
+//! [output buffer pool](crate::lease_output_buffer) is.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::raw::c_char;
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde_json::{json, Value};
+
+use crate::memory::Charge;
+use crate::{bytes_to_cbuffer, config, current_leased_bytes, temp_dir, ERR_JSON_ENCODE_FAILED};
+
+const FEATURES: &[(&str, bool)] = &[
+    ("std", cfg!(feature = "std")),
+    ("json", cfg!(feature = "json")),
+    ("tempfile", cfg!(feature = "tempfile")),
+    ("cobhan_debug", cfg!(feature = "cobhan_debug")),
+    ("encodings", cfg!(feature = "encodings")),
+    ("time", cfg!(feature = "time")),
+    ("decimal", cfg!(feature = "decimal")),
+    ("bigint", cfg!(feature = "bigint")),
+    ("ndarray", cfg!(feature = "ndarray")),
+    ("smallvec", cfg!(feature = "smallvec")),
+    ("arena", cfg!(feature = "arena")),
+    ("rayon", cfg!(feature = "rayon")),
+    ("copy_analysis", cfg!(feature = "copy_analysis")),
+    ("watchdog", cfg!(feature = "watchdog")),
+    ("arbitrary_precision", cfg!(feature = "arbitrary_precision")),
+    ("csv", cfg!(feature = "csv")),
+    ("gzip", cfg!(feature = "gzip")),
+    ("zstd", cfg!(feature = "zstd")),
+    ("sha2", cfg!(feature = "sha2")),
+    ("blake3", cfg!(feature = "blake3")),
+    ("backtrace", cfg!(feature = "backtrace")),
+    ("formats", cfg!(feature = "formats")),
+    ("testing", cfg!(feature = "testing")),
+    ("wasm", cfg!(feature = "wasm")),
+    ("native_endian", cfg!(feature = "native_endian")),
+];
+
+static NEXT_PROBE: AtomicU64 = AtomicU64::new(0);
+
+/// Whether a new file can be written to `dir`, leaving nothing behind.
+fn is_writable(dir: &Path) -> bool {
+    let probe = dir.join(format!(
+        ".cobhan-health-{}-{}",
+        process::id(),
+        NEXT_PROBE.fetch_add(1, Ordering::Relaxed)
+    ));
+    let written = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+        .and_then(|mut file| file.write_all(b"ok"));
+    let removed = fs::remove_file(&probe);
+    written.is_ok() && removed.is_ok()
+}
+
+#[cfg(feature = "rayon")]
+fn worker_pool() -> Value {
+    json!({ "threads": rayon::current_num_threads() })
+}
+
+#[cfg(not(feature = "rayon"))]
+fn worker_pool() -> Value {
+    Value::Null
+}
+
+/// The report [`cobhan_health`] writes, see the [module documentation](self).
+fn health_report() -> Value {
+    let features: Vec<&str> = FEATURES
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| *name)
+        .collect();
+    let dir = temp_dir();
+    json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "features": features,
+        "temp_dir": {
+            "path": dir.to_string_lossy(),
+            "writable": is_writable(&dir),
+        },
+        "worker_pool": worker_pool(),
+        "leases": {
+            "leased_bytes": current_leased_bytes(),
+            "max_leased_bytes": config::max_leased_bytes(),
+        },
+    })
+}
+
+/// Writes a JSON report on the library into a provided external Cobhan Buffer, see the
+/// [module documentation](self).
+///
+/// Returns `ERR_NONE` once the report is written, whatever it says, or the error writing it.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[no_mangle]
+pub unsafe extern "C" fn cobhan_health(buffer: *mut c_char) -> i32 {
+    let json_bytes = match serde_json::to_vec(&health_report()) {
+        Ok(json_bytes) => json_bytes,
+        Err(_) => return ERR_JSON_ENCODE_FAILED,
+    };
+    match Charge::reserve(json_bytes.len()) {
+        Ok(_charge) => bytes_to_cbuffer(&json_bytes, buffer),
+        Err(e) => e,
+    }
+}
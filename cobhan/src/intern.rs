@@ -0,0 +1,115 @@
+//! # String interning
+//!
+//! Hosts often send the same short strings on every call: tenant IDs, key names, enum-like
+//! values. An [`Interner`] keeps one shared copy of each, so [`cbuffer_to_interned_str`] hands
+//! back an `Arc<str>` clone instead of allocating a new `String` per call:
+//!
+//! ```ignore
+//! static TENANTS: OnceLock<cobhan::Interner> = OnceLock::new();
+//!
+//! let tenant = cobhan::cbuffer_to_interned_str(TENANTS.get_or_init(Default::default), input)?;
+//! ```
+//!
+//! Only strings up to [`Interner::max_len`] bytes are kept, and at most
+//! [`Interner::max_entries`] of them, so a host sending unique values cannot grow the cache
+//! without bound; other strings are returned in a fresh `Arc<str>`.
+
+use std::collections::HashSet;
+use std::os::raw::c_char;
+use std::str;
+use std::sync::{Arc, Mutex};
+
+use crate::{cbuffer_payload, ERR_INVALID_UTF8};
+
+/// A cache of shared strings, see the [module documentation](self).
+#[derive(Debug)]
+pub struct Interner {
+    strings: Mutex<HashSet<Arc<str>>>,
+    max_len: usize,
+    max_entries: usize,
+}
+
+impl Default for Interner {
+    /// Keeps up to 4096 strings of up to 64 bytes.
+    fn default() -> Self {
+        Interner::with_limits(64, 4096)
+    }
+}
+
+impl Interner {
+    /// An interner keeping up to `max_entries` strings of up to `max_len` bytes.
+    pub fn with_limits(max_len: usize, max_entries: usize) -> Self {
+        Interner {
+            strings: Mutex::new(HashSet::new()),
+            max_len,
+            max_entries,
+        }
+    }
+
+    /// Longest string kept, in bytes.
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    /// Most strings kept at once.
+    pub fn max_entries(&self) -> usize {
+        self.max_entries
+    }
+
+    /// Returns the shared copy of `string`, adding it if there is room.
+    pub fn intern(&self, string: &str) -> Arc<str> {
+        if string.len() > self.max_len {
+            return Arc::from(string);
+        }
+        let mut strings = self.strings.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(interned) = strings.get(string) {
+            return Arc::clone(interned);
+        }
+        let interned: Arc<str> = Arc::from(string);
+        if strings.len() < self.max_entries {
+            strings.insert(Arc::clone(&interned));
+        }
+        interned
+    }
+
+    /// Number of strings kept.
+    pub fn len(&self) -> usize {
+        self.strings.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// Returns `true` if no strings are kept.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops every kept string. Copies already handed out stay valid.
+    pub fn clear(&self) {
+        self.strings
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+}
+
+/// Takes a pointer to an external Cobhan Buffer and fallibly interprets it as a string shared
+/// through `interner`.
+///
+/// The String is fallibly checked to ensure UTF-8 formatting. A string already in the interner
+/// is returned without allocating.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn cbuffer_to_interned_str(
+    interner: &Interner,
+    buffer: *const c_char,
+) -> Result<Arc<str>, i32> {
+    let bytes = cbuffer_payload(buffer)?;
+    let string = str::from_utf8(&bytes).map_err(|_| {
+        debug_print!("cbuffer_to_interned_str: payload is invalid utf-8 string");
+        ERR_INVALID_UTF8
+    })?;
+    Ok(interner.intern(string))
+}
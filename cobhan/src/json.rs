@@ -0,0 +1,808 @@
+//! # JSON
+//!
+//! JSON object payloads, with the `json` feature. Large JSON arrays and newline-delimited JSON
+//! batches can be decoded one element at a time, and with the `rayon` feature batches of JSON
+//! documents can be decoded across all cores from a single call.
+//!
+//! [`cbuffer_to_hashmap_json_strict`] fails with `ERR_JSON_DUPLICATE_KEY` rather than keeping the
+//! last value of a repeated key, for signed or validated payloads. With `max_json_bytes`,
+//! `max_json_depth` or `max_json_keys` configured, the decoders of whole documents fail with
+//! `ERR_JSON_LIMITS_EXCEEDED` on oversized, deeply nested or key-heavy documents before building
+//! them, so hostile input cannot exhaust the stack or memory. With the `arbitrary_precision`
+//! feature, `cbuffer_to_json_value_precise` keeps numbers as written, so large integer IDs and
+//! precise decimals from Go or Java hosts survive.
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::ffi::c_char;
+use core::fmt;
+use std::collections::HashMap;
+use std::io::{self, BufRead, Read};
+
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::buffer::{
+    check_max_buffer_len, inline_payload, open_payload, payload_len, read_failed, read_header,
+    Payload,
+};
+use crate::memory::{read_charged, Charge};
+use crate::temp::{open_spill, temp_to_vector};
+use crate::{
+    bytes_to_cbuffer, BUFFER_HEADER_SIZE, ERR_JSON_DECODE_FAILED, ERR_JSON_DUPLICATE_KEY,
+    ERR_JSON_ENCODE_FAILED, ERR_JSON_LIMITS_EXCEEDED, ERR_NULL_PTR,
+};
+use crate::{capture, config};
+
+/// Takes a pointer to an external Cobhan Buffer and fallibly attempts to interpret it as a `Hashmap<String, serde_json::Value>`.
+///
+/// The JSON is fallibly checked to ensure UTF-8 formatting of any string properties, and fails
+/// with `ERR_JSON_LIMITS_EXCEEDED` if it is over the configured
+/// [`max_json_bytes`](crate::CobhanConfig::max_json_bytes), `max_json_depth` or `max_json_keys`,
+/// as payloads from untrusted hosts may be crafted to exhaust memory or stack.
+///
+/// ## Notes
+///
+/// This function does a memcopy from the provided Cobhan Buffer into Rust owned data. With a
+/// size limit configured, a spilled payload is read no further than one byte over the limit.
+/// With a depth or key limit configured, the payload is parsed twice, once to check the limits.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn cbuffer_to_hashmap_json(
+    buffer: *const c_char,
+) -> Result<HashMap<String, Value>, i32> {
+    let json_bytes = json_payload(buffer)?;
+    let _charge = Charge::reserve(json_bytes.len())?;
+    check_json_limits(&json_bytes)?;
+    serde_json::from_slice(&json_bytes).map_err(|_e| {
+        debug_print!(
+            "cbuffer_to_hashmap_json: serde_json::from_slice / JSON decode failed {}",
+            _e
+        );
+        ERR_JSON_DECODE_FAILED
+    })
+}
+
+/// Takes a pointer to an external Cobhan Buffer and fallibly decodes it as any JSON value,
+/// keeping numbers exactly as written.
+///
+/// With the `arbitrary_precision` feature, numbers are held as their decimal text rather than
+/// converted to `f64`, `i64` or `u64`, so large integer IDs and high-precision decimals written
+/// by Go or Java hosts survive a round trip. Note that the feature changes
+/// [`serde_json::Number`] for every crate in the build.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[cfg(feature = "arbitrary_precision")]
+pub unsafe fn cbuffer_to_json_value_precise(buffer: *const c_char) -> Result<Value, i32> {
+    let json_bytes = json_payload(buffer)?;
+    let _charge = Charge::reserve(json_bytes.len())?;
+    check_json_limits(&json_bytes)?;
+    serde_json::from_slice(&json_bytes).map_err(|_e| {
+        debug_print!(
+            "cbuffer_to_json_value_precise: serde_json::from_slice / JSON decode failed {}",
+            _e
+        );
+        ERR_JSON_DECODE_FAILED
+    })
+}
+
+/// Takes a pointer to an external Cobhan Buffer and decodes it like [`cbuffer_to_hashmap_json`],
+/// failing with `ERR_JSON_DUPLICATE_KEY` if any object in it, at any depth, has the same key
+/// more than once. The configured JSON limits apply as well.
+///
+/// The lenient decode keeps the last value of a repeated key, so a payload that was signed or
+/// validated with one value can carry another; use this for payloads whose content is trusted.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn cbuffer_to_hashmap_json_strict(
+    buffer: *const c_char,
+) -> Result<HashMap<String, Value>, i32> {
+    let json_bytes = json_payload(buffer)?;
+    let _charge = Charge::reserve(json_bytes.len())?;
+    check_json_limits(&json_bytes)?;
+    let duplicate = Cell::new(false);
+    let mut deserializer = serde_json::Deserializer::from_slice(&json_bytes);
+    let result = UniqueKeys {
+        duplicate: &duplicate,
+    }
+    .deserialize(&mut deserializer)
+    .and_then(|value| deserializer.end().map(|()| value));
+    if duplicate.get() {
+        debug_print!("cbuffer_to_hashmap_json_strict: duplicate key");
+        return Err(ERR_JSON_DUPLICATE_KEY);
+    }
+    let value = result.map_err(|_e| {
+        debug_print!("cbuffer_to_hashmap_json_strict: JSON decode failed {}", _e);
+        ERR_JSON_DECODE_FAILED
+    })?;
+    match value {
+        Value::Object(map) => Ok(map.into_iter().collect()),
+        _ => {
+            debug_print!("cbuffer_to_hashmap_json_strict: JSON is not an object");
+            Err(ERR_JSON_DECODE_FAILED)
+        }
+    }
+}
+
+/// The key `serde_json` hands a number under as a one-entry map with `arbitrary_precision`.
+#[cfg(feature = "arbitrary_precision")]
+const NUMBER_TOKEN: &str = "$serde_json::private::Number";
+
+/// Any JSON value whose objects have unique keys, decoded as a [`Value`]. Sets `duplicate` if
+/// an object has the same key more than once.
+#[derive(Clone, Copy)]
+struct UniqueKeys<'a> {
+    duplicate: &'a Cell<bool>,
+}
+
+impl<'de> DeserializeSeed<'de> for UniqueKeys<'_> {
+    type Value = Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for UniqueKeys<'_> {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("any JSON value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Bool(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::String(v.into()))
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Value, E> {
+        Ok(Value::String(v))
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut values = Vec::new();
+        while let Some(value) = seq.next_element_seed(self)? {
+            values.push(value);
+        }
+        Ok(Value::Array(values))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut object = serde_json::Map::new();
+        while let Some(key) = map.next_key::<String>()? {
+            #[cfg(feature = "arbitrary_precision")]
+            if object.is_empty() && key == NUMBER_TOKEN {
+                let digits = map.next_value::<String>()?;
+                return serde_json::from_str(&digits)
+                    .map(Value::Number)
+                    .map_err(de::Error::custom);
+            }
+            let value = map.next_value_seed(self)?;
+            if object.insert(key, value).is_some() {
+                self.duplicate.set(true);
+                return Err(de::Error::custom("duplicate key"));
+            }
+        }
+        Ok(Value::Object(object))
+    }
+}
+
+/// Gets the payload of a JSON document like `cbuffer_payload`, failing with
+/// `ERR_JSON_LIMITS_EXCEEDED` before a document over `max_json_bytes` is read whole or captured.
+pub(crate) unsafe fn json_payload<'a>(buffer: *const c_char) -> Result<Payload<'a>, i32> {
+    if buffer.is_null() {
+        debug_print!("json_payload: buffer is NULL");
+        return Err(ERR_NULL_PTR);
+    }
+    let (length, spilled) = read_header(buffer);
+    let payload = buffer.offset(BUFFER_HEADER_SIZE).cast::<u8>();
+    trace_print!("json_payload: raw length field is {}", length);
+    let payload_len = payload_len(payload, length, spilled)?;
+    let (max_bytes, _, _) = config::json_limits();
+
+    let json_bytes = if !spilled {
+        check_json_len(payload_len, max_bytes)?;
+        Payload::uncharged(inline_payload(payload, payload_len))
+    } else if let Some(max) = max_bytes {
+        // One byte over the limit is enough to tell that it was exceeded
+        trace_print!("json_payload: calling open_spill");
+        let mut charge = Charge::empty();
+        let reader = open_spill(payload, payload_len)?.take(max as u64 + 1);
+        let bytes = read_charged(reader, &mut charge, read_failed)?;
+        check_json_len(bytes.len(), max_bytes)?;
+        Payload::charged(bytes, charge)
+    } else {
+        trace_print!("json_payload: calling temp_to_vector");
+        let (bytes, charge) = temp_to_vector(payload, payload_len)?;
+        Payload::charged(bytes, charge)
+    };
+    capture::record_input(&json_bytes);
+    Ok(json_bytes)
+}
+
+/// Fails with `ERR_JSON_LIMITS_EXCEEDED` if a document of `len` bytes is over `max_bytes`.
+fn check_json_len(len: usize, max_bytes: Option<usize>) -> Result<(), i32> {
+    if len > max_bytes.unwrap_or(usize::MAX) {
+        warn_print!("check_json_len: {} bytes is over max_json_bytes", len);
+        return Err(ERR_JSON_LIMITS_EXCEEDED);
+    }
+    Ok(())
+}
+
+/// Fails with `ERR_JSON_LIMITS_EXCEEDED` if `json_bytes` is larger, nested deeper or has more
+/// keys than the configured JSON limits. Every decode of a whole JSON document from a host
+/// checks it first.
+///
+/// Malformed JSON within the limits passes, for the decode to reject.
+pub(crate) fn check_json_limits(json_bytes: &[u8]) -> Result<(), i32> {
+    let (max_bytes, max_depth, max_keys) = config::json_limits();
+    check_json_len(json_bytes.len(), max_bytes)?;
+    if max_depth.is_none() && max_keys.is_none() {
+        return Ok(());
+    }
+    let keys = Cell::new(0);
+    let exceeded = Cell::new(false);
+    let limits = WithinLimits {
+        depth: 0,
+        max_depth: max_depth.unwrap_or(usize::MAX),
+        keys: &keys,
+        max_keys: max_keys.unwrap_or(usize::MAX),
+        exceeded: &exceeded,
+    };
+    let _result = limits.deserialize(&mut serde_json::Deserializer::from_slice(json_bytes));
+    if exceeded.get() {
+        warn_print!("check_json_limits: {:?}", _result);
+        return Err(ERR_JSON_LIMITS_EXCEEDED);
+    }
+    Ok(())
+}
+
+/// Any JSON value nested at most `max_depth` levels deep below `depth`, adding its object keys
+/// to `keys`. Sets `exceeded` if it is over a limit. Nothing is kept.
+#[derive(Clone, Copy)]
+struct WithinLimits<'a> {
+    depth: usize,
+    max_depth: usize,
+    keys: &'a Cell<usize>,
+    max_keys: usize,
+    exceeded: &'a Cell<bool>,
+}
+
+impl WithinLimits<'_> {
+    fn nested<E: de::Error>(self) -> Result<Self, E> {
+        if self.depth >= self.max_depth {
+            self.exceeded.set(true);
+            return Err(E::custom(format_args!(
+                "nested deeper than {}",
+                self.max_depth
+            )));
+        }
+        Ok(WithinLimits {
+            depth: self.depth + 1,
+            ..self
+        })
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for WithinLimits<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for WithinLimits<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("any JSON value")
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_unit<E>(self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let element = self.nested()?;
+        while seq.next_element_seed(element)?.is_some() {}
+        Ok(())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let value = self.nested()?;
+        while map.next_key::<de::IgnoredAny>()?.is_some() {
+            self.keys.set(self.keys.get() + 1);
+            if self.keys.get() > self.max_keys {
+                self.exceeded.set(true);
+                return Err(de::Error::custom(format_args!(
+                    "more than {} keys",
+                    self.max_keys
+                )));
+            }
+            map.next_value_seed(value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Takes a `Hashmap<String, serde_json::Value>` and fallibly encodes it in JSON into a provided external Cobhan Buffer.
+///
+/// The JSON is fallibly checked to ensure UTF-8 formatting of any string properties.
+///
+/// Will cause an error code if the provided Cobhan Buffer is too small.
+///
+/// ## Notes
+///
+/// This function does a memcopy from the Rust data into the provided Cobhan Buffer.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn hashmap_json_to_cbuffer(json: &HashMap<String, Value>, buffer: *mut c_char) -> i32 {
+    match serde_json::to_vec(&json) {
+        Ok(json_bytes) => match Charge::reserve(json_bytes.len()) {
+            Ok(_charge) => bytes_to_cbuffer(&json_bytes, buffer),
+            Err(e) => e,
+        },
+        Err(_) => ERR_JSON_ENCODE_FAILED,
+    }
+}
+
+/// Takes pointers to several external Cobhan Buffers and decodes each as a JSON value, in
+/// parallel on the rayon thread pool.
+///
+/// The payloads are read on the calling thread, so spilled payloads are read through its
+/// [`SpillTransport`](crate::SpillTransport); only the decoding is spread across threads. The
+/// results are in the order of `buffers`, and each payload is held to the configured JSON limits
+/// like [`cbuffer_to_hashmap_json`]. In a forked child, once
+/// [`cobhan_after_fork`](crate::cobhan_after_fork) has run, the decoding stays on the calling
+/// thread too.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size of any buffer is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+#[cfg(feature = "rayon")]
+pub unsafe fn cbuffers_to_json_parallel(buffers: &[*const c_char]) -> Vec<Result<Value, i32>> {
+    use rayon::prelude::*;
+
+    let payloads: Vec<Result<Payload, i32>> =
+        buffers.iter().map(|buffer| json_payload(*buffer)).collect();
+    let decode = |payload: Result<Payload, i32>| {
+        let payload = payload?;
+        let _charge = Charge::reserve(payload.len())?;
+        check_json_limits(&payload)?;
+        serde_json::from_slice(&payload).map_err(|_e| {
+            debug_print!(
+                "cbuffers_to_json_parallel: serde_json::from_slice / JSON decode failed {}",
+                _e
+            );
+            ERR_JSON_DECODE_FAILED
+        })
+    };
+    // The parent's worker threads do not exist in a forked child
+    if crate::fork::is_forked_child() {
+        return payloads.into_iter().map(decode).collect();
+    }
+    payloads.into_par_iter().map(decode).collect()
+}
+
+/// Takes a pointer to an external Cobhan Buffer holding a JSON array and returns an iterator
+/// decoding its elements one at a time.
+///
+/// Only the element being decoded is held in memory, so arrays of millions of records can be
+/// processed without materializing the whole array. Spilled payloads are streamed from the
+/// transport with [`SpillTransport::open`](crate::SpillTransport::open), and there the
+/// configured `max_buffer_len` applies to each element rather than to the whole payload.
+///
+/// Errors reading the buffer, and a payload that is not a JSON array, are yielded as the last
+/// item, so elements before a syntax error are still returned.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+/// - The buffer is modified or freed before the iterator is dropped.
+pub unsafe fn cbuffer_json_array_iter<'a>(buffer: *const c_char) -> JsonArrayIter<'a> {
+    match open_payload(buffer) {
+        Ok(reader) => JsonArrayIter {
+            reader,
+            state: ArrayState::Start,
+            element: Vec::new(),
+        },
+        Err(e) => JsonArrayIter {
+            reader: Box::new(io::empty()),
+            state: ArrayState::Failed(e),
+            element: Vec::new(),
+        },
+    }
+}
+
+/// The elements of a JSON array payload, see [`cbuffer_json_array_iter`].
+pub struct JsonArrayIter<'a> {
+    reader: Box<dyn BufRead + 'a>,
+    state: ArrayState,
+    element: Vec<u8>,
+}
+
+enum ArrayState {
+    /// Before the opening bracket
+    Start,
+    /// After the opening bracket or a comma
+    BeforeElement,
+    /// After an element
+    AfterElement,
+    Failed(i32),
+    Done,
+}
+
+impl JsonArrayIter<'_> {
+    /// Skips whitespace and returns the next byte without consuming it.
+    fn peek(&mut self) -> Result<Option<u8>, i32> {
+        loop {
+            let available = self.reader.fill_buf().map_err(read_failed)?;
+            match available.iter().position(|b| !b.is_ascii_whitespace()) {
+                Some(i) => {
+                    let byte = available[i];
+                    self.reader.consume(i);
+                    return Ok(Some(byte));
+                }
+                None if available.is_empty() => return Ok(None),
+                None => {
+                    let len = available.len();
+                    self.reader.consume(len)
+                }
+            }
+        }
+    }
+
+    /// Consumes the closing bracket, which must end the payload.
+    fn finish(&mut self) -> Result<(), i32> {
+        self.reader.consume(1);
+        match self.peek()? {
+            None => Ok(()),
+            Some(_) => Err(decode_failed("trailing characters after the array")),
+        }
+    }
+
+    /// Reads the bytes of the next element, up to the comma or bracket that ends it.
+    fn read_element(&mut self) -> Result<(), i32> {
+        self.element.clear();
+        let (mut depth, mut in_string, mut escaped) = (0usize, false, false);
+        loop {
+            let available = self.reader.fill_buf().map_err(read_failed)?;
+            if available.is_empty() {
+                return Err(decode_failed("unterminated array"));
+            }
+            let mut end = None;
+            for (i, byte) in available.iter().enumerate() {
+                if in_string {
+                    match byte {
+                        _ if escaped => escaped = false,
+                        b'\\' => escaped = true,
+                        b'"' => in_string = false,
+                        _ => {}
+                    }
+                    continue;
+                }
+                match byte {
+                    b'"' => in_string = true,
+                    b'[' | b'{' => depth += 1,
+                    b',' | b']' | b'}' if depth == 0 => {
+                        end = Some(i);
+                        break;
+                    }
+                    b']' | b'}' => depth -= 1,
+                    _ => {}
+                }
+            }
+            let taken = end.unwrap_or(available.len());
+            check_max_buffer_len(self.element.len() + taken)?;
+            self.element.extend_from_slice(&available[..taken]);
+            self.reader.consume(taken);
+            if end.is_some() {
+                return Ok(());
+            }
+        }
+    }
+
+    fn next_element(&mut self) -> Result<Option<Value>, i32> {
+        loop {
+            match self.state {
+                ArrayState::Start => match self.peek()? {
+                    Some(b'[') => {
+                        self.reader.consume(1);
+                        if self.peek()? == Some(b']') {
+                            self.finish()?;
+                            return Ok(None);
+                        }
+                        self.state = ArrayState::BeforeElement;
+                    }
+                    _ => return Err(decode_failed("payload is not a JSON array")),
+                },
+                ArrayState::BeforeElement => {
+                    self.read_element()?;
+                    let _charge = Charge::reserve(self.element.len())?;
+                    let value = serde_json::from_slice(&self.element).map_err(|_e| {
+                        debug_print!(
+                            "cbuffer_json_array_iter: serde_json::from_slice / JSON decode failed {}",
+                            _e
+                        );
+                        ERR_JSON_DECODE_FAILED
+                    })?;
+                    self.state = ArrayState::AfterElement;
+                    return Ok(Some(value));
+                }
+                ArrayState::AfterElement => match self.peek()? {
+                    Some(b',') => {
+                        self.reader.consume(1);
+                        self.state = ArrayState::BeforeElement;
+                    }
+                    Some(b']') => {
+                        self.finish()?;
+                        return Ok(None);
+                    }
+                    _ => return Err(decode_failed("expected `,` or `]` after an element")),
+                },
+                ArrayState::Failed(e) => return Err(e),
+                ArrayState::Done => return Ok(None),
+            }
+        }
+    }
+}
+
+impl Iterator for JsonArrayIter<'_> {
+    type Item = Result<Value, i32>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.next_element();
+        if !matches!(result, Ok(Some(_))) {
+            self.state = ArrayState::Done;
+        }
+        result.transpose()
+    }
+}
+
+/// Takes a pointer to an external Cobhan Buffer holding newline-delimited JSON (JSON lines) and
+/// returns an iterator decoding one line at a time.
+///
+/// Blank lines are skipped. Like [`cbuffer_json_array_iter`], only the line being decoded is held
+/// in memory, spilled payloads are streamed, and an error ends the iteration.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+/// - The buffer is modified or freed before the iterator is dropped.
+pub unsafe fn cbuffer_to_ndjson_iter<'a>(buffer: *const c_char) -> NdjsonIter<'a> {
+    let (reader, failed): (Box<dyn BufRead + 'a>, _) = match open_payload(buffer) {
+        Ok(reader) => (reader, None),
+        Err(e) => (Box::new(io::empty()), Some(e)),
+    };
+    NdjsonIter {
+        reader,
+        line: Vec::new(),
+        failed,
+        done: false,
+    }
+}
+
+/// The lines of a newline-delimited JSON payload, see [`cbuffer_to_ndjson_iter`].
+pub struct NdjsonIter<'a> {
+    reader: Box<dyn BufRead + 'a>,
+    line: Vec<u8>,
+    failed: Option<i32>,
+    done: bool,
+}
+
+impl NdjsonIter<'_> {
+    /// Reads the next line, returning `false` at the end of the payload.
+    fn read_line(&mut self) -> Result<bool, i32> {
+        self.line.clear();
+        loop {
+            let available = self.reader.fill_buf().map_err(read_failed)?;
+            if available.is_empty() {
+                return Ok(!self.line.is_empty());
+            }
+            let newline = available.iter().position(|b| *b == b'\n');
+            let taken = newline.map_or(available.len(), |i| i + 1);
+            check_max_buffer_len(self.line.len() + taken)?;
+            self.line.extend_from_slice(&available[..taken]);
+            self.reader.consume(taken);
+            if newline.is_some() {
+                return Ok(true);
+            }
+        }
+    }
+
+    fn next_line(&mut self) -> Result<Option<Value>, i32> {
+        if let Some(e) = self.failed {
+            return Err(e);
+        }
+        while self.read_line()? {
+            if self.line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            let _charge = Charge::reserve(self.line.len())?;
+            return serde_json::from_slice(&self.line).map(Some).map_err(|_e| {
+                debug_print!(
+                    "cbuffer_to_ndjson_iter: serde_json::from_slice / JSON decode failed {}",
+                    _e
+                );
+                ERR_JSON_DECODE_FAILED
+            });
+        }
+        Ok(None)
+    }
+}
+
+impl Iterator for NdjsonIter<'_> {
+    type Item = Result<Value, i32>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.next_line();
+        self.done = !matches!(result, Ok(Some(_)));
+        result.transpose()
+    }
+}
+
+/// Takes records and encodes them into a provided external Cobhan Buffer as newline-delimited
+/// JSON, one record per line, each line ending with a newline.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn ndjson_to_cbuffer<'a, T: Serialize + 'a>(
+    records: impl Iterator<Item = &'a T>,
+    buffer: *mut c_char,
+) -> i32 {
+    let mut json_bytes = Vec::new();
+    for record in records {
+        if let Err(_e) = serde_json::to_writer(&mut json_bytes, record) {
+            debug_print!("ndjson_to_cbuffer: JSON encode failed {}", _e);
+            return ERR_JSON_ENCODE_FAILED;
+        }
+        json_bytes.push(b'\n');
+    }
+    match Charge::reserve(json_bytes.len()) {
+        Ok(_charge) => bytes_to_cbuffer(&json_bytes, buffer),
+        Err(e) => e,
+    }
+}
+
+fn decode_failed(_reason: &str) -> i32 {
+    debug_print!("cbuffer_json_array_iter: JSON decode failed: {}", _reason);
+    ERR_JSON_DECODE_FAILED
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs the limits pre-pass over `json`, returning whether a limit was exceeded and the
+    /// keys counted.
+    fn within(json: &str, max_depth: usize, max_keys: usize) -> (bool, usize) {
+        let keys = Cell::new(0);
+        let exceeded = Cell::new(false);
+        let limits = WithinLimits {
+            depth: 0,
+            max_depth,
+            keys: &keys,
+            max_keys,
+            exceeded: &exceeded,
+        };
+        let _ = limits.deserialize(&mut serde_json::Deserializer::from_str(json));
+        (exceeded.get(), keys.get())
+    }
+
+    #[test]
+    fn depth_counts_arrays_and_objects() {
+        assert_eq!(within("1", 0, 10), (false, 0));
+        assert_eq!(within("[]", 0, 10), (true, 0));
+        assert_eq!(within(r#"{"a": [1, {"b": 2}]}"#, 3, 10), (false, 2));
+        assert_eq!(within(r#"{"a": [1, {"b": {}}]}"#, 3, 10), (true, 2));
+    }
+
+    #[test]
+    fn keys_are_counted_across_objects() {
+        assert_eq!(
+            within(r#"[{"a": 1}, {"a": 1}, {"a": 1}]"#, 10, 3),
+            (false, 3)
+        );
+        assert_eq!(
+            within(r#"[{"a": 1}, {"a": 1, "b": 2}, {}]"#, 10, 2),
+            (true, 3)
+        );
+    }
+
+    #[test]
+    fn malformed_json_is_not_over_the_limits() {
+        assert_eq!(within(r#"{"a": "#, 10, 10), (false, 1));
+        assert_eq!(check_json_len(3, Some(3)), Ok(()));
+        assert_eq!(check_json_len(4, Some(3)), Err(ERR_JSON_LIMITS_EXCEEDED));
+        assert_eq!(check_json_len(usize::MAX, None), Ok(()));
+    }
+
+    #[test]
+    fn repeated_keys_are_found_at_any_depth() {
+        let unique = |json: &str| {
+            let duplicate = Cell::new(false);
+            let seed = UniqueKeys {
+                duplicate: &duplicate,
+            };
+            let value = seed.deserialize(&mut serde_json::Deserializer::from_str(json));
+            (value.ok(), duplicate.get())
+        };
+        let json = r#"{"a": {"a": [{"a": 1}, -2, 0.5, "x", true, null]}}"#;
+        assert_eq!(unique(json), (serde_json::from_str(json).ok(), false));
+        for json in [r#"{"a": 1, "a": 2}"#, r#"[{"b": {"a": 1, "a": 1}}]"#] {
+            assert_eq!(unique(json), (None, true));
+        }
+        assert_eq!(unique(r#"{"a": "#), (None, false));
+    }
+}
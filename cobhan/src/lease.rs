@@ -0,0 +1,197 @@
+//! # Output buffer leases
+//!
+//! Servers that allocate output buffers on the Rust side for many concurrent calls can lease
+//! them instead, so the payload bytes committed to buffers in flight stay under
+//! [`CobhanConfig::max_leased_bytes`](crate::CobhanConfig::max_leased_bytes):
+//!
+//! ```ignore
+//! let mut output = cobhan::lease_output_buffer(64 * 1024);
+//! let result = unsafe { render(input, output.as_mut_ptr()) };
+//! // ... hand the payload on, then drop `output` to return its bytes to the pool
+//! ```
+//!
+//! [`lease_output_buffer`] waits for other leases to be dropped while the pool is full,
+//! [`lease_output_buffer_until`] waits no longer than a [`Deadline`], and
+//! [`try_lease_output_buffer`] fails at once. A lease larger than the whole limit is granted
+//! once no other lease is outstanding, so it delays the others instead of never being granted.
+
+use std::os::raw::c_char;
+use std::sync::{Condvar, Mutex, MutexGuard};
+
+use crate::allocation::aligned_buffer;
+use crate::{config, encode_header_length, Deadline, BUFFER_HEADER_SIZE};
+use crate::{ERR_BUFFER_TOO_LARGE, ERR_DEADLINE_EXCEEDED, ERR_OUT_OF_MEMORY};
+
+const HEADER_SIZE: usize = BUFFER_HEADER_SIZE as usize;
+
+/// Bytes leased, and how many times the pool was emptied by `forget_leases`.
+struct Pool {
+    leased: usize,
+    generation: u64,
+}
+
+static POOL: Mutex<Pool> = Mutex::new(Pool {
+    leased: 0,
+    generation: 0,
+});
+static RETURNED: Condvar = Condvar::new();
+
+/// Payload bytes in output buffers currently leased, across all threads.
+pub fn current_leased_bytes() -> usize {
+    pool().leased
+}
+
+/// Returns every outstanding lease to the pool, for a forked child whose leasing threads are
+/// gone. Buffers leased before are not returned again when dropped.
+pub(crate) fn forget_leases() {
+    let mut pool = pool();
+    pool.leased = 0;
+    pool.generation += 1;
+    RETURNED.notify_all();
+}
+
+/// An output buffer leased from the pool, returned to it when dropped.
+///
+/// The allocation is 8 byte aligned, as host allocators guarantee.
+#[derive(Debug)]
+pub struct LeasedBuffer {
+    words: Vec<u64>,
+    capacity: usize,
+    generation: u64,
+}
+
+impl LeasedBuffer {
+    fn new(capacity: usize, generation: u64) -> Self {
+        LeasedBuffer {
+            words: aligned_buffer(&[], capacity),
+            capacity,
+            generation,
+        }
+    }
+
+    /// Payload capacity the buffer was leased with.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Pointer to the buffer header, to read the payload written into it.
+    pub fn as_ptr(&self) -> *const c_char {
+        self.words.as_ptr() as *const c_char
+    }
+
+    /// Pointer to the buffer header, to pass as an output buffer.
+    pub fn as_mut_ptr(&mut self) -> *mut c_char {
+        self.words.as_mut_ptr() as *mut c_char
+    }
+
+    /// Makes the buffer an empty output buffer again, its length field holding the capacity.
+    ///
+    /// A spilled payload is not discarded.
+    pub fn reset(&mut self) {
+        let mut header = [0u8; HEADER_SIZE];
+        header[..4].copy_from_slice(&encode_header_length(self.capacity as i32));
+        self.words[0] = u64::from_ne_bytes(header);
+    }
+}
+
+impl Drop for LeasedBuffer {
+    fn drop(&mut self) {
+        let mut pool = pool();
+        if pool.generation == self.generation {
+            pool.leased -= self.capacity;
+            RETURNED.notify_all();
+        }
+    }
+}
+
+fn pool() -> MutexGuard<'static, Pool> {
+    POOL.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn check_capacity(capacity: usize) -> Result<(), i32> {
+    if capacity > i32::MAX as usize {
+        debug_print!(
+            "lease_output_buffer: capacity {} does not fit a Cobhan Buffer header",
+            capacity
+        );
+        return Err(ERR_BUFFER_TOO_LARGE);
+    }
+    Ok(())
+}
+
+/// Takes `capacity` bytes from the pool if they fit now, see the [module documentation](self).
+fn take(pool: &mut Pool, capacity: usize) -> bool {
+    let max = config::max_leased_bytes().unwrap_or(usize::MAX);
+    let fits = match pool.leased.checked_add(capacity) {
+        Some(total) => total <= max || pool.leased == 0,
+        None => false,
+    };
+    if fits {
+        pool.leased += capacity;
+    }
+    fits
+}
+
+/// Leases an output buffer with room for `capacity` payload bytes, waiting while the pool is
+/// full.
+///
+/// Panics if `capacity` does not fit the i32 length field.
+pub fn lease_output_buffer(capacity: usize) -> LeasedBuffer {
+    assert!(
+        capacity <= i32::MAX as usize,
+        "capacity {} does not fit a Cobhan Buffer header",
+        capacity
+    );
+    let mut pool = pool();
+    while !take(&mut pool, capacity) {
+        pool = RETURNED.wait(pool).unwrap_or_else(|e| e.into_inner());
+    }
+    let generation = pool.generation;
+    drop(pool);
+    LeasedBuffer::new(capacity, generation)
+}
+
+/// Leases an output buffer like [`lease_output_buffer`], waiting while the pool is full until
+/// `deadline`.
+///
+/// Fails with `ERR_DEADLINE_EXCEEDED` if the deadline passes first, and with
+/// `ERR_BUFFER_TOO_LARGE` if `capacity` does not fit the i32 length field.
+pub fn lease_output_buffer_until(capacity: usize, deadline: Deadline) -> Result<LeasedBuffer, i32> {
+    check_capacity(capacity)?;
+    let mut pool = pool();
+    while !take(&mut pool, capacity) {
+        pool = match deadline.remaining() {
+            None => RETURNED.wait(pool).unwrap_or_else(|e| e.into_inner()),
+            Some(remaining) if !remaining.is_zero() => {
+                let waited = RETURNED.wait_timeout(pool, remaining);
+                waited.unwrap_or_else(|e| e.into_inner()).0
+            }
+            Some(_) => {
+                warn_print!(
+                    "lease_output_buffer_until: no room for {} bytes before the deadline",
+                    capacity
+                );
+                return Err(ERR_DEADLINE_EXCEEDED);
+            }
+        };
+    }
+    let generation = pool.generation;
+    drop(pool);
+    Ok(LeasedBuffer::new(capacity, generation))
+}
+
+/// Leases an output buffer like [`lease_output_buffer`] if the pool has room now.
+///
+/// Fails with `ERR_OUT_OF_MEMORY` if it does not, and with `ERR_BUFFER_TOO_LARGE` if `capacity`
+/// does not fit the i32 length field.
+pub fn try_lease_output_buffer(capacity: usize) -> Result<LeasedBuffer, i32> {
+    check_capacity(capacity)?;
+    let mut pool = pool();
+    if !take(&mut pool, capacity) {
+        warn_print!("try_lease_output_buffer: no room for {} bytes", capacity);
+        return Err(ERR_OUT_OF_MEMORY);
+    }
+    let generation = pool.generation;
+    drop(pool);
+    Ok(LeasedBuffer::new(capacity, generation))
+}
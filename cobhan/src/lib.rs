@@ -4,6 +4,11 @@
 //! consumed from all major languages/platforms in a safe and effective way,
 //! using easy helper functions to manage any unsafe data marshaling.
 //!
+//! ## Prelude
+//!
+//! * `use cobhan::prelude::*;` imports the buffer conversions, error codes, configuration and
+//!   spill transport API, which are also available at the crate root
+//!
 //! ## Types
 //!
 //! * Supported types
@@ -12,371 +17,433 @@
 //!     * fl64 - double precision 64bit IEEE 754 floating point
 //!     * Cobhan buffer - length delimited 8bit buffer (no null delimiters)
 //!         * utf-8 encoded string
+//!         * utf-16le encoded string
 //!         * JSON
 //!         * binary data
 //! * Cobhan buffer details
 //!     * Callers provide the output buffer allocation and capacity
 //!     * Called functions can transparently return larger values via temporary files
 //!     * **Modern [tmpfs](https://en.wikipedia.org/wiki/Tmpfs) is entirely memory backed**
+//!     * Libraries can replace temporary files with their own [`SpillTransport`]; hosts discard
+//!       the spills they have read with `cobhan_buffer_release`, or those of a whole
+//!       [`SpillScope`] with `cobhan_end_call`
+//!     * Functions that should not spill can report the required size, leave a size to retry
+//!       with, fill several buffers or truncate instead, see [`bytes_to_cbuffer_or_size`] and
+//!       [`write_with_retry`]
+//!     * Header fields are little-endian on every host; the `native_endian` feature restores
+//!       host byte order
+//!     * Format v2 (`buffer_format: 2`) reads the reserved header field as [`BufferFlags`],
+//!       marking spilled, compressed and truncated payloads and hinting their content type for
+//!       [`decode_any`]; format v1 can hold a CRC-32 there instead, see
+//!       [`bytes_to_cbuffer_with_crc`]
+//! * Reading payloads
+//!     * Inline payloads are borrowed in place where no copy is needed, and spilled payloads
+//!       are streamed by the chunk, line and chained readers, see [`cbuffer_for_each_chunk`]
+//!     * Payloads repeated on every call can be shared through an [`Interner`] or a
+//!       [`PayloadCache`] instead of being copied each time
+//! * JSON payloads
+//!     * With the `json` feature, JSON objects convert to and from hashmaps and serde values,
+//!       and large arrays and newline-delimited batches can be decoded one record at a time
+//!     * `max_json_bytes`, `max_json_depth` and `max_json_keys` bound the documents the
+//!       decoders accept, failing with `ERR_JSON_LIMITS_EXCEEDED`
 //! * Return values
 //!     * Functions that return scalar values can return the value directly
 //!         * Functions *can* use special case and return maximum positive or maximum negative or zero values to
-//!             represent error or overflow conditions
+//!           represent error or overflow conditions
 //!         * Functions *can* allow scalar values to wrap
 //!         * Functions should document their overflow / underflow behavior
+//!     * Exported functions can run their body in `ffi_guard`, which returns `ERR_PANIC` instead
+//!       of unwinding into the host; the panic message and location, and with the `backtrace`
+//!       feature a backtrace, are kept for hosts to read with `cobhan_get_last_error`
+//!     * `MainThreadCallback` binds a host callback to the thread that registered it, failing
+//!       with `ERR_WRONG_THREAD` elsewhere, for runtimes such as Node that require it
+//!     * Hosts with a moving garbage collector must keep inputs pinned while Rust reads them:
+//!       libraries can take inputs as `PinnedInput`, stating that contract, or enable
+//!       `defensive_copy_mode` (`COBHAN_DEFENSIVE_COPY_MODE=1`) so inline payloads are
+//!       copied as soon as they are read rather than borrowed in place
+//! * Wide scalars
+//!     * u64 values are passed as an i64 (hi, lo) pair, each holding 32 bits in the range
+//!       0..=u32::MAX so the halves remain exact in hosts that carry numbers as f64
+//!     * i128 / u128 values are passed as an i64 (hi, lo) pair holding the upper and lower 64 bits
+//! * Float arrays
+//!     * f32 arrays, such as ML tensors, are passed as packed little-endian IEEE 754 values,
+//!       with no count: `f32_slice_to_cbuffer` writes them, `cbuffer_to_f32_vec` copies them
+//!       out and `cbuffer_as_f32_slice` borrows them in place from a 4 byte aligned buffer
+//!     * Payloads that are not a multiple of 4 bytes fail with `ERR_ARRAY_LENGTH_MISMATCH`,
+//!       and borrowing a misaligned payload fails with `ERR_MISALIGNED_PAYLOAD`
+//! * Matrices
+//!     * 2D binary data, such as images or grids, is passed as u32 rows, u32 columns and u32
+//!       element size, all little-endian, followed by the row-major element data
+//!     * `matrix_to_cbuffer` and `cbuffer_to_matrix` check that the data is exactly
+//!       rows x columns x element size bytes, failing with `ERR_MALFORMED_PAYLOAD` otherwise
+//! * Booleans
+//!     * Scalar booleans are i32 values, 1 for true and 0 for false; any nonzero value is read as
+//!       true
+//!     * Bitsets are passed as a u32 little-endian bit count followed by the packed bits,
+//!       least significant bit first; padding bits are written as zero and ignored on read
+//! * String maps
+//!     * Flat string to string maps are passed as a u32 entry count followed by each entry as
+//!       u32 key length, key bytes, u32 value length, value bytes (lengths little-endian, strings
+//!       utf-8)
+//! * Versioned payloads
+//!     * Libraries that evolve a payload format can prefix it with a u8 format version:
+//!       `write_versioned` writes one, `read_versioned` splits it into the version and body,
+//!       and `require_version` fails with `ERR_UNSUPPORTED_VERSION` for versions a reader
+//!       does not understand, so old and new hosts can tell each other's payloads apart
+//! * Packed buffers
+//!     * Several independent payloads can share one buffer as a u32 payload count followed by
+//!       each payload as u32 length, payload bytes (lengths little-endian)
+//! * Self-describing records
+//!     * The [`tlv`] module packs heterogeneous typed values as u8 tag, u32 length, value bytes
+//!     * The [`records`] module packs many untyped records as u32 length, record bytes, so a
+//!       host can hand over a whole batch in one call instead of one call per record
+//! * Patches
+//!     * Hosts synchronizing large state blobs can send a delta instead of the full payload: the
+//!       [`patch`] module's `cbuffer_apply_patch` rebuilds the payload from a base buffer and a
+//!       patch of u8 op, copy (u32 offset, u32 length) or insert (u32 length, bytes) operations
+//! * RPC envelopes
+//!     * Plugin-style interfaces can pass a JSON request envelope, `{"method", "payload"}`, and
+//!       get back a response envelope, `{"status", "payload"}` or `{"status", "error"}`; the
+//!       `rpc` module's `handle_rpc` dispatches them to the handlers registered in a `Router`
+//! * Key dictionaries
+//!     * JSON payloads repeating the same object keys on every call can send each key once and
+//!       a number after that; the Rust side keeps the numbering in a `KeyDictionary` behind a
+//!       handle from `cobhan_dictionary_new`, released with `cobhan_dictionary_free`
+//! * N-dimensional arrays
+//!     * Arrays are passed as u32 dtype, u32 rank, rank x u64 dimensions, then row-major element
+//!       data
+//!     * Helpers are available with the `ndarray` feature
+//! * CSV
+//!     * Tabular payloads are CSV with a header row (RFC 4180 quoting, utf-8); with the `csv`
+//!       feature `cbuffer_to_csv_records` and `records_to_cbuffer` convert them to and from serde
+//!       records, matching columns to fields by name
+//! * Paths
+//!     * Paths are passed as raw bytes on Unix and as WTF-8 on Windows, so paths that are not
+//!       valid Unicode still round trip; other platforms require utf-8
+//! * Timestamps
+//!     * Scalar timestamps are i64 milliseconds since the Unix epoch (1970-01-01T00:00:00Z),
+//!       always UTC
+//!     * Buffer timestamps are RFC 3339 strings (e.g. `2021-10-01T12:00:00.000Z`)
+//!     * Helpers are available with the `time` feature
+//! * Deadlines
+//!     * Call deadlines are passed as an i64 timestamp, with zero meaning no deadline
+//!     * `Deadline::from_epoch_millis` reads one; `remaining` and `check` tell how much time
+//!       is left, and `Deadline::run` fails with `ERR_DEADLINE_EXCEEDED` once it has passed
+//! * Decimals
+//!     * Decimals are passed as canonical strings (e.g. `-1234.5600`), never as f64
+//!     * An optional 16 byte binary layout is available: u32 flags (bits 16-23 scale, bit 31 sign)
+//!       followed by the 96bit mantissa as lo, mid, hi u32 values, all little-endian
+//!     * Helpers are available with the `decimal` feature
+//! * Big integers
+//!     * Arbitrarily large integers are passed as minimal big-endian two's-complement bytes
+//!       (e.g. `255` is `00 FF`, `-1` is `FF`, and zero is an empty payload)
+//!     * Helpers are available with the `bigint` feature
+//!
+//! ## Configuration
+//!
+//! * [`configure`] applies a [`CobhanConfig`]: spill directory, maximum payload length,
+//!   spill policy (spill to the [`SpillTransport`] or fail with `ERR_BUFFER_TOO_SMALL`),
+//!   `cobhan_debug` sink and log level
+//! * `max_marshaling_bytes` caps the bytes the conversions hold at once, across threads, as
+//!   reported by [`current_marshaling_bytes`]; conversions that would exceed it fail with
+//!   `ERR_OUT_OF_MEMORY`
+//! * [`lease_output_buffer`] hands out output buffers from a pool capped by `max_leased_bytes`,
+//!   waiting for other leases to be dropped while it is full; `try_lease_output_buffer` fails
+//!   with `ERR_OUT_OF_MEMORY` instead and `lease_output_buffer_until` gives up at a `Deadline`
+//! * Libraries export [`cobhan_alloc`] and [`cobhan_free`] to allocate Cobhan Buffers in Rust;
+//!   [`cbuffer_into_vec`] turns one into a `Vec<u8>` that takes over its allocation, so a payload
+//!   written and consumed on the Rust side is never copied into new memory
+//! * Until `configure` is called, settings are read once from `COBHAN_TEMP_DIR`,
+//!   `COBHAN_MAX_BUFFER_LEN`, `COBHAN_MAX_MARSHALING_BYTES`, `COBHAN_MAX_LEASED_BYTES`,
+//!   `COBHAN_SPILL_POLICY`, `COBHAN_BUFFER_FORMAT`, `COBHAN_DEBUG_SINK`, `COBHAN_LOG_LEVEL` and
+//!   `COBHAN_DEFENSIVE_COPY_MODE`
+//! * Libraries also export [`cobhan_configure`], so hosts can apply the same settings as a JSON
+//!   object, e.g. `{"temp_dir": "/var/cache/app", "max_buffer_len": 16777216}`
+//! * Libraries export [`cobhan_set_log_level`], from 0 (off) to 5 (trace), and
+//!   [`cobhan_set_log_callback`], taking an `extern "C" fn(level, message)`, so hosts can tune
+//!   and route the `cobhan_debug` output of a deployed library at runtime
+//! * Libraries export [`cobhan_health`], writing a JSON report of the crate version, enabled
+//!   features, whether the spill directory is writable and the state of the worker and lease
+//!   pools, so orchestrators can probe a loaded library before routing traffic to it
+//! * Libraries export [`cobhan_after_fork`] for hosts that fork after loading them, such as Python
+//!   and Ruby, to call in the child: it forgets the parent's memory charges, leases and
+//!   dictionary handles, and keeps the parallel conversions off the parent's worker pool
+//! * Libraries export [`cobhan_shutdown`] for hosts to call before unloading them: it waits up to a
+//!   timeout for the threads `Deadline::run` started and removes the spill files written and not
+//!   yet removed, failing with `ERR_DEADLINE_EXCEEDED` if threads are still running
+//! * Hosts that unload and load a library again also call [`cobhan_deinit`] first, which drops the
+//!   configuration, transports, hooks and dictionaries the library held, so it starts afresh
+//! * [`cbuffer_copy_payload`] copies a buffer into another without allocating or locking, so crash
+//!   handlers can extract diagnostic buffers from a signal handler; libraries export it as
+//!   [`cobhan_copy_payload`], and its documentation lists the other async-signal-safe functions
+//!
+//! ## Testing
+//!
+//! * The `testing` feature adds [`testing::OwnedCBuffer`], an owned Cobhan buffer for
+//!   unit-testing exported functions from Rust without hand-packing headers
+//! * [`testing::MockTransport`] records spills in memory instead of writing temp files
+//! * [`cbuffer_diff`] reports the first differing flags field or payload byte of two buffers, and
+//!   libraries export it as [`cobhan_buffer_diff`] writing a JSON report, so cross-language
+//!   conformance tests say where buffers differ instead of just that they do
+//! * The [`conformance`] module writes canonical test vectors (empty, full-capacity, spilled,
+//!   invalid UTF-8 and invalid-length buffers) to a directory, and libraries export
+//!   `cobhan_conformance_verify` to check the buffers a new language binding produces against them
+//! * [`capture`] records the payloads crossing the boundary to a file, with a size cap and a
+//!   redaction hook, and replays the captured inputs into a function to reproduce host bugs; its
+//!   `set_tee_sink` also passes every output payload to a function, e.g. a channel or sampler
+//! * [`set_redaction_hook`] installs a function rewriting payload content before it reaches debug
+//!   output, capture files or error messages, so secrets stay out of diagnostics
+//! * `cargo bench -p cobhan --features testing` compares copied, borrowed and spilled reads and
+//!   writes, and JSON encoding and decoding, across payload sizes
+//! * The `copy_analysis` feature records the call sites of the copying conversions;
+//!   `cobhan::stats::copy_report` shows how many bytes each copies and how many of those could
+//!   have been borrowed in place
+//! * The `watchdog` feature times conversions and `ffi_guard` calls once
+//!   `cobhan::stats::set_slow_call_threshold` is given a threshold, keeping those that take longer,
+//!   with their payload size and whether they spilled, for `cobhan::stats::slow_calls` and a
+//!   callback
+//!
+//! ## Minimal builds
+//!
+//! * The default features are `std`, `json` and `tempfile`
+//! * `json` (serde_json) adds the JSON hashmap helpers, TLV JSON records, the `rpc` module, key
+//!   dictionaries and `cobhan_configure`
+//! * `tempfile` adds the default `TempFileTransport`; without it, payloads that do not fit fail
+//!   with `ERR_WRITE_TEMP_FILE_FAILED` unless a [`SpillTransport`] is installed
+//! * `default-features = false, features = ["std"]` builds a core for strings and bytes whose
+//!   only dependencies are base64 and hex
+//! * Error codes are the same in every build
+//!
+//! ## no_std
+//!
+//! * The default `std` feature can be disabled to build under `no_std` + `alloc`
+//! * Header parsing and the in-memory conversions remain available; temp files, paths, JSON
+//!   hashmaps, string maps and the `encodings`, `time`, `decimal`, `bigint`, `ndarray`, `csv`,
+//!   `gzip`, `zstd`, `sha2`, `blake3`, `backtrace`, `formats` and `testing` features require
+//!   `std`
+//! * Without `std`, payloads that do not fit are spilled through a [`SpillTransport`] installed
+//!   with [`set_spill_transport`], and fail until one is installed
+//!
+//! ## WebAssembly
+//!
+//! * With the `wasm` feature on wasm32 targets, hosts pass buffers as u32 offsets into linear
+//!   memory; the `wasm` module resolves them after bounds-checking the header and payload
+//! * Libraries export `wasm::allocate` and `wasm::deallocate` wrappers so hosts can allocate
+//!   buffers inside the module
+//! * The temp file path is disabled in wasm modules
+//!
+//! ## Android and iOS
+//!
+//! * Temp files are created in the app's cache directory on Android and in the app container's
+//!   `tmp` directory on iOS, see [`temp_dir`]
+//! * [`set_temp_dir`] overrides the directory, e.g. with `Context.getCacheDir()` passed down
+//!   from Java
+//! * `cobhan_debug` output goes to logcat (tag `cobhan`) on Android and to os_log on iOS
 
-use std::borrow::Cow;
-use std::collections::HashMap;
-use std::fs;
-use std::io::Write;
-use std::os::raw::c_char;
-use std::ptr::copy_nonoverlapping;
-use std::slice::from_raw_parts;
-use std::str;
-
-use serde_json::Value;
-use tempfile::NamedTempFile;
-
-/// No Error
-pub const ERR_NONE: i32 = 0;
-
-/// One of the provided pointers is NULL / nil / 0
-pub const ERR_NULL_PTR: i32 = -1;
-
-/// One of the provided buffer lengths is too large
-pub const ERR_BUFFER_TOO_LARGE: i32 = -2;
-
-/// One of the provided buffers was too small
-pub const ERR_BUFFER_TOO_SMALL: i32 = -3;
-
-/// Failed to copy a buffer (copy length != expected length)
-pub const ERR_COPY_FAILED: i32 = -4;
-
-/// Failed to decode a JSON buffer
-pub const ERR_JSON_DECODE_FAILED: i32 = -5;
-
-/// Failed to encode to JSON buffer
-pub const ERR_JSON_ENCODE_FAILED: i32 = -6;
-
-/// UTF8 in a String or JSON is invalid.
-pub const ERR_INVALID_UTF8: i32 = -7;
-
-/// TempFile for large partial data failed to read.
-pub const ERR_READ_TEMP_FILE_FAILED: i32 = -8;
-
-/// TempFile for large partial data failed to write.
-pub const ERR_WRITE_TEMP_FILE_FAILED: i32 = -9;
-
-/// 64 bit buffer header provides 8 byte alignment for data pointers
-pub const BUFFER_HEADER_SIZE: isize = 64 / 8;
+#![cfg_attr(not(feature = "std"), no_std)]
 
-const SIZEOF_INT32: isize = 32 / 8;
+extern crate alloc;
 
 #[cfg(feature = "cobhan_debug")]
-macro_rules! debug_print {
-    ($( $args:expr ),*) => { println!($($args ),*); };
+macro_rules! log_print {
+    ($level:ident, $( $args:expr ),*) => {
+        crate::platform::debug_write(crate::LogLevel::$level, format_args!($($args ),*));
+    };
 }
 
 #[cfg(not(feature = "cobhan_debug"))]
-macro_rules! debug_print {
-    ($( $args:expr ),*) => {};
+macro_rules! log_print {
+    ($level:ident, $( $args:expr ),*) => {};
 }
 
-/// Takes a pointer to an external Cobhan Buffer and fallibly attempts to interpret it as a `Vec<u8>`.
-///
-/// ## Notes
-///
-/// This function does a memcopy from the provided Cobhan Buffer into Rust owned data.
-///
-/// ## Safety
-///
-/// Behavior is undefined if any of the following conditions are violated:
-/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
-/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
-pub unsafe fn cbuffer_to_vector(buffer: *const c_char) -> Result<Vec<u8>, i32> {
-    if buffer.is_null() {
-        debug_print!("cbuffer_to_vector: buffer is NULL");
-        return Err(ERR_NULL_PTR);
-    }
-    let length = *(buffer as *const i32);
-    let _reserved = buffer.offset(SIZEOF_INT32) as *const i32;
-    let payload = buffer.offset(BUFFER_HEADER_SIZE) as *const u8;
-    debug_print!("cbuffer_to_vector: raw length field is {}", length);
-
-    if length < 0 {
-        debug_print!("cbuffer_to_vector: calling temp_to_vector");
-        return temp_to_vector(payload, length);
-    }
-
-    //Allocation: to_vec() is a clone/copy
-    Ok(from_raw_parts(payload, length as usize).to_vec())
+/// Failures of the system rather than of the call's input, e.g. a spill file that could not be
+/// written or a caught panic.
+#[allow(unused_macros)] // Only used with std
+macro_rules! error_print {
+    ($( $args:expr ),*) => { log_print!(Error, $($args ),*) };
 }
 
-/// Takes a pointer to an external Cobhan Buffer and fallibly attempts to interpret it as a `String`.
-///
-/// The String is fallibly checked to ensure UTF-8 formatting.
-///
-/// ## Notes
-///
-/// This function does a memcopy from the provided Cobhan Buffer into Rust owned data.
-///
-/// ## Safety
-///
-/// Behavior is undefined if any of the following conditions are violated:
-/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
-/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
-pub unsafe fn cbuffer_to_string(buffer: *const c_char) -> Result<String, i32> {
-    if buffer.is_null() {
-        debug_print!("cbuffer_to_string: buffer is NULL");
-        return Err(ERR_NULL_PTR);
-    }
-    let length = *(buffer as *const i32);
-    let _reserved = buffer.offset(SIZEOF_INT32) as *const i32;
-    let payload = buffer.offset(BUFFER_HEADER_SIZE) as *const u8;
-    debug_print!("cbuffer_to_string: raw length field is {}", length);
-
-    debug_print!("cbuffer_to_string: raw length field is {}", length);
-
-    if length < 0 {
-        debug_print!("cbuffer_to_string: calling temp_to_string");
-        return temp_to_string(payload, length);
-    }
-
-    str::from_utf8(from_raw_parts(payload, length as usize))
-        .map(|s| s.to_owned())
-        .map_err(|_| {
-            debug_print!(
-                "cbuffer_to_string: payload is invalid utf-8 string (length = {})",
-                length
-            );
-            ERR_INVALID_UTF8
-        })
+/// Calls refused by a configured limit or policy.
+macro_rules! warn_print {
+    ($( $args:expr ),*) => { log_print!(Warn, $($args ),*) };
 }
 
-/// Gets a tempfile data for a payload and interprets it as a `String`.
-unsafe fn temp_to_string(payload: *const u8, length: i32) -> Result<String, i32> {
-    let file_name =
-        str::from_utf8(from_raw_parts(payload, (0 - length) as usize)).map_err(|_| {
-            debug_print!(
-                "temp_to_string: temp file name is invalid utf-8 string (length = {})",
-                0 - length
-            );
-            ERR_INVALID_UTF8
-        })?;
-
-    debug_print!("temp_to_string: reading temp file {}", file_name);
-
-    fs::read_to_string(file_name).map_err(|_e| {
-        debug_print!(
-            "temp_to_string: Error reading temp file {}: {}",
-            file_name,
-            _e
-        );
-        ERR_READ_TEMP_FILE_FAILED
-    })
-}
-
-/// Gets a tempfile data for a payload and interprets it as a `Vec<u8>`.
-unsafe fn temp_to_vector(payload: *const u8, length: i32) -> Result<Vec<u8>, i32> {
-    let file_name =
-        str::from_utf8(from_raw_parts(payload, (0 - length) as usize)).map_err(|_| {
-            debug_print!(
-                "temp_to_vector: temp file name is invalid utf-8 string (length = {})",
-                0 - length
-            );
-            ERR_INVALID_UTF8
-        })?;
-
-    fs::read(file_name).map_err(|_e| {
-        debug_print!(
-            "temp_to_vector: failed to read temporary file {}: {}",
-            file_name,
-            _e
-        );
-        ERR_READ_TEMP_FILE_FAILED
-    })
+/// Process-wide events such as shutdown.
+#[allow(unused_macros)] // Only used with std
+macro_rules! info_print {
+    ($( $args:expr ),*) => { log_print!(Info, $($args ),*) };
 }
 
-/// Takes a pointer to an external Cobhan Buffer and fallibly attempts to interpret it as a `Hashmap<String, serde_json::Value>`.
-///
-/// The JSON is fallibly checked to ensure UTF-8 formatting of any string properties.
-///
-/// ## Notes
-///
-/// This function does a memcopy from the provided Cobhan Buffer into Rust owned data.
-///
-/// ## Safety
-///
-/// Behavior is undefined if any of the following conditions are violated:
-/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
-/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
-pub unsafe fn cbuffer_to_hashmap_json(
-    buffer: *const c_char,
-) -> Result<HashMap<String, Value>, i32> {
-    if buffer.is_null() {
-        debug_print!("cbuffer_to_hashmap_json: buffer is NULL");
-        return Err(ERR_NULL_PTR);
-    }
-    let length = *(buffer as *const i32);
-    let _reserved = buffer.offset(SIZEOF_INT32) as *const i32;
-    let payload = buffer.offset(BUFFER_HEADER_SIZE) as *const u8;
-    debug_print!("cbuffer_to_hashmap_json: raw length field is {}", length);
-
-    let json_bytes = if length >= 0 {
-        Cow::Borrowed(from_raw_parts(payload, length as usize))
-    } else {
-        debug_print!("cbuffer_to_hashmap_json: calling temp_to_vector");
-        Cow::Owned(temp_to_vector(payload, length)?)
-    };
-
-    serde_json::from_slice(&json_bytes).map_err(|_e| {
-        debug_print!(
-            "cbuffer_to_hashmap_json: serde_json::from_slice / JSON decode failed {}",
-            _e
-        );
-        ERR_JSON_DECODE_FAILED
-    })
-}
-
-/// Takes a `Hashmap<String, serde_json::Value>` and fallibly encodes it in JSON into a provided external Cobhan Buffer.
-///
-/// The JSON is fallibly checked to ensure UTF-8 formatting of any string properties.
-///
-/// Will cause an error code if the provided Cobhan Buffer is too small.
-///
-/// ## Notes
-///
-/// This function does a memcopy from the Rust data into the provided Cobhan Buffer.
-///
-/// ## Safety
-///
-/// Behavior is undefined if any of the following conditions are violated:
-/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
-/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
-pub unsafe fn hashmap_json_to_cbuffer(json: &HashMap<String, Value>, buffer: *mut c_char) -> i32 {
-    match serde_json::to_vec(&json) {
-        Ok(json_bytes) => bytes_to_cbuffer(&json_bytes, buffer),
-        Err(_) => ERR_JSON_ENCODE_FAILED,
-    }
-}
-
-/// Takes a `String` and fallibly encodes it into a provided external Cobhan Buffer.
-///
-/// Will cause an error code if the provided Cobhan Buffer is too small.
-///
-/// ## Notes
-///
-/// This function does a memcopy from the Rust data into the provided Cobhan Buffer.
-///
-/// ## Safety
-///
-/// Behavior is undefined if any of the following conditions are violated:
-/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
-/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
-pub unsafe fn string_to_cbuffer(string: &str, buffer: *mut c_char) -> i32 {
-    bytes_to_cbuffer(string.as_bytes(), buffer)
-}
-
-/// Takes a `Vec<u8>` and fallibly encodes it into a provided external Cobhan Buffer.
-///
-/// Will cause an error code if the provided Cobhan Buffer is too small.
-///
-/// ## Notes
-///
-/// This function does a memcopy from the Rust data into the provided Cobhan Buffer.
-///
-/// ## Safety
-///
-/// Behavior is undefined if any of the following conditions are violated:
-/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
-/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
-pub unsafe fn bytes_to_cbuffer(bytes: &[u8], buffer: *mut c_char) -> i32 {
-    if buffer.is_null() {
-        debug_print!("bytes_to_cbuffer: buffer is NULL");
-        return ERR_NULL_PTR;
-    }
-
-    let length = buffer as *mut i32;
-    let _reserved = buffer.offset(SIZEOF_INT32) as *mut i32;
-    let payload = (buffer.offset(BUFFER_HEADER_SIZE)) as *mut u8;
-
-    let buffer_cap = *length;
-    debug_print!("bytes_to_cbuffer: buffer capacity is {}", buffer_cap);
-
-    if buffer_cap <= 0 {
-        debug_print!("bytes_to_cbuffer: Invalid buffer capacity");
-        return ERR_BUFFER_TOO_SMALL;
-    }
-
-    let bytes_len = bytes.len();
-    debug_print!("bytes_to_cbuffer: bytes.len() is {}", bytes_len);
-
-    if buffer_cap < (bytes_len as i32) {
-        debug_print!("bytes_to_cbuffer: calling bytes_to_temp");
-        return bytes_to_temp(bytes, buffer);
-    }
-
-    copy_nonoverlapping(bytes.as_ptr(), payload, bytes_len);
-
-    *length = bytes_len as i32;
-
-    ERR_NONE
+/// Why other calls failed, and what they did with large payloads.
+macro_rules! debug_print {
+    ($( $args:expr ),*) => { log_print!(Debug, $($args ),*) };
 }
 
-/// Sets a tempfile data for a payload and writes bytes to it.
-unsafe fn bytes_to_temp(bytes: &[u8], buffer: *mut c_char) -> i32 {
-    // TODO: eventually replace this pattern with if-let once that is stable -jsenkpiel
-    let tmp_file_path = match write_new_file(bytes) {
-        Ok(t) => t,
-        Err(r) => return r,
-    };
-    debug_print!(
-        "bytes_to_temp: write_new_file wrote {} bytes to {}",
-        bytes.len(),
-        tmp_file_path
-    );
-
-    let length = buffer as *mut i32;
-    let tmp_file_path_len = tmp_file_path.len() as i32;
-
-    //NOTE: We explicitly test this so we don't recursively attempt to create temp files with string_to_cbuffer()
-    if *length < tmp_file_path_len {
-        //Temp file path won't fit in output buffer, we're out of luck
-        debug_print!(
-            "bytes_to_temp: temp file path {} is larger than buffer capacity {}",
-            tmp_file_path,
-            *length
-        );
-        let _ = fs::remove_file(tmp_file_path);
-        return ERR_BUFFER_TOO_SMALL;
-    }
-
-    let result = string_to_cbuffer(&tmp_file_path, buffer);
-    if result != ERR_NONE {
-        debug_print!(
-            "bytes_to_temp: failed to store temp path {} in buffer",
-            tmp_file_path
-        );
-        let _ = fs::remove_file(tmp_file_path);
-        return result;
-    }
-
-    *length = 0 - tmp_file_path_len;
-
-    result
+/// The steps of every call, such as the length fields read.
+macro_rules! trace_print {
+    ($( $args:expr ),*) => { log_print!(Trace, $($args ),*) };
 }
 
-// Writes to a new named temporary file and returns the file name.
-fn write_new_file(bytes: &[u8]) -> Result<String, i32> {
-    let mut tmpfile = NamedTempFile::new().map_err(|_| ERR_WRITE_TEMP_FILE_FAILED)?;
-
-    if tmpfile.write_all(bytes).is_err() {
-        return Err(ERR_WRITE_TEMP_FILE_FAILED);
-    };
-
-    let (_, path) = tmpfile.keep().map_err(|_| ERR_WRITE_TEMP_FILE_FAILED)?;
-
-    path.into_os_string()
-        .into_string()
-        .map_err(|_| ERR_WRITE_TEMP_FILE_FAILED)
+#[cfg(feature = "std")]
+mod allocation;
+#[cfg(feature = "arena")]
+mod arena;
+#[cfg(feature = "ndarray")]
+pub mod array;
+mod buffer;
+#[cfg(feature = "std")]
+mod cache;
+#[cfg(feature = "std")]
+pub mod capture;
+#[cfg(not(feature = "std"))]
+mod capture {
+    //! Without `std` there is no capture.
+
+    pub(crate) fn record_input(_bytes: &[u8]) {}
+
+    pub(crate) fn record_output(_bytes: &[u8]) {}
 }
+mod checksum;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+mod compress;
+#[cfg(feature = "std")]
+pub mod config;
+#[cfg(not(feature = "std"))]
+mod config;
+#[cfg(all(feature = "std", feature = "json"))]
+pub mod conformance;
+mod content;
+mod convert;
+#[cfg(feature = "std")]
+mod deadline;
+#[cfg(all(feature = "std", feature = "json"))]
+mod dictionary;
+mod diff;
+#[cfg(any(feature = "sha2", feature = "blake3"))]
+mod digest;
+mod error;
+mod flags;
+#[cfg(feature = "std")]
+mod fork;
+#[cfg(feature = "formats")]
+mod format;
+#[cfg(feature = "std")]
+mod guard;
+#[cfg(all(feature = "std", feature = "json"))]
+mod health;
+#[cfg(feature = "std")]
+mod intern;
+#[cfg(all(feature = "std", feature = "json"))]
+mod json;
+#[cfg(feature = "std")]
+mod lease;
+mod memory;
+pub mod patch;
+mod pinned;
+#[cfg(feature = "std")]
+mod platform;
+pub mod prelude;
+pub mod records;
+#[cfg(feature = "std")]
+mod redact;
+mod retry;
+#[cfg(all(feature = "std", feature = "json"))]
+pub mod rpc;
+#[cfg(feature = "std")]
+mod scope;
+#[cfg(feature = "std")]
+mod shutdown;
+mod signal;
+#[cfg(any(feature = "copy_analysis", feature = "watchdog"))]
+pub mod stats;
+#[cfg(not(any(feature = "copy_analysis", feature = "watchdog")))]
+mod stats;
+#[cfg(feature = "csv")]
+mod table;
+mod temp;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod tlv;
+pub mod transport;
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub mod wasm;
+
+#[cfg(feature = "std")]
+pub use allocation::{cbuffer_into_vec, cobhan_alloc, cobhan_free};
+#[cfg(feature = "arena")]
+pub use arena::{cbuffer_to_bytes_in, cbuffer_to_str_in, with_arena, Arena};
+#[cfg(feature = "ndarray")]
+pub use array::{array_to_cbuffer, cbuffer_to_array, ArrayElement};
+pub use buffer::*;
+#[cfg(feature = "std")]
+pub use cache::{cbuffer_to_vector_cached, PayloadCache};
+pub use checksum::{bytes_to_cbuffer_with_crc, cbuffer_verify_crc, crc32};
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+pub use compress::{cbuffer_to_decompressed_vector, compressed_bytes_to_cbuffer, Compression};
+#[cfg(all(feature = "std", feature = "json"))]
+pub use config::cobhan_configure;
+#[cfg(feature = "std")]
+pub use config::{
+    cobhan_set_log_callback, cobhan_set_log_level, configure, current_config, CobhanConfig,
+    DebugSink, LevelSink, LogCallback, LogLevel, SpillPolicy,
+};
+#[cfg(all(feature = "std", feature = "json"))]
+pub use conformance::{cobhan_conformance_verify, cobhan_conformance_write_vectors};
+pub use content::{
+    cbuffer_content_type, decode_any, set_cbuffer_content_type, ContentType, DecodedValue,
+};
+pub use convert::*;
+#[cfg(feature = "std")]
+pub use deadline::Deadline;
+#[cfg(all(feature = "std", feature = "json"))]
+pub use dictionary::{
+    cbuffer_to_json_with_dictionary, cobhan_dictionary_free, cobhan_dictionary_new,
+    json_to_cbuffer_with_dictionary, with_dictionary, KeyDictionary,
+};
+#[cfg(all(feature = "std", feature = "json"))]
+pub use diff::cobhan_buffer_diff;
+pub use diff::{cbuffer_diff, diff_payloads, DiffReport};
+#[cfg(any(feature = "sha2", feature = "blake3"))]
+pub use digest::{cbuffer_digest, DigestAlgorithm};
+pub use error::*;
+pub use flags::{cbuffer_flags, set_cbuffer_flags, BufferFlags, BufferFormat};
+#[cfg(feature = "std")]
+pub use fork::cobhan_after_fork;
+#[cfg(feature = "formats")]
+pub use format::{
+    cbuffer_to_value_as, decode_with, format_for, register_format, transcode_cbuffer,
+    unregister_format, value_to_cbuffer_as, CobhanFormat, DecodeSeed, JsonFormat,
+};
+#[cfg(feature = "std")]
+pub use guard::{
+    clear_last_error, cobhan_get_last_error, ffi_guard, last_error, set_last_error,
+    MainThreadCallback,
+};
+#[cfg(all(feature = "std", feature = "json"))]
+pub use health::cobhan_health;
+#[cfg(feature = "std")]
+pub use intern::{cbuffer_to_interned_str, Interner};
+#[cfg(all(feature = "std", feature = "json"))]
+pub use json::*;
+#[cfg(feature = "std")]
+pub use lease::{
+    current_leased_bytes, lease_output_buffer, lease_output_buffer_until, try_lease_output_buffer,
+    LeasedBuffer,
+};
+pub use memory::current_marshaling_bytes;
+pub use pinned::PinnedInput;
+#[cfg(feature = "std")]
+pub use redact::{clear_redaction_hook, set_redaction_hook, RedactionHook};
+pub use retry::{cbuffer_required_size, write_with_retry, RetryPolicy};
+#[cfg(feature = "std")]
+pub use scope::{cobhan_end_call, SpillScope};
+#[cfg(feature = "std")]
+pub use shutdown::{cobhan_deinit, cobhan_shutdown};
+pub use signal::{cbuffer_copy_payload, cobhan_copy_payload};
+#[cfg(feature = "csv")]
+pub use table::{cbuffer_to_csv_records, records_to_cbuffer};
+#[cfg(feature = "tempfile")]
+pub use transport::TempFileTransport;
+pub use transport::{reset_spill_transport, set_spill_transport, SpillTransport};
+#[cfg(feature = "std")]
+pub use transport::{reset_temp_dir, set_temp_dir, temp_dir, with_thread_spill_transport};
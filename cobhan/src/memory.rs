@@ -0,0 +1,127 @@
+//! # Memory accounting
+//!
+//! The conversions charge the bytes they copy out of buffers, read back from spills or encode
+//! into JSON to a process-wide account while they hold them, and release the charge when they
+//! return. [`current_marshaling_bytes`] reports the bytes held across all threads at that
+//! moment, and [`CobhanConfig::max_marshaling_bytes`](crate::CobhanConfig::max_marshaling_bytes)
+//! caps it: a conversion that would take the account over the cap fails with
+//! `ERR_OUT_OF_MEMORY` before allocating, so an embedder can bound the peak footprint of the FFI
+//! layer however many calls run at once. Payloads whose size is not known up front, such as
+//! spilled or compressed ones, are charged piece by piece as they are read.
+//!
+//! Values returned to the caller belong to the caller and are no longer counted.
+
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "std")]
+use std::io::{self, Read};
+
+#[cfg(feature = "std")]
+use crate::buffer::check_max_buffer_len;
+use crate::{config, ERR_OUT_OF_MEMORY};
+
+static MARSHALING_BYTES: AtomicUsize = AtomicUsize::new(0);
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+/// Bytes currently held by conversions, across all threads.
+pub fn current_marshaling_bytes() -> usize {
+    MARSHALING_BYTES.load(Ordering::Relaxed)
+}
+
+/// Bytes charged to the account, released when dropped.
+#[must_use]
+pub(crate) struct Charge {
+    len: usize,
+    generation: usize,
+}
+
+impl Charge {
+    /// Charges `len` bytes, failing with `ERR_OUT_OF_MEMORY` if that would exceed the configured
+    /// `max_marshaling_bytes`.
+    pub(crate) fn reserve(len: usize) -> Result<Self, i32> {
+        let max = config::max_marshaling_bytes().unwrap_or(usize::MAX);
+        MARSHALING_BYTES
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |held| {
+                held.checked_add(len).filter(|total| *total <= max)
+            })
+            .map_err(|_held| {
+                warn_print!(
+                    "Charge::reserve: {} bytes on top of {} is over the limit of {}",
+                    len,
+                    _held,
+                    max
+                );
+                ERR_OUT_OF_MEMORY
+            })?;
+        Ok(Charge {
+            len,
+            generation: GENERATION.load(Ordering::Relaxed),
+        })
+    }
+
+    /// A charge of nothing, to be grown as bytes are taken.
+    pub(crate) fn empty() -> Self {
+        Charge {
+            len: 0,
+            generation: GENERATION.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Charges `additional` bytes more, for a conversion whose output grows as it goes.
+    pub(crate) fn grow(&mut self, additional: usize) -> Result<(), i32> {
+        let extra = Charge::reserve(additional)?;
+        // The bytes charged before `forget_charges` were already released
+        if extra.generation != self.generation {
+            self.len = 0;
+            self.generation = extra.generation;
+        }
+        self.len += extra.len;
+        core::mem::forget(extra);
+        Ok(())
+    }
+}
+
+/// Size of the pieces [`read_charged`] reads.
+#[cfg(feature = "std")]
+const READ_CHUNK: usize = 64 * 1024;
+
+/// Reads `reader` to the end, charging each piece to `charge` and holding the total to the
+/// configured `max_buffer_len` before the piece is kept, so a payload of unknown size is not
+/// allocated whole before the account is checked. Read errors are mapped with `failed`.
+#[cfg(feature = "std")]
+pub(crate) fn read_charged(
+    mut reader: impl Read,
+    charge: &mut Charge,
+    failed: fn(io::Error) -> i32,
+) -> Result<Vec<u8>, i32> {
+    let mut bytes = Vec::new();
+    let mut chunk = alloc::vec![0; READ_CHUNK];
+    loop {
+        let read = match reader.read(&mut chunk) {
+            Ok(0) => return Ok(bytes),
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(failed(e)),
+        };
+        check_max_buffer_len(bytes.len() + read)?;
+        charge.grow(read)?;
+        bytes.extend_from_slice(&chunk[..read]);
+    }
+}
+
+impl Drop for Charge {
+    fn drop(&mut self) {
+        // Charges taken before `forget_charges` were already released
+        if self.generation == GENERATION.load(Ordering::Relaxed) {
+            MARSHALING_BYTES.fetch_sub(self.len, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Releases every outstanding charge, for a forked child whose charging threads are gone.
+#[cfg(feature = "std")]
+pub(crate) fn forget_charges() {
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+    MARSHALING_BYTES.store(0, Ordering::Relaxed);
+}
@@ -0,0 +1,163 @@
+//! # Patches
+//!
+//! Hosts keeping a large state blob in sync across the boundary can send the changes to a base
+//! payload instead of the whole payload. A patch is a sequence of operations, each laid out as:
+//!
+//! * u8 op - one of the `OP_*` constants
+//!     * `OP_COPY` - u32 offset, u32 length: copies that range of the base payload
+//!     * `OP_INSERT` - u32 length, bytes: inserts the bytes
+//!
+//! All integers are little-endian. Applying a patch runs the operations in order, so the result
+//! is the concatenation of the copied ranges and inserted bytes; an empty patch yields an empty
+//! payload.
+
+use alloc::vec::Vec;
+use core::ffi::c_char;
+
+use crate::buffer::check_max_buffer_len;
+use crate::convert::push_length_prefixed;
+use crate::memory::Charge;
+use crate::{
+    bytes_to_cbuffer, cbuffer_payload, PayloadReader, ERR_BUFFER_TOO_LARGE, ERR_MALFORMED_PAYLOAD,
+};
+
+/// Copy a range of the base payload
+pub const OP_COPY: u8 = 1;
+
+/// Insert literal bytes
+pub const OP_INSERT: u8 = 2;
+
+/// Builds a patch one operation at a time.
+#[derive(Debug, Default, Clone)]
+pub struct PatchWriter {
+    bytes: Vec<u8>,
+}
+
+impl PatchWriter {
+    pub fn new() -> Self {
+        PatchWriter { bytes: Vec::new() }
+    }
+
+    /// Appends an operation copying `length` bytes of the base payload starting at `offset`.
+    ///
+    /// Fails with `ERR_BUFFER_TOO_LARGE` if either does not fit in a u32.
+    pub fn copy(&mut self, offset: usize, length: usize) -> Result<(), i32> {
+        if offset > u32::MAX as usize || length > u32::MAX as usize {
+            debug_print!(
+                "PatchWriter: copy of {} bytes at {} is too large",
+                length,
+                offset
+            );
+            return Err(ERR_BUFFER_TOO_LARGE);
+        }
+        self.bytes.push(OP_COPY);
+        self.bytes.extend_from_slice(&(offset as u32).to_le_bytes());
+        self.bytes.extend_from_slice(&(length as u32).to_le_bytes());
+        Ok(())
+    }
+
+    /// Appends an operation inserting `bytes`.
+    ///
+    /// Fails with `ERR_BUFFER_TOO_LARGE` if `bytes` is longer than `u32::MAX` bytes.
+    pub fn insert(&mut self, bytes: &[u8]) -> Result<(), i32> {
+        if bytes.len() > u32::MAX as usize {
+            debug_print!("PatchWriter: insert of {} bytes is too large", bytes.len());
+            return Err(ERR_BUFFER_TOO_LARGE);
+        }
+        self.bytes.push(OP_INSERT);
+        push_length_prefixed(&mut self.bytes, bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// Fallibly encodes the patch into a provided external Cobhan Buffer.
+    ///
+    /// ## Safety
+    ///
+    /// See [`bytes_to_cbuffer`].
+    pub unsafe fn to_cbuffer(&self, buffer: *mut c_char) -> i32 {
+        bytes_to_cbuffer(&self.bytes, buffer)
+    }
+}
+
+/// Applies `patch` to `base`, returning the patched payload.
+///
+/// Fails with `ERR_MALFORMED_PAYLOAD` if the patch is truncated, holds an unknown operation or
+/// copies a range outside of `base`. A few bytes of patch can copy the whole base many times,
+/// so the patched payload is held to `max_buffer_len` and charged to `max_marshaling_bytes` as
+/// it grows, failing with `ERR_BUFFER_TOO_LARGE` or `ERR_OUT_OF_MEMORY`.
+pub fn apply_patch(base: &[u8], patch: &[u8]) -> Result<Vec<u8>, i32> {
+    let mut reader = PayloadReader::new(patch);
+    let mut out = Vec::new();
+    let mut charge = Charge::reserve(0)?;
+    let mut append = |out: &mut Vec<u8>, bytes: &[u8]| -> Result<(), i32> {
+        check_max_buffer_len(out.len().saturating_add(bytes.len()))?;
+        charge.grow(bytes.len())?;
+        out.extend_from_slice(bytes);
+        Ok(())
+    };
+    while !reader.is_empty() {
+        match reader.read_u8()? {
+            OP_COPY => {
+                let offset = reader.read_u32()? as usize;
+                let length = reader.read_u32()? as usize;
+                let range = offset
+                    .checked_add(length)
+                    .and_then(|end| base.get(offset..end));
+                match range {
+                    Some(range) => append(&mut out, range)?,
+                    None => {
+                        debug_print!(
+                            "apply_patch: copy of {} bytes at {} is outside the {} byte base",
+                            length,
+                            offset,
+                            base.len()
+                        );
+                        return Err(ERR_MALFORMED_PAYLOAD);
+                    }
+                }
+            }
+            OP_INSERT => append(&mut out, reader.read_length_prefixed()?)?,
+            _op => {
+                debug_print!("apply_patch: unknown op {}", _op);
+                return Err(ERR_MALFORMED_PAYLOAD);
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Takes pointers to external Cobhan Buffers holding a base payload and a patch, and fallibly
+/// encodes the patched payload into a provided external Cobhan Buffer.
+///
+/// Will cause an error code if the patch is malformed or the output Cobhan Buffer is too small.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn cbuffer_apply_patch(
+    base_buffer: *const c_char,
+    patch_buffer: *const c_char,
+    out_buffer: *mut c_char,
+) -> i32 {
+    let base = match cbuffer_payload(base_buffer) {
+        Ok(base) => base,
+        Err(e) => return e,
+    };
+    let patch = match cbuffer_payload(patch_buffer) {
+        Ok(patch) => patch,
+        Err(e) => return e,
+    };
+    match apply_patch(&base, &patch) {
+        Ok(patched) => bytes_to_cbuffer(&patched, out_buffer),
+        Err(e) => e,
+    }
+}
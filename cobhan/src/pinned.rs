@@ -0,0 +1,84 @@
+//! # Pinned inputs
+//!
+//! Hosts with a moving garbage collector, such as Go and .NET, must pin a buffer for as long as
+//! Rust may read it. Go pins memory passed to a function for the duration of the call; .NET
+//! only while the buffer is `fixed` or pinned with a `GCHandle`. Inline payloads are borrowed
+//! in place by default, so a library that keeps reading an input after its host let go of it
+//! reads freed or moved memory.
+//!
+//! Libraries can choose between two contracts:
+//!
+//! * Take inputs as [`PinnedInput`], whose constructor states that the host keeps the buffer
+//!   pinned until the exported function returns, and whose lifetime keeps borrowed payloads
+//!   from outliving the call.
+//! * Enable `CobhanConfig::defensive_copy_mode`, so inline payloads are copied as soon as they
+//!   are read and no borrowed view is ever held.
+//!
+//! ```ignore
+//! #[no_mangle]
+//! pub unsafe extern "C" fn token_count(input: *const c_char) -> i32 {
+//!     // The host pins `input` for the duration of the call
+//!     let input = cobhan::PinnedInput::new(input);
+//!     match input.payload() {
+//!         Ok(text) => count(&text),
+//!         Err(e) => e,
+//!     }
+//! }
+//! ```
+
+use alloc::borrow::Cow;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ffi::c_char;
+use core::marker::PhantomData;
+
+use crate::buffer::{cbuffer_payload, Payload};
+use crate::{cbuffer_to_string, cbuffer_to_vector};
+
+/// An input buffer the host keeps pinned, neither moved nor collected, for the lifetime
+/// `'call`: the rest of the exported function call.
+#[derive(Clone, Copy, Debug)]
+pub struct PinnedInput<'call> {
+    buffer: *const c_char,
+    call: PhantomData<&'call [u8]>,
+}
+
+impl<'call> PinnedInput<'call> {
+    /// Takes a pointer to an external Cobhan Buffer that the host keeps pinned for `'call`.
+    ///
+    /// ## Safety
+    ///
+    /// Behavior is undefined if any of the following conditions are violated:
+    /// - The host keeps the buffer at this address, neither moved nor freed, for `'call`.
+    /// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+    /// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+    pub unsafe fn new(buffer: *const c_char) -> Self {
+        PinnedInput {
+            buffer,
+            call: PhantomData,
+        }
+    }
+
+    /// The pointer to the buffer.
+    pub fn as_ptr(&self) -> *const c_char {
+        self.buffer
+    }
+
+    /// The payload, borrowed in place if it is inline and `defensive_copy_mode` is off.
+    pub fn payload(&self) -> Result<Cow<'call, [u8]>, i32> {
+        // SAFETY: the caller of `new` guaranteed the buffer is valid and pinned for 'call
+        unsafe { cbuffer_payload(self.buffer).map(Payload::into_cow) }
+    }
+
+    /// The payload copied into a `Vec`, see [`cbuffer_to_vector`].
+    pub fn to_vec(&self) -> Result<Vec<u8>, i32> {
+        // SAFETY: the caller of `new` guaranteed the buffer is valid and pinned for 'call
+        unsafe { cbuffer_to_vector(self.buffer) }
+    }
+
+    /// The payload copied into a `String`, see [`cbuffer_to_string`].
+    pub fn to_string(&self) -> Result<String, i32> {
+        // SAFETY: the caller of `new` guaranteed the buffer is valid and pinned for 'call
+        unsafe { cbuffer_to_string(self.buffer) }
+    }
+}
@@ -0,0 +1,143 @@
+//! Platform specific temp directory discovery and debug output.
+//!
+//! Mobile apps run in a sandbox without a world-writable `/tmp`: on Android spill files go to the
+//! app's cache directory and debug output to logcat, and on iOS spill files go to the app
+//! container's `tmp` directory and debug output to the unified logging system.
+
+#[cfg(target_os = "android")]
+use std::fs;
+use std::path::PathBuf;
+
+#[cfg(feature = "cobhan_debug")]
+use crate::{DebugSink, LogLevel};
+
+/// Directory spill files are created in when no directory has been set.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub(crate) fn default_temp_dir() -> PathBuf {
+    std::env::temp_dir()
+}
+
+/// Directory spill files are created in when no directory has been set.
+///
+/// `std::env::temp_dir()` is `/data/local/tmp` on Android, which apps cannot write to, so this
+/// prefers `TMPDIR` and then the cache directory of the app that owns the process.
+#[cfg(target_os = "android")]
+pub(crate) fn default_temp_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("TMPDIR") {
+        return PathBuf::from(dir);
+    }
+    android_cache_dir().unwrap_or_else(std::env::temp_dir)
+}
+
+/// `/data/data/<package>/cache`, with the package name taken from the process name.
+#[cfg(target_os = "android")]
+fn android_cache_dir() -> Option<PathBuf> {
+    let cmdline = fs::read("/proc/self/cmdline").ok()?;
+    let process = cmdline.split(|b| *b == 0).next()?;
+    let process = std::str::from_utf8(process).ok()?;
+    // Secondary processes are named `<package>:<process>`
+    let package = process.split(':').next()?;
+    if package.is_empty() || package.contains('/') {
+        return None;
+    }
+    let cache = PathBuf::from("/data/data").join(package).join("cache");
+    if cache.is_dir() {
+        Some(cache)
+    } else {
+        None
+    }
+}
+
+/// Directory spill files are created in when no directory has been set.
+///
+/// Prefers `TMPDIR`, which iOS points at the app container's `tmp` directory, and falls back to
+/// `$HOME/tmp` inside the container.
+#[cfg(target_os = "ios")]
+pub(crate) fn default_temp_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("TMPDIR") {
+        return PathBuf::from(dir);
+    }
+    match std::env::var_os("HOME") {
+        Some(home) => PathBuf::from(home).join("tmp"),
+        None => std::env::temp_dir(),
+    }
+}
+
+#[cfg(all(
+    feature = "cobhan_debug",
+    any(target_os = "android", target_os = "ios")
+))]
+fn to_cstring(message: &str) -> std::ffi::CString {
+    // Interior NULs would truncate the message, so they are dropped
+    std::ffi::CString::new(message.replace('\0', "")).unwrap_or_default()
+}
+
+/// Writes one line of debug output to the configured [`DebugSink`], if `level` is enabled.
+#[cfg(feature = "cobhan_debug")]
+pub(crate) fn debug_write(level: LogLevel, args: std::fmt::Arguments<'_>) {
+    if !crate::config::log_enabled(level) {
+        return;
+    }
+    let sink = crate::config::with_config(|config| config.debug_sink.clone());
+    let message = args.to_string();
+    match sink {
+        DebugSink::Platform => platform_write(level, &message),
+        DebugSink::Stderr => eprintln!("{}", message),
+        DebugSink::Off => {}
+        DebugSink::Callback(callback) => callback(&message),
+        DebugSink::Leveled(callback) => callback(level, &message),
+    }
+}
+
+/// Writes one line of debug output to standard output.
+#[cfg(all(
+    feature = "cobhan_debug",
+    not(any(target_os = "android", target_os = "ios"))
+))]
+fn platform_write(_level: LogLevel, message: &str) {
+    println!("{}", message);
+}
+
+/// Writes one line of debug output to logcat, tagged `cobhan`.
+#[cfg(all(feature = "cobhan_debug", target_os = "android"))]
+fn platform_write(level: LogLevel, message: &str) {
+    use std::os::raw::{c_char, c_int};
+
+    // android/log.h priorities
+    let prio: c_int = match level {
+        LogLevel::Error => 6,
+        LogLevel::Warn => 5,
+        LogLevel::Info => 4,
+        LogLevel::Debug | LogLevel::Off => 3,
+        LogLevel::Trace => 2,
+    };
+
+    #[link(name = "log")]
+    extern "C" {
+        fn __android_log_write(prio: c_int, tag: *const c_char, text: *const c_char) -> c_int;
+    }
+
+    let text = to_cstring(message);
+    unsafe {
+        __android_log_write(prio, b"cobhan\0".as_ptr() as *const c_char, text.as_ptr());
+    }
+}
+
+/// Writes one line of debug output with `syslog(3)`, which iOS records in os_log.
+#[cfg(all(feature = "cobhan_debug", target_os = "ios"))]
+fn platform_write(level: LogLevel, message: &str) {
+    let priority = match level {
+        LogLevel::Error => libc::LOG_ERR,
+        LogLevel::Warn => libc::LOG_WARNING,
+        LogLevel::Info => libc::LOG_INFO,
+        LogLevel::Debug | LogLevel::Trace | LogLevel::Off => libc::LOG_DEBUG,
+    };
+    let text = to_cstring(message);
+    unsafe {
+        libc::syslog(
+            priority,
+            b"cobhan: %s\0".as_ptr() as *const libc::c_char,
+            text.as_ptr(),
+        );
+    }
+}
@@ -0,0 +1,58 @@
+//! # Prelude
+//!
+//! The buffer conversions, error codes, configuration and spill transport API in one import:
+//!
+//! ```ignore
+//! use cobhan::prelude::*;
+//! ```
+//!
+//! Every item is also available at the crate root. Module-specific items, such as the [`tlv`]
+//! tags or the [`testing`](crate#testing) helpers, are left to their modules.
+//!
+//! [`tlv`]: crate::tlv
+
+#[cfg(feature = "arena")]
+pub use crate::arena::{cbuffer_to_bytes_in, cbuffer_to_str_in, with_arena, Arena};
+#[cfg(feature = "ndarray")]
+pub use crate::array::{array_to_cbuffer, cbuffer_to_array, ArrayElement};
+pub use crate::buffer::*;
+#[cfg(feature = "std")]
+pub use crate::cache::{cbuffer_to_vector_cached, PayloadCache};
+pub use crate::checksum::{bytes_to_cbuffer_with_crc, cbuffer_verify_crc, crc32};
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+pub use crate::compress::{
+    cbuffer_to_decompressed_vector, compressed_bytes_to_cbuffer, Compression,
+};
+#[cfg(all(feature = "std", feature = "json"))]
+pub use crate::config::cobhan_configure;
+#[cfg(feature = "std")]
+pub use crate::config::{
+    cobhan_set_log_callback, cobhan_set_log_level, configure, current_config, CobhanConfig,
+    DebugSink, LevelSink, LogCallback, LogLevel, SpillPolicy,
+};
+pub use crate::convert::*;
+#[cfg(feature = "std")]
+pub use crate::deadline::Deadline;
+#[cfg(any(feature = "sha2", feature = "blake3"))]
+pub use crate::digest::{cbuffer_digest, DigestAlgorithm};
+pub use crate::error::*;
+pub use crate::flags::{cbuffer_flags, set_cbuffer_flags, BufferFlags, BufferFormat};
+#[cfg(feature = "std")]
+pub use crate::guard::{
+    clear_last_error, cobhan_get_last_error, ffi_guard, last_error, set_last_error,
+    MainThreadCallback,
+};
+#[cfg(feature = "std")]
+pub use crate::intern::{cbuffer_to_interned_str, Interner};
+#[cfg(all(feature = "std", feature = "json"))]
+pub use crate::json::*;
+pub use crate::memory::current_marshaling_bytes;
+pub use crate::pinned::PinnedInput;
+pub use crate::retry::{cbuffer_required_size, write_with_retry, RetryPolicy};
+#[cfg(feature = "csv")]
+pub use crate::table::{cbuffer_to_csv_records, records_to_cbuffer};
+#[cfg(feature = "tempfile")]
+pub use crate::transport::TempFileTransport;
+pub use crate::transport::{reset_spill_transport, set_spill_transport, SpillTransport};
+#[cfg(feature = "std")]
+pub use crate::transport::{reset_temp_dir, set_temp_dir, temp_dir, with_thread_spill_transport};
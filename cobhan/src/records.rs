@@ -0,0 +1,116 @@
+//! # Length-prefixed records
+//!
+//! Hosts that make one call per record can pack many records into one buffer instead, each laid
+//! out as:
+//!
+//! * u32 length - little-endian byte length of the record
+//! * record bytes
+//!
+//! There is no record count, so a [`RecordWriter`] appends records as they come and a
+//! [`RecordReader`] reads them until the payload ends. A payload ending inside a record is
+//! malformed.
+
+use alloc::vec::Vec;
+use core::ffi::c_char;
+
+use crate::convert::push_length_prefixed;
+use crate::{bytes_to_cbuffer, cbuffer_payload, PayloadReader};
+
+/// Builds a records payload one record at a time.
+#[derive(Debug, Default, Clone)]
+pub struct RecordWriter {
+    bytes: Vec<u8>,
+    records: usize,
+}
+
+impl RecordWriter {
+    pub fn new() -> Self {
+        RecordWriter::default()
+    }
+
+    /// Appends a record.
+    ///
+    /// Fails with `ERR_BUFFER_TOO_LARGE` if the record is longer than `u32::MAX` bytes.
+    pub fn write_record(&mut self, record: &[u8]) -> Result<(), i32> {
+        push_length_prefixed(&mut self.bytes, record)?;
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Number of records written.
+    pub fn len(&self) -> usize {
+        self.records
+    }
+
+    /// Returns `true` if no records were written.
+    pub fn is_empty(&self) -> bool {
+        self.records == 0
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// Fallibly encodes the payload into a provided external Cobhan Buffer.
+    ///
+    /// ## Safety
+    ///
+    /// See [`bytes_to_cbuffer`].
+    pub unsafe fn to_cbuffer(&self, buffer: *mut c_char) -> i32 {
+        bytes_to_cbuffer(&self.bytes, buffer)
+    }
+}
+
+/// Iterates the records of a records payload without copying.
+///
+/// A record cut short by the end of the payload yields `ERR_MALFORMED_PAYLOAD` and ends the
+/// iteration.
+pub struct RecordReader<'a> {
+    reader: PayloadReader<'a>,
+    failed: bool,
+}
+
+impl<'a> RecordReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        RecordReader {
+            reader: PayloadReader::new(bytes),
+            failed: false,
+        }
+    }
+}
+
+impl<'a> Iterator for RecordReader<'a> {
+    type Item = Result<&'a [u8], i32>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.reader.is_empty() {
+            return None;
+        }
+        let record = self.reader.read_length_prefixed();
+        self.failed = record.is_err();
+        Some(record)
+    }
+}
+
+/// Takes a pointer to an external Cobhan Buffer holding a records payload and passes each record
+/// to `f`, in order.
+///
+/// Records of inline payloads are borrowed in place. The first error, from `f` or a malformed
+/// payload, stops the iteration and is returned.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn cbuffer_for_each_record(
+    buffer: *const c_char,
+    mut f: impl FnMut(&[u8]) -> Result<(), i32>,
+) -> Result<(), i32> {
+    let bytes = cbuffer_payload(buffer)?;
+    RecordReader::new(&bytes).try_for_each(|record| f(record?))
+}
@@ -0,0 +1,58 @@
+//! # Redaction
+//!
+//! [`set_redaction_hook`] installs a function that rewrites payload content before it reaches
+//! any diagnostic: `cobhan_debug` output, capture files and the messages of
+//! [`last_error`](crate::last_error) and RPC error responses. With it, debug output can be
+//! enabled in production without secrets ending up in logs:
+//!
+//! ```ignore
+//! fn mask_tokens(bytes: &[u8]) -> Cow<'_, [u8]> {
+//!     if bytes.windows(6).any(|w| w == b"token=") {
+//!         Cow::Borrowed(b"<redacted>")
+//!     } else {
+//!         Cow::Borrowed(bytes)
+//!     }
+//! }
+//!
+//! cobhan::set_redaction_hook(mask_tokens);
+//! ```
+//!
+//! The hook applies to diagnostics only: payloads are read and written unchanged, and
+//! [`set_tee_sink`](crate::capture::set_tee_sink) sinks receive them as written. Capture files
+//! apply the hook before [`CaptureOptions::redact`](crate::capture::CaptureOptions::redact).
+
+use std::borrow::Cow;
+use std::sync::RwLock;
+
+/// Rewrites payload content before it is shown in a diagnostic.
+pub type RedactionHook = for<'a> fn(&'a [u8]) -> Cow<'a, [u8]>;
+
+static HOOK: RwLock<Option<RedactionHook>> = RwLock::new(None);
+
+/// Applies `hook` to payload content in every diagnostic from now on, replacing any hook
+/// already installed.
+pub fn set_redaction_hook(hook: RedactionHook) {
+    *HOOK.write().unwrap_or_else(|e| e.into_inner()) = Some(hook);
+}
+
+/// Removes the hook installed with [`set_redaction_hook`].
+pub fn clear_redaction_hook() {
+    HOOK.write().unwrap_or_else(|e| e.into_inner()).take();
+}
+
+/// `bytes` as the installed hook rewrites them, or unchanged if there is none.
+pub(crate) fn redact(bytes: &[u8]) -> Cow<'_, [u8]> {
+    let hook = *HOOK.read().unwrap_or_else(|e| e.into_inner());
+    match hook {
+        Some(hook) => hook(bytes),
+        None => Cow::Borrowed(bytes),
+    }
+}
+
+/// `text` as the installed hook rewrites it, with invalid UTF-8 in the result replaced.
+pub(crate) fn redact_str(text: &str) -> Cow<'_, str> {
+    match redact(text.as_bytes()) {
+        Cow::Borrowed(bytes) => String::from_utf8_lossy(bytes),
+        Cow::Owned(bytes) => Cow::Owned(String::from_utf8_lossy(&bytes).into_owned()),
+    }
+}
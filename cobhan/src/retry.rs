@@ -0,0 +1,110 @@
+//! # Grow and retry
+//!
+//! A function that fails with `ERR_BUFFER_TOO_SMALL` rather than spilling can tell the host how
+//! large a buffer to retry with. [`write_with_retry`] leaves that size in the reserved header
+//! field of the output buffer, so a host can wrap every such function in the same loop instead
+//! of knowing, per function, how to find the size:
+//!
+//! ```text
+//! result = call(input, output)
+//! while result == ERR_BUFFER_TOO_SMALL:
+//!     output = allocate(reserved field of output)
+//!     result = call(input, output)
+//! ```
+//!
+//! Unlike [`bytes_to_cbuffer_or_size`](crate::bytes_to_cbuffer_or_size), which returns the size
+//! in place of the result, the function keeps returning a negative error code, so hosts that
+//! check results with `< 0` need no change. Like a checksum, the size takes the whole reserved
+//! field, so hints cannot be combined with the [`BufferFlags`](crate::BufferFlags) of format v2.
+
+use core::ffi::c_char;
+
+use crate::buffer::{read_header_reserved, write_header_reserved};
+use crate::flags::BufferFormat;
+use crate::{bytes_to_cbuffer, bytes_to_cbuffer_or_size, config};
+use crate::{ERR_BUFFER_TOO_SMALL, ERR_INVALID_CONFIG, ERR_NULL_PTR};
+
+/// What [`write_with_retry`] does with a payload that does not fit the buffer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RetryPolicy {
+    /// Spill it, like [`bytes_to_cbuffer`]; the host never retries.
+    #[default]
+    Spill,
+    /// Fail with `ERR_BUFFER_TOO_SMALL`, hinting the payload length.
+    Exact,
+    /// Fail with `ERR_BUFFER_TOO_SMALL`, hinting the payload length rounded up to a power of
+    /// two, so a host reusing the buffer for later calls with larger payloads grows it less
+    /// often.
+    NextPowerOfTwo,
+}
+
+impl RetryPolicy {
+    /// The capacity to hint for a payload of `required` bytes, which fits the length field.
+    fn hint(self, required: usize) -> u32 {
+        match self {
+            RetryPolicy::NextPowerOfTwo => {
+                let rounded = required.checked_next_power_of_two().unwrap_or(required);
+                let limit = config::max_buffer_len().unwrap_or(i32::MAX as usize);
+                rounded.min(limit).min(i32::MAX as usize).max(required) as u32
+            }
+            _ => required as u32,
+        }
+    }
+}
+
+/// Takes bytes and encodes them into a provided external Cobhan Buffer if they fit, otherwise
+/// follows `on_too_small`, see the [module documentation](self).
+///
+/// With [`RetryPolicy::Exact`] and [`RetryPolicy::NextPowerOfTwo`], a payload that does not fit
+/// is not written: the hinted capacity is stored in the reserved field and the call fails with
+/// `ERR_BUFFER_TOO_SMALL`. They fail with `ERR_INVALID_CONFIG` with format v2 configured. Other
+/// failures are the error codes of [`bytes_to_cbuffer`].
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn write_with_retry(
+    bytes: impl AsRef<[u8]>,
+    buffer: *mut c_char,
+    on_too_small: RetryPolicy,
+) -> i32 {
+    if on_too_small == RetryPolicy::Spill {
+        return bytes_to_cbuffer(bytes, buffer);
+    }
+    if config::buffer_format() == BufferFormat::V2 {
+        debug_print!("write_with_retry: the reserved field holds format v2 flags");
+        return ERR_INVALID_CONFIG;
+    }
+    let result = bytes_to_cbuffer_or_size(bytes, buffer);
+    if result <= 0 {
+        return result;
+    }
+    let hint = on_too_small.hint(result as usize);
+    debug_print!(
+        "write_with_retry: {} bytes required, hinting {}",
+        result,
+        hint
+    );
+    write_header_reserved(buffer, hint);
+    ERR_BUFFER_TOO_SMALL
+}
+
+/// Takes a pointer to an output buffer that [`write_with_retry`] failed to write with
+/// `ERR_BUFFER_TOO_SMALL` and returns the capacity to retry with.
+///
+/// The reserved field means something else after other calls, so only read it after that
+/// failure.
+///
+/// ## Safety
+///
+/// Behavior is undefined if the Cobhan Buffer Header size is not correctly reserved or
+/// formatted.
+pub unsafe fn cbuffer_required_size(buffer: *const c_char) -> Result<usize, i32> {
+    if buffer.is_null() {
+        debug_print!("cbuffer_required_size: buffer is NULL");
+        return Err(ERR_NULL_PTR);
+    }
+    Ok(read_header_reserved(buffer) as usize)
+}
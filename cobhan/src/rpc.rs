@@ -0,0 +1,227 @@
+//! # Request/response RPC
+//!
+//! Plugin-style interfaces often export a single function taking a method name and a payload.
+//! This module fixes the framing for that, over two buffers holding JSON envelopes:
+//!
+//! ```text
+//! request:  {"method": "resize", "payload": {"width": 640}}
+//! response: {"status": 0, "payload": {"width": 640, "height": 480}}
+//!           {"status": -36, "error": {"message": "no handler for method \"rotate\""}}
+//! ```
+//!
+//! A `status` of zero (`ERR_NONE`) comes with the handler's `payload`, any other status with an
+//! `error` object. [`handle_rpc`] decodes the request, calls the handler registered for its
+//! method in a [`Router`] and writes the response:
+//!
+//! ```ignore
+//! static ROUTER: OnceLock<Router> = OnceLock::new();
+//!
+//! #[no_mangle]
+//! pub unsafe extern "C" fn plugin_call(request: *const c_char, response: *mut c_char) -> i32 {
+//!     let router = ROUTER.get_or_init(|| {
+//!         let mut router = Router::new();
+//!         router.register("resize", resize);
+//!         router
+//!     });
+//!     cobhan::ffi_guard(|| unsafe { handle_rpc(request, response, router) })
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::os::raw::c_char;
+
+use serde_json::{json, Map, Value};
+
+use crate::bytes_to_cbuffer;
+use crate::json::{check_json_limits, json_payload};
+use crate::memory::Charge;
+use crate::redact::redact_str;
+use crate::{ERR_JSON_DECODE_FAILED, ERR_JSON_ENCODE_FAILED, ERR_NONE, ERR_UNKNOWN_METHOD};
+
+/// A request envelope: the method to call and its payload.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RpcRequest {
+    pub method: String,
+    /// `null` if the request has none.
+    pub payload: Value,
+}
+
+impl RpcRequest {
+    /// Reads a request envelope: an object with a string `method` and an optional `payload`.
+    pub fn from_json(json: Value) -> Result<Self, RpcError> {
+        let mut envelope = match json {
+            Value::Object(envelope) => envelope,
+            _ => return Err(invalid_request("not an object")),
+        };
+        let method = match envelope.remove("method") {
+            Some(Value::String(method)) => method,
+            _ => return Err(invalid_request("missing string \"method\"")),
+        };
+        let payload = envelope.remove("payload").unwrap_or(Value::Null);
+        Ok(RpcRequest { method, payload })
+    }
+}
+
+fn invalid_request(reason: &str) -> RpcError {
+    debug_print!("handle_rpc: invalid request envelope, {}", reason);
+    RpcError::new(
+        ERR_JSON_DECODE_FAILED,
+        format!("invalid request envelope: {}", reason),
+    )
+}
+
+/// A response envelope: `ERR_NONE` and the handler's payload, or an error status and object.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RpcResponse {
+    pub status: i32,
+    pub payload: Option<Value>,
+    pub error: Option<Value>,
+}
+
+impl RpcResponse {
+    /// A successful response carrying `payload`.
+    pub fn success(payload: Value) -> Self {
+        RpcResponse {
+            status: ERR_NONE,
+            payload: Some(payload),
+            error: None,
+        }
+    }
+
+    /// A failed response, see [`RpcError`].
+    pub fn failure(error: RpcError) -> Self {
+        RpcResponse {
+            status: error.status,
+            payload: None,
+            error: Some(error.error),
+        }
+    }
+
+    /// The response envelope, leaving out whichever of `payload` and `error` is `None`.
+    pub fn to_json(&self) -> Value {
+        let mut envelope = Map::new();
+        envelope.insert("status".to_owned(), self.status.into());
+        if let Some(payload) = &self.payload {
+            envelope.insert("payload".to_owned(), payload.clone());
+        }
+        if let Some(error) = &self.error {
+            envelope.insert("error".to_owned(), error.clone());
+        }
+        Value::Object(envelope)
+    }
+}
+
+/// A handler failure: a negative status, an `ERR_*` code or an application code, and an error
+/// JSON object for the host.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RpcError {
+    pub status: i32,
+    pub error: Value,
+}
+
+impl RpcError {
+    /// An error with `{"message": message}` as its error object.
+    pub fn new(status: i32, message: impl Into<String>) -> Self {
+        RpcError {
+            status,
+            error: json!({ "message": message.into() }),
+        }
+    }
+}
+
+/// Wraps a Cobhan error code, so handlers can use `?` on the conversions.
+impl From<i32> for RpcError {
+    fn from(status: i32) -> Self {
+        RpcError::new(status, format!("cobhan error {}", status))
+    }
+}
+
+/// A function handling one RPC method: takes the request payload, returns the response payload.
+pub type RpcHandler = dyn Fn(Value) -> Result<Value, RpcError> + Send + Sync;
+
+/// The handlers for each method name.
+#[derive(Default)]
+pub struct Router {
+    handlers: HashMap<String, Box<RpcHandler>>,
+}
+
+impl fmt::Debug for Router {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut methods: Vec<&str> = self.handlers.keys().map(String::as_str).collect();
+        methods.sort_unstable();
+        f.debug_struct("Router").field("methods", &methods).finish()
+    }
+}
+
+impl Router {
+    /// A router with no handlers.
+    pub fn new() -> Self {
+        Router::default()
+    }
+
+    /// Registers `handler` for `method`, replacing any handler already registered for it.
+    pub fn register(
+        &mut self,
+        method: &str,
+        handler: impl Fn(Value) -> Result<Value, RpcError> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.handlers.insert(method.to_owned(), Box::new(handler));
+        self
+    }
+
+    /// Calls the handler for the request's method; fails with `ERR_UNKNOWN_METHOD` if there is
+    /// none.
+    pub fn dispatch(&self, request: RpcRequest) -> RpcResponse {
+        let handler = match self.handlers.get(&request.method) {
+            Some(handler) => handler,
+            None => {
+                let method = redact_str(&request.method);
+                debug_print!("Router::dispatch: no handler for {:?}", method);
+                return RpcResponse::failure(RpcError::new(
+                    ERR_UNKNOWN_METHOD,
+                    format!("no handler for method {:?}", method),
+                ));
+            }
+        };
+        match handler(request.payload) {
+            Ok(payload) => RpcResponse::success(payload),
+            Err(error) => RpcResponse::failure(error),
+        }
+    }
+}
+
+/// Takes a pointer to an external Cobhan Buffer holding a request envelope, dispatches it
+/// through `router` and encodes the response envelope into the provided response buffer.
+///
+/// A request that cannot be read or decoded, or is over the configured JSON limits, gets a
+/// response with the error status, so the return value only reports whether the response was
+/// written: `ERR_NONE`, or the error writing it, e.g. `ERR_BUFFER_TOO_SMALL`.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size of either buffer is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn handle_rpc(request: *const c_char, response: *mut c_char, router: &Router) -> i32 {
+    let reply = match decode_request(request) {
+        Ok(request) => router.dispatch(request),
+        Err(error) => RpcResponse::failure(error),
+    };
+    match serde_json::to_vec(&reply.to_json()) {
+        Ok(json_bytes) => match Charge::reserve(json_bytes.len()) {
+            Ok(_charge) => bytes_to_cbuffer(&json_bytes, response),
+            Err(e) => e,
+        },
+        Err(_) => ERR_JSON_ENCODE_FAILED,
+    }
+}
+
+unsafe fn decode_request(request: *const c_char) -> Result<RpcRequest, RpcError> {
+    let json_bytes = json_payload(request)?;
+    let _charge = Charge::reserve(json_bytes.len())?;
+    check_json_limits(&json_bytes)?;
+    let json = serde_json::from_slice(&json_bytes)
+        .map_err(|e| invalid_request(&format!("JSON decode failed, {}", e)))?;
+    RpcRequest::from_json(json)
+}
@@ -0,0 +1,135 @@
+//! # Call scopes
+//!
+//! Hosts that forget to remove the temporary files of spilled outputs leak them. An exported
+//! function can track every spill it makes in a [`SpillScope`] and hand the scope id to the
+//! host, which ends the call with `cobhan_end_call` once it has read the outputs, discarding
+//! all of them at once:
+//!
+//! ```ignore
+//! #[no_mangle]
+//! pub unsafe extern "C" fn render(
+//!     input: *const c_char,
+//!     output: *mut c_char,
+//!     scope: *mut i64,
+//! ) -> i32 {
+//!     let spills = cobhan::SpillScope::begin();
+//!     *scope = spills.id();
+//!     // ... every spill from this thread until `spills` is dropped belongs to the scope
+//! }
+//! ```
+//!
+//! Dropping the guard stops tracking the thread's spills but keeps those already made, for the
+//! host to read. Scopes nest: a scope begun inside another tracks the thread's spills until it
+//! is dropped, then the outer scope does again.
+
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Mutex;
+
+use crate::transport;
+use crate::{ERR_INVALID_HANDLE, ERR_NONE};
+
+/// Spill references of the scopes not ended yet, by scope id.
+static SCOPES: Mutex<BTreeMap<i64, Vec<String>>> = Mutex::new(BTreeMap::new());
+
+static NEXT_SCOPE_ID: AtomicI64 = AtomicI64::new(1);
+
+thread_local! {
+    /// The id of the scope tracking this thread's spills, or 0. A `Cell<i64>` registers no
+    /// destructor on host threads.
+    static CURRENT_SCOPE: Cell<i64> = const { Cell::new(0) };
+}
+
+/// Tracks the spills of the current thread until dropped, see the
+/// [module documentation](self).
+///
+/// The guard cannot be sent to another thread, as dropping it there would restore that thread's
+/// scope rather than the one it began on.
+#[derive(Debug)]
+pub struct SpillScope {
+    id: i64,
+    outer: i64,
+    thread_bound: PhantomData<*const ()>,
+}
+
+impl SpillScope {
+    /// Starts tracking the current thread's spills in a new scope.
+    pub fn begin() -> Self {
+        let id = NEXT_SCOPE_ID.fetch_add(1, Ordering::Relaxed);
+        SCOPES
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(id, Vec::new());
+        let outer = CURRENT_SCOPE.with(|current| current.replace(id));
+        SpillScope {
+            id,
+            outer,
+            thread_bound: PhantomData,
+        }
+    }
+
+    /// The id the host passes to `cobhan_end_call`.
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+}
+
+impl Drop for SpillScope {
+    /// Stops tracking the thread's spills; the scope's spills stay until `cobhan_end_call`.
+    fn drop(&mut self) {
+        CURRENT_SCOPE.with(|current| current.set(self.outer));
+    }
+}
+
+/// Adds a spill to the scope tracking the current thread's spills, if any.
+pub(crate) fn record_spill(reference: &str) {
+    let id = CURRENT_SCOPE.with(Cell::get);
+    if id == 0 {
+        return;
+    }
+    if let Some(spills) = SCOPES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get_mut(&id)
+    {
+        spills.push(reference.to_owned());
+    }
+}
+
+/// Forgets every scope not ended yet, leaving their spills, for `cobhan_deinit` and forked
+/// children.
+pub(crate) fn forget_scopes() {
+    SCOPES.lock().unwrap_or_else(|e| e.into_inner()).clear();
+}
+
+/// Ends the call whose [`SpillScope`] has id `scope_id`, discarding every spill made in the
+/// scope through the current transport.
+///
+/// Returns `ERR_NONE`, or `ERR_INVALID_HANDLE` if no scope has that id, e.g. it already ended.
+/// Ending a scope whose guard is still alive discards the spills so far and stops tracking.
+#[no_mangle]
+pub extern "C" fn cobhan_end_call(scope_id: i64) -> i32 {
+    let spills = SCOPES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .remove(&scope_id);
+    match spills {
+        Some(spills) => {
+            debug_print!(
+                "cobhan_end_call: discarding {} spills of scope {}",
+                spills.len(),
+                scope_id
+            );
+            for reference in spills {
+                transport::with_current(|t| t.discard(&reference));
+            }
+            ERR_NONE
+        }
+        None => {
+            debug_print!("cobhan_end_call: no scope {}", scope_id);
+            ERR_INVALID_HANDLE
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! # Shutdown and unloading
+//!
+//! Libraries export [`cobhan_shutdown`] for hosts to call before unloading them, so no thread
+//! of the library is left running code that is about to be unmapped and no spill file is left
+//! on disk. It waits for the helper threads started by [`Deadline::run`](crate::Deadline::run)
+//! to finish, then removes the spill files the default transport wrote that still exist.
+//!
+//! The library has no other threads of its own: the worker pool of the parallel conversions
+//! belongs to rayon and outlives it. Other transports keep their own payloads, and the host
+//! removes the spill files of input buffers it wrote itself. A forked child that ran
+//! [`cobhan_after_fork`](crate::cobhan_after_fork) only removes the spill files it wrote
+//! itself, leaving the parent's.
+//!
+//! Hosts that unload and load a library again, such as development servers reloading native
+//! modules, also call [`cobhan_deinit`] before unloading it. A library whose threads still have
+//! thread-local destructors registered is kept mapped by the dynamic loader, so loading it again
+//! finds its process-wide state as it was left; `cobhan_deinit` puts that state back as if
+//! freshly loaded, dropping the callbacks into host code it held. Conversions and spills only
+//! register thread-local destructors on threads that set a last error, catch a panic, run under
+//! `with_thread_spill_transport` or use `with_arena`, so a library used without those is
+//! unmapped when closed.
+
+use std::convert::TryFrom;
+use std::time::Duration;
+
+#[cfg(feature = "json")]
+use crate::dictionary;
+#[cfg(feature = "tempfile")]
+use crate::transport;
+use crate::{capture, clear_last_error, clear_redaction_hook, config, deadline};
+use crate::{reset_spill_transport, ERR_DEADLINE_EXCEEDED, ERR_NONE};
+
+/// Waits up to `timeout_millis` milliseconds for the library's helper threads, then removes
+/// its live spill files, see the [module documentation](self).
+///
+/// Zero or a negative timeout does not wait. Returns `ERR_NONE`, or `ERR_DEADLINE_EXCEEDED` if
+/// helper threads were still running at the timeout; the spill files are removed either way.
+#[no_mangle]
+pub extern "C" fn cobhan_shutdown(timeout_millis: i64) -> i32 {
+    let timeout = Duration::from_millis(u64::try_from(timeout_millis).unwrap_or(0));
+    let finished = deadline::wait_for_helpers(timeout);
+    #[cfg(feature = "tempfile")]
+    {
+        let _removed = transport::remove_live_spills();
+        info_print!("cobhan_shutdown: removed {} spill files", _removed);
+    }
+    if !finished {
+        warn_print!(
+            "cobhan_shutdown: helper threads still running after {:?}",
+            timeout
+        );
+        return ERR_DEADLINE_EXCEEDED;
+    }
+    ERR_NONE
+}
+
+/// Puts the library's process-wide state back as it was when loaded, see the
+/// [module documentation](self).
+///
+/// The configuration is read from the environment again on next use, and the spill transport,
+/// capture, tee sink, redaction hook, key dictionaries, registered formats and call scopes are
+/// dropped, as is the calling thread's last error. Output buffer leases still held keep counting
+/// against the pool.
+#[no_mangle]
+pub extern "C" fn cobhan_deinit() {
+    config::reset();
+    reset_spill_transport();
+    capture::stop_capture();
+    capture::clear_tee_sink();
+    clear_redaction_hook();
+    #[cfg(feature = "json")]
+    dictionary::forget_dictionaries();
+    #[cfg(feature = "formats")]
+    crate::format::forget_formats();
+    crate::scope::forget_scopes();
+    #[cfg(feature = "copy_analysis")]
+    crate::stats::reset_copy_report();
+    #[cfg(feature = "watchdog")]
+    crate::stats::reset_watchdog();
+    clear_last_error();
+}
@@ -0,0 +1,77 @@
+//! # Async-signal safety
+//!
+//! Crash handlers run inside a signal handler, where allocating, taking a lock or reading the
+//! configuration can deadlock or corrupt the heap the crash left behind. The functions below do
+//! none of those, nor write debug output, so they can be called from a signal handler:
+//!
+//! - [`cbuffer_copy_payload`], and [`cobhan_copy_payload`] exported for hosts, which copy a
+//!   buffer into another, e.g. a diagnostic buffer into one the crash reporter preallocated
+//! - [`crc32`](crate::crc32), to check the copied payload
+//! - [`encode_header_length`](crate::encode_header_length) and
+//!   [`decode_header_length`](crate::decode_header_length)
+//! - The `const` methods of [`BufferFlags`](crate::BufferFlags)
+//!
+//! Every other function may allocate or lock, including the borrowing conversions, which read
+//! the configured limits.
+
+use core::ffi::c_char;
+use core::ptr;
+
+use crate::buffer::{read_header_length, read_header_reserved, write_header_length};
+use crate::buffer::{write_header_reserved, BUFFER_HEADER_SIZE};
+use crate::{ERR_BUFFER_TOO_SMALL, ERR_INVALID_LENGTH, ERR_NONE, ERR_NULL_PTR};
+
+/// Copies the Cobhan Buffer at `source` into the one at `destination`, header included, without
+/// allocating or locking; see the [module documentation](self).
+///
+/// The payload is copied as it is, so a spilled payload is copied as its spill reference, which
+/// the spill transport reads back as usual. The buffers may overlap. Fails with
+/// `ERR_BUFFER_TOO_SMALL`, writing nothing, if the payload does not fit `destination`'s
+/// capacity, and with `ERR_INVALID_LENGTH` if `source`'s length field is not representable.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`core::ptr::copy`][] is violated.
+pub unsafe fn cbuffer_copy_payload(source: *const c_char, destination: *mut c_char) -> i32 {
+    if source.is_null() || destination.is_null() {
+        return ERR_NULL_PTR;
+    }
+    let length = read_header_length(source);
+    let payload = source.offset(BUFFER_HEADER_SIZE).cast::<u8>();
+    // Checked like `span_len`, which writes debug output
+    let len = match length.checked_abs() {
+        Some(len) if (payload as usize).checked_add(len as usize).is_some() => len as usize,
+        _ => return ERR_INVALID_LENGTH,
+    };
+    let capacity = read_header_length(destination);
+    if capacity < 0 || len > capacity as usize {
+        return ERR_BUFFER_TOO_SMALL;
+    }
+    let reserved = read_header_reserved(source);
+    ptr::copy(
+        payload,
+        destination.offset(BUFFER_HEADER_SIZE).cast::<u8>(),
+        len,
+    );
+    write_header_length(destination, length);
+    write_header_reserved(destination, reserved);
+    ERR_NONE
+}
+
+/// Copies the Cobhan Buffer at `source` into the one at `destination` like
+/// [`cbuffer_copy_payload`], for hosts' crash handlers.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`core::ptr::copy`][] is violated.
+#[no_mangle]
+pub unsafe extern "C" fn cobhan_copy_payload(
+    source: *const c_char,
+    destination: *mut c_char,
+) -> i32 {
+    cbuffer_copy_payload(source, destination)
+}
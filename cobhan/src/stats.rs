@@ -0,0 +1,294 @@
+//! # Copy analysis
+//!
+//! With the `copy_analysis` feature, the copying conversions ([`cbuffer_to_vector`],
+//! [`cbuffer_to_string`] and [`cbuffer_to_cstring`]) record their call site, and
+//! [`copy_report`] tells which sites copy the most bytes and how many of those bytes were
+//! inline payloads that a borrowing conversion could have used in place:
+//!
+//! ```text
+//! for site in cobhan::stats::copy_report() {
+//!     println!("{}:{} {} {} of {} copied bytes borrowable", site.file, site.line,
+//!         site.conversion, site.borrowable_bytes, site.copied_bytes);
+//! }
+//! ```
+//!
+//! Spilled payloads have to be read into owned memory anyway, so they count as copied only.
+//! The analysis takes a lock per conversion and is meant for profiling builds.
+//!
+//! [`cbuffer_to_vector`]: crate::cbuffer_to_vector
+//! [`cbuffer_to_string`]: crate::cbuffer_to_string
+//! [`cbuffer_to_cstring`]: crate::cbuffer_to_cstring
+//!
+//! # Slow-call watchdog
+//!
+//! With the `watchdog` feature, conversions reading or writing a whole payload and calls run
+//! under [`ffi_guard`](crate::ffi_guard) are timed once [`set_slow_call_threshold`] is given a
+//! threshold. Those taking at least that long are kept as a [`SlowCall`], telling the payload
+//! size and whether the spill transport was used, and passed to the callback set with
+//! [`set_slow_call_callback`]:
+//!
+//! ```text
+//! cobhan::stats::set_slow_call_threshold(Some(Duration::from_millis(50)));
+//! cobhan::stats::set_slow_call_callback(Some(Arc::new(|call| {
+//!     log::warn!("{} took {:?} for {} bytes (spilled: {})", call.operation, call.duration,
+//!         call.bytes, call.spilled);
+//! })));
+//! ```
+//!
+//! Conversions that fail are not recorded. Without a threshold, the default, nothing is timed.
+
+#[cfg(feature = "watchdog")]
+use std::cell::Cell;
+#[cfg(feature = "copy_analysis")]
+use std::collections::HashMap;
+#[cfg(feature = "watchdog")]
+use std::collections::VecDeque;
+#[cfg(feature = "copy_analysis")]
+use std::panic::Location;
+#[cfg(feature = "watchdog")]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(any(feature = "copy_analysis", feature = "watchdog"))]
+use std::sync::Mutex;
+#[cfg(feature = "watchdog")]
+use std::sync::{Arc, RwLock};
+#[cfg(feature = "watchdog")]
+use std::time::{Duration, Instant};
+
+use core::ffi::c_char;
+
+#[cfg(feature = "copy_analysis")]
+use crate::buffer::read_header;
+
+/// The copies made at one call site of one conversion.
+#[cfg(feature = "copy_analysis")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CopySite {
+    pub file: &'static str,
+    pub line: u32,
+    pub column: u32,
+    /// Name of the conversion called.
+    pub conversion: &'static str,
+    pub calls: u64,
+    /// Payload bytes copied into owned memory.
+    pub copied_bytes: u64,
+    /// Copied bytes that were inline, so could have been borrowed in place.
+    pub borrowable_bytes: u64,
+}
+
+#[cfg(feature = "copy_analysis")]
+type SiteKey = (&'static str, u32, u32, &'static str);
+
+#[cfg(feature = "copy_analysis")]
+static SITES: Mutex<Option<HashMap<SiteKey, CopySite>>> = Mutex::new(None);
+
+/// The call sites seen since the last [`reset_copy_report`], most copied bytes first.
+#[cfg(feature = "copy_analysis")]
+pub fn copy_report() -> Vec<CopySite> {
+    let sites = SITES.lock().unwrap_or_else(|e| e.into_inner());
+    let mut report: Vec<CopySite> = sites.iter().flat_map(|s| s.values().cloned()).collect();
+    report.sort_by(|a, b| {
+        (b.copied_bytes, a.file, a.line, a.column).cmp(&(a.copied_bytes, b.file, b.line, b.column))
+    });
+    report
+}
+
+/// Forgets every call site.
+#[cfg(feature = "copy_analysis")]
+pub fn reset_copy_report() {
+    SITES.lock().unwrap_or_else(|e| e.into_inner()).take();
+}
+
+/// Records that `conversion`, called from the caller's call site, copied the `len` byte payload
+/// of `buffer`.
+#[cfg(feature = "copy_analysis")]
+#[track_caller]
+pub(crate) unsafe fn record_copy(conversion: &'static str, buffer: *const c_char, len: usize) {
+    let location = Location::caller();
+    let (_, spilled) = read_header(buffer);
+    let key = (
+        location.file(),
+        location.line(),
+        location.column(),
+        conversion,
+    );
+    let mut sites = SITES.lock().unwrap_or_else(|e| e.into_inner());
+    let site = sites
+        .get_or_insert_with(HashMap::new)
+        .entry(key)
+        .or_insert_with(|| CopySite {
+            file: location.file(),
+            line: location.line(),
+            column: location.column(),
+            conversion,
+            calls: 0,
+            copied_bytes: 0,
+            borrowable_bytes: 0,
+        });
+    site.calls += 1;
+    site.copied_bytes += len as u64;
+    if !spilled {
+        site.borrowable_bytes += len as u64;
+    }
+}
+
+// Without `copy_analysis` nothing is recorded
+
+#[cfg(not(feature = "copy_analysis"))]
+#[inline(always)]
+pub(crate) unsafe fn record_copy(_conversion: &'static str, _buffer: *const c_char, _len: usize) {}
+
+/// A timed call that took at least the [slow-call threshold](set_slow_call_threshold).
+#[cfg(feature = "watchdog")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SlowCall {
+    /// Name of the conversion, or `"ffi_guard"` for a guarded call.
+    pub operation: &'static str,
+    pub duration: Duration,
+    /// Payload bytes converted, 0 for a guarded call.
+    pub bytes: usize,
+    /// Whether the spill transport was used, by the guarded call for the conversions it made.
+    pub spilled: bool,
+}
+
+/// Called with each [`SlowCall`] as it is recorded.
+#[cfg(feature = "watchdog")]
+pub type SlowCallCallback = Arc<dyn Fn(&SlowCall) + Send + Sync>;
+
+/// Most slow calls kept by [`slow_calls`]; older ones are dropped first.
+#[cfg(feature = "watchdog")]
+pub const SLOW_CALLS_KEPT: usize = 256;
+
+#[cfg(feature = "watchdog")]
+const NO_THRESHOLD: u64 = u64::MAX;
+
+#[cfg(feature = "watchdog")]
+static THRESHOLD_NANOS: AtomicU64 = AtomicU64::new(NO_THRESHOLD);
+#[cfg(feature = "watchdog")]
+static SLOW_CALLS: Mutex<VecDeque<SlowCall>> = Mutex::new(VecDeque::new());
+#[cfg(feature = "watchdog")]
+static CALLBACK: RwLock<Option<SlowCallCallback>> = RwLock::new(None);
+
+#[cfg(feature = "watchdog")]
+thread_local! {
+    static TRANSPORT_USES: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Sets how long a timed call takes before it is recorded, or stops timing calls with `None`.
+#[cfg(feature = "watchdog")]
+pub fn set_slow_call_threshold(threshold: Option<Duration>) {
+    let nanos = threshold.map_or(NO_THRESHOLD, |threshold| {
+        threshold.as_nanos().min(u128::from(NO_THRESHOLD - 1)) as u64
+    });
+    THRESHOLD_NANOS.store(nanos, Ordering::Relaxed);
+}
+
+/// The threshold set with [`set_slow_call_threshold`].
+#[cfg(feature = "watchdog")]
+pub fn slow_call_threshold() -> Option<Duration> {
+    match THRESHOLD_NANOS.load(Ordering::Relaxed) {
+        NO_THRESHOLD => None,
+        nanos => Some(Duration::from_nanos(nanos)),
+    }
+}
+
+/// Sets the callback slow calls are passed to, or removes it with `None`.
+///
+/// The callback runs on the thread that made the call, after it returns.
+#[cfg(feature = "watchdog")]
+pub fn set_slow_call_callback(callback: Option<SlowCallCallback>) {
+    *CALLBACK.write().unwrap_or_else(|e| e.into_inner()) = callback;
+}
+
+/// The slow calls recorded since the last [`reset_slow_calls`], oldest first.
+#[cfg(feature = "watchdog")]
+pub fn slow_calls() -> Vec<SlowCall> {
+    let calls = SLOW_CALLS.lock().unwrap_or_else(|e| e.into_inner());
+    calls.iter().cloned().collect()
+}
+
+/// Forgets every slow call.
+#[cfg(feature = "watchdog")]
+pub fn reset_slow_calls() {
+    SLOW_CALLS.lock().unwrap_or_else(|e| e.into_inner()).clear();
+}
+
+/// Notes that this thread is using the spill transport.
+#[cfg(feature = "watchdog")]
+pub(crate) fn note_transport_use() {
+    TRANSPORT_USES.with(|uses| uses.set(uses.get() + 1));
+}
+
+/// Times a call for the watchdog, if a threshold is set.
+#[cfg(feature = "watchdog")]
+pub(crate) struct CallTimer {
+    start: Option<(Instant, u64)>,
+}
+
+#[cfg(feature = "watchdog")]
+impl CallTimer {
+    pub(crate) fn start() -> Self {
+        let start = match THRESHOLD_NANOS.load(Ordering::Relaxed) {
+            NO_THRESHOLD => None,
+            _ => Some((Instant::now(), TRANSPORT_USES.with(Cell::get))),
+        };
+        CallTimer { start }
+    }
+
+    /// Records the call as `operation` on `bytes` bytes if it took at least the threshold.
+    pub(crate) fn finish(self, operation: &'static str, bytes: usize) {
+        let (start, uses) = match self.start {
+            Some(start) => start,
+            None => return,
+        };
+        let duration = start.elapsed();
+        let threshold = THRESHOLD_NANOS.load(Ordering::Relaxed);
+        if threshold == NO_THRESHOLD || duration < Duration::from_nanos(threshold) {
+            return;
+        }
+        let call = SlowCall {
+            operation,
+            duration,
+            bytes,
+            spilled: TRANSPORT_USES.with(Cell::get) != uses,
+        };
+        {
+            let mut calls = SLOW_CALLS.lock().unwrap_or_else(|e| e.into_inner());
+            if calls.len() == SLOW_CALLS_KEPT {
+                calls.pop_front();
+            }
+            calls.push_back(call.clone());
+        }
+        let callback = CALLBACK.read().unwrap_or_else(|e| e.into_inner()).clone();
+        if let Some(callback) = callback {
+            callback(&call);
+        }
+    }
+}
+
+/// Forgets the slow calls and stops timing calls.
+#[cfg(feature = "watchdog")]
+pub(crate) fn reset_watchdog() {
+    set_slow_call_threshold(None);
+    set_slow_call_callback(None);
+    reset_slow_calls();
+}
+
+// Without `watchdog` nothing is timed
+
+#[cfg(all(feature = "std", not(feature = "watchdog")))]
+#[inline(always)]
+pub(crate) fn note_transport_use() {}
+
+#[cfg(not(feature = "watchdog"))]
+pub(crate) struct CallTimer;
+
+#[cfg(not(feature = "watchdog"))]
+impl CallTimer {
+    #[inline(always)]
+    pub(crate) fn start() -> Self {
+        CallTimer
+    }
+
+    #[inline(always)]
+    pub(crate) fn finish(self, _operation: &'static str, _bytes: usize) {}
+}
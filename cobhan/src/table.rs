@@ -0,0 +1,79 @@
+//! # CSV buffers
+//!
+//! With the `csv` feature, tabular payloads can be passed as CSV with a header row and decoded
+//! straight into records with serde, so a host's extract is parsed once on the Rust side:
+//!
+//! ```ignore
+//! #[derive(Deserialize, Serialize)]
+//! struct Sale { region: String, units: u32 }
+//!
+//! let sales: Vec<Sale> = cobhan::cbuffer_to_csv_records(input)?;
+//! cobhan::records_to_cbuffer(totals.iter(), output);
+//! ```
+//!
+//! Fields are matched to the header row by name, and large extracts spill to a temp file like
+//! any other payload.
+
+use std::os::raw::c_char;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::memory::Charge;
+use crate::{bytes_to_cbuffer, cbuffer_payload, ERR_CSV_DECODE_FAILED, ERR_CSV_ENCODE_FAILED};
+
+/// Takes a pointer to an external Cobhan Buffer and fallibly decodes it as CSV with a header
+/// row, one record per row.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn cbuffer_to_csv_records<T: DeserializeOwned>(
+    buffer: *const c_char,
+) -> Result<Vec<T>, i32> {
+    let csv_bytes = cbuffer_payload(buffer)?;
+    let _charge = Charge::reserve(csv_bytes.len())?;
+    csv::Reader::from_reader(&csv_bytes[..])
+        .deserialize()
+        .collect::<Result<_, _>>()
+        .map_err(|_e| {
+            debug_print!("cbuffer_to_csv_records: CSV decode failed {}", _e);
+            ERR_CSV_DECODE_FAILED
+        })
+}
+
+/// Takes records and encodes them into a provided external Cobhan Buffer as CSV, with a header
+/// row taken from the field names of the first record.
+///
+/// No rows, and no header, are written for no records.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn records_to_cbuffer<'a, T: Serialize + 'a>(
+    records: impl Iterator<Item = &'a T>,
+    buffer: *mut c_char,
+) -> i32 {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for record in records {
+        if let Err(_e) = writer.serialize(record) {
+            debug_print!("records_to_cbuffer: CSV encode failed {}", _e);
+            return ERR_CSV_ENCODE_FAILED;
+        }
+    }
+    let csv_bytes = match writer.into_inner() {
+        Ok(csv_bytes) => csv_bytes,
+        Err(_e) => {
+            debug_print!("records_to_cbuffer: CSV encode failed {}", _e);
+            return ERR_CSV_ENCODE_FAILED;
+        }
+    };
+    match Charge::reserve(csv_bytes.len()) {
+        Ok(_charge) => bytes_to_cbuffer(&csv_bytes, buffer),
+        Err(e) => e,
+    }
+}
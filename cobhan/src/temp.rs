@@ -0,0 +1,464 @@
+//! # Spilled payloads
+//!
+//! Hands payloads that do not fit the caller's buffer to the current
+//! [`SpillTransport`](crate::SpillTransport), and reads them back.
+
+#[cfg(feature = "std")]
+use alloc::boxed::Box;
+use alloc::string::String;
+#[cfg(feature = "tempfile")]
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+#[cfg(feature = "tempfile")]
+use core::cell::Cell;
+use core::ffi::c_char;
+use core::slice::from_raw_parts;
+use core::str;
+#[cfg(feature = "tempfile")]
+use core::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "tempfile")]
+use std::collections::hash_map::RandomState;
+#[cfg(feature = "tempfile")]
+use std::collections::BTreeMap;
+#[cfg(feature = "tempfile")]
+use std::fs::{self, File, OpenOptions};
+#[cfg(feature = "tempfile")]
+use std::hash::{BuildHasher, Hasher};
+#[cfg(feature = "std")]
+use std::io::Read;
+#[cfg(feature = "tempfile")]
+use std::io::{self, Seek, SeekFrom, Write};
+#[cfg(all(feature = "tempfile", unix))]
+use std::os::unix::fs::OpenOptionsExt;
+#[cfg(feature = "tempfile")]
+use std::path::Path;
+#[cfg(feature = "tempfile")]
+use std::sync::Mutex;
+
+#[cfg(not(feature = "std"))]
+use crate::buffer::check_max_buffer_len;
+#[cfg(feature = "std")]
+use crate::buffer::read_failed;
+use crate::buffer::{mark_payload, read_header_length, write_header_length, write_payload};
+use crate::flags::{BufferFlags, BufferFormat};
+#[cfg(feature = "std")]
+use crate::memory::read_charged;
+use crate::memory::Charge;
+#[cfg(feature = "std")]
+use crate::scope;
+#[cfg(feature = "tempfile")]
+use crate::ERR_WRITE_TEMP_FILE_FAILED;
+use crate::{config, transport};
+use crate::{ERR_BUFFER_TOO_SMALL, ERR_INVALID_UTF8, ERR_NONE, ERR_READ_TEMP_FILE_FAILED};
+
+/// Gets a tempfile data for a payload and interprets it as a `String`, with the charge for it.
+pub(crate) unsafe fn temp_to_string(
+    payload: *const u8,
+    length: usize,
+) -> Result<(String, Charge), i32> {
+    let (bytes, charge) = temp_to_vector(payload, length)?;
+    let string = String::from_utf8(bytes).map_err(|_| {
+        debug_print!("temp_to_string: temp file is invalid utf-8");
+        ERR_READ_TEMP_FILE_FAILED
+    })?;
+    Ok((string, charge))
+}
+
+/// Gets a tempfile data for a payload and interprets it as a `Vec<u8>`, with the charge for it.
+///
+/// The payload is charged as it is read, so the caller keeps the charge while it holds the bytes.
+pub(crate) unsafe fn temp_to_vector(
+    payload: *const u8,
+    length: usize,
+) -> Result<(Vec<u8>, Charge), i32> {
+    #[cfg(feature = "std")]
+    {
+        let mut charge = Charge::empty();
+        let bytes = read_charged(open_spill(payload, length)?, &mut charge, read_failed)?;
+        Ok((bytes, charge))
+    }
+    // Without std the transport hands the payload back whole
+    #[cfg(not(feature = "std"))]
+    {
+        let bytes = read_spill(payload, length)?;
+        check_max_buffer_len(bytes.len())?;
+        let charge = Charge::reserve(bytes.len())?;
+        Ok((bytes, charge))
+    }
+}
+
+/// Reads back the spilled payload whose reference is at `payload`.
+pub(crate) unsafe fn read_spill(payload: *const u8, length: usize) -> Result<Vec<u8>, i32> {
+    let file_name = str::from_utf8(from_raw_parts(payload, length)).map_err(|_| {
+        debug_print!(
+            "read_spill: temp file name is invalid utf-8 string (length = {})",
+            length
+        );
+        ERR_INVALID_UTF8
+    })?;
+
+    transport::with_current(|t| t.read(file_name))
+}
+
+/// Opens the spilled payload whose reference is at `payload` to be read in pieces.
+#[cfg(feature = "std")]
+pub(crate) unsafe fn open_spill(
+    payload: *const u8,
+    length: usize,
+) -> Result<Box<dyn Read + Send>, i32> {
+    let file_name = str::from_utf8(from_raw_parts(payload, length)).map_err(|_| {
+        debug_print!(
+            "open_spill: temp file name is invalid utf-8 string (length = {})",
+            length
+        );
+        ERR_INVALID_UTF8
+    })?;
+
+    transport::with_current(|t| t.open(file_name))
+}
+
+/// Sets a tempfile data for a payload and writes bytes to it.
+pub(crate) unsafe fn bytes_to_temp(bytes: &[u8], buffer: *mut c_char) -> i32 {
+    if !config::spill_allowed() {
+        warn_print!("bytes_to_temp: spilling is disabled by the spill policy");
+        return ERR_BUFFER_TOO_SMALL;
+    }
+
+    // TODO: eventually replace this pattern with if-let once that is stable -jsenkpiel
+    let tmp_file_path = match transport::with_current(|t| t.spill(bytes)) {
+        Ok(t) => t,
+        Err(r) => return r,
+    };
+    debug_print!(
+        "bytes_to_temp: spilled {} bytes to {}",
+        bytes.len(),
+        tmp_file_path
+    );
+
+    let result = write_spill_reference(tmp_file_path.as_bytes(), buffer);
+    if result != ERR_NONE {
+        debug_print!(
+            "bytes_to_temp: failed to store temp path {} in buffer",
+            tmp_file_path
+        );
+        transport::with_current(|t| t.discard(&tmp_file_path));
+        return result;
+    }
+    #[cfg(feature = "std")]
+    scope::record_spill(&tmp_file_path);
+    result
+}
+
+/// Writes a spill reference into `buffer` and marks the buffer as spilled.
+pub(crate) unsafe fn write_spill_reference(reference: &[u8], buffer: *mut c_char) -> i32 {
+    let buffer_cap = read_header_length(buffer);
+    let reference_len = reference.len();
+
+    //NOTE: We explicitly test this so we don't recursively attempt to create temp files with write_payload()
+    if buffer_cap < 0 || reference_len > buffer_cap as usize {
+        //Temp file path won't fit in output buffer, we're out of luck
+        debug_print!(
+            "write_spill_reference: reference of {} bytes is larger than buffer capacity {}",
+            reference_len,
+            buffer_cap
+        );
+        return ERR_BUFFER_TOO_SMALL;
+    }
+
+    let result = write_payload(reference, buffer);
+    if result != ERR_NONE {
+        return result;
+    }
+
+    // write_payload checked that the reference fits, so its length fits the i32 field. Format
+    // v2 keeps the positive length and flags the reference instead
+    match config::buffer_format() {
+        BufferFormat::V1 => write_header_length(buffer, 0 - reference_len as i32),
+        BufferFormat::V2 => mark_payload(buffer, BufferFlags::TEMP_FILE),
+    }
+
+    result
+}
+
+/// Names tried for a new temporary file before giving up.
+#[cfg(feature = "tempfile")]
+const NEW_FILE_ATTEMPTS: usize = 64;
+
+// Creates a file readable by the current user only, failing if it exists.
+#[cfg(feature = "tempfile")]
+fn create_new(path: &Path) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    options.open(path)
+}
+
+// Creates a new named temporary file and returns it with its file name.
+//
+// The random part of the name comes from `RandomState` rather than a thread-local generator
+// such as the `tempfile` crate's: seeding one looks up the current thread, which registers a
+// destructor on host threads that would run after the library is unloaded.
+#[cfg(feature = "tempfile")]
+fn create_temp_file() -> Result<(String, File), i32> {
+    let dir = transport::temp_dir();
+    for _ in 0..NEW_FILE_ATTEMPTS {
+        let name = format!(".tmp{:016x}", RandomState::new().build_hasher().finish());
+        let path = dir.join(name);
+        let file = match create_new(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(_e) => {
+                error_print!("create_temp_file: failed to create {:?}: {}", path, _e);
+                return Err(ERR_WRITE_TEMP_FILE_FAILED);
+            }
+        };
+        return match path.into_os_string().into_string() {
+            Ok(file_name) => Ok((file_name, file)),
+            Err(path) => {
+                let _ = fs::remove_file(path);
+                Err(ERR_WRITE_TEMP_FILE_FAILED)
+            }
+        };
+    }
+    error_print!("create_temp_file: no free file name in {:?}", dir);
+    Err(ERR_WRITE_TEMP_FILE_FAILED)
+}
+
+// Writes to a new named temporary file and returns the file name.
+#[cfg(feature = "tempfile")]
+pub(crate) fn write_new_file(bytes: &[u8]) -> Result<String, i32> {
+    let (file_name, mut file) = create_temp_file()?;
+    if let Err(_e) = file.write_all(bytes) {
+        error_print!("write_new_file: failed to write {}: {}", file_name, _e);
+        let _ = fs::remove_file(file_name);
+        return Err(ERR_WRITE_TEMP_FILE_FAILED);
+    }
+    Ok(file_name)
+}
+
+/// A thread's spill file, rewritten by each of its spills once the host released the previous
+/// one, see [`CobhanConfig::reuse_spill_files`](crate::CobhanConfig::reuse_spill_files).
+#[cfg(feature = "tempfile")]
+struct ScratchFile {
+    path: String,
+    file: Arc<File>,
+    /// Whether the file holds, or is being rewritten with, a spill not released yet.
+    pending: bool,
+    /// Streams opened on the file with [`open_spill_file`] and not dropped yet.
+    readers: usize,
+}
+
+/// Spill files of the threads that spilled with `reuse_spill_files` on, by `SCRATCH_SLOT`.
+#[cfg(feature = "tempfile")]
+static SCRATCH_FILES: Mutex<BTreeMap<u64, ScratchFile>> = Mutex::new(BTreeMap::new());
+
+#[cfg(feature = "tempfile")]
+static NEXT_SCRATCH_SLOT: AtomicU64 = AtomicU64::new(1);
+
+#[cfg(feature = "tempfile")]
+thread_local! {
+    /// This thread's key in `SCRATCH_FILES`, or 0 before its first spill. A `Cell<u64>`
+    /// registers no destructor on host threads.
+    static SCRATCH_SLOT: Cell<u64> = const { Cell::new(0) };
+}
+
+// Writes to the current thread's spill file and returns the file name, or `None` if the file is
+// in use.
+//
+// The file is truncated and rewritten if the host released its last spill and no stream is
+// reading it. While it is in use the caller writes the payload to a new file instead, and a
+// file the host removed is replaced.
+#[cfg(feature = "tempfile")]
+pub(crate) fn write_scratch_file(bytes: &[u8]) -> Result<Option<String>, i32> {
+    let slot = SCRATCH_SLOT.with(|slot| {
+        if slot.get() == 0 {
+            slot.set(NEXT_SCRATCH_SLOT.fetch_add(1, Ordering::Relaxed));
+        }
+        slot.get()
+    });
+    let mut files = SCRATCH_FILES.lock().unwrap_or_else(|e| e.into_inner());
+    let (file_name, file) = match files.get_mut(&slot) {
+        Some(scratch) if scratch.pending || scratch.readers > 0 => {
+            debug_print!("write_scratch_file: {} is still in use", scratch.path);
+            return Ok(None);
+        }
+        Some(scratch) if fs::metadata(&scratch.path).is_ok() => {
+            scratch.pending = true;
+            (scratch.path.clone(), scratch.file.clone())
+        }
+        _ => {
+            let (file_name, file) = create_temp_file()?;
+            let file = Arc::new(file);
+            let scratch = ScratchFile {
+                path: file_name.clone(),
+                file: file.clone(),
+                pending: true,
+                readers: 0,
+            };
+            files.insert(slot, scratch);
+            (file_name, file)
+        }
+    };
+    // The pending file is this thread's alone, so it is rewritten without holding the lock
+    drop(files);
+
+    let mut writer = &*file;
+    let rewritten = writer
+        .set_len(0)
+        .and_then(|_| writer.seek(SeekFrom::Start(0)))
+        .and_then(|_| writer.write_all(bytes));
+    if let Err(_e) = rewritten {
+        error_print!(
+            "write_scratch_file: failed to rewrite {}: {}",
+            file_name,
+            _e
+        );
+        release_scratch_file(&file_name);
+        return Err(ERR_WRITE_TEMP_FILE_FAILED);
+    }
+    Ok(Some(file_name))
+}
+
+/// Marks the spill in the thread spill file `reference` as released, returning `false` if
+/// `reference` is not a thread spill file.
+#[cfg(feature = "tempfile")]
+pub(crate) fn release_scratch_file(reference: &str) -> bool {
+    let mut files = SCRATCH_FILES.lock().unwrap_or_else(|e| e.into_inner());
+    match files.values_mut().find(|scratch| scratch.path == reference) {
+        Some(scratch) => {
+            scratch.pending = false;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Removes the thread spill files, returning how many still existed.
+#[cfg(feature = "tempfile")]
+pub(crate) fn remove_scratch_files() -> usize {
+    let files = std::mem::take(&mut *SCRATCH_FILES.lock().unwrap_or_else(|e| e.into_inner()));
+    files
+        .values()
+        .filter(|scratch| fs::remove_file(&scratch.path).is_ok())
+        .count()
+}
+
+/// Forgets the thread spill files without removing them, for a forked child, whose parent keeps
+/// rewriting them. The calling thread, the only one left in the child, takes a new slot.
+#[cfg(feature = "tempfile")]
+pub(crate) fn forget_scratch_files() {
+    SCRATCH_FILES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clear();
+    NEXT_SCRATCH_SLOT.store(1, Ordering::Relaxed);
+    SCRATCH_SLOT.with(|slot| slot.set(0));
+}
+
+/// A stream over a spill file. Thread spill files are not rewritten while one is open.
+#[cfg(feature = "tempfile")]
+struct SpillFileReader {
+    file: File,
+    scratch: Option<String>,
+}
+
+#[cfg(feature = "tempfile")]
+impl Read for SpillFileReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+#[cfg(feature = "tempfile")]
+impl Drop for SpillFileReader {
+    fn drop(&mut self) {
+        if let Some(path) = &self.scratch {
+            let mut files = SCRATCH_FILES.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(scratch) = files.values_mut().find(|scratch| &scratch.path == path) {
+                scratch.readers -= 1;
+            }
+        }
+    }
+}
+
+/// Opens the spill file `reference` to be read in pieces.
+#[cfg(feature = "tempfile")]
+pub(crate) fn open_spill_file(reference: &str) -> io::Result<Box<dyn Read + Send>> {
+    let mut files = SCRATCH_FILES.lock().unwrap_or_else(|e| e.into_inner());
+    let scratch = files.values_mut().find(|scratch| scratch.path == reference);
+    let file = File::open(reference)?;
+    let scratch = scratch.map(|scratch| {
+        scratch.readers += 1;
+        scratch.path.clone()
+    });
+    Ok(Box::new(SpillFileReader { file, scratch }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::read_header;
+
+    fn buffer(capacity: i32) -> [u64; 4] {
+        let mut words = [0u64; 4];
+        unsafe { write_header_length(words.as_mut_ptr().cast(), capacity) };
+        words
+    }
+
+    #[test]
+    fn v1_spill_references_have_negative_lengths() {
+        let mut words = buffer(24);
+        let ptr = words.as_mut_ptr().cast::<c_char>();
+        unsafe {
+            assert_eq!(write_spill_reference(b"/tmp/spill", ptr), ERR_NONE);
+            assert_eq!(read_header(ptr), (-10, true));
+        }
+    }
+
+    #[test]
+    fn references_that_do_not_fit_are_not_written() {
+        let mut words = buffer(4);
+        let ptr = words.as_mut_ptr().cast::<c_char>();
+        unsafe {
+            assert_eq!(
+                write_spill_reference(b"/tmp/spill", ptr),
+                ERR_BUFFER_TOO_SMALL
+            );
+            assert_eq!(read_header(ptr), (4, false));
+        }
+    }
+
+    #[test]
+    fn references_must_be_utf8() {
+        let reference = [b'/', 0xff];
+        unsafe {
+            assert_eq!(
+                read_spill(reference.as_ptr(), reference.len()),
+                Err(ERR_INVALID_UTF8)
+            );
+            assert_eq!(
+                temp_to_string(reference.as_ptr(), reference.len()).map(|(string, _)| string),
+                Err(ERR_INVALID_UTF8)
+            );
+        }
+    }
+
+    #[cfg(feature = "tempfile")]
+    #[test]
+    fn new_files_hold_the_payload() {
+        let first = write_new_file(b"spilled").unwrap();
+        let second = write_new_file(b"").unwrap();
+        assert_ne!(first, second);
+        assert_eq!(fs::read(&first).unwrap(), b"spilled");
+        assert_eq!(fs::read(&second).unwrap(), b"");
+        unsafe {
+            assert_eq!(
+                temp_to_vector(first.as_ptr(), first.len()).map(|(bytes, _)| bytes),
+                Ok(b"spilled".to_vec())
+            );
+        }
+        fs::remove_file(first).unwrap();
+        fs::remove_file(second).unwrap();
+    }
+}
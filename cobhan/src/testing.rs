@@ -0,0 +1,316 @@
+//! # Test helpers
+//!
+//! Owned Cobhan Buffers for unit-testing exported functions from Rust, without hand-packing
+//! headers in every test file. Enable with the `testing` feature, typically from
+//! `[dev-dependencies]`.
+//!
+//! ```ignore
+//! let input = OwnedCBuffer::from_bytes(b"Initial value");
+//! let mut output = OwnedCBuffer::with_capacity(64);
+//! assert_eq!(unsafe { toUpper(input.as_ptr(), output.as_mut_ptr()) }, cobhan::ERR_NONE);
+//! assert_eq!(output.to_string().unwrap(), "INITIAL VALUE");
+//! ```
+//!
+//! [`with_mock_transport`] swaps the temp file spill path for an in-memory [`MockTransport`] on
+//! the current thread, so tests can assert what would have spilled without touching disk:
+//!
+//! ```ignore
+//! with_mock_transport(|mock| {
+//!     let mut output = OwnedCBuffer::with_capacity(64);
+//!     assert_eq!(unsafe { generateRandom(output.as_mut_ptr()) }, cobhan::ERR_NONE);
+//!     assert_eq!(mock.spills().len(), 1);
+//!     assert_eq!(output.to_vec().unwrap().len(), mock.spills()[0].len);
+//! });
+//! ```
+//!
+//! Settings are process-wide, so tests that change them take turns with [`configured`]:
+//!
+//! ```ignore
+//! let _guard = configured(CobhanConfig {
+//!     max_buffer_len: Some(4),
+//!     ..Default::default()
+//! });
+//! ```
+
+use std::collections::HashMap;
+use std::os::raw::c_char;
+use std::path::PathBuf;
+use std::str;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use serde_json::Value;
+
+use crate::allocation::aligned_buffer;
+use crate::buffer::{read_header, read_header_flags};
+use crate::config::{self, CobhanConfig};
+use crate::transport::{self, SpillTransport};
+use crate::BufferFlags;
+use crate::{
+    cbuffer_to_hashmap_json, cbuffer_to_string, cbuffer_to_vector, decode_header_length,
+    encode_header_length, BUFFER_HEADER_SIZE, ERR_BUFFER_TOO_LARGE, ERR_JSON_ENCODE_FAILED,
+    ERR_READ_TEMP_FILE_FAILED, SIZEOF_INT32,
+};
+
+const HEADER_SIZE: usize = BUFFER_HEADER_SIZE as usize;
+
+/// A heap-allocated Cobhan Buffer owned by the test.
+///
+/// The allocation is 8 byte aligned, as host allocators guarantee.
+pub struct OwnedCBuffer {
+    words: Vec<u64>,
+    capacity: usize,
+}
+
+impl OwnedCBuffer {
+    /// Allocates an output buffer with room for `capacity` payload bytes.
+    ///
+    /// Panics if `capacity` does not fit the i32 length field.
+    pub fn with_capacity(capacity: usize) -> Self {
+        assert!(
+            capacity <= i32::MAX as usize,
+            "capacity {} does not fit a Cobhan Buffer header",
+            capacity
+        );
+        OwnedCBuffer {
+            words: aligned_buffer(&[], capacity),
+            capacity,
+        }
+    }
+
+    /// Allocates an input buffer holding a copy of `bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut buffer = OwnedCBuffer::with_capacity(bytes.len());
+        buffer.bytes_mut()[HEADER_SIZE..].copy_from_slice(bytes);
+        buffer
+    }
+
+    /// Allocates an input buffer whose payload was spilled under `reference`.
+    ///
+    /// The length field is the negated reference length, as a host writes it after storing a
+    /// large payload in a temp file.
+    pub fn spilled(reference: &str) -> Self {
+        let mut buffer = OwnedCBuffer::from_bytes(reference.as_bytes());
+        buffer.set_length_field(-(reference.len() as i32));
+        buffer
+    }
+
+    /// Allocates an input buffer holding a JSON encoding of `value`.
+    pub fn from_json(value: &Value) -> Result<Self, i32> {
+        serde_json::to_vec(value)
+            .map(|bytes| OwnedCBuffer::from_bytes(&bytes))
+            .map_err(|_| ERR_JSON_ENCODE_FAILED)
+    }
+
+    fn bytes(&self) -> &[u8] {
+        // SAFETY: `words` holds at least HEADER_SIZE + capacity bytes
+        unsafe {
+            std::slice::from_raw_parts(
+                self.words.as_ptr() as *const u8,
+                HEADER_SIZE + self.capacity,
+            )
+        }
+    }
+
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        // SAFETY: `words` holds at least HEADER_SIZE + capacity bytes
+        unsafe {
+            std::slice::from_raw_parts_mut(
+                self.words.as_mut_ptr() as *mut u8,
+                HEADER_SIZE + self.capacity,
+            )
+        }
+    }
+
+    fn set_length_field(&mut self, length: i32) {
+        self.bytes_mut()[..SIZEOF_INT32 as usize].copy_from_slice(&encode_header_length(length));
+    }
+
+    /// Payload capacity the buffer was allocated with.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The raw i32 length field: payload length, or negated temp file path length.
+    pub fn length_field(&self) -> i32 {
+        let b = self.bytes();
+        decode_header_length([b[0], b[1], b[2], b[3]])
+    }
+
+    /// The reserved field, read as format v2 flags.
+    pub fn flags(&self) -> BufferFlags {
+        // SAFETY: the buffer has a full header
+        unsafe { read_header_flags(self.as_ptr()) }
+    }
+
+    /// Restores the length field to the full capacity so the buffer can be reused as an output.
+    pub fn reset(&mut self) {
+        self.set_length_field(self.capacity as i32);
+    }
+
+    pub fn as_ptr(&self) -> *const c_char {
+        self.words.as_ptr() as *const c_char
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut c_char {
+        self.words.as_mut_ptr() as *mut c_char
+    }
+
+    /// Transport reference of the spilled payload, if the payload was spilled.
+    pub fn spill_reference(&self) -> Option<String> {
+        let length = self.length_field();
+        if !self.is_spilled() {
+            return None;
+        }
+        let reference_len = (length.unsigned_abs() as usize).min(self.capacity);
+        str::from_utf8(&self.bytes()[HEADER_SIZE..HEADER_SIZE + reference_len])
+            .ok()
+            .map(str::to_owned)
+    }
+
+    /// Path of the temp file holding the payload, if the payload was spilled to a temp file.
+    pub fn temp_file_path(&self) -> Option<PathBuf> {
+        self.spill_reference().map(PathBuf::from)
+    }
+
+    /// Returns `true` if the payload was spilled to a temp file.
+    pub fn is_spilled(&self) -> bool {
+        // SAFETY: the buffer has a full header
+        unsafe { read_header(self.as_ptr()).1 }
+    }
+
+    /// Copies the payload out, following the temp file if the payload was spilled.
+    pub fn to_vec(&self) -> Result<Vec<u8>, i32> {
+        self.check_length()?;
+        unsafe { cbuffer_to_vector(self.as_ptr()) }
+    }
+
+    /// Copies the payload out as a utf-8 `String`, following the temp file if needed.
+    pub fn to_string(&self) -> Result<String, i32> {
+        self.check_length()?;
+        unsafe { cbuffer_to_string(self.as_ptr()) }
+    }
+
+    /// Decodes the payload as a JSON object, following the temp file if needed.
+    pub fn to_hashmap_json(&self) -> Result<HashMap<String, Value>, i32> {
+        self.check_length()?;
+        unsafe { cbuffer_to_hashmap_json(self.as_ptr()) }
+    }
+
+    /// Guards the readers against a length field larger than the allocation.
+    fn check_length(&self) -> Result<(), i32> {
+        if self.length_field().unsigned_abs() as usize > self.capacity {
+            return Err(ERR_BUFFER_TOO_LARGE);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for OwnedCBuffer {
+    /// Discards the spilled payload, if any, through the current transport.
+    fn drop(&mut self) {
+        if let Some(reference) = self.spill_reference() {
+            transport::with_current(|t| t.discard(&reference));
+        }
+    }
+}
+
+/// A spill recorded by [`MockTransport`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Spill {
+    /// Reference written into the output buffer
+    pub reference: String,
+    /// Number of payload bytes that would have gone to a temp file
+    pub len: usize,
+}
+
+#[derive(Default)]
+struct MockState {
+    next_id: u64,
+    spills: Vec<Spill>,
+    payloads: HashMap<String, Vec<u8>>,
+}
+
+/// An in-memory [`SpillTransport`] that records spills instead of writing temp files.
+///
+/// References look like `mock-spill:1`, and are only meaningful to the transport that issued them.
+#[derive(Default)]
+pub struct MockTransport {
+    state: Mutex<MockState>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        MockTransport::default()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Every spill made through this transport, in order, including discarded ones.
+    pub fn spills(&self) -> Vec<Spill> {
+        self.state().spills.clone()
+    }
+
+    /// Total number of bytes spilled through this transport.
+    pub fn spilled_len(&self) -> usize {
+        self.state().spills.iter().map(|s| s.len).sum()
+    }
+
+    /// The stored payload for `reference`, or `None` if it is unknown or was discarded.
+    pub fn payload(&self, reference: &str) -> Option<Vec<u8>> {
+        self.state().payloads.get(reference).cloned()
+    }
+
+    fn store(state: &mut MockState, bytes: &[u8]) -> String {
+        state.next_id += 1;
+        let reference = format!("mock-spill:{}", state.next_id);
+        state.payloads.insert(reference.clone(), bytes.to_vec());
+        reference
+    }
+
+    /// Stores `bytes` as a host would before passing a spilled input buffer.
+    ///
+    /// Use with [`OwnedCBuffer::spilled`] to exercise the negative-length read path.
+    pub fn insert(&self, bytes: &[u8]) -> String {
+        MockTransport::store(&mut self.state(), bytes)
+    }
+}
+
+impl SpillTransport for MockTransport {
+    fn spill(&self, bytes: &[u8]) -> Result<String, i32> {
+        let mut state = self.state();
+        let reference = MockTransport::store(&mut state, bytes);
+        state.spills.push(Spill {
+            reference: reference.clone(),
+            len: bytes.len(),
+        });
+        Ok(reference)
+    }
+
+    fn read(&self, reference: &str) -> Result<Vec<u8>, i32> {
+        self.payload(reference).ok_or(ERR_READ_TEMP_FILE_FAILED)
+    }
+
+    fn discard(&self, reference: &str) {
+        self.state().payloads.remove(reference);
+    }
+}
+
+/// Runs `f` with a fresh [`MockTransport`] installed for the current thread.
+pub fn with_mock_transport<R>(f: impl FnOnce(&MockTransport) -> R) -> R {
+    let mock = Arc::new(MockTransport::new());
+    transport::with_thread_spill_transport(mock.clone(), || f(&mock))
+}
+
+static CONFIGURED: Mutex<()> = Mutex::new(());
+
+/// Installs `config` until the returned guard is dropped, waiting for any other test of the
+/// binary that holds a guard.
+///
+/// Other process-wide state, such as hooks and capture, can be set up while holding the guard.
+pub fn configured(config: CobhanConfig) -> MutexGuard<'static, ()> {
+    let guard = CONFIGURED.lock().unwrap_or_else(|e| e.into_inner());
+    config::configure(config);
+    guard
+}
@@ -0,0 +1,276 @@
+//! # Type-length-value records
+//!
+//! A TLV payload is a sequence of records, each laid out as:
+//!
+//! * u8 tag - one of the `TAG_*` constants, or an application-defined tag (`TAG_APP_MIN` and above)
+//! * u32 length - little-endian byte length of the value
+//! * value bytes
+//!     * `TAG_STRING` - utf-8 encoded string
+//!     * `TAG_BYTES` - binary data
+//!     * `TAG_I64` - 8 byte little-endian i64
+//!     * `TAG_F64` - 8 byte little-endian IEEE 754 f64
+//!     * `TAG_JSON` - utf-8 encoded JSON document (decoded as `Other` without the `json` feature)
+//!     * `TAG_NESTED` - a complete TLV payload of its own, nested at most `MAX_NESTING_DEPTH`
+//!       levels deep
+
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ffi::c_char;
+use core::str;
+
+#[cfg(feature = "json")]
+use serde_json::Value;
+
+use crate::{
+    bytes_to_cbuffer, cbuffer_payload, PayloadReader, ERR_BUFFER_TOO_LARGE, ERR_INVALID_UTF8,
+    ERR_MALFORMED_PAYLOAD,
+};
+#[cfg(feature = "json")]
+use crate::{ERR_JSON_DECODE_FAILED, ERR_JSON_ENCODE_FAILED};
+
+/// utf-8 encoded string
+pub const TAG_STRING: u8 = 1;
+
+/// Binary data
+pub const TAG_BYTES: u8 = 2;
+
+/// 8 byte little-endian i64
+pub const TAG_I64: u8 = 3;
+
+/// 8 byte little-endian IEEE 754 f64
+pub const TAG_F64: u8 = 4;
+
+/// utf-8 encoded JSON document
+pub const TAG_JSON: u8 = 5;
+
+/// Nested TLV payload
+pub const TAG_NESTED: u8 = 6;
+
+/// First tag available for application-defined record types
+pub const TAG_APP_MIN: u8 = 0x80;
+
+/// Levels of `TAG_NESTED` payloads decoded before failing with `ERR_MALFORMED_PAYLOAD`, so a
+/// hostile payload cannot overflow the stack.
+pub const MAX_NESTING_DEPTH: usize = 64;
+
+/// A decoded TLV record.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TlvValue {
+    String(String),
+    Bytes(Vec<u8>),
+    I64(i64),
+    F64(f64),
+    #[cfg(feature = "json")]
+    Json(Value),
+    Nested(Vec<TlvValue>),
+    /// A record with a tag this module does not interpret, kept as raw bytes.
+    Other(u8, Vec<u8>),
+}
+
+/// Builds a TLV payload one record at a time.
+#[derive(Debug, Default, Clone)]
+pub struct TlvWriter {
+    bytes: Vec<u8>,
+}
+
+impl TlvWriter {
+    pub fn new() -> Self {
+        TlvWriter { bytes: Vec::new() }
+    }
+
+    /// Appends a raw record with the given tag.
+    ///
+    /// Fails with `ERR_BUFFER_TOO_LARGE` if the value is longer than `u32::MAX` bytes.
+    pub fn write_record(&mut self, tag: u8, value: &[u8]) -> Result<(), i32> {
+        if value.len() > u32::MAX as usize {
+            debug_print!("TlvWriter: value of {} bytes is too large", value.len());
+            return Err(ERR_BUFFER_TOO_LARGE);
+        }
+        self.bytes.push(tag);
+        self.bytes
+            .extend_from_slice(&(value.len() as u32).to_le_bytes());
+        self.bytes.extend_from_slice(value);
+        Ok(())
+    }
+
+    pub fn write_string(&mut self, value: &str) -> Result<(), i32> {
+        self.write_record(TAG_STRING, value.as_bytes())
+    }
+
+    pub fn write_bytes(&mut self, value: &[u8]) -> Result<(), i32> {
+        self.write_record(TAG_BYTES, value)
+    }
+
+    pub fn write_i64(&mut self, value: i64) -> Result<(), i32> {
+        self.write_record(TAG_I64, &value.to_le_bytes())
+    }
+
+    pub fn write_f64(&mut self, value: f64) -> Result<(), i32> {
+        self.write_record(TAG_F64, &value.to_le_bytes())
+    }
+
+    #[cfg(feature = "json")]
+    pub fn write_json(&mut self, value: &Value) -> Result<(), i32> {
+        let json_bytes = serde_json::to_vec(value).map_err(|_| ERR_JSON_ENCODE_FAILED)?;
+        self.write_record(TAG_JSON, &json_bytes)
+    }
+
+    pub fn write_nested(&mut self, nested: &TlvWriter) -> Result<(), i32> {
+        self.write_record(TAG_NESTED, &nested.bytes)
+    }
+
+    /// Appends a record for any `TlvValue`, recursively encoding nested values.
+    pub fn write_value(&mut self, value: &TlvValue) -> Result<(), i32> {
+        match value {
+            TlvValue::String(s) => self.write_string(s),
+            TlvValue::Bytes(b) => self.write_bytes(b),
+            TlvValue::I64(i) => self.write_i64(*i),
+            TlvValue::F64(f) => self.write_f64(*f),
+            #[cfg(feature = "json")]
+            TlvValue::Json(j) => self.write_json(j),
+            TlvValue::Nested(values) => {
+                let mut nested = TlvWriter::new();
+                values.iter().try_for_each(|v| nested.write_value(v))?;
+                self.write_nested(&nested)
+            }
+            TlvValue::Other(tag, b) => self.write_record(*tag, b),
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// Fallibly encodes the payload into a provided external Cobhan Buffer.
+    ///
+    /// ## Safety
+    ///
+    /// See [`bytes_to_cbuffer`].
+    pub unsafe fn to_cbuffer(&self, buffer: *mut c_char) -> i32 {
+        bytes_to_cbuffer(&self.bytes, buffer)
+    }
+}
+
+/// Iterates the raw `(tag, value)` records of a TLV payload without copying.
+pub struct TlvReader<'a> {
+    reader: PayloadReader<'a>,
+    failed: bool,
+}
+
+impl<'a> TlvReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        TlvReader {
+            reader: PayloadReader::new(bytes),
+            failed: false,
+        }
+    }
+
+    fn read_record(&mut self) -> Result<(u8, &'a [u8]), i32> {
+        let tag = self.reader.read_u8()?;
+        let value = self.reader.read_length_prefixed()?;
+        Ok((tag, value))
+    }
+}
+
+impl<'a> Iterator for TlvReader<'a> {
+    type Item = Result<(u8, &'a [u8]), i32>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.reader.is_empty() {
+            return None;
+        }
+        let record = self.read_record();
+        self.failed = record.is_err();
+        Some(record)
+    }
+}
+
+/// Decodes one raw record into a `TlvValue`.
+pub fn decode_record(tag: u8, value: &[u8]) -> Result<TlvValue, i32> {
+    decode_record_at(tag, value, 0)
+}
+
+/// Decodes every record in a TLV payload.
+pub fn decode_all(bytes: &[u8]) -> Result<Vec<TlvValue>, i32> {
+    decode_all_at(bytes, 0)
+}
+
+// Decodes a record of a payload nested `depth` levels deep.
+fn decode_record_at(tag: u8, value: &[u8], depth: usize) -> Result<TlvValue, i32> {
+    match tag {
+        TAG_STRING => str::from_utf8(value)
+            .map(|s| TlvValue::String(s.to_owned()))
+            .map_err(|_| ERR_INVALID_UTF8),
+        TAG_BYTES => Ok(TlvValue::Bytes(value.to_vec())),
+        TAG_I64 => fixed8(value).map(|b| TlvValue::I64(i64::from_le_bytes(b))),
+        TAG_F64 => fixed8(value).map(|b| TlvValue::F64(f64::from_le_bytes(b))),
+        #[cfg(feature = "json")]
+        TAG_JSON => serde_json::from_slice(value)
+            .map(TlvValue::Json)
+            .map_err(|_e| {
+                debug_print!("decode_record: JSON decode failed {}", _e);
+                ERR_JSON_DECODE_FAILED
+            }),
+        TAG_NESTED if depth >= MAX_NESTING_DEPTH => {
+            debug_print!("decode_record: nested deeper than {}", MAX_NESTING_DEPTH);
+            Err(ERR_MALFORMED_PAYLOAD)
+        }
+        TAG_NESTED => decode_all_at(value, depth + 1).map(TlvValue::Nested),
+        _ => Ok(TlvValue::Other(tag, value.to_vec())),
+    }
+}
+
+// Decodes the records of a payload nested `depth` levels deep.
+fn decode_all_at(bytes: &[u8], depth: usize) -> Result<Vec<TlvValue>, i32> {
+    TlvReader::new(bytes)
+        .map(|record| record.and_then(|(tag, value)| decode_record_at(tag, value, depth)))
+        .collect()
+}
+
+fn fixed8(value: &[u8]) -> Result<[u8; 8], i32> {
+    let mut fixed = [0u8; 8];
+    if value.len() != fixed.len() {
+        debug_print!("fixed8: value length {} is not 8", value.len());
+        return Err(ERR_MALFORMED_PAYLOAD);
+    }
+    fixed.copy_from_slice(value);
+    Ok(fixed)
+}
+
+/// Takes a pointer to an external Cobhan Buffer and fallibly decodes it as a TLV payload.
+///
+/// ## Notes
+///
+/// This function copies from the provided Cobhan Buffer into Rust owned data.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn cbuffer_to_tlv(buffer: *const c_char) -> Result<Vec<TlvValue>, i32> {
+    let bytes = cbuffer_payload(buffer)?;
+    decode_all(&bytes)
+}
+
+/// Takes a slice of `TlvValue` and fallibly encodes it as a TLV payload into a provided external Cobhan Buffer.
+///
+/// Will cause an error code if the provided Cobhan Buffer is too small.
+///
+/// ## Safety
+///
+/// Behavior is undefined if any of the following conditions are violated:
+/// - The Cobhan Buffer Header size is not correctly reserved or formatted.
+/// - Any of the Safety conditions of [`std::slice::from_raw_parts`][] is violated.
+pub unsafe fn tlv_to_cbuffer(values: &[TlvValue], buffer: *mut c_char) -> i32 {
+    let mut writer = TlvWriter::new();
+    if let Err(e) = values.iter().try_for_each(|v| writer.write_value(v)) {
+        return e;
+    }
+    writer.to_cbuffer(buffer)
+}
@@ -0,0 +1,348 @@
+//! # Large payload transport
+//!
+//! When a payload does not fit the caller's buffer it is handed to a [`SpillTransport`], and the
+//! buffer receives the transport's reference to it with a negated length. Readers that find a
+//! negative length in an input buffer pass the reference back to the current transport.
+//!
+//! With the `tempfile` feature the default `TempFileTransport` writes each payload to a new
+//! named temporary file in `temp_dir()` and uses the file path as the reference, or with
+//! [`reuse_spill_files`](crate::CobhanConfig::reuse_spill_files) rewrites one file per thread.
+//! Hosts release a spill they have read with `cobhan_buffer_release`. A process-wide
+//! transport can be installed with [`set_spill_transport`], and with `std` a transport for the
+//! current thread only with `with_thread_spill_transport`, which takes precedence.
+//!
+//! Without `tempfile`, and in wasm modules built with the `wasm` feature, there is no default
+//! transport: until one is installed, payloads that do not fit fail with
+//! `ERR_WRITE_TEMP_FILE_FAILED` and spilled input buffers with `ERR_READ_TEMP_FILE_FAILED`.
+
+#[cfg(feature = "std")]
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+#[cfg(feature = "std")]
+use std::cell::RefCell;
+#[cfg(feature = "tempfile")]
+use std::collections::BTreeSet;
+#[cfg(feature = "tempfile")]
+use std::fs;
+#[cfg(feature = "std")]
+use std::io::{Cursor, Read};
+#[cfg(feature = "std")]
+use std::path::PathBuf;
+#[cfg(feature = "std")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "tempfile")]
+use std::sync::Mutex;
+#[cfg(feature = "std")]
+use std::sync::RwLock;
+
+#[cfg(feature = "tempfile")]
+use crate::temp::{
+    open_spill_file, release_scratch_file, remove_scratch_files, write_new_file, write_scratch_file,
+};
+use crate::ERR_READ_TEMP_FILE_FAILED;
+#[cfg(any(
+    not(feature = "tempfile"),
+    all(feature = "wasm", target_arch = "wasm32")
+))]
+use crate::ERR_WRITE_TEMP_FILE_FAILED;
+#[cfg(feature = "std")]
+use crate::{config, platform, stats};
+
+/// Stores payloads that are too large for the caller's buffer.
+pub trait SpillTransport: Send + Sync {
+    /// Stores `bytes` and returns the reference to write into the buffer.
+    fn spill(&self, bytes: &[u8]) -> Result<String, i32>;
+
+    /// Reads back the payload stored under `reference`.
+    fn read(&self, reference: &str) -> Result<Vec<u8>, i32>;
+
+    /// Opens the payload stored under `reference` to be read in pieces, for readers that
+    /// stream it rather than hold it whole. By default it is read whole with [`read`](Self::read).
+    #[cfg(feature = "std")]
+    fn open(&self, reference: &str) -> Result<Box<dyn Read + Send>, i32> {
+        Ok(Box::new(Cursor::new(self.read(reference)?)))
+    }
+
+    /// Discards the payload stored under `reference`, ignoring failures.
+    fn discard(&self, reference: &str);
+}
+
+/// Spills payloads to named temporary files in [`temp_dir`].
+///
+/// Only the files it wrote, and has not already removed, are discarded; any other reference is
+/// ignored.
+#[cfg(feature = "tempfile")]
+pub struct TempFileTransport;
+
+/// Spill files written and not discarded, which the host may not have removed yet either.
+#[cfg(feature = "tempfile")]
+static LIVE_SPILLS: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// Removes the spill files that [`TempFileTransport`] wrote and that still exist, including the
+/// thread spill files, returning how many.
+#[cfg(feature = "tempfile")]
+pub(crate) fn remove_live_spills() -> usize {
+    let files = std::mem::take(&mut *LIVE_SPILLS.lock().unwrap_or_else(|e| e.into_inner()));
+    let removed = files
+        .iter()
+        .filter(|file| fs::remove_file(file).is_ok())
+        .count();
+    removed + remove_scratch_files()
+}
+
+/// Forgets the spill files written so far without removing them, for a forked child, whose
+/// parent's host may not have read them yet.
+#[cfg(feature = "tempfile")]
+pub(crate) fn forget_live_spills() {
+    LIVE_SPILLS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clear();
+}
+
+#[cfg(feature = "tempfile")]
+impl SpillTransport for TempFileTransport {
+    fn spill(&self, bytes: &[u8]) -> Result<String, i32> {
+        if config::reuse_spill_files() {
+            if let Some(file) = write_scratch_file(bytes)? {
+                return Ok(file);
+            }
+        }
+        let file = write_new_file(bytes)?;
+        let mut files = LIVE_SPILLS.lock().unwrap_or_else(|e| e.into_inner());
+        // Hosts remove the files they read, so forget those now and then
+        if files.len() >= 64 && files.len().is_power_of_two() {
+            files.retain(|file| fs::metadata(file).is_ok());
+        }
+        files.insert(file.clone());
+        Ok(file)
+    }
+
+    fn read(&self, reference: &str) -> Result<Vec<u8>, i32> {
+        trace_print!("TempFileTransport: reading temp file {}", reference);
+        fs::read(reference).map_err(|_e| {
+            error_print!(
+                "TempFileTransport: failed to read temporary file {}: {}",
+                reference,
+                _e
+            );
+            ERR_READ_TEMP_FILE_FAILED
+        })
+    }
+
+    fn open(&self, reference: &str) -> Result<Box<dyn Read + Send>, i32> {
+        trace_print!("TempFileTransport: opening temp file {}", reference);
+        match open_spill_file(reference) {
+            Ok(reader) => Ok(reader),
+            Err(_e) => {
+                error_print!(
+                    "TempFileTransport: failed to open temporary file {}: {}",
+                    reference,
+                    _e
+                );
+                Err(ERR_READ_TEMP_FILE_FAILED)
+            }
+        }
+    }
+
+    fn discard(&self, reference: &str) {
+        // A thread spill file is kept for the thread's next spill
+        if release_scratch_file(reference) {
+            return;
+        }
+        // Only files this transport wrote are removed, as the reference comes from the host
+        let live = LIVE_SPILLS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(reference);
+        if live {
+            let _ = fs::remove_file(reference);
+        } else {
+            warn_print!(
+                "TempFileTransport: {} is not a spill file, ignoring",
+                reference
+            );
+        }
+    }
+}
+
+/// Creates spill files in `dir` instead of the platform temp directory.
+///
+/// Needed where the platform directory is not writable or not known, e.g. an Android app whose
+/// cache directory is only available from Java (`Context.getCacheDir()`). Shorthand for setting
+/// [`CobhanConfig::temp_dir`](crate::CobhanConfig::temp_dir).
+#[cfg(feature = "std")]
+pub fn set_temp_dir(dir: impl Into<PathBuf>) {
+    let dir = dir.into();
+    config::update(|config| config.temp_dir = Some(dir));
+}
+
+/// Goes back to creating spill files in the platform temp directory.
+#[cfg(feature = "std")]
+pub fn reset_temp_dir() {
+    config::update(|config| config.temp_dir = None);
+}
+
+/// Directory spill files are created in.
+///
+/// Either the configured directory, or the platform temp directory: the app's cache directory
+/// on Android, the app container's `tmp` directory on iOS, and `std::env::temp_dir()`
+/// elsewhere.
+#[cfg(feature = "std")]
+pub fn temp_dir() -> PathBuf {
+    config::with_config(|config| config.temp_dir.clone()).unwrap_or_else(platform::default_temp_dir)
+}
+
+#[cfg(feature = "std")]
+static GLOBAL_TRANSPORT: RwLock<Option<Arc<dyn SpillTransport>>> = RwLock::new(None);
+
+/// Number of `with_thread_spill_transport` calls running, across all threads. Threads only touch
+/// `THREAD_TRANSPORT`, which registers a destructor, while there are any.
+#[cfg(feature = "std")]
+static THREAD_TRANSPORT_SCOPES: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(feature = "std")]
+thread_local! {
+    static THREAD_TRANSPORT: RefCell<Option<Arc<dyn SpillTransport>>> = RefCell::new(None);
+}
+
+/// Installs `transport` for every thread that has no thread transport of its own.
+#[cfg(feature = "std")]
+pub fn set_spill_transport(transport: Arc<dyn SpillTransport>) {
+    *GLOBAL_TRANSPORT.write().unwrap_or_else(|e| e.into_inner()) = Some(transport);
+}
+
+/// Restores the default transport (`TempFileTransport`, with the `tempfile` feature) as the
+/// process-wide transport.
+#[cfg(feature = "std")]
+pub fn reset_spill_transport() {
+    *GLOBAL_TRANSPORT.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Runs `f` with `transport` installed for the current thread only.
+///
+/// The previous thread transport is restored when `f` returns or panics.
+#[cfg(feature = "std")]
+pub fn with_thread_spill_transport<R>(
+    transport: Arc<dyn SpillTransport>,
+    f: impl FnOnce() -> R,
+) -> R {
+    struct Restore(Option<Arc<dyn SpillTransport>>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            THREAD_TRANSPORT.with(|t| *t.borrow_mut() = previous);
+            THREAD_TRANSPORT_SCOPES.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    THREAD_TRANSPORT_SCOPES.fetch_add(1, Ordering::Relaxed);
+    let _restore = Restore(THREAD_TRANSPORT.with(|t| t.borrow_mut().replace(transport)));
+    f()
+}
+
+/// Runs `f` against the transport in effect on the current thread.
+#[cfg(feature = "std")]
+pub(crate) fn with_current<R>(f: impl FnOnce(&dyn SpillTransport) -> R) -> R {
+    stats::note_transport_use();
+    if THREAD_TRANSPORT_SCOPES.load(Ordering::Relaxed) > 0 {
+        if let Some(transport) = THREAD_TRANSPORT.with(|t| t.borrow().clone()) {
+            return f(transport.as_ref());
+        }
+    }
+    let global = GLOBAL_TRANSPORT
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    match global {
+        Some(transport) => f(transport.as_ref()),
+        #[cfg(all(
+            feature = "tempfile",
+            not(all(feature = "wasm", target_arch = "wasm32"))
+        ))]
+        None => f(&TempFileTransport),
+        #[cfg(any(
+            not(feature = "tempfile"),
+            all(feature = "wasm", target_arch = "wasm32")
+        ))]
+        None => f(&NoTransport),
+    }
+}
+
+#[cfg(not(feature = "std"))]
+mod global {
+    use alloc::boxed::Box;
+    use alloc::sync::Arc;
+    use core::ptr;
+    use core::sync::atomic::{AtomicPtr, Ordering};
+
+    use super::SpillTransport;
+
+    static GLOBAL_TRANSPORT: AtomicPtr<Arc<dyn SpillTransport>> = AtomicPtr::new(ptr::null_mut());
+
+    /// Replaces the installed transport.
+    ///
+    /// The previous transport is leaked rather than dropped, since another thread may still be
+    /// using it; transports are expected to be installed once at startup.
+    pub(super) fn replace(transport: Option<Arc<dyn SpillTransport>>) {
+        let new = match transport {
+            Some(transport) => Box::into_raw(Box::new(transport)),
+            None => ptr::null_mut(),
+        };
+        GLOBAL_TRANSPORT.swap(new, Ordering::AcqRel);
+    }
+
+    pub(super) fn current() -> Option<&'static dyn SpillTransport> {
+        let installed = GLOBAL_TRANSPORT.load(Ordering::Acquire);
+        // SAFETY: installed transports are never freed
+        unsafe { installed.as_ref() }.map(|transport| transport.as_ref())
+    }
+}
+
+/// Used without the `tempfile` feature, and in wasm modules, until a transport is installed.
+#[cfg(any(
+    not(feature = "tempfile"),
+    all(feature = "wasm", target_arch = "wasm32")
+))]
+struct NoTransport;
+
+#[cfg(any(
+    not(feature = "tempfile"),
+    all(feature = "wasm", target_arch = "wasm32")
+))]
+impl SpillTransport for NoTransport {
+    fn spill(&self, _bytes: &[u8]) -> Result<String, i32> {
+        Err(ERR_WRITE_TEMP_FILE_FAILED)
+    }
+
+    fn read(&self, _reference: &str) -> Result<Vec<u8>, i32> {
+        Err(ERR_READ_TEMP_FILE_FAILED)
+    }
+
+    fn discard(&self, _reference: &str) {}
+}
+
+/// Installs `transport` for every thread.
+#[cfg(not(feature = "std"))]
+pub fn set_spill_transport(transport: Arc<dyn SpillTransport>) {
+    global::replace(Some(transport));
+}
+
+/// Removes the installed transport, so payloads that do not fit fail again.
+#[cfg(not(feature = "std"))]
+pub fn reset_spill_transport() {
+    global::replace(None);
+}
+
+/// Runs `f` against the installed transport.
+#[cfg(not(feature = "std"))]
+pub(crate) fn with_current<R>(f: impl FnOnce(&dyn SpillTransport) -> R) -> R {
+    match global::current() {
+        Some(transport) => f(transport),
+        None => f(&NoTransport),
+    }
+}
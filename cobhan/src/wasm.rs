@@ -0,0 +1,176 @@
+//! # WebAssembly linear memory buffers
+//!
+//! A wasm32 module has no way to receive raw host pointers. Instead the host allocates Cobhan
+//! Buffers inside the module's linear memory (with [`allocate`], exported by the library) and
+//! passes each one as a u32 offset. The helpers here resolve offsets into buffer pointers after
+//! checking that the header and payload lie entirely within linear memory, so the same
+//! cobhan-style core can be compiled to both a native cdylib and a wasm module.
+//!
+//! A library exposes the allocation helpers and offset-based entry points itself:
+//!
+//! ```ignore
+//! #[no_mangle]
+//! pub extern "C" fn cobhan_allocate(capacity: u32) -> u32 {
+//!     cobhan::wasm::allocate(capacity)
+//! }
+//!
+//! #[no_mangle]
+//! pub extern "C" fn cobhan_deallocate(offset: u32, capacity: u32) {
+//!     unsafe { cobhan::wasm::deallocate(offset, capacity) }
+//! }
+//!
+//! #[no_mangle]
+//! pub extern "C" fn toUpper(input: u32, output: u32) -> i32 {
+//!     let input = match unsafe { cobhan::wasm::cbuffer_to_string(input) } {
+//!         Ok(s) => s,
+//!         Err(e) => return e,
+//!     };
+//!     unsafe { cobhan::wasm::string_to_cbuffer(&input.to_uppercase(), output) }
+//! }
+//! ```
+//!
+//! Modules run without a file system, so the temp file path is disabled: payloads that do not
+//! fit fail with `ERR_WRITE_TEMP_FILE_FAILED` unless a [`SpillTransport`](crate::SpillTransport)
+//! is installed.
+
+use alloc::alloc::{alloc_zeroed, dealloc, Layout};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::arch::wasm32;
+use core::ffi::c_char;
+
+use crate::{
+    read_header_length, write_header_length, BUFFER_HEADER_SIZE, ERR_BUFFER_TOO_LARGE, ERR_NULL_PTR,
+};
+
+const HEADER_SIZE: usize = BUFFER_HEADER_SIZE as usize;
+
+const PAGE_SIZE: usize = 64 * 1024;
+
+/// Current size of linear memory in bytes.
+fn memory_len() -> usize {
+    wasm32::memory_size(0) * PAGE_SIZE
+}
+
+fn layout(capacity: u32) -> Option<Layout> {
+    Layout::from_size_align(HEADER_SIZE + capacity as usize, 8).ok()
+}
+
+/// Allocates a zeroed Cobhan Buffer with room for `capacity` payload bytes and returns its offset.
+///
+/// The length field is set to `capacity`, ready for use as an output buffer. Returns 0 if the
+/// allocation fails or `capacity` does not fit the i32 length field.
+pub fn allocate(capacity: u32) -> u32 {
+    if capacity > i32::MAX as u32 {
+        return 0;
+    }
+    let layout = match layout(capacity) {
+        Some(layout) => layout,
+        None => return 0,
+    };
+    // SAFETY: the layout is never zero sized
+    let buffer = unsafe { alloc_zeroed(layout) };
+    if buffer.is_null() {
+        return 0;
+    }
+    unsafe { write_header_length(buffer as *mut c_char, capacity as i32) };
+    buffer as usize as u32
+}
+
+/// Frees a Cobhan Buffer returned by [`allocate`].
+///
+/// ## Safety
+///
+/// `offset` must have been returned by [`allocate`] with the same `capacity`, and not freed since.
+pub unsafe fn deallocate(offset: u32, capacity: u32) {
+    if offset == 0 {
+        return;
+    }
+    if let Some(layout) = layout(capacity) {
+        dealloc(offset as usize as *mut u8, layout);
+    }
+}
+
+/// Checks that the buffer at `offset` lies within linear memory.
+fn check(offset: u32) -> Result<usize, i32> {
+    if offset == 0 {
+        debug_print!("wasm::check: offset is 0");
+        return Err(ERR_NULL_PTR);
+    }
+    let start = offset as usize;
+    let memory_len = memory_len();
+    if start
+        .checked_add(HEADER_SIZE)
+        .is_none_or(|end| end > memory_len)
+    {
+        debug_print!("wasm::check: header at {} is outside linear memory", offset);
+        return Err(ERR_BUFFER_TOO_LARGE);
+    }
+    // SAFETY: the header lies within linear memory
+    let length = unsafe { read_header_length(start as *const c_char) };
+    let payload_end = start
+        .checked_add(HEADER_SIZE)
+        .and_then(|p| p.checked_add(length.unsigned_abs() as usize));
+    if payload_end.is_none_or(|end| end > memory_len) {
+        debug_print!(
+            "wasm::check: payload of {} bytes at {} is outside linear memory",
+            length,
+            offset
+        );
+        return Err(ERR_BUFFER_TOO_LARGE);
+    }
+    Ok(start)
+}
+
+/// Resolves an input buffer offset into a Cobhan Buffer pointer.
+pub fn resolve(offset: u32) -> Result<*const c_char, i32> {
+    check(offset).map(|start| start as *const c_char)
+}
+
+/// Resolves an output buffer offset into a Cobhan Buffer pointer.
+pub fn resolve_mut(offset: u32) -> Result<*mut c_char, i32> {
+    check(offset).map(|start| start as *mut c_char)
+}
+
+/// Reads the buffer at `offset` as a `Vec<u8>`, see [`crate::cbuffer_to_vector`].
+///
+/// ## Safety
+///
+/// `offset` must refer to a Cobhan Buffer the host allocated for this call, not to memory owned
+/// by Rust data.
+pub unsafe fn cbuffer_to_vector(offset: u32) -> Result<Vec<u8>, i32> {
+    crate::cbuffer_to_vector(resolve(offset)?)
+}
+
+/// Reads the buffer at `offset` as a `String`, see [`crate::cbuffer_to_string`].
+///
+/// ## Safety
+///
+/// `offset` must refer to a Cobhan Buffer the host allocated for this call, not to memory owned
+/// by Rust data.
+pub unsafe fn cbuffer_to_string(offset: u32) -> Result<String, i32> {
+    crate::cbuffer_to_string(resolve(offset)?)
+}
+
+/// Writes `bytes` into the buffer at `offset`, see [`crate::bytes_to_cbuffer`].
+///
+/// ## Safety
+///
+/// `offset` must refer to a Cobhan Buffer the host allocated for this call, not to memory owned
+/// by Rust data.
+pub unsafe fn bytes_to_cbuffer(bytes: impl AsRef<[u8]>, offset: u32) -> i32 {
+    match resolve_mut(offset) {
+        Ok(buffer) => crate::bytes_to_cbuffer(bytes, buffer),
+        Err(e) => e,
+    }
+}
+
+/// Writes `string` into the buffer at `offset`, see [`crate::string_to_cbuffer`].
+///
+/// ## Safety
+///
+/// `offset` must refer to a Cobhan Buffer the host allocated for this call, not to memory owned
+/// by Rust data.
+pub unsafe fn string_to_cbuffer(string: impl AsRef<str>, offset: u32) -> i32 {
+    bytes_to_cbuffer(string.as_ref().as_bytes(), offset)
+}
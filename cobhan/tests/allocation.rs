@@ -0,0 +1,67 @@
+use std::convert::TryInto;
+
+use cobhan::testing::with_mock_transport;
+use cobhan::*;
+
+#[test]
+fn alloc_makes_an_aligned_output_buffer() {
+    let buffer = cobhan_alloc(100);
+    assert!(!buffer.is_null());
+    assert_eq!(buffer as usize % 8, 0);
+    let header = unsafe { std::slice::from_raw_parts(buffer as *const u8, 4) };
+    assert_eq!(decode_header_length(header.try_into().unwrap()), 100);
+    assert_eq!(unsafe { cobhan_free(buffer) }, ERR_NONE);
+
+    assert!(cobhan_alloc(-1).is_null());
+}
+
+#[test]
+fn into_vec_takes_over_the_allocation() {
+    let buffer = cobhan_alloc(64);
+    assert_eq!(unsafe { string_to_cbuffer("rendered", buffer) }, ERR_NONE);
+    let payload = unsafe { buffer.offset(BUFFER_HEADER_SIZE) } as usize;
+    let bytes = unsafe { cbuffer_into_vec(buffer) }.unwrap();
+    assert_eq!(bytes, b"rendered");
+    assert!(bytes.capacity() >= 64);
+    // The Vec starts within the buffer's allocation, before the payload
+    let start = bytes.as_ptr() as usize;
+    assert!(start <= buffer as usize && payload - start < 16);
+
+    // Released along with the Vec
+    assert_eq!(unsafe { cobhan_free(buffer) }, ERR_INVALID_HANDLE);
+}
+
+#[test]
+fn into_vec_reads_spilled_payloads() {
+    with_mock_transport(|transport| {
+        let buffer = cobhan_alloc(16);
+        let payload = vec![7u8; 1000];
+        assert_eq!(unsafe { bytes_to_cbuffer(&payload, buffer) }, ERR_NONE);
+        assert_eq!(transport.spills().len(), 1);
+        assert_eq!(unsafe { cbuffer_into_vec(buffer) }.unwrap(), payload);
+    });
+}
+
+#[test]
+fn foreign_buffers_are_rejected() {
+    let mut words = [0u64; 2];
+    let foreign = words.as_mut_ptr() as *mut std::os::raw::c_char;
+    assert_eq!(unsafe { cobhan_free(foreign) }, ERR_INVALID_HANDLE);
+    assert_eq!(
+        unsafe { cbuffer_into_vec(foreign) },
+        Err(ERR_INVALID_HANDLE)
+    );
+    assert_eq!(unsafe { cobhan_free(std::ptr::null_mut()) }, ERR_NULL_PTR);
+
+    let buffer = cobhan_alloc(4);
+    assert_eq!(unsafe { cobhan_free(buffer) }, ERR_NONE);
+    assert_eq!(unsafe { cobhan_free(buffer) }, ERR_INVALID_HANDLE);
+}
+
+#[test]
+fn into_vec_rejects_lengths_over_the_capacity() {
+    let buffer = cobhan_alloc(4);
+    unsafe { (buffer as *mut [u8; 4]).write(encode_header_length(5)) };
+    assert_eq!(unsafe { cbuffer_into_vec(buffer) }, Err(ERR_INVALID_LENGTH));
+    assert_eq!(unsafe { cobhan_free(buffer) }, ERR_INVALID_HANDLE);
+}
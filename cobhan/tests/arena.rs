@@ -0,0 +1,61 @@
+//! Conversions into the per-call arena.
+
+use cobhan::testing::{with_mock_transport, OwnedCBuffer};
+use cobhan::*;
+
+#[test]
+fn payloads_are_copied_into_the_arena() {
+    let key = OwnedCBuffer::from_bytes(b"key");
+    let value = OwnedCBuffer::from_bytes(&[1, 2, 3]);
+    let (key, value) = with_arena(|arena| unsafe {
+        let key = cbuffer_to_str_in(arena, key.as_ptr()).unwrap();
+        let value = cbuffer_to_bytes_in(arena, value.as_ptr()).unwrap();
+        assert!(arena.allocated_bytes() >= key.len() + value.len());
+        (key.to_string(), value.to_vec())
+    });
+    assert_eq!(key, "key");
+    assert_eq!(value, [1, 2, 3]);
+}
+
+#[test]
+fn spilled_payloads_are_followed() {
+    with_mock_transport(|mock| {
+        let input = OwnedCBuffer::spilled(&mock.insert(b"spilled"));
+        let copied = with_arena(|arena| unsafe {
+            cbuffer_to_str_in(arena, input.as_ptr()).map(str::to_string)
+        });
+        assert_eq!(copied, Ok("spilled".to_string()));
+    });
+}
+
+#[test]
+fn arena_memory_is_reused_between_calls() {
+    let input = OwnedCBuffer::from_bytes(&[7; 1024]);
+    let first = with_arena(|arena| {
+        unsafe { cbuffer_to_bytes_in(arena, input.as_ptr()) }.unwrap();
+        arena.allocated_bytes()
+    });
+    let second = with_arena(|arena| {
+        unsafe { cbuffer_to_bytes_in(arena, input.as_ptr()) }.unwrap();
+        arena.allocated_bytes()
+    });
+    assert_eq!(first, second);
+
+    // A nested call does not share the outer arena
+    with_arena(|outer| {
+        let outer_bytes = outer.alloc_bytes(b"outer");
+        with_arena(|inner| assert_eq!(inner.alloc_str("inner"), "inner"));
+        assert_eq!(outer_bytes, b"outer");
+    });
+}
+
+#[test]
+fn invalid_utf8_is_rejected() {
+    let input = OwnedCBuffer::from_bytes(b"\xc3");
+    with_arena(|arena| {
+        assert_eq!(
+            unsafe { cbuffer_to_str_in(arena, input.as_ptr()) },
+            Err(ERR_INVALID_UTF8)
+        );
+    });
+}
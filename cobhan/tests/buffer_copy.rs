@@ -0,0 +1,109 @@
+//! Payloads copied and moved between buffers by the exported helpers.
+
+use cobhan::testing::{with_mock_transport, OwnedCBuffer};
+use cobhan::*;
+
+#[test]
+fn copies_leave_the_source_alone() {
+    let source = OwnedCBuffer::from_bytes(b"result");
+    let mut destination = OwnedCBuffer::with_capacity(16);
+    assert_eq!(
+        unsafe { cobhan_buffer_copy(source.as_ptr(), destination.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(destination.to_vec().unwrap(), b"result");
+    assert_eq!(source.to_vec().unwrap(), b"result");
+
+    let mut small = OwnedCBuffer::with_capacity(2);
+    assert_eq!(
+        unsafe { cobhan_buffer_copy(source.as_ptr(), small.as_mut_ptr()) },
+        ERR_BUFFER_TOO_SMALL
+    );
+}
+
+#[test]
+fn spilled_sources_are_copied_into_a_new_spill() {
+    with_mock_transport(|mock| {
+        let payload = vec![b's'; 100];
+        let reference = mock.insert(&payload);
+        let source = OwnedCBuffer::spilled(&reference);
+
+        let mut inline = OwnedCBuffer::with_capacity(128);
+        assert_eq!(
+            unsafe { cobhan_buffer_copy(source.as_ptr(), inline.as_mut_ptr()) },
+            ERR_NONE
+        );
+        assert!(!inline.is_spilled());
+        assert_eq!(inline.to_vec().unwrap(), payload);
+
+        let mut spilled = OwnedCBuffer::with_capacity(32);
+        assert_eq!(
+            unsafe { cobhan_buffer_copy(source.as_ptr(), spilled.as_mut_ptr()) },
+            ERR_NONE
+        );
+        assert_ne!(spilled.spill_reference().unwrap(), reference);
+        assert_eq!(spilled.to_vec().unwrap(), payload);
+        assert_eq!(mock.spills().len(), 1);
+    });
+}
+
+#[test]
+fn moves_hand_spills_over() {
+    with_mock_transport(|mock| {
+        let reference = mock.insert(&[b'm'; 100]);
+        let mut source = OwnedCBuffer::spilled(&reference);
+        let mut destination = OwnedCBuffer::with_capacity(32);
+        assert_eq!(
+            unsafe { cobhan_buffer_move(source.as_mut_ptr(), destination.as_mut_ptr()) },
+            ERR_NONE
+        );
+        assert_eq!(destination.spill_reference(), Some(reference.clone()));
+        assert_eq!(destination.to_vec().unwrap(), vec![b'm'; 100]);
+        assert_eq!(
+            (source.length_field(), source.flags()),
+            (0, BufferFlags::empty())
+        );
+        assert!(mock.spills().is_empty());
+
+        // A reference that does not fit leaves the source as it was
+        let mut source = OwnedCBuffer::spilled(&reference);
+        let mut small = OwnedCBuffer::with_capacity(2);
+        assert_eq!(
+            unsafe { cobhan_buffer_move(source.as_mut_ptr(), small.as_mut_ptr()) },
+            ERR_BUFFER_TOO_SMALL
+        );
+        assert_eq!(source.spill_reference(), Some(reference));
+    });
+}
+
+#[test]
+fn inline_moves_copy_and_empty_the_source() {
+    let mut source = OwnedCBuffer::from_bytes(b"moved");
+    let mut destination = OwnedCBuffer::with_capacity(16);
+    assert_eq!(
+        unsafe { cobhan_buffer_move(source.as_mut_ptr(), destination.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(destination.to_vec().unwrap(), b"moved");
+    assert_eq!(source.to_vec().unwrap(), b"");
+
+    // Into itself is a no-op
+    assert_eq!(
+        unsafe { cobhan_buffer_move(destination.as_mut_ptr(), destination.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(destination.to_vec().unwrap(), b"moved");
+}
+
+#[test]
+fn null_buffers_are_rejected() {
+    let mut buffer = OwnedCBuffer::with_capacity(16);
+    assert_eq!(
+        unsafe { cobhan_buffer_copy(std::ptr::null(), buffer.as_mut_ptr()) },
+        ERR_NULL_PTR
+    );
+    assert_eq!(
+        unsafe { cobhan_buffer_move(buffer.as_mut_ptr(), std::ptr::null_mut()) },
+        ERR_NULL_PTR
+    );
+}
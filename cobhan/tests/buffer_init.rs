@@ -0,0 +1,62 @@
+//! Headers written into raw host memory by the exported init and reset helpers.
+
+use std::os::raw::c_char;
+
+use cobhan::*;
+
+/// Raw memory as a minimal host would allocate it, filled with garbage.
+fn raw_memory(bytes: usize) -> Vec<u64> {
+    vec![u64::MAX; bytes.div_ceil(8)]
+}
+
+fn header(memory: &[u64]) -> (i32, i32) {
+    let bytes = memory[0].to_ne_bytes();
+    (
+        decode_header_length([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        decode_header_length([bytes[4], bytes[5], bytes[6], bytes[7]]),
+    )
+}
+
+#[test]
+fn init_writes_an_output_header() {
+    let mut memory = raw_memory(BUFFER_HEADER_SIZE as usize + 16);
+    let buffer = memory.as_mut_ptr() as *mut c_char;
+    assert_eq!(unsafe { cobhan_buffer_init(buffer, 16) }, ERR_NONE);
+    assert_eq!(header(&memory), (16, 0));
+
+    let buffer = memory.as_mut_ptr() as *mut c_char;
+    assert_eq!(unsafe { string_to_cbuffer("written", buffer) }, ERR_NONE);
+    assert_eq!(unsafe { cbuffer_to_string(buffer) }.unwrap(), "written");
+    assert_eq!(header(&memory), (7, 0));
+}
+
+#[test]
+fn reset_writes_an_empty_input() {
+    let mut memory = raw_memory(BUFFER_HEADER_SIZE as usize + 16);
+    let buffer = memory.as_mut_ptr() as *mut c_char;
+    assert_eq!(unsafe { cobhan_buffer_init(buffer, 16) }, ERR_NONE);
+    assert_eq!(unsafe { string_to_cbuffer("written", buffer) }, ERR_NONE);
+    assert_eq!(unsafe { cobhan_buffer_reset(buffer) }, ERR_NONE);
+    assert_eq!(header(&memory), (0, 0));
+    let buffer = memory.as_mut_ptr() as *mut c_char;
+    assert_eq!(unsafe { cbuffer_to_vector(buffer) }.unwrap(), b"");
+}
+
+#[test]
+fn invalid_arguments_are_rejected() {
+    let mut memory = raw_memory(BUFFER_HEADER_SIZE as usize);
+    let buffer = memory.as_mut_ptr() as *mut c_char;
+    assert_eq!(
+        unsafe { cobhan_buffer_init(buffer, -1) },
+        ERR_INVALID_LENGTH
+    );
+    assert_eq!(header(&memory), (-1, -1));
+    assert_eq!(
+        unsafe { cobhan_buffer_init(std::ptr::null_mut(), 16) },
+        ERR_NULL_PTR
+    );
+    assert_eq!(
+        unsafe { cobhan_buffer_reset(std::ptr::null_mut()) },
+        ERR_NULL_PTR
+    );
+}
@@ -0,0 +1,77 @@
+//! Cached payloads are shared between calls until the cache is cleared or evicts them.
+
+use std::sync::Arc;
+
+use cobhan::testing::{with_mock_transport, OwnedCBuffer};
+use cobhan::*;
+
+#[test]
+fn repeated_payloads_share_one_copy() {
+    let cache = PayloadCache::default();
+    let first = OwnedCBuffer::from_bytes(b"{\"policy\":\"allow\"}");
+    let second = OwnedCBuffer::from_bytes(b"{\"policy\":\"allow\"}");
+    let a = unsafe { cbuffer_to_vector_cached(&cache, first.as_ptr()) }.unwrap();
+    let b = unsafe { cbuffer_to_vector_cached(&cache, second.as_ptr()) }.unwrap();
+    assert_eq!(a.as_slice(), b"{\"policy\":\"allow\"}");
+    assert!(Arc::ptr_eq(&a, &b));
+    assert_eq!(cache.len(), 1);
+    assert_eq!(cache.size_bytes(), a.len());
+
+    let other =
+        unsafe { cbuffer_to_vector_cached(&cache, OwnedCBuffer::from_bytes(b"deny").as_ptr()) };
+    assert!(!Arc::ptr_eq(&a, &other.unwrap()));
+    assert_eq!(cache.len(), 2);
+
+    cache.clear();
+    assert!(cache.is_empty());
+    assert_eq!(cache.size_bytes(), 0);
+    let c = unsafe { cbuffer_to_vector_cached(&cache, first.as_ptr()) }.unwrap();
+    assert!(!Arc::ptr_eq(&a, &c));
+    assert_eq!(a, c);
+}
+
+#[test]
+fn least_recently_used_payloads_are_evicted() {
+    let cache = PayloadCache::with_max_bytes(8);
+    let a = cache.get_or_insert(b"aaaa");
+    cache.get_or_insert(b"bbbb");
+    assert!(Arc::ptr_eq(&a, &cache.get_or_insert(b"aaaa")));
+
+    cache.get_or_insert(b"cccc");
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.size_bytes(), 8);
+    assert!(Arc::ptr_eq(&a, &cache.get_or_insert(b"aaaa")));
+    let b = cache.get_or_insert(b"bbbb");
+    assert!(!Arc::ptr_eq(&b, &cache.get_or_insert(b"cccc")));
+
+    let large = cache.get_or_insert(b"too large");
+    assert!(!Arc::ptr_eq(&large, &cache.get_or_insert(b"too large")));
+    assert_eq!(cache.len(), 2);
+}
+
+#[test]
+fn spilled_payloads_are_cached() {
+    with_mock_transport(|_| {
+        let cache = PayloadCache::default();
+        let payload = vec![3u8; 4096];
+        let mut buffer = OwnedCBuffer::with_capacity(16);
+        assert_eq!(
+            unsafe { bytes_to_cbuffer(&payload, buffer.as_mut_ptr()) },
+            ERR_NONE
+        );
+        assert!(buffer.is_spilled());
+        let a = unsafe { cbuffer_to_vector_cached(&cache, buffer.as_ptr()) }.unwrap();
+        let b = unsafe { cbuffer_to_vector_cached(&cache, buffer.as_ptr()) }.unwrap();
+        assert_eq!(*a, payload);
+        assert!(Arc::ptr_eq(&a, &b));
+    });
+}
+
+#[test]
+fn null_buffers_are_rejected() {
+    let cache = PayloadCache::default();
+    assert_eq!(
+        unsafe { cbuffer_to_vector_cached(&cache, std::ptr::null()) },
+        Err(ERR_NULL_PTR)
+    );
+}
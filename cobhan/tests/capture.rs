@@ -0,0 +1,135 @@
+//! Capture state is process-wide, so the tests take turns.
+
+use std::os::raw::c_char;
+use std::sync::{Arc, Mutex};
+
+use cobhan::capture::*;
+use cobhan::testing::{configured, OwnedCBuffer};
+use cobhan::*;
+
+unsafe extern "C" fn to_upper(input: *const c_char, output: *mut c_char) -> i32 {
+    match cbuffer_to_string(input) {
+        Ok(input) => string_to_cbuffer(input.to_uppercase(), output),
+        Err(e) => e,
+    }
+}
+
+fn call(input: &str) -> String {
+    let input = OwnedCBuffer::from_bytes(input.as_bytes());
+    let mut output = OwnedCBuffer::with_capacity(64);
+    assert_eq!(
+        unsafe { to_upper(input.as_ptr(), output.as_mut_ptr()) },
+        ERR_NONE
+    );
+    output.to_string().unwrap()
+}
+
+#[test]
+fn captures_inputs_and_outputs_and_replays_them() {
+    let _guard = configured(CobhanConfig::default());
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("calls.cbncap");
+
+    start_capture(&path, CaptureOptions::default()).unwrap();
+    assert!(is_capturing());
+    assert_eq!(call("first"), "FIRST");
+    stop_capture();
+    assert!(!is_capturing());
+    assert_eq!(call("not captured"), "NOT CAPTURED");
+
+    let records = read_capture(&path).unwrap();
+    let directions: Vec<_> = records.iter().map(|r| r.direction).collect();
+    // The test reads the output back through cobhan too
+    assert_eq!(
+        directions,
+        [Direction::Input, Direction::Output, Direction::Input]
+    );
+    assert_eq!(records[0].bytes, b"first");
+    assert_eq!(records[1].bytes, b"FIRST");
+
+    let outputs = unsafe { replay(&path, to_upper, 64) }.unwrap();
+    assert_eq!(outputs[0], Ok(b"FIRST".to_vec()));
+}
+
+#[test]
+fn redacts_and_truncates_payloads() {
+    let _guard = configured(CobhanConfig::default());
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("calls.cbncap");
+
+    start_capture(
+        &path,
+        CaptureOptions {
+            max_payload_len: 8,
+            redact: Some(Arc::new(|_, bytes: &[u8]| {
+                String::from_utf8_lossy(bytes)
+                    .replace("hunter2", "*******")
+                    .into_bytes()
+            })),
+        },
+    )
+    .unwrap();
+    call("hunter2");
+    call("a long payload");
+    stop_capture();
+
+    let records = read_capture(&path).unwrap();
+    assert_eq!(records[0].bytes, b"*******");
+    assert!(!records[0].is_truncated());
+    let long = &records[3];
+    assert_eq!(long.bytes, b"a long p");
+    assert_eq!(long.len, 14);
+    assert!(long.is_truncated());
+
+    let outputs = unsafe { replay(&path, to_upper, 64) }.unwrap();
+    assert_eq!(outputs[2], Err(ERR_BUFFER_TOO_LARGE));
+}
+
+#[test]
+fn rejects_files_that_are_not_captures() {
+    let _guard = configured(CobhanConfig::default());
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("not.cbncap");
+    std::fs::write(&path, b"plain text").unwrap();
+    assert_eq!(read_capture(&path), Err(ERR_MALFORMED_PAYLOAD));
+    assert_eq!(
+        read_capture(dir.path().join("missing")),
+        Err(ERR_CAPTURE_FAILED)
+    );
+    assert_eq!(
+        start_capture(dir.path().join("missing/calls.cbncap"), Default::default()),
+        Err(ERR_CAPTURE_FAILED)
+    );
+}
+
+#[test]
+fn tee_sinks_see_every_output() {
+    let _guard = configured(CobhanConfig::default());
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let sink_seen = Arc::clone(&seen);
+    set_tee_sink(Arc::new(move |bytes: &[u8]| {
+        sink_seen.lock().unwrap().push(bytes.to_vec());
+        // Writes made by the sink itself are not teed again
+        let mut copy = OwnedCBuffer::with_capacity(64);
+        assert_eq!(
+            unsafe { bytes_to_cbuffer(bytes, copy.as_mut_ptr()) },
+            ERR_NONE
+        );
+    }));
+    assert!(is_teeing());
+    assert_eq!(call("first"), "FIRST");
+    assert_eq!(call("second"), "SECOND");
+    clear_tee_sink();
+    assert!(!is_teeing());
+    assert_eq!(call("after"), "AFTER");
+
+    assert_eq!(*seen.lock().unwrap(), [&b"FIRST"[..], b"SECOND"]);
+}
+
+#[test]
+fn tee_sink_panics_do_not_fail_the_write() {
+    let _guard = configured(CobhanConfig::default());
+    set_tee_sink(Arc::new(|_: &[u8]| panic!("sink failed")));
+    assert_eq!(call("written"), "WRITTEN");
+    clear_tee_sink();
+}
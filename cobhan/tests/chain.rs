@@ -0,0 +1,78 @@
+use std::io::{BufRead, Read};
+
+use cobhan::testing::{with_mock_transport, OwnedCBuffer};
+use cobhan::*;
+
+#[test]
+fn chain_reader_reads_payloads_in_order() {
+    let first = OwnedCBuffer::from_bytes(b"multi-");
+    let empty = OwnedCBuffer::from_bytes(b"");
+    let last = OwnedCBuffer::from_bytes(b"part");
+    let buffers = [first.as_ptr(), empty.as_ptr(), last.as_ptr()];
+    let mut reader = unsafe { cbuffers_chain_reader(&buffers) }.unwrap();
+    let mut text = String::new();
+    reader.read_to_string(&mut text).unwrap();
+    assert_eq!(text, "multi-part");
+    assert_eq!(reader.error(), None);
+
+    let mut reader = unsafe { cbuffers_chain_reader(&[]) }.unwrap();
+    assert_eq!(reader.fill_buf().unwrap(), b"");
+}
+
+#[test]
+fn chain_reader_streams_spilled_payloads() {
+    with_mock_transport(|_| {
+        let mut spilled = OwnedCBuffer::with_capacity(16);
+        let payload = vec![b'x'; 1000];
+        assert_eq!(
+            unsafe { bytes_to_cbuffer(&payload, spilled.as_mut_ptr()) },
+            ERR_NONE
+        );
+        assert!(spilled.is_spilled());
+        let inline = OwnedCBuffer::from_bytes(b"\nline two\n");
+        let buffers = [inline.as_ptr(), spilled.as_ptr(), inline.as_ptr()];
+        let reader = unsafe { cbuffers_chain_reader(&buffers) }.unwrap();
+        let lines: Vec<String> = reader.lines().map(Result::unwrap).collect();
+        assert_eq!(
+            lines,
+            [
+                "",
+                "line two",
+                &String::from_utf8(payload).unwrap(),
+                "line two"
+            ]
+        );
+    });
+}
+
+#[test]
+fn chain_reader_checks_headers_up_front() {
+    let buffer = OwnedCBuffer::from_bytes(b"ok");
+    let buffers = [buffer.as_ptr(), std::ptr::null()];
+    assert_eq!(
+        unsafe { cbuffers_chain_reader(&buffers) }.err(),
+        Some(ERR_NULL_PTR)
+    );
+
+    let mut invalid = OwnedCBuffer::with_capacity(0);
+    unsafe { (invalid.as_mut_ptr() as *mut [u8; 4]).write(encode_header_length(i32::MIN)) };
+    let buffers = [buffer.as_ptr(), invalid.as_ptr()];
+    assert_eq!(
+        unsafe { cbuffers_chain_reader(&buffers) }.err(),
+        Some(ERR_INVALID_LENGTH)
+    );
+}
+
+#[test]
+fn chain_reader_ends_with_the_failed_payload() {
+    with_mock_transport(|_| {
+        let first = OwnedCBuffer::from_bytes(b"read ");
+        let missing = OwnedCBuffer::spilled("mock:missing");
+        let buffers = [first.as_ptr(), missing.as_ptr()];
+        let mut reader = unsafe { cbuffers_chain_reader(&buffers) }.unwrap();
+        let mut bytes = Vec::new();
+        assert!(reader.read_to_end(&mut bytes).is_err());
+        assert_eq!(bytes, b"read ");
+        assert_eq!(reader.error(), Some(ERR_READ_TEMP_FILE_FAILED));
+    });
+}
@@ -0,0 +1,71 @@
+//! CRC-32 checksums in the reserved field. The buffer format is process-wide configuration, so
+//! the tests take turns.
+
+use std::sync::MutexGuard;
+
+use cobhan::testing::{configured, with_mock_transport, OwnedCBuffer};
+use cobhan::*;
+
+fn format(format: BufferFormat) -> MutexGuard<'static, ()> {
+    configured(CobhanConfig {
+        buffer_format: format,
+        ..Default::default()
+    })
+}
+
+#[test]
+fn crc32_matches_the_ieee_check_value() {
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    assert_eq!(crc32(b""), 0);
+}
+
+#[test]
+fn checksummed_payloads_verify_until_corrupted() {
+    let _guard = format(BufferFormat::V1);
+    let mut output = OwnedCBuffer::with_capacity(16);
+    assert_eq!(
+        unsafe { bytes_to_cbuffer_with_crc(b"123456789", output.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(output.flags().bits(), 0xCBF4_3926);
+    assert_eq!(unsafe { cbuffer_verify_crc(output.as_ptr()) }, ERR_NONE);
+    assert_eq!(output.to_vec().unwrap(), b"123456789");
+
+    // Flip one payload bit
+    let payload = unsafe { output.as_mut_ptr().add(BUFFER_HEADER_SIZE as usize + 3) };
+    unsafe { *payload ^= 0x10 };
+    assert_eq!(
+        unsafe { cbuffer_verify_crc(output.as_ptr()) },
+        ERR_CHECKSUM_MISMATCH
+    );
+    assert_eq!(
+        unsafe { cbuffer_verify_crc(std::ptr::null()) },
+        ERR_NULL_PTR
+    );
+}
+
+#[test]
+fn spilled_payloads_are_checksummed_in_full() {
+    let _guard = format(BufferFormat::V1);
+    with_mock_transport(|_| {
+        let payload = vec![b'z'; 100];
+        let mut output = OwnedCBuffer::with_capacity(32);
+        assert_eq!(
+            unsafe { bytes_to_cbuffer_with_crc(&payload, output.as_mut_ptr()) },
+            ERR_NONE
+        );
+        assert!(output.is_spilled());
+        assert_eq!(output.flags().bits(), crc32(&payload));
+        assert_eq!(unsafe { cbuffer_verify_crc(output.as_ptr()) }, ERR_NONE);
+    });
+}
+
+#[test]
+fn checksums_are_refused_in_format_v2() {
+    let _guard = format(BufferFormat::V2);
+    let mut output = OwnedCBuffer::with_capacity(16);
+    assert_eq!(
+        unsafe { bytes_to_cbuffer_with_crc(b"flags", output.as_mut_ptr()) },
+        ERR_INVALID_CONFIG
+    );
+}
@@ -0,0 +1,91 @@
+//! Payloads visited in fixed-size chunks, in place and streamed from spills.
+
+use cobhan::testing::OwnedCBuffer;
+use cobhan::*;
+
+fn chunks(buffer: &OwnedCBuffer, chunk_size: usize) -> Result<Vec<Vec<u8>>, i32> {
+    let mut chunks = Vec::new();
+    unsafe {
+        cbuffer_for_each_chunk(buffer.as_ptr(), chunk_size, |chunk| {
+            chunks.push(chunk.to_vec());
+            Ok(())
+        })
+    }?;
+    Ok(chunks)
+}
+
+#[test]
+fn inline_payloads_are_passed_in_place() {
+    let input = OwnedCBuffer::from_bytes(b"0123456789");
+    assert_eq!(
+        chunks(&input, 3).unwrap(),
+        vec![
+            b"012".to_vec(),
+            b"345".to_vec(),
+            b"678".to_vec(),
+            b"9".to_vec()
+        ]
+    );
+    assert_eq!(chunks(&input, 10).unwrap(), vec![b"0123456789".to_vec()]);
+    assert_eq!(chunks(&input, 64).unwrap(), vec![b"0123456789".to_vec()]);
+
+    let payload = unsafe { input.as_ptr().offset(BUFFER_HEADER_SIZE) }.cast::<u8>();
+    let mut starts = Vec::new();
+    unsafe {
+        cbuffer_for_each_chunk(input.as_ptr(), 4, |chunk| {
+            starts.push(chunk.as_ptr());
+            Ok(())
+        })
+    }
+    .unwrap();
+    assert_eq!(
+        starts,
+        vec![payload, payload.wrapping_add(4), payload.wrapping_add(8)]
+    );
+
+    let empty = OwnedCBuffer::from_bytes(b"");
+    assert_eq!(chunks(&empty, 3).unwrap(), Vec::<Vec<u8>>::new());
+}
+
+#[test]
+fn spilled_payloads_are_streamed_in_whole_chunks() {
+    let payload: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+    let mut output = OwnedCBuffer::with_capacity(128);
+    assert_eq!(
+        unsafe { bytes_to_cbuffer(&payload, output.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert!(output.is_spilled());
+
+    for chunk_size in [1, 3000, 8192, 100_000, 1_000_000] {
+        let chunks = chunks(&output, chunk_size).unwrap();
+        let (last, whole) = chunks.split_last().unwrap();
+        assert!(whole.iter().all(|chunk| chunk.len() == chunk_size));
+        assert_eq!(last.len(), (payload.len() - 1) % chunk_size + 1);
+        assert_eq!(chunks.concat(), payload, "chunk size {}", chunk_size);
+    }
+}
+
+#[test]
+fn errors_stop_the_iteration() {
+    let input = OwnedCBuffer::from_bytes(b"0123456789");
+    let mut calls = 0;
+    let result = unsafe {
+        cbuffer_for_each_chunk(input.as_ptr(), 2, |_| {
+            calls += 1;
+            if calls == 2 {
+                Err(ERR_MALFORMED_PAYLOAD)
+            } else {
+                Ok(())
+            }
+        })
+    };
+    assert_eq!(result, Err(ERR_MALFORMED_PAYLOAD));
+    assert_eq!(calls, 2);
+
+    assert_eq!(chunks(&input, 0), Err(ERR_INVALID_LENGTH));
+    assert_eq!(
+        unsafe { cbuffer_for_each_chunk(std::ptr::null(), 2, |_| Ok(())) },
+        Err(ERR_NULL_PTR)
+    );
+}
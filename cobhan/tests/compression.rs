@@ -0,0 +1,161 @@
+//! Compressed payloads and the `COMPRESSED` flag. The buffer format and length limit are
+//! process-wide configuration, so the tests take turns.
+
+use cobhan::testing::{configured, with_mock_transport, OwnedCBuffer};
+use cobhan::*;
+
+fn text() -> Vec<u8> {
+    b"the quick brown fox jumps over the lazy dog; ".repeat(100)
+}
+
+#[test]
+fn payloads_round_trip_with_either_algorithm() {
+    let _guard = configured(CobhanConfig::default());
+    for (compression, magic) in [
+        (Compression::Gzip, &[0x1f, 0x8b][..]),
+        (Compression::Zstd, &[0x28, 0xb5, 0x2f, 0xfd][..]),
+    ] {
+        let mut output = OwnedCBuffer::with_capacity(1024);
+        assert_eq!(
+            unsafe { compressed_bytes_to_cbuffer(text(), compression, output.as_mut_ptr()) },
+            ERR_NONE
+        );
+        assert!(output.flags().contains(BufferFlags::COMPRESSED));
+        let compressed = output.to_vec().unwrap();
+        assert!(compressed.starts_with(magic), "{:?}", compression);
+        assert!(compressed.len() < text().len() / 10);
+        assert_eq!(
+            unsafe { cbuffer_to_decompressed_vector(output.as_ptr()) },
+            Ok(text())
+        );
+    }
+
+    // Unflagged payloads are read as they are
+    let input = OwnedCBuffer::from_bytes(b"plain");
+    assert_eq!(
+        unsafe { cbuffer_to_decompressed_vector(input.as_ptr()) },
+        Ok(b"plain".to_vec())
+    );
+}
+
+#[test]
+fn v1_sets_only_the_compressed_flag() {
+    let _guard = configured(CobhanConfig::default());
+    let mut output = OwnedCBuffer::with_capacity(1024);
+    let stale = BufferFlags::TRUNCATED.with_app_bits(5);
+    assert_eq!(
+        unsafe { set_cbuffer_flags(output.as_mut_ptr(), stale) },
+        ERR_NONE
+    );
+    assert_eq!(
+        unsafe { compressed_bytes_to_cbuffer(b"x", Compression::Gzip, output.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(output.flags(), BufferFlags::COMPRESSED.with_app_bits(5));
+}
+
+#[test]
+fn v2_spills_stay_flagged_as_compressed() {
+    let _guard = configured(CobhanConfig {
+        buffer_format: BufferFormat::V2,
+        ..Default::default()
+    });
+    with_mock_transport(|mock| {
+        let mut output = OwnedCBuffer::with_capacity(32);
+        assert_eq!(
+            unsafe { compressed_bytes_to_cbuffer(text(), Compression::Zstd, output.as_mut_ptr()) },
+            ERR_NONE
+        );
+        assert_eq!(
+            output.flags(),
+            BufferFlags::TEMP_FILE | BufferFlags::COMPRESSED
+        );
+        assert_eq!(mock.spills().len(), 1);
+        assert_eq!(
+            unsafe { cbuffer_to_decompressed_vector(output.as_ptr()) },
+            Ok(text())
+        );
+    });
+}
+
+#[test]
+fn corrupt_payloads_fail_to_decompress() {
+    let _guard = configured(CobhanConfig {
+        buffer_format: BufferFormat::V2,
+        ..Default::default()
+    });
+    let mut output = OwnedCBuffer::with_capacity(1024);
+    assert_eq!(
+        unsafe { compressed_bytes_to_cbuffer(text(), Compression::Gzip, output.as_mut_ptr()) },
+        ERR_NONE
+    );
+    let mut corrupt = output.to_vec().unwrap();
+    corrupt.truncate(corrupt.len() / 2);
+
+    for payload in [corrupt, b"not compressed".to_vec()] {
+        let mut input = OwnedCBuffer::from_bytes(&payload);
+        assert_eq!(
+            unsafe { set_cbuffer_flags(input.as_mut_ptr(), BufferFlags::COMPRESSED) },
+            ERR_NONE
+        );
+        assert_eq!(
+            unsafe { cbuffer_to_decompressed_vector(input.as_ptr()) },
+            Err(ERR_COMPRESSION_FAILED)
+        );
+    }
+}
+
+#[test]
+fn v1_ignores_the_flag_on_payloads_without_a_magic() {
+    let _guard = configured(CobhanConfig::default());
+    // A v1 host that left garbage in the reserved field
+    let mut input = OwnedCBuffer::from_bytes(b"not compressed");
+    assert_eq!(
+        unsafe { set_cbuffer_flags(input.as_mut_ptr(), BufferFlags::from_bits(u32::MAX)) },
+        ERR_NONE
+    );
+    assert_eq!(
+        unsafe { cbuffer_to_decompressed_vector(input.as_ptr()) },
+        Ok(b"not compressed".to_vec())
+    );
+
+    let mut corrupt = OwnedCBuffer::from_bytes(&[0x1f, 0x8b, 0]);
+    assert_eq!(
+        unsafe { set_cbuffer_flags(corrupt.as_mut_ptr(), BufferFlags::COMPRESSED) },
+        ERR_NONE
+    );
+    assert_eq!(
+        unsafe { cbuffer_to_decompressed_vector(corrupt.as_ptr()) },
+        Err(ERR_COMPRESSION_FAILED)
+    );
+}
+
+#[test]
+fn decompressed_payloads_are_held_to_max_buffer_len() {
+    let _guard = configured(CobhanConfig {
+        max_buffer_len: Some(1000),
+        ..Default::default()
+    });
+    let zeros = vec![0u8; 100_000];
+    for compression in [Compression::Gzip, Compression::Zstd] {
+        let mut output = OwnedCBuffer::with_capacity(1000);
+        assert_eq!(
+            unsafe { compressed_bytes_to_cbuffer(&zeros, compression, output.as_mut_ptr()) },
+            ERR_NONE
+        );
+        assert_eq!(
+            unsafe { cbuffer_to_decompressed_vector(output.as_ptr()) },
+            Err(ERR_BUFFER_TOO_LARGE)
+        );
+        assert_eq!(
+            unsafe {
+                compressed_bytes_to_cbuffer(&zeros[..1000], compression, output.as_mut_ptr())
+            },
+            ERR_NONE
+        );
+        assert_eq!(
+            unsafe { cbuffer_to_decompressed_vector(output.as_ptr()) },
+            Ok(zeros[..1000].to_vec())
+        );
+    }
+}
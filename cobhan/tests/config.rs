@@ -0,0 +1,478 @@
+//! Process-wide configuration. Every test installs its own configuration, so they take turns.
+
+use std::env;
+use std::os::raw::c_char;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use cobhan::testing::{configured, OwnedCBuffer};
+use cobhan::*;
+
+#[test]
+fn max_buffer_len_limits_writes_and_reads() {
+    let _guard = configured(CobhanConfig {
+        max_buffer_len: Some(4),
+        ..Default::default()
+    });
+
+    let mut output = OwnedCBuffer::with_capacity(64);
+    assert_eq!(
+        unsafe { bytes_to_cbuffer(b"12345", output.as_mut_ptr()) },
+        ERR_BUFFER_TOO_LARGE
+    );
+    assert_eq!(
+        unsafe { bytes_to_cbuffer(b"1234", output.as_mut_ptr()) },
+        ERR_NONE
+    );
+
+    let input = OwnedCBuffer::from_bytes(b"12345");
+    assert_eq!(
+        unsafe { cbuffer_to_vector(input.as_ptr()) },
+        Err(ERR_BUFFER_TOO_LARGE)
+    );
+    assert_eq!(
+        unsafe { cbuffer_to_string(input.as_ptr()) },
+        Err(ERR_BUFFER_TOO_LARGE)
+    );
+}
+
+#[test]
+fn settings_are_read_from_the_environment_again_after_deinit() {
+    let _guard = configured(CobhanConfig {
+        max_buffer_len: Some(4),
+        ..Default::default()
+    });
+    env::set_var("COBHAN_MAX_BUFFER_LEN", "8");
+    cobhan_deinit();
+
+    let mut output = OwnedCBuffer::with_capacity(64);
+    assert_eq!(
+        unsafe { bytes_to_cbuffer(b"123456789", output.as_mut_ptr()) },
+        ERR_BUFFER_TOO_LARGE
+    );
+    env::remove_var("COBHAN_MAX_BUFFER_LEN");
+    assert_eq!(
+        unsafe { bytes_to_cbuffer(b"12345678", output.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(current_config().max_buffer_len, Some(8));
+}
+
+#[test]
+fn limits_apply_to_patched_payloads() {
+    let base = [7u8; 8];
+    // Nine bytes of patch copy the whole base
+    let mut writer = cobhan::patch::PatchWriter::new();
+    for _ in 0..3 {
+        writer.copy(0, base.len()).unwrap();
+    }
+    let patch = writer.into_bytes();
+
+    let _guard = configured(CobhanConfig {
+        max_buffer_len: Some(16),
+        ..Default::default()
+    });
+    assert_eq!(
+        cobhan::patch::apply_patch(&base, &patch),
+        Err(ERR_BUFFER_TOO_LARGE)
+    );
+    assert_eq!(
+        cobhan::patch::apply_patch(&base, &patch[..18]),
+        Ok(vec![7; 16])
+    );
+    drop(_guard);
+
+    let _guard = configured(CobhanConfig {
+        max_marshaling_bytes: Some(16),
+        ..Default::default()
+    });
+    assert_eq!(
+        cobhan::patch::apply_patch(&base, &patch),
+        Err(ERR_OUT_OF_MEMORY)
+    );
+    assert_eq!(current_marshaling_bytes(), 0);
+}
+
+#[test]
+fn max_marshaling_bytes_limits_conversions_in_flight() {
+    let _guard = configured(CobhanConfig {
+        max_marshaling_bytes: Some(4),
+        ..Default::default()
+    });
+
+    let input = OwnedCBuffer::from_bytes(b"12345");
+    assert_eq!(
+        unsafe { cbuffer_to_vector(input.as_ptr()) },
+        Err(ERR_OUT_OF_MEMORY)
+    );
+    assert_eq!(
+        unsafe { cbuffer_to_string(input.as_ptr()) },
+        Err(ERR_OUT_OF_MEMORY)
+    );
+    let input = OwnedCBuffer::from_bytes(b"1234");
+    assert_eq!(
+        unsafe { cbuffer_to_vector(input.as_ptr()) },
+        Ok(b"1234".to_vec())
+    );
+
+    let mut output = OwnedCBuffer::with_capacity(64);
+    let mut json = std::collections::HashMap::new();
+    json.insert("key".to_string(), serde_json::Value::Null);
+    assert_eq!(
+        unsafe { hashmap_json_to_cbuffer(&json, output.as_mut_ptr()) },
+        ERR_OUT_OF_MEMORY
+    );
+
+    // Charges are released when the conversions return
+    assert_eq!(current_marshaling_bytes(), 0);
+}
+
+#[test]
+fn max_marshaling_bytes_limits_spilled_chunks() {
+    let _guard = configured(CobhanConfig {
+        max_marshaling_bytes: Some(8),
+        ..Default::default()
+    });
+
+    // The chunks outgrow the buffer at 6 bytes, within the limit, and keep growing past it
+    let mut output = OwnedCBuffer::with_capacity(4);
+    let chunks: [&[u8]; 4] = [b"123", b"456", b"789", b"abc"];
+    assert_eq!(
+        unsafe { chunks_to_cbuffer(chunks, output.as_mut_ptr()) },
+        ERR_OUT_OF_MEMORY
+    );
+    assert_eq!(current_marshaling_bytes(), 0);
+}
+
+#[test]
+fn max_marshaling_bytes_limits_spilled_reads_and_decoded_output() {
+    let _guard = configured(CobhanConfig::default());
+    let mut output = OwnedCBuffer::with_capacity(4096);
+    assert_eq!(
+        unsafe { bytes_to_cbuffer([7; 200_000], output.as_mut_ptr()) },
+        ERR_NONE
+    );
+    configure(CobhanConfig {
+        max_marshaling_bytes: Some(100_000),
+        ..Default::default()
+    });
+    assert_eq!(
+        unsafe { cbuffer_to_vector(output.as_ptr()) },
+        Err(ERR_OUT_OF_MEMORY)
+    );
+    assert_eq!(
+        unsafe { cbuffer_to_string(output.as_ptr()) },
+        Err(ERR_OUT_OF_MEMORY)
+    );
+
+    // 12 bytes unpack to 64
+    let mut bitset = 64u32.to_le_bytes().to_vec();
+    bitset.extend_from_slice(&[0xff; 8]);
+    let input = OwnedCBuffer::from_bytes(&bitset);
+    configure(CobhanConfig {
+        max_marshaling_bytes: Some(32),
+        ..Default::default()
+    });
+    assert_eq!(
+        unsafe { cbuffer_to_bitvec(input.as_ptr()) },
+        Err(ERR_OUT_OF_MEMORY)
+    );
+    configure(CobhanConfig {
+        max_marshaling_bytes: Some(64),
+        ..Default::default()
+    });
+    assert_eq!(
+        unsafe { cbuffer_to_bitvec(input.as_ptr()) },
+        Ok(vec![true; 64])
+    );
+    assert_eq!(current_marshaling_bytes(), 0);
+}
+
+#[test]
+fn max_buffer_len_limits_spilled_reads() {
+    let _guard = configured(CobhanConfig::default());
+    let mut output = OwnedCBuffer::with_capacity(4096);
+    let payload = vec![7; 8192];
+    assert_eq!(
+        unsafe { bytes_to_cbuffer(&payload, output.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert!(output.is_spilled());
+
+    configure(CobhanConfig {
+        max_buffer_len: Some(4096),
+        ..Default::default()
+    });
+    assert_eq!(
+        unsafe { cbuffer_to_vector(output.as_ptr()) },
+        Err(ERR_BUFFER_TOO_LARGE)
+    );
+}
+
+#[test]
+fn reject_policy_does_not_spill() {
+    let _guard = configured(CobhanConfig {
+        spill_policy: SpillPolicy::Reject,
+        ..Default::default()
+    });
+
+    let mut output = OwnedCBuffer::with_capacity(4);
+    assert_eq!(
+        unsafe { bytes_to_cbuffer(b"12345", output.as_mut_ptr()) },
+        ERR_BUFFER_TOO_SMALL
+    );
+    assert_eq!(output.temp_file_path(), None);
+}
+
+#[test]
+fn temp_dir_receives_spill_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let _guard = configured(CobhanConfig {
+        temp_dir: Some(dir.path().to_path_buf()),
+        ..Default::default()
+    });
+    assert_eq!(temp_dir(), dir.path());
+
+    let mut output = OwnedCBuffer::with_capacity(4096);
+    assert_eq!(
+        unsafe { bytes_to_cbuffer([7; 8192], output.as_mut_ptr()) },
+        ERR_NONE
+    );
+    let path = output.temp_file_path().expect("payload should spill");
+    assert_eq!(path.parent(), Some(dir.path()));
+
+    reset_temp_dir();
+    assert_eq!(current_config().temp_dir, None);
+}
+
+#[test]
+fn from_env_reads_cobhan_variables() {
+    let _guard = configured(CobhanConfig::default());
+    env::set_var("COBHAN_TEMP_DIR", "/var/cobhan");
+    env::set_var("COBHAN_MAX_BUFFER_LEN", "1024");
+    env::set_var("COBHAN_MAX_MARSHALING_BYTES", "4096");
+    env::set_var("COBHAN_MAX_LEASED_BYTES", "8192");
+    env::set_var("COBHAN_SPILL_POLICY", "reject");
+    env::set_var("COBHAN_BUFFER_FORMAT", "2");
+    env::set_var("COBHAN_DEBUG_SINK", "off");
+    env::set_var("COBHAN_LOG_LEVEL", "warn");
+    env::set_var("COBHAN_DEFENSIVE_COPY_MODE", "1");
+    env::set_var("COBHAN_REUSE_SPILL_FILES", "1");
+    env::set_var("COBHAN_MAX_JSON_BYTES", "65536");
+    env::set_var("COBHAN_MAX_JSON_DEPTH", "32");
+    env::set_var("COBHAN_MAX_JSON_KEYS", "1000");
+    let config = CobhanConfig::from_env();
+    assert_eq!(config.temp_dir, Some(PathBuf::from("/var/cobhan")));
+    assert_eq!(config.max_buffer_len, Some(1024));
+    assert_eq!(config.max_marshaling_bytes, Some(4096));
+    assert_eq!(config.max_leased_bytes, Some(8192));
+    assert_eq!(config.spill_policy, SpillPolicy::Reject);
+    assert_eq!(config.buffer_format, BufferFormat::V2);
+    assert!(matches!(config.debug_sink, DebugSink::Off));
+    assert_eq!(config.log_level, LogLevel::Warn);
+    assert!(config.defensive_copy_mode);
+    assert!(config.reuse_spill_files);
+    assert_eq!(config.max_json_bytes, Some(65536));
+    assert_eq!(config.max_json_depth, Some(32));
+    assert_eq!(config.max_json_keys, Some(1000));
+
+    // Values that do not parse fall back to the defaults
+    env::set_var("COBHAN_MAX_BUFFER_LEN", "lots");
+    env::set_var("COBHAN_SPILL_POLICY", "sometimes");
+    env::set_var("COBHAN_BUFFER_FORMAT", "3");
+    env::set_var("COBHAN_DEBUG_SINK", "");
+    env::set_var("COBHAN_LOG_LEVEL", "loud");
+    env::set_var("COBHAN_DEFENSIVE_COPY_MODE", "yes");
+    env::set_var("COBHAN_REUSE_SPILL_FILES", "yes");
+    env::set_var("COBHAN_MAX_JSON_DEPTH", "-1");
+    let config = CobhanConfig::from_env();
+    assert_eq!(config.max_buffer_len, None);
+    assert_eq!(config.spill_policy, SpillPolicy::Spill);
+    assert_eq!(config.buffer_format, BufferFormat::V1);
+    assert!(matches!(config.debug_sink, DebugSink::Platform));
+    assert_eq!(config.log_level, LogLevel::Debug);
+    assert!(!config.defensive_copy_mode);
+    assert!(!config.reuse_spill_files);
+    assert_eq!(config.max_json_depth, None);
+
+    for name in [
+        "COBHAN_TEMP_DIR",
+        "COBHAN_MAX_BUFFER_LEN",
+        "COBHAN_MAX_MARSHALING_BYTES",
+        "COBHAN_MAX_LEASED_BYTES",
+        "COBHAN_SPILL_POLICY",
+        "COBHAN_BUFFER_FORMAT",
+        "COBHAN_DEBUG_SINK",
+        "COBHAN_LOG_LEVEL",
+        "COBHAN_DEFENSIVE_COPY_MODE",
+        "COBHAN_REUSE_SPILL_FILES",
+        "COBHAN_MAX_JSON_BYTES",
+        "COBHAN_MAX_JSON_DEPTH",
+        "COBHAN_MAX_JSON_KEYS",
+    ] {
+        env::remove_var(name);
+    }
+}
+
+#[cfg(feature = "cobhan_debug")]
+#[test]
+fn callback_sink_receives_debug_output() {
+    use std::sync::Arc;
+
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let sink = lines.clone();
+    let _guard = configured(CobhanConfig {
+        debug_sink: DebugSink::Callback(Arc::new(move |line: &str| {
+            sink.lock().unwrap().push(line.to_string())
+        })),
+        ..Default::default()
+    });
+
+    assert_eq!(
+        unsafe { cbuffer_to_vector(std::ptr::null()) },
+        Err(ERR_NULL_PTR)
+    );
+    assert!(lines
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|line| line.contains("buffer is NULL")));
+}
+
+#[cfg(feature = "cobhan_debug")]
+#[test]
+fn log_level_filters_debug_output() {
+    use std::sync::Arc;
+
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let sink = lines.clone();
+    let _guard = configured(CobhanConfig {
+        debug_sink: DebugSink::Leveled(Arc::new(move |level, line: &str| {
+            sink.lock().unwrap().push((level, line.to_string()))
+        })),
+        max_buffer_len: Some(4),
+        ..Default::default()
+    });
+    let logged = |wanted: LogLevel, text: &str| {
+        lines
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|(level, line)| *level == wanted && line.contains(text))
+    };
+
+    unsafe { cbuffer_to_vector(std::ptr::null()) }.unwrap_err();
+    assert!(logged(LogLevel::Debug, "buffer is NULL"));
+
+    assert_eq!(cobhan_set_log_level(LogLevel::Info as i32), ERR_NONE);
+    assert_eq!(current_config().log_level, LogLevel::Info);
+    lines.lock().unwrap().clear();
+    unsafe { cbuffer_to_vector(std::ptr::null()) }.unwrap_err();
+    assert!(lines.lock().unwrap().is_empty());
+
+    // Limits are logged as warnings, and the steps of a call only at trace
+    let input = OwnedCBuffer::from_bytes(b"12345");
+    unsafe { cbuffer_to_vector(input.as_ptr()) }.unwrap_err();
+    assert!(logged(LogLevel::Warn, "over the limit"));
+    assert!(!logged(LogLevel::Trace, "raw length field"));
+    assert_eq!(cobhan_set_log_level(LogLevel::Trace as i32), ERR_NONE);
+    unsafe { cbuffer_to_vector(input.as_ptr()) }.unwrap_err();
+    assert!(logged(LogLevel::Trace, "raw length field"));
+    assert_eq!(cobhan_set_log_level(LogLevel::Info as i32), ERR_NONE);
+
+    for level in [-1, 6] {
+        assert_eq!(cobhan_set_log_level(level), ERR_INVALID_CONFIG);
+    }
+    assert_eq!(current_config().log_level, LogLevel::Info);
+}
+
+#[cfg(feature = "cobhan_debug")]
+#[test]
+fn extern_log_callback_receives_levels_and_lines() {
+    static LINES: Mutex<Vec<(i32, String)>> = Mutex::new(Vec::new());
+
+    unsafe extern "C" fn callback(level: i32, message: *const c_char) {
+        let line = std::ffi::CStr::from_ptr(message)
+            .to_string_lossy()
+            .into_owned();
+        LINES.lock().unwrap().push((level, line));
+    }
+
+    let _guard = configured(CobhanConfig::default());
+    unsafe { cobhan_set_log_callback(Some(callback)) };
+    assert!(matches!(current_config().debug_sink, DebugSink::Leveled(_)));
+    unsafe { cbuffer_to_vector(std::ptr::null()) }.unwrap_err();
+    assert!(LINES
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|(level, line)| *level == 4 && line.contains("buffer is NULL")));
+
+    unsafe { cobhan_set_log_callback(None) };
+    assert!(matches!(current_config().debug_sink, DebugSink::Platform));
+}
+
+#[test]
+fn cobhan_configure_applies_json_settings() {
+    let _guard = configured(CobhanConfig::default());
+    let json = br#"{"temp_dir": "/var/cobhan", "max_buffer_len": 1024, "spill_policy": "reject"}"#;
+    let input = OwnedCBuffer::from_bytes(json);
+    assert_eq!(unsafe { cobhan_configure(input.as_ptr()) }, ERR_NONE);
+    let config = current_config();
+    assert_eq!(config.temp_dir, Some(PathBuf::from("/var/cobhan")));
+    assert_eq!(config.max_buffer_len, Some(1024));
+    assert_eq!(config.spill_policy, SpillPolicy::Reject);
+
+    // Settings that are not present keep their value, null clears
+    let input = OwnedCBuffer::from_bytes(
+        br#"{"max_buffer_len": null, "max_marshaling_bytes": 65536, "max_leased_bytes": 4096,
+            "buffer_format": 2, "debug_sink": "off", "log_level": "error",
+            "defensive_copy_mode": true, "reuse_spill_files": true,
+            "max_json_bytes": 65536, "max_json_depth": 32, "max_json_keys": null}"#,
+    );
+    assert_eq!(unsafe { cobhan_configure(input.as_ptr()) }, ERR_NONE);
+    let config = current_config();
+    assert_eq!(config.buffer_format, BufferFormat::V2);
+    assert_eq!(config.temp_dir, Some(PathBuf::from("/var/cobhan")));
+    assert_eq!(config.max_buffer_len, None);
+    assert_eq!(config.max_marshaling_bytes, Some(65536));
+    assert_eq!(config.max_leased_bytes, Some(4096));
+    assert!(matches!(config.debug_sink, DebugSink::Off));
+    assert_eq!(config.log_level, LogLevel::Error);
+    assert!(config.defensive_copy_mode);
+    assert!(config.reuse_spill_files);
+    assert_eq!(config.max_json_bytes, Some(65536));
+    assert_eq!(config.max_json_depth, Some(32));
+    assert_eq!(config.max_json_keys, None);
+}
+
+#[test]
+fn cobhan_configure_rejects_invalid_settings() {
+    let _guard = configured(CobhanConfig::default());
+    for json in [
+        &br#"{"max_buffer_len": 16, "spill_policy": "sometimes"}"#[..],
+        br#"{"max_buffer_len": -1}"#,
+        br#"{"temp_dir": 7}"#,
+        br#"{"buffer_format": 3}"#,
+        br#"{"buffer_format": "2"}"#,
+        br#"{"log_level": 4}"#,
+        br#"{"defensive_copy_mode": 1}"#,
+        br#"{"reuse_spill_files": "yes"}"#,
+        br#"{"max_json_depth": -1}"#,
+        br#"{"max_json_keys": "many"}"#,
+        br#"{"temp_dri": "/tmp"}"#,
+    ] {
+        let input = OwnedCBuffer::from_bytes(json);
+        assert_eq!(
+            unsafe { cobhan_configure(input.as_ptr()) },
+            ERR_INVALID_CONFIG
+        );
+        assert_eq!(current_config().max_buffer_len, None);
+    }
+
+    let input = OwnedCBuffer::from_bytes(b"[1, 2]");
+    assert_eq!(
+        unsafe { cobhan_configure(input.as_ptr()) },
+        ERR_JSON_DECODE_FAILED
+    );
+}
@@ -0,0 +1,117 @@
+use std::fs;
+
+use cobhan::conformance::{vector, vectors, verify_vector, write_vectors, Mismatch};
+use cobhan::testing::OwnedCBuffer;
+use cobhan::*;
+use serde_json::{json, Value};
+
+fn code<T>(result: &Result<T, i32>) -> i32 {
+    match result {
+        Ok(_) => ERR_NONE,
+        Err(e) => *e,
+    }
+}
+
+#[test]
+fn written_vectors_read_back_as_the_manifest_says() {
+    let dir = tempfile::tempdir().unwrap();
+    write_vectors(dir.path()).unwrap();
+    let manifest: Value =
+        serde_json::from_slice(&fs::read(dir.path().join("manifest.json")).unwrap()).unwrap();
+    let entries = manifest["vectors"].as_array().unwrap();
+    assert_eq!(entries.len(), vectors().len());
+
+    for entry in entries {
+        let name = entry["name"].as_str().unwrap();
+        let buffer = fs::read(dir.path().join(format!("{}.bin", name))).unwrap();
+        let payload = fs::read(dir.path().join(format!("{}.payload", name))).unwrap();
+        assert_eq!(
+            buffer.len(),
+            BUFFER_HEADER_SIZE as usize + entry["capacity"].as_u64().unwrap() as usize,
+            "{}",
+            name
+        );
+        assert_eq!(
+            dir.path().join(format!("{}.spill", name)).exists(),
+            entry["spilled"] == json!(true)
+        );
+
+        let ptr = buffer.as_ptr().cast();
+        let bytes = unsafe { cbuffer_to_vector(ptr) };
+        assert_eq!(code(&bytes), entry["read_result"], "{}", name);
+        if let Ok(bytes) = bytes {
+            assert_eq!(bytes, payload, "{}", name);
+        }
+        let string = unsafe { cbuffer_to_string(ptr) };
+        assert_eq!(code(&string), entry["string_result"], "{}", name);
+
+        let mut report = OwnedCBuffer::with_capacity(256);
+        let name_buffer = OwnedCBuffer::from_bytes(name.as_bytes());
+        let result =
+            unsafe { cobhan_conformance_verify(name_buffer.as_ptr(), ptr, report.as_mut_ptr()) };
+        assert_eq!(result, ERR_NONE);
+        assert_eq!(report.to_vec().unwrap(), b"null", "{}", name);
+    }
+}
+
+#[test]
+fn mismatches_are_reported() {
+    let utf8 = vector("utf8").unwrap();
+    let mut wrong = utf8.payload.clone();
+    wrong[3] ^= 1;
+    let produced = OwnedCBuffer::from_bytes(&wrong);
+    let mismatch = unsafe { verify_vector(&utf8, produced.as_ptr()) }.unwrap();
+    assert!(matches!(
+        mismatch,
+        Some(Mismatch::Payload(DiffReport::Payload { offset: 3, .. }))
+    ));
+
+    let spill = vector("temp_spill").unwrap();
+    let produced = OwnedCBuffer::from_bytes(&spill.payload);
+    assert_eq!(
+        unsafe { verify_vector(&spill, produced.as_ptr()) },
+        Ok(Some(Mismatch::Spilled {
+            expected: true,
+            actual: false,
+        }))
+    );
+
+    let invalid = vector("invalid_length").unwrap();
+    assert_eq!(
+        unsafe { verify_vector(&invalid, produced.as_ptr()) },
+        Ok(Some(Mismatch::Read {
+            expected: ERR_INVALID_LENGTH,
+            actual: ERR_NONE,
+        }))
+    );
+
+    let mut report = OwnedCBuffer::with_capacity(256);
+    let name = OwnedCBuffer::from_bytes(b"temp_spill");
+    let result =
+        unsafe { cobhan_conformance_verify(name.as_ptr(), produced.as_ptr(), report.as_mut_ptr()) };
+    assert_eq!(result, ERR_NONE);
+    assert_eq!(report.to_hashmap_json().unwrap()["field"], json!("spilled"));
+}
+
+#[test]
+fn unknown_vectors_are_rejected() {
+    let mut report = OwnedCBuffer::with_capacity(256);
+    let name = OwnedCBuffer::from_bytes(b"no_such_vector");
+    let produced = OwnedCBuffer::from_bytes(b"");
+    let result =
+        unsafe { cobhan_conformance_verify(name.as_ptr(), produced.as_ptr(), report.as_mut_ptr()) };
+    assert_eq!(result, ERR_UNKNOWN_TEST_VECTOR);
+}
+
+#[test]
+fn vectors_are_written_through_the_export() {
+    let dir = tempfile::tempdir().unwrap();
+    let target = dir.path().join("vectors");
+    let path = OwnedCBuffer::from_bytes(target.to_str().unwrap().as_bytes());
+    assert_eq!(
+        unsafe { cobhan_conformance_write_vectors(path.as_ptr()) },
+        ERR_NONE
+    );
+    assert!(target.join("manifest.json").exists());
+    assert!(target.join("temp_spill.spill").exists());
+}
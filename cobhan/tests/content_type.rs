@@ -0,0 +1,128 @@
+//! Content type hints in the flags field. The hints are only honored in format v2, which is
+//! process-wide configuration, so the tests take turns.
+
+use std::sync::MutexGuard;
+
+use cobhan::testing::{configured, OwnedCBuffer};
+use cobhan::*;
+use serde_json::json;
+
+fn format(format: BufferFormat) -> MutexGuard<'static, ()> {
+    configured(CobhanConfig {
+        buffer_format: format,
+        ..Default::default()
+    })
+}
+
+fn hinted(payload: &[u8], content_type: ContentType) -> OwnedCBuffer {
+    let mut buffer = OwnedCBuffer::with_capacity(64);
+    assert_eq!(
+        unsafe { bytes_to_cbuffer(payload, buffer.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(
+        unsafe { set_cbuffer_content_type(buffer.as_mut_ptr(), content_type) },
+        ERR_NONE
+    );
+    buffer
+}
+
+#[test]
+fn codes_round_trip() {
+    for code in 0..=u8::MAX {
+        assert_eq!(ContentType::from_code(code).code(), code);
+    }
+    assert_eq!(ContentType::from_code(4), ContentType::MsgPack);
+    assert_eq!(ContentType::from_code(200), ContentType::Other(200));
+}
+
+#[test]
+fn hints_share_the_flags_field() {
+    let flags = BufferFlags::TRUNCATED
+        .with_app_bits(0xbeef)
+        .with_content_type(ContentType::Json);
+    assert_eq!(flags.bits(), 0xbeef_0204);
+    assert_eq!(flags.content_type(), ContentType::Json);
+    assert_eq!(
+        flags.with_content_type(ContentType::Unspecified),
+        BufferFlags::TRUNCATED.with_app_bits(0xbeef)
+    );
+    assert_eq!(format!("{:?}", flags), "{TRUNCATED, Json, app 0xbeef}");
+}
+
+#[test]
+fn hints_are_set_after_writing_and_cleared_by_the_next_write() {
+    let _guard = format(BufferFormat::V2);
+    let mut buffer = hinted(b"{}", ContentType::Json);
+    assert_eq!(
+        unsafe { cbuffer_content_type(buffer.as_ptr()) },
+        Ok(ContentType::Json)
+    );
+    assert_eq!(buffer.length_field(), 2);
+
+    buffer.reset();
+    assert_eq!(
+        unsafe { bytes_to_cbuffer(b"next", buffer.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(buffer.flags().content_type(), ContentType::Unspecified);
+
+    assert_eq!(
+        unsafe { cbuffer_content_type(std::ptr::null()) },
+        Err(ERR_NULL_PTR)
+    );
+    assert_eq!(
+        unsafe { set_cbuffer_content_type(std::ptr::null_mut(), ContentType::Utf8) },
+        ERR_NULL_PTR
+    );
+}
+
+#[test]
+fn payloads_are_decoded_by_their_hint() {
+    let _guard = format(BufferFormat::V2);
+    let decode = |buffer: &OwnedCBuffer| unsafe { decode_any(buffer.as_ptr()) };
+    assert_eq!(
+        decode(&hinted(b"hello", ContentType::Utf8)),
+        Ok(DecodedValue::Text("hello".to_owned()))
+    );
+    assert_eq!(
+        decode(&hinted(br#"{"a": [1]}"#, ContentType::Json)),
+        Ok(DecodedValue::Json(json!({"a": [1]})))
+    );
+    assert_eq!(
+        decode(&hinted(b"\x81\xa1a\x01", ContentType::MsgPack)),
+        Ok(DecodedValue::Bytes(
+            ContentType::MsgPack,
+            b"\x81\xa1a\x01".to_vec()
+        ))
+    );
+    assert_eq!(
+        decode(&OwnedCBuffer::from_bytes(b"raw")),
+        Ok(DecodedValue::Bytes(
+            ContentType::Unspecified,
+            b"raw".to_vec()
+        ))
+    );
+
+    assert_eq!(
+        decode(&hinted(b"\xff", ContentType::Utf8)),
+        Err(ERR_INVALID_UTF8)
+    );
+    assert_eq!(
+        decode(&hinted(b"{", ContentType::Json)),
+        Err(ERR_JSON_DECODE_FAILED)
+    );
+}
+
+#[test]
+fn v1_ignores_hints() {
+    let _guard = format(BufferFormat::V1);
+    let buffer = hinted(b"{}", ContentType::Json);
+    assert_eq!(
+        unsafe { decode_any(buffer.as_ptr()) },
+        Ok(DecodedValue::Bytes(
+            ContentType::Unspecified,
+            b"{}".to_vec()
+        ))
+    );
+}
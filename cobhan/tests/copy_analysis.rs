@@ -0,0 +1,66 @@
+//! Copy analysis attributes copies to the call sites of the copying conversions. The report is
+//! process-wide, so the tests take turns.
+
+use std::sync::MutexGuard;
+
+use cobhan::stats::{copy_report, reset_copy_report, CopySite};
+use cobhan::testing::{configured, with_mock_transport, OwnedCBuffer};
+use cobhan::*;
+
+fn serialized() -> MutexGuard<'static, ()> {
+    let guard = configured(CobhanConfig::default());
+    reset_copy_report();
+    guard
+}
+
+fn site(conversion: &str) -> CopySite {
+    copy_report()
+        .into_iter()
+        .find(|site| site.conversion == conversion)
+        .unwrap()
+}
+
+#[test]
+fn inline_copies_are_borrowable() {
+    let _guard = serialized();
+    let input = OwnedCBuffer::from_bytes(b"0123456789");
+    for _ in 0..3 {
+        unsafe { cbuffer_to_vector(input.as_ptr()) }.unwrap();
+    }
+    let line = line!() - 2;
+    unsafe { cbuffer_to_cstring(input.as_ptr()) }.unwrap();
+
+    let report = copy_report();
+    assert_eq!(report.len(), 2);
+    let vector = &report[0];
+    assert_eq!(vector.file, file!());
+    assert_eq!(vector.line, line);
+    assert_eq!(vector.conversion, "cbuffer_to_vector");
+    assert_eq!(vector.calls, 3);
+    assert_eq!(vector.copied_bytes, 30);
+    assert_eq!(vector.borrowable_bytes, 30);
+    assert_eq!(site("cbuffer_to_cstring").copied_bytes, 10);
+
+    reset_copy_report();
+    assert!(copy_report().is_empty());
+}
+
+#[test]
+fn spilled_copies_are_not_borrowable() {
+    let _guard = serialized();
+    with_mock_transport(|mock| {
+        let input = OwnedCBuffer::spilled(&mock.insert(b"spilled"));
+        unsafe { cbuffer_to_string(input.as_ptr()) }.unwrap();
+    });
+    let string = site("cbuffer_to_string");
+    assert_eq!(string.copied_bytes, 7);
+    assert_eq!(string.borrowable_bytes, 0);
+}
+
+#[test]
+fn failed_conversions_are_not_recorded() {
+    let _guard = serialized();
+    let input = OwnedCBuffer::from_bytes(b"\xff");
+    assert!(unsafe { cbuffer_to_string(input.as_ptr()) }.is_err());
+    assert!(copy_report().is_empty());
+}
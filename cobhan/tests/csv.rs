@@ -0,0 +1,98 @@
+//! CSV payloads decoded into and encoded from serde records.
+
+use cobhan::testing::OwnedCBuffer;
+use cobhan::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+struct Sale {
+    region: String,
+    units: u32,
+    price: Option<f64>,
+}
+
+fn sale(region: &str, units: u32, price: Option<f64>) -> Sale {
+    Sale {
+        region: region.to_string(),
+        units,
+        price,
+    }
+}
+
+#[test]
+fn records_round_trip_with_a_header_row() {
+    let sales = vec![
+        sale("west, coast", 3, Some(9.5)),
+        sale("\"north\"", 0, None),
+    ];
+    let mut output = OwnedCBuffer::with_capacity(256);
+    assert_eq!(
+        unsafe { records_to_cbuffer(sales.iter(), output.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(
+        output.to_string().unwrap(),
+        "region,units,price\n\"west, coast\",3,9.5\n\"\"\"north\"\"\",0,\n"
+    );
+    let decoded: Vec<Sale> = unsafe { cbuffer_to_csv_records(output.as_ptr()) }.unwrap();
+    assert_eq!(decoded, sales);
+}
+
+#[test]
+fn columns_are_matched_by_header_name() {
+    let input = OwnedCBuffer::from_bytes(b"units,region,price\r\n7,east,1.25\r\n");
+    let decoded: Vec<Sale> = unsafe { cbuffer_to_csv_records(input.as_ptr()) }.unwrap();
+    assert_eq!(decoded, vec![sale("east", 7, Some(1.25))]);
+
+    let input = OwnedCBuffer::from_bytes(b"region,units,price\n");
+    let decoded: Vec<Sale> = unsafe { cbuffer_to_csv_records(input.as_ptr()) }.unwrap();
+    assert!(decoded.is_empty());
+}
+
+#[test]
+fn malformed_rows_fail_to_decode() {
+    for csv in [
+        &b"region,units,price\neast,many,1\n"[..],
+        b"region,units,price\neast,1\n",
+        b"region,price\neast,1\n",
+    ] {
+        let input = OwnedCBuffer::from_bytes(csv);
+        assert_eq!(
+            unsafe { cbuffer_to_csv_records::<Sale>(input.as_ptr()) },
+            Err(ERR_CSV_DECODE_FAILED)
+        );
+    }
+}
+
+#[test]
+fn nested_records_fail_to_encode() {
+    #[derive(Serialize)]
+    struct Nested {
+        sale: Sale,
+        tags: Vec<String>,
+    }
+    let records = [Nested {
+        sale: sale("east", 1, None),
+        tags: vec!["a".to_string()],
+    }];
+    let mut output = OwnedCBuffer::with_capacity(256);
+    assert_eq!(
+        unsafe { records_to_cbuffer(records.iter(), output.as_mut_ptr()) },
+        ERR_CSV_ENCODE_FAILED
+    );
+}
+
+#[test]
+fn large_extracts_spill_and_read_back() {
+    let sales: Vec<Sale> = (0..2_000)
+        .map(|i| sale(&format!("region {}", i), i, Some(i as f64 / 4.0)))
+        .collect();
+    let mut output = OwnedCBuffer::with_capacity(128);
+    assert_eq!(
+        unsafe { records_to_cbuffer(sales.iter(), output.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert!(output.is_spilled());
+    let decoded: Vec<Sale> = unsafe { cbuffer_to_csv_records(output.as_ptr()) }.unwrap();
+    assert_eq!(decoded, sales);
+}
@@ -0,0 +1,77 @@
+//! Deadlines passed by hosts as epoch milliseconds.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use cobhan::*;
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64
+}
+
+#[test]
+fn zero_and_negative_mean_no_deadline() {
+    for millis in [0, -1, i64::MIN] {
+        let deadline = Deadline::from_epoch_millis(millis);
+        assert_eq!(deadline, Deadline::none());
+        assert_eq!(deadline.remaining(), None);
+        assert!(!deadline.is_expired());
+        assert_eq!(deadline.check(), Ok(()));
+        assert_eq!(deadline.to_epoch_millis(), 0);
+    }
+    assert_eq!(Deadline::default(), Deadline::none());
+}
+
+#[test]
+fn future_deadlines_count_down() {
+    let millis = now_millis() + 60_000;
+    let deadline = Deadline::from_epoch_millis(millis);
+    let remaining = deadline.remaining().unwrap();
+    assert!(remaining > Duration::from_secs(55), "{:?}", remaining);
+    assert!(remaining <= Duration::from_secs(60), "{:?}", remaining);
+    assert_eq!(deadline.check(), Ok(()));
+    assert!((deadline.to_epoch_millis() - millis).abs() < 1000);
+
+    let remaining = Deadline::after(Duration::from_secs(5)).remaining().unwrap();
+    assert!(remaining > Duration::from_secs(4) && remaining <= Duration::from_secs(5));
+}
+
+#[test]
+fn past_deadlines_are_expired() {
+    for millis in [1, now_millis() - 1000] {
+        let deadline = Deadline::from_epoch_millis(millis);
+        assert_eq!(deadline.remaining(), Some(Duration::ZERO));
+        assert!(deadline.is_expired());
+        assert_eq!(deadline.check(), Err(ERR_DEADLINE_EXCEEDED));
+        assert_eq!(deadline.run(|| 1), Err(ERR_DEADLINE_EXCEEDED));
+    }
+}
+
+#[test]
+fn run_returns_results_in_time_and_fails_after() {
+    let caller = std::thread::current().id();
+    assert_eq!(
+        Deadline::none().run(move || std::thread::current().id() == caller),
+        Ok(true)
+    );
+    assert_eq!(Deadline::after(Duration::from_secs(30)).run(|| 42), Ok(42));
+
+    let deadline = Deadline::after(Duration::from_millis(20));
+    let result = deadline.run(|| std::thread::sleep(Duration::from_secs(2)));
+    assert_eq!(result, Err(ERR_DEADLINE_EXCEEDED));
+    assert!(deadline.is_expired());
+}
+
+#[test]
+fn panics_in_run_reach_the_caller() {
+    let deadline = Deadline::after(Duration::from_secs(30));
+    assert_eq!(
+        ffi_guard(|| deadline
+            .run(|| panic!("in the helper"))
+            .unwrap_or_else(|e| e)),
+        ERR_PANIC
+    );
+    assert!(last_error().unwrap().contains("in the helper"));
+}
@@ -0,0 +1,113 @@
+//! JSON payloads encoded with key dictionaries.
+
+use cobhan::testing::OwnedCBuffer;
+use cobhan::*;
+use serde_json::json;
+
+#[test]
+fn keys_are_sent_once() {
+    let mut encoder = KeyDictionary::new();
+    let mut decoder = KeyDictionary::new();
+    let first = json!({"service": "api", "tags": [{"service": "db"}], "latency_ms": 12});
+    let encoded = encoder.encode(&first);
+    assert_eq!(
+        encoded,
+        json!({"keys": ["latency_ms", "service", "tags"], "value": {"0": 12, "1": "api", "2": [{"1": "db"}]}})
+    );
+    assert_eq!(decoder.decode(&encoded), Ok(first));
+
+    let second = json!({"service": "web", "status": 200});
+    let encoded = encoder.encode(&second);
+    assert_eq!(
+        encoded,
+        json!({"keys": ["status"], "value": {"1": "web", "3": 200}})
+    );
+    assert_eq!(decoder.decode(&encoded), Ok(second));
+    assert_eq!((encoder.len(), decoder.len()), (4, 4));
+    assert_eq!(encoder.encode(&json!([1, "x"]))["keys"], json!([]));
+}
+
+#[test]
+fn malformed_payloads_are_rejected() {
+    let mut decoder = KeyDictionary::new();
+    for encoded in [
+        json!([]),
+        json!({"value": {}}),
+        json!({"keys": ["a", 1], "value": {}}),
+    ] {
+        assert_eq!(decoder.decode(&encoded), Err(ERR_JSON_DECODE_FAILED));
+    }
+    assert!(decoder.is_empty());
+
+    // New keys are still numbered, to stay in step with the encoder
+    assert_eq!(
+        decoder.decode(&json!({"keys": ["a"], "value": {"1": true}})),
+        Err(ERR_JSON_DECODE_FAILED)
+    );
+    assert_eq!(
+        decoder.decode(&json!({"keys": [], "value": {"0": true}})),
+        Ok(json!({"a": true}))
+    );
+    assert_eq!(
+        decoder.decode(&json!({"keys": [], "value": {"x": true}})),
+        Err(ERR_JSON_DECODE_FAILED)
+    );
+}
+
+#[test]
+fn handles_carry_dictionaries_across_calls() {
+    let (encoder, decoder) = (cobhan_dictionary_new(), cobhan_dictionary_new());
+    assert!(encoder > 0 && decoder > encoder);
+    let mut buffer = OwnedCBuffer::with_capacity(256);
+    for latency in 0..3 {
+        let value = json!({"service": "api", "latency_ms": latency});
+        assert_eq!(
+            unsafe { json_to_cbuffer_with_dictionary(encoder, &value, buffer.as_mut_ptr()) },
+            ERR_NONE
+        );
+        assert_eq!(
+            unsafe { cbuffer_to_json_with_dictionary(decoder, buffer.as_ptr()) },
+            Ok(value)
+        );
+    }
+    assert_eq!(
+        buffer.to_vec().unwrap(),
+        br#"{"keys":[],"value":{"0":2,"1":"api"}}"#
+    );
+    assert_eq!(with_dictionary(decoder, |d| d.len()), Ok(2));
+
+    assert_eq!(cobhan_dictionary_free(encoder), ERR_NONE);
+    assert_eq!(cobhan_dictionary_free(encoder), ERR_INVALID_HANDLE);
+    assert_eq!(
+        unsafe { json_to_cbuffer_with_dictionary(encoder, &json!({}), buffer.as_mut_ptr()) },
+        ERR_INVALID_HANDLE
+    );
+    assert_eq!(
+        unsafe { cbuffer_to_json_with_dictionary(0, buffer.as_ptr()) },
+        Err(ERR_INVALID_HANDLE)
+    );
+    assert_eq!(cobhan_dictionary_free(decoder), ERR_NONE);
+}
+
+#[test]
+fn keys_of_unwritten_payloads_are_sent_again() {
+    let handle = cobhan_dictionary_new();
+    let value = json!({"a_rather_long_key_name": 1});
+    let mut small = OwnedCBuffer::with_capacity(4);
+    assert_eq!(
+        unsafe { json_to_cbuffer_with_dictionary(handle, &value, small.as_mut_ptr()) },
+        ERR_BUFFER_TOO_SMALL
+    );
+    assert_eq!(with_dictionary(handle, |d| d.len()), Ok(0));
+
+    let mut buffer = OwnedCBuffer::with_capacity(64);
+    assert_eq!(
+        unsafe { json_to_cbuffer_with_dictionary(handle, &value, buffer.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(
+        buffer.to_vec().unwrap(),
+        br#"{"keys":["a_rather_long_key_name"],"value":{"0":1}}"#
+    );
+    assert_eq!(cobhan_dictionary_free(handle), ERR_NONE);
+}
@@ -0,0 +1,123 @@
+//! Buffer diffs. Flags are only compared in format v2, which is process-wide configuration, so
+//! the tests take turns.
+
+use std::sync::MutexGuard;
+
+use cobhan::testing::{configured, with_mock_transport, OwnedCBuffer};
+use cobhan::*;
+use serde_json::{json, Value};
+
+fn format(format: BufferFormat) -> MutexGuard<'static, ()> {
+    configured(CobhanConfig {
+        buffer_format: format,
+        ..Default::default()
+    })
+}
+
+fn diff(expected: &OwnedCBuffer, actual: &OwnedCBuffer) -> Option<DiffReport> {
+    unsafe { cbuffer_diff(expected.as_ptr(), actual.as_ptr()) }.unwrap()
+}
+
+#[test]
+fn the_first_differing_byte_is_reported() {
+    let _guard = format(BufferFormat::V1);
+    let expected = OwnedCBuffer::from_bytes(b"hello world");
+    assert_eq!(
+        diff(&expected, &OwnedCBuffer::from_bytes(b"hello world")),
+        None
+    );
+
+    let report = diff(&expected, &OwnedCBuffer::from_bytes(b"hello World")).unwrap();
+    assert_eq!(
+        report,
+        DiffReport::Payload {
+            offset: 6,
+            expected: Some(b'w'),
+            actual: Some(b'W'),
+            expected_len: 11,
+            actual_len: 11,
+        }
+    );
+    assert_eq!(
+        report.to_string(),
+        "payloads differ at byte 6: expected 0x77, got 0x57 (lengths 11 and 11)"
+    );
+
+    let report = diff(&expected, &OwnedCBuffer::from_bytes(b"hello")).unwrap();
+    assert_eq!(
+        report.to_string(),
+        "payloads differ at byte 5: expected 0x20, got end of payload (lengths 11 and 5)"
+    );
+    assert_eq!(diff_payloads(b"", b""), None);
+}
+
+#[test]
+fn spilled_payloads_equal_inline_ones() {
+    let _guard = format(BufferFormat::V2);
+    with_mock_transport(|_| {
+        let payload = vec![9u8; 256];
+        let mut spilled = OwnedCBuffer::with_capacity(16);
+        assert_eq!(
+            unsafe { bytes_to_cbuffer(&payload, spilled.as_mut_ptr()) },
+            ERR_NONE
+        );
+        assert!(spilled.is_spilled());
+        assert_eq!(diff(&OwnedCBuffer::from_bytes(&payload), &spilled), None);
+    });
+}
+
+#[test]
+fn v2_flags_are_compared_first() {
+    let _guard = format(BufferFormat::V2);
+    let expected = OwnedCBuffer::from_bytes(b"{}");
+    let mut actual = OwnedCBuffer::from_bytes(b"[]");
+    let flags = BufferFlags::empty().with_content_type(ContentType::Json);
+    assert_eq!(
+        unsafe { set_cbuffer_flags(actual.as_mut_ptr(), flags) },
+        ERR_NONE
+    );
+    assert_eq!(
+        diff(&expected, &actual),
+        Some(DiffReport::Flags {
+            expected: BufferFlags::empty(),
+            actual: flags,
+        })
+    );
+}
+
+#[test]
+fn reports_are_exported_as_json() {
+    let _guard = format(BufferFormat::V1);
+    let expected = OwnedCBuffer::from_bytes(b"abc");
+    let mut report = OwnedCBuffer::with_capacity(256);
+    let code = unsafe {
+        cobhan_buffer_diff(
+            expected.as_ptr(),
+            OwnedCBuffer::from_bytes(b"abd").as_ptr(),
+            report.as_mut_ptr(),
+        )
+    };
+    assert_eq!(code, ERR_NONE);
+    let report: Value = serde_json::from_slice(&report.to_vec().unwrap()).unwrap();
+    assert_eq!(
+        report,
+        json!({
+            "field": "payload",
+            "offset": 2,
+            "expected": b'c',
+            "actual": b'd',
+            "expected_len": 3,
+            "actual_len": 3,
+        })
+    );
+
+    let mut report = OwnedCBuffer::with_capacity(256);
+    let code =
+        unsafe { cobhan_buffer_diff(expected.as_ptr(), expected.as_ptr(), report.as_mut_ptr()) };
+    assert_eq!(code, ERR_NONE);
+    assert_eq!(report.to_vec().unwrap(), b"null");
+
+    let code =
+        unsafe { cobhan_buffer_diff(expected.as_ptr(), std::ptr::null(), report.as_mut_ptr()) };
+    assert_eq!(code, ERR_NULL_PTR);
+}
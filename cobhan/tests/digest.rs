@@ -0,0 +1,66 @@
+//! Payload digests, computed in place and streamed from spills.
+
+use cobhan::testing::{with_mock_transport, OwnedCBuffer};
+use cobhan::*;
+
+fn digest(payload: &[u8], algorithm: DigestAlgorithm) -> String {
+    let input = OwnedCBuffer::from_bytes(payload);
+    hex::encode(unsafe { cbuffer_digest(input.as_ptr(), algorithm) }.unwrap())
+}
+
+#[test]
+fn digests_match_the_published_test_vectors() {
+    assert_eq!(
+        digest(b"abc", DigestAlgorithm::Sha256),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(
+        digest(b"abc", DigestAlgorithm::Sha512),
+        "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+         2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+    );
+    assert_eq!(
+        digest(b"abc", DigestAlgorithm::Blake3),
+        "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+    );
+    assert_eq!(
+        digest(b"", DigestAlgorithm::Blake3),
+        "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+    );
+}
+
+#[test]
+fn spilled_payloads_hash_like_inline_ones() {
+    let payload: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+    let mut output = OwnedCBuffer::with_capacity(128);
+    assert_eq!(
+        unsafe { bytes_to_cbuffer(&payload, output.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert!(output.is_spilled());
+    for algorithm in [
+        DigestAlgorithm::Sha256,
+        DigestAlgorithm::Sha512,
+        DigestAlgorithm::Blake3,
+    ] {
+        assert_eq!(
+            hex::encode(unsafe { cbuffer_digest(output.as_ptr(), algorithm) }.unwrap()),
+            digest(&payload, algorithm)
+        );
+    }
+}
+
+#[test]
+fn unreadable_buffers_fail() {
+    assert_eq!(
+        unsafe { cbuffer_digest(std::ptr::null(), DigestAlgorithm::Sha256) },
+        Err(ERR_NULL_PTR)
+    );
+    with_mock_transport(|_| {
+        let input = OwnedCBuffer::spilled("mock-spill:404");
+        assert_eq!(
+            unsafe { cbuffer_digest(input.as_ptr(), DigestAlgorithm::Blake3) },
+            Err(ERR_READ_TEMP_FILE_FAILED)
+        );
+    });
+}
@@ -0,0 +1,60 @@
+//! Header byte order: length fields are little-endian on every host, so these also pass on
+//! big-endian targets such as s390x.
+
+#![cfg(not(feature = "native_endian"))]
+
+use std::os::raw::c_char;
+
+use cobhan::*;
+
+/// An 8 byte aligned buffer whose raw bytes the test can inspect.
+struct Raw {
+    words: Vec<u64>,
+}
+
+impl Raw {
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let mut words = vec![0u64; bytes.len().div_ceil(8)];
+        raw_bytes(&mut words)[..bytes.len()].copy_from_slice(bytes);
+        Raw { words }
+    }
+
+    fn bytes(&mut self) -> &[u8] {
+        raw_bytes(&mut self.words)
+    }
+
+    fn as_ptr(&self) -> *const c_char {
+        self.words.as_ptr() as *const c_char
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut c_char {
+        self.words.as_mut_ptr() as *mut c_char
+    }
+}
+
+fn raw_bytes(words: &mut [u64]) -> &mut [u8] {
+    unsafe { std::slice::from_raw_parts_mut(words.as_mut_ptr() as *mut u8, words.len() * 8) }
+}
+
+#[test]
+fn header_length_is_little_endian() {
+    assert_eq!(encode_header_length(0x0102_0304), [0x04, 0x03, 0x02, 0x01]);
+    assert_eq!(encode_header_length(-2), [0xfe, 0xff, 0xff, 0xff]);
+    assert_eq!(decode_header_length([0x04, 0x03, 0x02, 0x01]), 0x0102_0304);
+}
+
+#[test]
+fn reads_little_endian_header() {
+    let buffer = Raw::from_bytes(&[5, 0, 0, 0, 0, 0, 0, 0, b'h', b'e', b'l', b'l', b'o']);
+    let value = unsafe { cbuffer_to_string(buffer.as_ptr()) };
+    assert_eq!(value.unwrap(), "hello");
+}
+
+#[test]
+fn writes_little_endian_header() {
+    let mut buffer = Raw::from_bytes(&[16, 0, 0, 0, 0, 0, 0, 0]);
+    buffer.words.resize(3, 0);
+    let result = unsafe { string_to_cbuffer("hi", buffer.as_mut_ptr()) };
+    assert_eq!(result, ERR_NONE);
+    assert_eq!(&buffer.bytes()[..10], &[2, 0, 0, 0, 0, 0, 0, 0, b'h', b'i']);
+}
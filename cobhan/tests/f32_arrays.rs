@@ -0,0 +1,102 @@
+//! Packed f32 arrays, as shipped by ML inference hosts.
+
+use std::borrow::Cow;
+use std::os::raw::c_char;
+
+use cobhan::testing::{with_mock_transport, OwnedCBuffer};
+use cobhan::*;
+
+const VALUES: [f32; 5] = [0.0, -1.5, 3.25, f32::MAX, f32::MIN_POSITIVE];
+
+#[test]
+fn f32_values_round_trip_little_endian() {
+    let mut output = OwnedCBuffer::with_capacity(64);
+    assert_eq!(
+        unsafe { f32_slice_to_cbuffer(&VALUES, output.as_mut_ptr()) },
+        ERR_NONE
+    );
+    let bytes = output.to_vec().unwrap();
+    assert_eq!(bytes.len(), 20);
+    assert_eq!(&bytes[4..8], &(-1.5f32).to_le_bytes());
+    assert_eq!(
+        unsafe { cbuffer_to_f32_vec(output.as_ptr()) }.unwrap(),
+        VALUES
+    );
+
+    let nan = OwnedCBuffer::from_bytes(&f32::NAN.to_le_bytes());
+    assert!(unsafe { cbuffer_to_f32_vec(nan.as_ptr()) }.unwrap()[0].is_nan());
+
+    let empty = OwnedCBuffer::from_bytes(b"");
+    assert_eq!(
+        unsafe { cbuffer_to_f32_vec(empty.as_ptr()) }.unwrap(),
+        Vec::<f32>::new()
+    );
+}
+
+#[test]
+fn aligned_inline_payloads_are_borrowed() {
+    let mut output = OwnedCBuffer::with_capacity(64);
+    assert_eq!(
+        unsafe { f32_slice_to_cbuffer(&VALUES, output.as_mut_ptr()) },
+        ERR_NONE
+    );
+    let values = unsafe { cbuffer_as_f32_slice(output.as_ptr()) }.unwrap();
+    assert_eq!(&*values, &VALUES);
+    if cfg!(target_endian = "little") {
+        assert!(matches!(values, Cow::Borrowed(_)));
+    }
+}
+
+#[test]
+fn spilled_payloads_are_copied() {
+    with_mock_transport(|_| {
+        let mut output = OwnedCBuffer::with_capacity(16);
+        assert_eq!(
+            unsafe { f32_slice_to_cbuffer(&VALUES, output.as_mut_ptr()) },
+            ERR_NONE
+        );
+        assert!(output.is_spilled());
+        let values = unsafe { cbuffer_as_f32_slice(output.as_ptr()) }.unwrap();
+        assert!(matches!(values, Cow::Owned(_)));
+        assert_eq!(&*values, &VALUES);
+        assert_eq!(
+            unsafe { cbuffer_to_f32_vec(output.as_ptr()) }.unwrap(),
+            VALUES
+        );
+    });
+}
+
+#[test]
+fn partial_values_are_rejected() {
+    let input = OwnedCBuffer::from_bytes(&[0, 0, 128, 63, 0, 0]);
+    assert_eq!(
+        unsafe { cbuffer_to_f32_vec(input.as_ptr()) },
+        Err(ERR_ARRAY_LENGTH_MISMATCH)
+    );
+    assert_eq!(
+        unsafe { cbuffer_as_f32_slice(input.as_ptr()) },
+        Err(ERR_ARRAY_LENGTH_MISMATCH)
+    );
+    assert_eq!(
+        unsafe { cbuffer_to_f32_vec(std::ptr::null()) },
+        Err(ERR_NULL_PTR)
+    );
+}
+
+#[test]
+fn misaligned_buffers_are_copied_or_rejected() {
+    // A header written 2 bytes into 8 byte aligned memory
+    let mut words = vec![0u64; 4];
+    let buffer = unsafe { (words.as_mut_ptr() as *mut c_char).add(2) };
+    unsafe {
+        std::ptr::copy_nonoverlapping(encode_header_length(8).as_ptr(), buffer.cast(), 4);
+        let payload = buffer.add(BUFFER_HEADER_SIZE as usize).cast::<u8>();
+        std::ptr::copy_nonoverlapping(1.0f32.to_le_bytes().as_ptr(), payload, 4);
+        std::ptr::copy_nonoverlapping(2.0f32.to_le_bytes().as_ptr(), payload.add(4), 4);
+    }
+    assert_eq!(unsafe { cbuffer_to_f32_vec(buffer) }.unwrap(), [1.0, 2.0]);
+    assert_eq!(
+        unsafe { cbuffer_as_f32_slice(buffer) },
+        Err(ERR_MISALIGNED_PAYLOAD)
+    );
+}
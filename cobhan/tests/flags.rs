@@ -0,0 +1,192 @@
+//! Header flags and the v2 buffer format. The format is process-wide configuration, so the tests
+//! take turns.
+
+use std::sync::MutexGuard;
+
+use cobhan::testing::{configured, with_mock_transport, OwnedCBuffer};
+use cobhan::*;
+
+/// Writes buffers in `format` until the returned guard is dropped.
+fn format(format: BufferFormat) -> MutexGuard<'static, ()> {
+    configured(CobhanConfig {
+        buffer_format: format,
+        ..Default::default()
+    })
+}
+
+fn set_flags(buffer: &mut OwnedCBuffer, flags: BufferFlags) {
+    assert_eq!(
+        unsafe { set_cbuffer_flags(buffer.as_mut_ptr(), flags) },
+        ERR_NONE
+    );
+}
+
+#[test]
+fn flags_combine_and_keep_app_bits_apart() {
+    let mut flags = BufferFlags::TEMP_FILE | BufferFlags::TRUNCATED;
+    assert_eq!(flags.bits(), 0b101);
+    assert!(flags.contains(BufferFlags::TEMP_FILE));
+    assert!(!flags.contains(BufferFlags::COMPRESSED));
+    assert!(!flags.contains(BufferFlags::TEMP_FILE | BufferFlags::COMPRESSED));
+
+    flags.insert(BufferFlags::COMPRESSED);
+    flags.remove(BufferFlags::TEMP_FILE);
+    assert_eq!(flags, BufferFlags::COMPRESSED | BufferFlags::TRUNCATED);
+    assert_eq!(flags & BufferFlags::TRUNCATED, BufferFlags::TRUNCATED);
+
+    let flags = flags.with_app_bits(0xbeef);
+    assert_eq!(flags.app_bits(), 0xbeef);
+    assert_eq!(flags.bits(), 0xbeef_0006);
+    assert_eq!(flags.with_app_bits(0).bits(), 0b110);
+    assert_eq!(
+        format!("{:?}", flags),
+        "{COMPRESSED, TRUNCATED, app 0xbeef}"
+    );
+    assert_eq!(
+        format!("{:?}", BufferFlags::from_bits(0x40)),
+        "{reserved 0x40}"
+    );
+    assert_eq!(BufferFlags::default(), BufferFlags::empty());
+}
+
+#[test]
+fn flags_are_read_and_written_in_place() {
+    let mut buffer = OwnedCBuffer::from_bytes(b"payload");
+    assert_eq!(
+        unsafe { cbuffer_flags(buffer.as_ptr()) },
+        Ok(BufferFlags::empty())
+    );
+    set_flags(&mut buffer, BufferFlags::COMPRESSED.with_app_bits(7));
+    assert_eq!(buffer.flags(), BufferFlags::COMPRESSED.with_app_bits(7));
+    assert_eq!(buffer.length_field(), 7);
+
+    assert_eq!(
+        unsafe { cbuffer_flags(std::ptr::null()) },
+        Err(ERR_NULL_PTR)
+    );
+    assert_eq!(
+        unsafe { set_cbuffer_flags(std::ptr::null_mut(), BufferFlags::empty()) },
+        ERR_NULL_PTR
+    );
+}
+
+#[test]
+fn v1_ignores_the_reserved_field() {
+    let _guard = format(BufferFormat::V1);
+    let mut input = OwnedCBuffer::from_bytes(b"not a reference");
+    set_flags(&mut input, BufferFlags::from_bits(u32::MAX));
+    assert!(!input.is_spilled());
+    assert_eq!(input.to_vec().unwrap(), b"not a reference");
+
+    // Writes leave it alone
+    let mut output = OwnedCBuffer::with_capacity(16);
+    set_flags(&mut output, BufferFlags::from_bits(0xdead_beef));
+    assert_eq!(
+        unsafe { bytes_to_cbuffer(b"out", output.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(output.flags().bits(), 0xdead_beef);
+}
+
+#[test]
+fn v2_inline_writes_clear_format_flags_and_keep_app_bits() {
+    let _guard = format(BufferFormat::V2);
+    let mut output = OwnedCBuffer::with_capacity(16);
+    set_flags(
+        &mut output,
+        (BufferFlags::TEMP_FILE | BufferFlags::TRUNCATED).with_app_bits(0x0042),
+    );
+    assert_eq!(
+        unsafe { bytes_to_cbuffer(b"out", output.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(output.flags(), BufferFlags::empty().with_app_bits(0x0042));
+    assert_eq!(output.length_field(), 3);
+    assert_eq!(output.to_vec().unwrap(), b"out");
+}
+
+#[test]
+fn v2_spills_are_flagged_and_read_back() {
+    let _guard = format(BufferFormat::V2);
+    with_mock_transport(|mock| {
+        let mut output = OwnedCBuffer::with_capacity(32);
+        let payload = vec![b'x'; 100];
+        assert_eq!(
+            unsafe { bytes_to_cbuffer(&payload, output.as_mut_ptr()) },
+            ERR_NONE
+        );
+        assert_eq!(output.flags(), BufferFlags::TEMP_FILE);
+        let reference = output.spill_reference().unwrap();
+        assert_eq!(output.length_field(), reference.len() as i32);
+        assert!(output.is_spilled());
+        assert_eq!(mock.payload(&reference), Some(payload.clone()));
+        assert_eq!(output.to_vec().unwrap(), payload);
+        assert_eq!(
+            unsafe { cbuffer_to_string(output.as_ptr()) }.unwrap(),
+            "x".repeat(100)
+        );
+    });
+}
+
+#[test]
+fn v2_reads_v1_spills() {
+    let _guard = format(BufferFormat::V2);
+    with_mock_transport(|mock| {
+        let reference = mock.insert(b"from a v1 host");
+        let input = OwnedCBuffer::spilled(&reference);
+        assert!(input.length_field() < 0);
+        assert_eq!(input.to_vec().unwrap(), b"from a v1 host");
+    });
+}
+
+#[test]
+fn v1_reads_v2_spills_as_references() {
+    let _guard = format(BufferFormat::V1);
+    with_mock_transport(|mock| {
+        let reference = mock.insert(b"spilled");
+        let mut input = OwnedCBuffer::from_bytes(reference.as_bytes());
+        set_flags(&mut input, BufferFlags::TEMP_FILE);
+        assert_eq!(input.to_vec().unwrap(), reference.as_bytes());
+    });
+}
+
+#[test]
+fn truncating_writes_fill_the_buffer_and_flag_the_rest() {
+    let _guard = format(BufferFormat::V1);
+    let mut output = OwnedCBuffer::with_capacity(8);
+    set_flags(&mut output, BufferFlags::TEMP_FILE.with_app_bits(9));
+    let outcome = unsafe { bytes_to_cbuffer_truncating(b"hello, world", output.as_mut_ptr()) };
+    assert_eq!(
+        outcome,
+        Ok(WriteOutcome {
+            written: 8,
+            total: 12
+        })
+    );
+    assert!(outcome.unwrap().is_truncated());
+    assert_eq!(output.flags(), BufferFlags::TRUNCATED.with_app_bits(9));
+    assert!(!output.is_spilled());
+    assert_eq!(output.to_vec().unwrap(), b"hello, w");
+
+    // A payload that fits clears the flag
+    output.reset();
+    let outcome = unsafe { bytes_to_cbuffer_truncating(b"hello", output.as_mut_ptr()) }.unwrap();
+    assert!(!outcome.is_truncated());
+    assert_eq!(outcome.total, 5);
+    assert_eq!(output.flags(), BufferFlags::empty().with_app_bits(9));
+    assert_eq!(output.to_vec().unwrap(), b"hello");
+}
+
+#[test]
+fn truncating_writes_reject_unusable_buffers() {
+    let _guard = format(BufferFormat::V2);
+    let mut output = OwnedCBuffer::with_capacity(0);
+    assert_eq!(
+        unsafe { bytes_to_cbuffer_truncating(b"x", output.as_mut_ptr()) },
+        Err(ERR_BUFFER_TOO_SMALL)
+    );
+    assert_eq!(
+        unsafe { bytes_to_cbuffer_truncating(b"x", std::ptr::null_mut()) },
+        Err(ERR_NULL_PTR)
+    );
+}
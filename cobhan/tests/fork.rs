@@ -0,0 +1,104 @@
+//! `cobhan_after_fork` cannot be undone, so this binary has a single test, run as if the
+//! calling thread were the only one left after a fork.
+
+use serde_json::json;
+
+use cobhan::testing::{configured, OwnedCBuffer};
+use cobhan::*;
+
+#[test]
+fn after_fork_resets_inherited_state() {
+    let _guard = configured(CobhanConfig {
+        max_leased_bytes: Some(100),
+        reuse_spill_files: true,
+        ..Default::default()
+    });
+    let handle = cobhan_dictionary_new();
+    let inherited = lease_output_buffer(100);
+    let scope = SpillScope::begin();
+    let scope_id = scope.id();
+    assert_eq!(try_lease_output_buffer(1).unwrap_err(), ERR_OUT_OF_MEMORY);
+    // The parent's host still has to read the spill in its thread spill file, and the one
+    // written to a new file while that was pending
+    #[cfg(feature = "tempfile")]
+    let parent_spills = {
+        let mut released = spill(&[1; 100]);
+        let path = released.temp_file_path().unwrap();
+        assert_eq!(
+            unsafe { cobhan_buffer_release(released.as_mut_ptr()) },
+            ERR_NONE
+        );
+        let pending = spill(&[2; 100]);
+        assert_eq!(pending.temp_file_path().unwrap(), path);
+        let new_file = spill(&[5; 100]);
+        let new_path = new_file.temp_file_path().unwrap();
+        assert_ne!(new_path, path);
+        std::mem::forget(pending);
+        std::mem::forget(new_file);
+        [(path, vec![2; 100]), (new_path, vec![5; 100])]
+    };
+    drop(scope);
+
+    cobhan_after_fork();
+
+    // Ending the parent's scope in the child leaves its spills to the parent's host
+    assert_eq!(cobhan_end_call(scope_id), ERR_INVALID_HANDLE);
+
+    // The child spills to files of its own and leaves the parent's in place
+    #[cfg(feature = "tempfile")]
+    {
+        let mut child = spill(&[3; 100]);
+        assert_ne!(child.temp_file_path().unwrap(), parent_spills[0].0);
+        assert_eq!(
+            unsafe { cobhan_buffer_release(child.as_mut_ptr()) },
+            ERR_NONE
+        );
+        assert_eq!(spill(&[4; 100]).to_vec(), Ok(vec![4; 100]));
+        assert_eq!(cobhan_shutdown(0), ERR_NONE);
+        for (path, payload) in &parent_spills {
+            assert_eq!(&std::fs::read(path).unwrap(), payload);
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    // The parent's handles and leases are gone, and its leases are not returned twice
+    assert_eq!(cobhan_dictionary_free(handle), ERR_INVALID_HANDLE);
+    assert_eq!(current_leased_bytes(), 0);
+    let lease = try_lease_output_buffer(100).unwrap();
+    drop(inherited);
+    assert_eq!(current_leased_bytes(), 100);
+    drop(lease);
+    assert_eq!(current_leased_bytes(), 0);
+    assert_eq!(current_marshaling_bytes(), 0);
+
+    // New handles and conversions work as before
+    let handle = cobhan_dictionary_new();
+    let mut output = OwnedCBuffer::with_capacity(64);
+    let value = json!({"a": 1});
+    assert_eq!(
+        unsafe { json_to_cbuffer_with_dictionary(handle, &value, output.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(cobhan_dictionary_free(handle), ERR_NONE);
+
+    #[cfg(feature = "rayon")]
+    {
+        let inputs: Vec<_> = (0..4)
+            .map(|i| OwnedCBuffer::from_bytes(i.to_string().as_bytes()))
+            .collect();
+        let pointers: Vec<_> = inputs.iter().map(OwnedCBuffer::as_ptr).collect();
+        let values = unsafe { cbuffers_to_json_parallel(&pointers) };
+        assert_eq!(values, (0..4).map(|i| Ok(json!(i))).collect::<Vec<_>>());
+    }
+}
+
+#[cfg(feature = "tempfile")]
+fn spill(payload: &[u8]) -> OwnedCBuffer {
+    let mut buffer = OwnedCBuffer::with_capacity(64);
+    assert_eq!(
+        unsafe { bytes_to_cbuffer(payload, buffer.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert!(buffer.is_spilled());
+    buffer
+}
@@ -0,0 +1,147 @@
+//! Registered formats. Content type hints are only honored in format v2, which is process-wide
+//! configuration, so the tests take turns; each registers its own content type code.
+
+use std::sync::{Arc, MutexGuard};
+
+use cobhan::testing::{configured, OwnedCBuffer};
+use cobhan::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+fn format(format: BufferFormat) -> MutexGuard<'static, ()> {
+    configured(CobhanConfig {
+        buffer_format: format,
+        ..Default::default()
+    })
+}
+
+/// JSON behind a magic prefix, standing in for a proprietary format.
+struct Prefixed;
+
+const ERR_PREFIXED: i32 = -1000;
+
+impl CobhanFormat for Prefixed {
+    fn encode(&self, value: &dyn erased_serde::Serialize, out: &mut Vec<u8>) -> Result<(), i32> {
+        out.extend_from_slice(b"PX");
+        JsonFormat.encode(value, out)
+    }
+
+    fn decode(&self, bytes: &[u8], seed: &mut DecodeSeed<'_>) -> Result<(), i32> {
+        match bytes.strip_prefix(b"PX") {
+            Some(json) => JsonFormat.decode(json, seed),
+            None => Err(ERR_PREFIXED),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Policy {
+    name: String,
+    allow: bool,
+}
+
+#[test]
+fn values_round_trip_through_registered_formats() {
+    let _guard = format(BufferFormat::V2);
+    let prefixed = ContentType::Other(200);
+    register_format(prefixed, Arc::new(Prefixed));
+    let policy = Policy {
+        name: "default".to_string(),
+        allow: true,
+    };
+    let mut buffer = OwnedCBuffer::with_capacity(64);
+    assert_eq!(
+        unsafe { value_to_cbuffer_as(&policy, prefixed, buffer.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(
+        buffer.to_vec().unwrap(),
+        b"PX{\"name\":\"default\",\"allow\":true}"
+    );
+    assert_eq!(buffer.flags().content_type(), prefixed);
+    let decoded: Policy = unsafe { cbuffer_to_value_as(buffer.as_ptr(), prefixed) }.unwrap();
+    assert_eq!(decoded, policy);
+
+    let json = OwnedCBuffer::from_bytes(b"{}");
+    assert_eq!(
+        unsafe { cbuffer_to_value_as::<Policy>(json.as_ptr(), prefixed) },
+        Err(ERR_PREFIXED)
+    );
+    unregister_format(prefixed);
+    assert_eq!(
+        unsafe { cbuffer_to_value_as::<Policy>(buffer.as_ptr(), prefixed) },
+        Err(ERR_UNKNOWN_FORMAT)
+    );
+}
+
+#[test]
+fn decode_any_uses_registered_formats() {
+    let _guard = format(BufferFormat::V2);
+    let prefixed = ContentType::Other(201);
+    let mut buffer = OwnedCBuffer::from_bytes(b"PX[1,2]");
+    assert_eq!(
+        unsafe { set_cbuffer_content_type(buffer.as_mut_ptr(), prefixed) },
+        ERR_NONE
+    );
+    assert_eq!(
+        unsafe { decode_any(buffer.as_ptr()) },
+        Ok(DecodedValue::Bytes(prefixed, b"PX[1,2]".to_vec()))
+    );
+
+    register_format(prefixed, Arc::new(Prefixed));
+    assert_eq!(
+        unsafe { decode_any(buffer.as_ptr()) },
+        Ok(DecodedValue::Structured(prefixed, json!([1, 2])))
+    );
+    unregister_format(prefixed);
+}
+
+#[test]
+fn payloads_are_transcoded_between_formats() {
+    let _guard = format(BufferFormat::V2);
+    let prefixed = ContentType::Other(202);
+    register_format(prefixed, Arc::new(Prefixed));
+    let mut input = OwnedCBuffer::from_bytes(b"PX{\"a\":[true,null]}");
+    assert_eq!(
+        unsafe { set_cbuffer_content_type(input.as_mut_ptr(), prefixed) },
+        ERR_NONE
+    );
+    let mut output = OwnedCBuffer::with_capacity(64);
+    assert_eq!(
+        unsafe { transcode_cbuffer(input.as_ptr(), output.as_mut_ptr(), ContentType::Json) },
+        ERR_NONE
+    );
+    assert_eq!(output.to_vec().unwrap(), b"{\"a\":[true,null]}");
+    assert_eq!(output.flags().content_type(), ContentType::Json);
+
+    let mut back = OwnedCBuffer::with_capacity(64);
+    assert_eq!(
+        unsafe { transcode_cbuffer(output.as_ptr(), back.as_mut_ptr(), prefixed) },
+        ERR_NONE
+    );
+    assert_eq!(back.to_vec().unwrap(), input.to_vec().unwrap());
+
+    assert_eq!(
+        unsafe { transcode_cbuffer(input.as_ptr(), back.as_mut_ptr(), ContentType::MsgPack) },
+        ERR_UNKNOWN_FORMAT
+    );
+    unregister_format(prefixed);
+}
+
+#[test]
+fn v1_payloads_have_no_format_to_transcode_from() {
+    let _guard = format(BufferFormat::V1);
+    let input = OwnedCBuffer::from_bytes(b"{}");
+    let mut output = OwnedCBuffer::with_capacity(64);
+    assert_eq!(
+        unsafe { transcode_cbuffer(input.as_ptr(), output.as_mut_ptr(), ContentType::Json) },
+        ERR_UNKNOWN_FORMAT
+    );
+
+    let mut buffer = OwnedCBuffer::with_capacity(64);
+    assert_eq!(
+        unsafe { value_to_cbuffer_as(&json!({"v": 1}), ContentType::Json, buffer.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(buffer.to_vec().unwrap(), b"{\"v\":1}");
+}
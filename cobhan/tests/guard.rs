@@ -0,0 +1,103 @@
+//! Catching panics at the FFI boundary and reading them back through the last error.
+
+use cobhan::testing::OwnedCBuffer;
+use cobhan::*;
+
+fn host_last_error() -> String {
+    let mut buffer = OwnedCBuffer::with_capacity(4096);
+    assert_eq!(
+        unsafe { cobhan_get_last_error(buffer.as_mut_ptr()) },
+        ERR_NONE
+    );
+    String::from_utf8(buffer.to_vec().unwrap()).unwrap()
+}
+
+#[test]
+fn results_pass_through_and_clear_the_last_error() {
+    set_last_error("from an earlier call");
+    assert_eq!(last_error().as_deref(), Some("from an earlier call"));
+    assert_eq!(ffi_guard(|| ERR_INVALID_UTF8), ERR_INVALID_UTF8);
+    assert_eq!(last_error(), None);
+    assert_eq!(host_last_error(), "");
+}
+
+#[test]
+fn panics_are_caught_with_their_message_and_location() {
+    assert_eq!(ffi_guard(|| panic!("static message")), ERR_PANIC);
+    let message = last_error().unwrap();
+    assert!(message.starts_with("panicked at "), "{}", message);
+    assert!(message.contains("tests/guard.rs:"), "{}", message);
+    assert!(message.contains(": static message"), "{}", message);
+    assert_eq!(host_last_error(), message);
+
+    let id = 42;
+    assert_eq!(ffi_guard(|| panic!("formatted {}", id)), ERR_PANIC);
+    assert!(last_error().unwrap().contains(": formatted 42"));
+
+    clear_last_error();
+    assert_eq!(host_last_error(), "");
+}
+
+#[test]
+fn guards_nest_and_the_last_error_is_per_thread() {
+    let outer = ffi_guard(|| {
+        assert_eq!(ffi_guard(|| panic!("inner")), ERR_PANIC);
+        ERR_NONE
+    });
+    assert_eq!(outer, ERR_NONE);
+    // The outer guard cleared the error on entry, the inner one recorded the panic after
+    assert!(last_error().unwrap().contains(": inner"));
+
+    std::thread::spawn(|| assert_eq!(last_error(), None))
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn panics_outside_a_guard_are_not_recorded() {
+    clear_last_error();
+    assert!(std::panic::catch_unwind(|| panic!("unguarded")).is_err());
+    assert_eq!(last_error(), None);
+}
+
+#[cfg(feature = "backtrace")]
+#[test]
+fn backtraces_are_appended() {
+    assert_eq!(ffi_guard(|| panic!("with a backtrace")), ERR_PANIC);
+    let message = last_error().unwrap();
+    assert!(message.contains("\nstack backtrace:\n"), "{}", message);
+}
+
+#[test]
+fn callbacks_only_run_on_their_thread() {
+    let context = std::rc::Rc::new(std::cell::Cell::new(0));
+    let callback = {
+        let context = context.clone();
+        MainThreadCallback::new(move |value: i32| context.set(context.get() + value))
+    };
+    assert!(callback.is_current_thread());
+    assert_eq!(callback.thread(), std::thread::current().id());
+    assert_eq!(callback.call(|f| f(2)), Ok(()));
+    (callback.get().unwrap())(3);
+    assert_eq!(context.get(), 5);
+
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            assert!(!callback.is_current_thread());
+            assert_eq!(callback.call(|f| f(10)), Err(ERR_WRONG_THREAD));
+            assert!(callback.get().is_err());
+        });
+    });
+    assert_eq!(context.get(), 5);
+
+    drop(callback);
+    assert_eq!(std::rc::Rc::strong_count(&context), 1);
+}
+
+#[test]
+fn callbacks_dropped_off_their_thread_are_leaked() {
+    let context = std::rc::Rc::new(());
+    let callback = MainThreadCallback::new(context.clone());
+    std::thread::spawn(move || drop(callback)).join().unwrap();
+    assert_eq!(std::rc::Rc::strong_count(&context), 2);
+}
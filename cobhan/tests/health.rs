@@ -0,0 +1,75 @@
+//! The health report reads the process-wide configuration, so the tests take turns.
+
+use std::fs;
+
+use cobhan::testing::{configured, OwnedCBuffer};
+use cobhan::*;
+use serde_json::Value;
+
+fn report() -> Value {
+    let mut output = OwnedCBuffer::with_capacity(4096);
+    assert_eq!(unsafe { cobhan_health(output.as_mut_ptr()) }, ERR_NONE);
+    serde_json::from_slice(&output.to_vec().unwrap()).unwrap()
+}
+
+#[test]
+fn reports_the_build() {
+    let _guard = configured(CobhanConfig::default());
+    let health = report();
+    assert_eq!(health["version"], env!("CARGO_PKG_VERSION"));
+    let features = health["features"].as_array().unwrap();
+    for feature in ["std", "json", "tempfile", "testing"] {
+        assert!(features.contains(&Value::from(feature)), "{}", feature);
+    }
+    assert_eq!(
+        features.contains(&Value::from("rayon")),
+        cfg!(feature = "rayon")
+    );
+    assert_eq!(health["worker_pool"].is_null(), !cfg!(feature = "rayon"));
+}
+
+#[test]
+fn probes_the_temp_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    let _guard = configured(CobhanConfig {
+        temp_dir: Some(dir.path().to_path_buf()),
+        ..Default::default()
+    });
+    let health = report();
+    assert_eq!(health["temp_dir"]["path"], dir.path().to_str().unwrap());
+    assert_eq!(health["temp_dir"]["writable"], true);
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+
+    let missing = dir.path().join("missing");
+    configure(CobhanConfig {
+        temp_dir: Some(missing),
+        ..Default::default()
+    });
+    assert_eq!(report()["temp_dir"]["writable"], false);
+}
+
+#[test]
+fn reports_the_lease_pool() {
+    let _guard = configured(CobhanConfig {
+        max_leased_bytes: Some(1024),
+        ..Default::default()
+    });
+    let leased = lease_output_buffer(100);
+    let health = report();
+    assert_eq!(health["leases"]["leased_bytes"], 100);
+    assert_eq!(health["leases"]["max_leased_bytes"], 1024);
+    drop(leased);
+}
+
+#[test]
+fn small_buffers_get_an_error() {
+    let _guard = configured(CobhanConfig {
+        spill_policy: SpillPolicy::Reject,
+        ..Default::default()
+    });
+    let mut output = OwnedCBuffer::with_capacity(8);
+    assert_eq!(
+        unsafe { cobhan_health(output.as_mut_ptr()) },
+        ERR_BUFFER_TOO_SMALL
+    );
+}
@@ -0,0 +1,53 @@
+//! Interned strings are shared between calls until the interner is cleared or full.
+
+use std::sync::Arc;
+
+use cobhan::testing::OwnedCBuffer;
+use cobhan::*;
+
+#[test]
+fn repeated_strings_share_one_copy() {
+    let interner = Interner::default();
+    let first = OwnedCBuffer::from_bytes(b"tenant-42");
+    let second = OwnedCBuffer::from_bytes(b"tenant-42");
+    let a = unsafe { cbuffer_to_interned_str(&interner, first.as_ptr()) }.unwrap();
+    let b = unsafe { cbuffer_to_interned_str(&interner, second.as_ptr()) }.unwrap();
+    assert_eq!(&*a, "tenant-42");
+    assert!(Arc::ptr_eq(&a, &b));
+    assert_eq!(interner.len(), 1);
+
+    interner.clear();
+    assert!(interner.is_empty());
+    let c = unsafe { cbuffer_to_interned_str(&interner, first.as_ptr()) }.unwrap();
+    assert!(!Arc::ptr_eq(&a, &c));
+    assert_eq!(a, c);
+}
+
+#[test]
+fn limits_bound_the_cache() {
+    let interner = Interner::with_limits(4, 2);
+    let long = interner.intern("too long");
+    assert!(!Arc::ptr_eq(&long, &interner.intern("too long")));
+    assert!(interner.is_empty());
+
+    interner.intern("a");
+    interner.intern("b");
+    let c = interner.intern("c");
+    assert_eq!(&*c, "c");
+    assert!(!Arc::ptr_eq(&c, &interner.intern("c")));
+    assert_eq!(interner.len(), 2);
+}
+
+#[test]
+fn invalid_utf8_is_rejected() {
+    let interner = Interner::default();
+    let input = OwnedCBuffer::from_bytes(b"\xff");
+    assert_eq!(
+        unsafe { cbuffer_to_interned_str(&interner, input.as_ptr()) },
+        Err(ERR_INVALID_UTF8)
+    );
+    assert_eq!(
+        unsafe { cbuffer_to_interned_str(&interner, std::ptr::null()) },
+        Err(ERR_NULL_PTR)
+    );
+}
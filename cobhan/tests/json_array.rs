@@ -0,0 +1,99 @@
+//! JSON arrays decoded one element at a time, inline and streamed from a spill.
+
+use cobhan::testing::{with_mock_transport, OwnedCBuffer};
+use cobhan::*;
+use serde_json::{json, Value};
+
+fn elements(json: &[u8]) -> Vec<Result<Value, i32>> {
+    let input = OwnedCBuffer::from_bytes(json);
+    unsafe { cbuffer_json_array_iter(input.as_ptr()) }.collect()
+}
+
+#[test]
+fn elements_are_decoded_in_order() {
+    let json = br#" [ {"id": 1, "tags": ["a", "b]"]}, "x,y", [[], {}], null, -25 ] "#;
+    assert_eq!(
+        elements(json),
+        vec![
+            Ok(json!({"id": 1, "tags": ["a", "b]"]})),
+            Ok(json!("x,y")),
+            Ok(json!([[], {}])),
+            Ok(Value::Null),
+            Ok(json!(-25)),
+        ]
+    );
+    assert_eq!(elements(b"[]"), vec![]);
+    assert_eq!(elements(b" [ \n ] "), vec![]);
+    assert_eq!(
+        elements(br#"["\"]\\", 1]"#),
+        vec![Ok(json!("\"]\\")), Ok(json!(1))]
+    );
+}
+
+#[test]
+fn malformed_arrays_end_with_an_error() {
+    assert_eq!(
+        elements(b"[1, 2,, 3]"),
+        vec![Ok(json!(1)), Ok(json!(2)), Err(ERR_JSON_DECODE_FAILED)]
+    );
+    assert_eq!(
+        elements(b"[1,]"),
+        vec![Ok(json!(1)), Err(ERR_JSON_DECODE_FAILED)]
+    );
+    assert_eq!(elements(b"[1 2]"), vec![Err(ERR_JSON_DECODE_FAILED)]);
+    assert_eq!(
+        elements(b"[1, {\"a\": 2"),
+        vec![Ok(json!(1)), Err(ERR_JSON_DECODE_FAILED)]
+    );
+    assert_eq!(
+        elements(b"[1] 2"),
+        vec![Ok(json!(1)), Err(ERR_JSON_DECODE_FAILED)]
+    );
+    assert_eq!(
+        elements(b"[1}"),
+        vec![Ok(json!(1)), Err(ERR_JSON_DECODE_FAILED)]
+    );
+    assert_eq!(elements(br#"{"a": 1}"#), vec![Err(ERR_JSON_DECODE_FAILED)]);
+    assert_eq!(elements(b""), vec![Err(ERR_JSON_DECODE_FAILED)]);
+
+    let mut iter = unsafe { cbuffer_json_array_iter(std::ptr::null()) };
+    assert_eq!(iter.next(), Some(Err(ERR_NULL_PTR)));
+    assert_eq!(iter.next(), None);
+}
+
+fn records(count: usize) -> Vec<u8> {
+    let records: Vec<Value> = (0..count)
+        .map(|id| json!({"id": id, "name": format!("record {}", id)}))
+        .collect();
+    serde_json::to_vec(&records).unwrap()
+}
+
+#[test]
+fn spilled_arrays_are_streamed_from_the_temp_file() {
+    let json = records(10_000);
+    let mut output = OwnedCBuffer::with_capacity(128);
+    assert_eq!(
+        unsafe { bytes_to_cbuffer(&json, output.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert!(output.is_spilled());
+
+    let mut count = 0;
+    for (id, element) in unsafe { cbuffer_json_array_iter(output.as_ptr()) }.enumerate() {
+        assert_eq!(element.unwrap()["id"], id);
+        count += 1;
+    }
+    assert_eq!(count, 10_000);
+}
+
+#[test]
+fn spills_from_other_transports_are_read_whole() {
+    with_mock_transport(|mock| {
+        let reference = mock.insert(&records(3));
+        let input = OwnedCBuffer::spilled(&reference);
+        let ids: Vec<Value> = unsafe { cbuffer_json_array_iter(input.as_ptr()) }
+            .map(|element| element.unwrap()["id"].clone())
+            .collect();
+        assert_eq!(ids, vec![json!(0), json!(1), json!(2)]);
+    });
+}
@@ -0,0 +1,214 @@
+//! Size, depth and key count limits of decoded JSON documents. The limits are process-wide
+//! configuration, so the tests take turns.
+
+use std::io::{self, Cursor, Read};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, MutexGuard};
+
+use cobhan::testing::{configured, OwnedCBuffer};
+use cobhan::*;
+
+fn limits(
+    max_json_bytes: Option<usize>,
+    max_json_depth: Option<usize>,
+    max_json_keys: Option<usize>,
+) -> MutexGuard<'static, ()> {
+    configured(CobhanConfig {
+        max_json_bytes,
+        max_json_depth,
+        max_json_keys,
+        ..Default::default()
+    })
+}
+
+fn decode(json: &str) -> Result<usize, i32> {
+    let input = OwnedCBuffer::from_bytes(json.as_bytes());
+    let lenient = unsafe { cbuffer_to_hashmap_json(input.as_ptr()) }.map(|json| json.len());
+    let strict = unsafe { cbuffer_to_hashmap_json_strict(input.as_ptr()) }.map(|json| json.len());
+    assert_eq!(lenient, strict);
+    lenient
+}
+
+fn nested(depth: usize) -> String {
+    format!("{}1{}", r#"{"a":["#.repeat(depth), "]}".repeat(depth))
+}
+
+#[test]
+fn documents_within_the_limits_decode() {
+    let _guard = limits(Some(64), Some(4), Some(3));
+    assert_eq!(decode(r#"{"a": [1, {"b": 2}], "c": {}}"#), Ok(2));
+    assert_eq!(decode(&nested(2)), Ok(1));
+}
+
+#[test]
+fn large_documents_are_rejected() {
+    let _guard = limits(Some(16), None, None);
+    assert_eq!(decode(r#"{"a": 1}"#), Ok(1));
+    assert_eq!(
+        decode(r#"{"a": "0123456789"}"#),
+        Err(ERR_JSON_LIMITS_EXCEEDED)
+    );
+}
+
+#[test]
+fn deep_documents_are_rejected() {
+    let _guard = limits(None, Some(10), None);
+    // Each level of `nested` is an object holding an array
+    assert_eq!(decode(&nested(5)), Ok(1));
+    assert_eq!(decode(&nested(6)), Err(ERR_JSON_LIMITS_EXCEEDED));
+    assert_eq!(decode(&nested(10_000)), Err(ERR_JSON_LIMITS_EXCEEDED));
+}
+
+#[test]
+fn documents_with_many_keys_are_rejected() {
+    let _guard = limits(None, None, Some(3));
+    assert_eq!(decode(r#"{"a": {"b": 1}, "c": 2}"#), Ok(2));
+    assert_eq!(
+        decode(r#"{"a": {"b": 1}, "c": [{"d": 2}]}"#),
+        Err(ERR_JSON_LIMITS_EXCEEDED)
+    );
+}
+
+#[test]
+fn malformed_documents_within_the_limits_fail_to_decode() {
+    let _guard = limits(Some(64), Some(4), Some(4));
+    assert_eq!(decode(r#"{"a": "#), Err(ERR_JSON_DECODE_FAILED));
+    assert_eq!(decode(&nested(200)), Err(ERR_JSON_LIMITS_EXCEEDED));
+
+    let _guard = {
+        drop(_guard);
+        limits(None, None, None)
+    };
+    assert_eq!(decode(&nested(200)), Err(ERR_JSON_DECODE_FAILED));
+}
+
+/// Streams one spilled document, counting the bytes read from it.
+struct Streamed {
+    document: Vec<u8>,
+    read: Arc<AtomicUsize>,
+}
+
+struct Counted {
+    document: Cursor<Vec<u8>>,
+    read: Arc<AtomicUsize>,
+}
+
+impl Read for Counted {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.document.read(buf)?;
+        self.read.fetch_add(read, Ordering::Relaxed);
+        Ok(read)
+    }
+}
+
+impl SpillTransport for Streamed {
+    fn spill(&self, _bytes: &[u8]) -> Result<String, i32> {
+        Err(ERR_WRITE_TEMP_FILE_FAILED)
+    }
+
+    fn read(&self, _reference: &str) -> Result<Vec<u8>, i32> {
+        panic!("the document is read whole");
+    }
+
+    fn open(&self, _reference: &str) -> Result<Box<dyn Read + Send>, i32> {
+        Ok(Box::new(Counted {
+            document: Cursor::new(self.document.clone()),
+            read: self.read.clone(),
+        }))
+    }
+
+    fn discard(&self, _reference: &str) {}
+}
+
+#[test]
+fn large_spilled_documents_are_rejected_before_they_are_read() {
+    let _guard = limits(Some(64), None, None);
+    let read = Arc::new(AtomicUsize::new(0));
+    for (document, expected) in [
+        (
+            format!(r#"{{"a": "{}"}}"#, "x".repeat(10_000)),
+            Err(ERR_JSON_LIMITS_EXCEEDED),
+        ),
+        (r#"{"a": 1, "b": 2}"#.to_string(), Ok(2)),
+    ] {
+        let transport = Arc::new(Streamed {
+            document: document.into_bytes(),
+            read: read.clone(),
+        });
+        with_thread_spill_transport(transport, || {
+            read.store(0, Ordering::Relaxed);
+            let input = OwnedCBuffer::spilled("document");
+            let lenient = unsafe { cbuffer_to_hashmap_json(input.as_ptr()) }.map(|json| json.len());
+            assert_eq!(lenient, expected);
+            assert!(read.load(Ordering::Relaxed) <= 65);
+            let strict =
+                unsafe { cbuffer_to_hashmap_json_strict(input.as_ptr()) }.map(|json| json.len());
+            assert_eq!(strict, expected);
+        });
+    }
+}
+
+#[test]
+fn every_decoder_of_whole_documents_applies_the_limits() {
+    let _guard = configured(CobhanConfig {
+        max_json_depth: Some(10),
+        buffer_format: BufferFormat::V2,
+        ..Default::default()
+    });
+    let within = OwnedCBuffer::from_bytes(nested(5).as_bytes());
+    let deep = OwnedCBuffer::from_bytes(nested(6).as_bytes());
+
+    let mut hinted = OwnedCBuffer::with_capacity(256);
+    unsafe {
+        assert_eq!(bytes_to_cbuffer(nested(6), hinted.as_mut_ptr()), ERR_NONE);
+        assert_eq!(
+            set_cbuffer_content_type(hinted.as_mut_ptr(), ContentType::Json),
+            ERR_NONE
+        );
+        assert_eq!(decode_any(hinted.as_ptr()), Err(ERR_JSON_LIMITS_EXCEEDED));
+    }
+
+    // The envelope is one level deeper than the value it encodes
+    let (encoder, decoder) = (cobhan_dictionary_new(), cobhan_dictionary_new());
+    let mut encoded = OwnedCBuffer::with_capacity(256);
+    let value = serde_json::from_str(&nested(4)).unwrap();
+    unsafe {
+        assert_eq!(
+            json_to_cbuffer_with_dictionary(encoder, &value, encoded.as_mut_ptr()),
+            ERR_NONE
+        );
+        assert_eq!(
+            cbuffer_to_json_with_dictionary(decoder, encoded.as_ptr()),
+            Ok(value)
+        );
+        assert_eq!(
+            cbuffer_to_json_with_dictionary(decoder, deep.as_ptr()),
+            Err(ERR_JSON_LIMITS_EXCEEDED)
+        );
+    }
+    assert_eq!(cobhan_dictionary_free(encoder), ERR_NONE);
+    assert_eq!(cobhan_dictionary_free(decoder), ERR_NONE);
+
+    let mut router = cobhan::rpc::Router::new();
+    router.register("echo", Ok);
+    let request = format!(r#"{{"method": "echo", "payload": {}}}"#, nested(5));
+    let request = OwnedCBuffer::from_bytes(request.as_bytes());
+    let mut response = OwnedCBuffer::with_capacity(256);
+    let status = unsafe {
+        assert_eq!(
+            cobhan::rpc::handle_rpc(request.as_ptr(), response.as_mut_ptr(), &router),
+            ERR_NONE
+        );
+        response.to_hashmap_json().unwrap()["status"].clone()
+    };
+    assert_eq!(status, ERR_JSON_LIMITS_EXCEEDED);
+
+    #[cfg(feature = "rayon")]
+    assert_eq!(
+        unsafe { cbuffers_to_json_parallel(&[within.as_ptr(), deep.as_ptr()]) }
+            .into_iter()
+            .map(|result| result.err())
+            .collect::<Vec<_>>(),
+        [None, Some(ERR_JSON_LIMITS_EXCEEDED)]
+    );
+}
@@ -0,0 +1,118 @@
+//! The lease pool and its limit are process-wide, so the tests take turns.
+
+use std::sync::mpsc;
+use std::sync::MutexGuard;
+use std::thread;
+use std::time::Duration;
+
+use cobhan::testing::configured;
+use cobhan::*;
+
+/// Limits the pool to `max_leased_bytes` until the returned guard is dropped.
+fn limited(max_leased_bytes: Option<usize>) -> MutexGuard<'static, ()> {
+    let guard = configured(CobhanConfig {
+        max_leased_bytes,
+        ..Default::default()
+    });
+    assert_eq!(current_leased_bytes(), 0);
+    guard
+}
+
+#[test]
+fn leased_buffers_are_output_buffers() {
+    let _guard = limited(None);
+    let mut output = lease_output_buffer(16);
+    assert_eq!(output.capacity(), 16);
+    assert_eq!(current_leased_bytes(), 16);
+
+    unsafe {
+        assert_eq!(bytes_to_cbuffer(b"leased", output.as_mut_ptr()), ERR_NONE);
+        assert_eq!(cbuffer_to_vector(output.as_ptr()).unwrap(), b"leased");
+        output.reset();
+        assert_eq!(bytes_to_cbuffer([0; 16], output.as_mut_ptr()), ERR_NONE);
+    }
+
+    drop(output);
+    assert_eq!(current_leased_bytes(), 0);
+}
+
+#[test]
+fn try_lease_fails_while_the_pool_is_full() {
+    let _guard = limited(Some(100));
+    let first = try_lease_output_buffer(60).unwrap();
+    assert_eq!(try_lease_output_buffer(60).unwrap_err(), ERR_OUT_OF_MEMORY);
+    let second = try_lease_output_buffer(40).unwrap();
+    assert_eq!(current_leased_bytes(), 100);
+    assert_eq!(try_lease_output_buffer(1).unwrap_err(), ERR_OUT_OF_MEMORY);
+
+    drop(first);
+    assert!(try_lease_output_buffer(60).is_ok());
+    drop(second);
+    assert_eq!(current_leased_bytes(), 0);
+}
+
+#[test]
+fn lease_until_gives_up_at_the_deadline() {
+    let _guard = limited(Some(100));
+    let _held = lease_output_buffer(100);
+    let deadline = Deadline::after(Duration::from_millis(20));
+    assert_eq!(
+        lease_output_buffer_until(1, deadline).unwrap_err(),
+        ERR_DEADLINE_EXCEEDED
+    );
+    assert!(deadline.is_expired());
+}
+
+#[test]
+fn blocking_leases_wait_for_a_return() {
+    let _guard = limited(Some(100));
+    let held = lease_output_buffer(100);
+    let (sender, receiver) = mpsc::channel();
+    let waiter = thread::spawn(move || {
+        let output = lease_output_buffer(50);
+        sender.send(output.capacity()).unwrap();
+    });
+    assert!(receiver.recv_timeout(Duration::from_millis(50)).is_err());
+
+    drop(held);
+    assert_eq!(receiver.recv_timeout(Duration::from_secs(10)), Ok(50));
+    waiter.join().unwrap();
+
+    let held = lease_output_buffer(100);
+    let waiter = thread::spawn(|| {
+        let deadline = Deadline::after(Duration::from_secs(10));
+        lease_output_buffer_until(100, deadline).map(|output| output.capacity())
+    });
+    thread::sleep(Duration::from_millis(20));
+    drop(held);
+    assert_eq!(waiter.join().unwrap(), Ok(100));
+    assert_eq!(current_leased_bytes(), 0);
+}
+
+#[test]
+fn oversized_leases_wait_for_an_empty_pool() {
+    let _guard = limited(Some(100));
+    let small = lease_output_buffer(10);
+    assert_eq!(try_lease_output_buffer(200).unwrap_err(), ERR_OUT_OF_MEMORY);
+    drop(small);
+
+    let large = try_lease_output_buffer(200).unwrap();
+    assert_eq!(current_leased_bytes(), 200);
+    assert_eq!(try_lease_output_buffer(1).unwrap_err(), ERR_OUT_OF_MEMORY);
+    drop(large);
+}
+
+#[test]
+fn capacities_must_fit_the_length_field() {
+    let _guard = limited(None);
+    let capacity = i32::MAX as usize + 1;
+    assert_eq!(
+        try_lease_output_buffer(capacity).unwrap_err(),
+        ERR_BUFFER_TOO_LARGE
+    );
+    assert_eq!(
+        lease_output_buffer_until(capacity, Deadline::none()).unwrap_err(),
+        ERR_BUFFER_TOO_LARGE
+    );
+    assert_eq!(current_leased_bytes(), 0);
+}
@@ -0,0 +1,132 @@
+//! Length field edge cases. The same assertions hold on 32 bit targets (i686, armv7), where
+//! `usize` is no wider than the length field.
+
+use cobhan::testing::OwnedCBuffer;
+use cobhan::*;
+
+/// An output buffer with room for `capacity` payload bytes and `length` in its header.
+fn buffer(length: i32, capacity: usize) -> OwnedCBuffer {
+    let mut buffer = OwnedCBuffer::with_capacity(capacity);
+    unsafe { *(buffer.as_mut_ptr() as *mut [u8; 4]) = encode_header_length(length) };
+    buffer
+}
+
+#[test]
+fn min_length_field_is_rejected() {
+    let buffer = buffer(i32::MIN, 0);
+    unsafe {
+        assert_eq!(cbuffer_to_vector(buffer.as_ptr()), Err(ERR_INVALID_LENGTH));
+        assert_eq!(cbuffer_to_string(buffer.as_ptr()), Err(ERR_INVALID_LENGTH));
+        assert_eq!(
+            cbuffer_to_hashmap_json(buffer.as_ptr()),
+            Err(ERR_INVALID_LENGTH)
+        );
+        assert_eq!(cbuffer_to_bitvec(buffer.as_ptr()), Err(ERR_INVALID_LENGTH));
+    }
+}
+
+#[test]
+fn min_capacity_is_rejected() {
+    let mut buffer = buffer(i32::MIN, 0);
+    let result = unsafe { bytes_to_cbuffer(b"value", buffer.as_mut_ptr()) };
+    assert_eq!(result, ERR_BUFFER_TOO_SMALL);
+}
+
+#[test]
+fn extreme_length_fields_round_trip() {
+    for length in [i32::MAX, i32::MIN + 1, i32::MIN, 0, -1, 1] {
+        assert_eq!(decode_header_length(encode_header_length(length)), length);
+    }
+}
+
+#[test]
+fn payload_filling_capacity_is_written_in_place() {
+    let mut output = buffer(8, 8);
+    let result = unsafe { bytes_to_cbuffer([7; 8], output.as_mut_ptr()) };
+    assert_eq!(result, ERR_NONE);
+    assert_eq!(output.length_field(), 8);
+    assert_eq!(
+        unsafe { cbuffer_to_vector(output.as_ptr()) },
+        Ok(vec![7; 8])
+    );
+}
+
+#[test]
+fn payload_one_past_capacity_spills() {
+    let mut output = buffer(4096, 4096);
+    let payload = vec![7; 4097];
+    let result = unsafe { bytes_to_cbuffer(&payload, output.as_mut_ptr()) };
+    assert_eq!(result, ERR_NONE);
+
+    assert!(output.length_field() < 0);
+    assert_eq!(unsafe { cbuffer_to_vector(output.as_ptr()) }, Ok(payload));
+}
+
+#[test]
+fn size_probes_report_the_required_capacity() {
+    assert_eq!(query_required_size(b"hello"), 5);
+    assert_eq!(query_required_size(b""), 0);
+
+    // A zero-capacity probe, then an exactly sized buffer
+    let mut probe = buffer(0, 0);
+    let required = unsafe { string_to_cbuffer_or_size("hello", probe.as_mut_ptr()) };
+    assert_eq!(required, 5);
+    assert_eq!(probe.length_field(), 0);
+
+    let mut output = buffer(required, required as usize);
+    let result = unsafe { string_to_cbuffer_or_size("hello", output.as_mut_ptr()) };
+    assert_eq!(result, ERR_NONE);
+    assert_eq!(
+        unsafe { cbuffer_to_string(output.as_ptr()) },
+        Ok("hello".to_string())
+    );
+
+    // Too small buffers are left alone rather than spilled
+    let mut output = buffer(4, 4);
+    let result = unsafe { bytes_to_cbuffer_or_size(b"hello", output.as_mut_ptr()) };
+    assert_eq!(result, 5);
+    assert_eq!(output.length_field(), 4);
+
+    let mut output = buffer(0, 0);
+    assert_eq!(
+        unsafe { bytes_to_cbuffer_or_size(b"", output.as_mut_ptr()) },
+        ERR_NONE
+    );
+    let mut output = buffer(-1, 0);
+    assert_eq!(
+        unsafe { bytes_to_cbuffer_or_size(b"", output.as_mut_ptr()) },
+        ERR_BUFFER_TOO_SMALL
+    );
+}
+
+#[test]
+fn vectored_writes_fill_buffers_in_order() {
+    let mut buffers = [buffer(4, 4), buffer(0, 0), buffer(4, 4), buffer(8, 8)];
+    let mut ptrs: Vec<_> = buffers.iter_mut().map(OwnedCBuffer::as_mut_ptr).collect();
+    let result = unsafe { bytes_to_cbuffers_vectored(b"0123456789", &ptrs) };
+    assert_eq!(result, ERR_NONE);
+    let payloads: Vec<_> = buffers
+        .iter()
+        .map(|b| unsafe { cbuffer_to_vector(b.as_ptr()) }.unwrap())
+        .collect();
+    assert_eq!(
+        payloads,
+        [&b"0123"[..], b"", b"4567", b"89"].map(|p| p.to_vec())
+    );
+
+    // Nothing is written unless the payload fits the buffers together
+    let mut short = [buffer(4, 4), buffer(4, 4)];
+    ptrs = short.iter_mut().map(OwnedCBuffer::as_mut_ptr).collect();
+    let result = unsafe { bytes_to_cbuffers_vectored(b"0123456789", &ptrs) };
+    assert_eq!(result, ERR_BUFFER_TOO_SMALL);
+    assert_eq!(short[1].length_field(), 4);
+
+    assert_eq!(
+        unsafe { bytes_to_cbuffers_vectored(b"x", &[]) },
+        ERR_BUFFER_TOO_SMALL
+    );
+    assert_eq!(
+        unsafe { bytes_to_cbuffers_vectored(b"x", &[std::ptr::null_mut()]) },
+        ERR_NULL_PTR
+    );
+}
@@ -0,0 +1,84 @@
+//! 2D byte matrices with a dimensions header.
+
+use cobhan::testing::OwnedCBuffer;
+use cobhan::*;
+
+#[test]
+fn matrices_round_trip_with_their_shape() {
+    // 2x3 RGB pixels
+    let data: Vec<u8> = (0..18).collect();
+    let mut output = OwnedCBuffer::with_capacity(64);
+    assert_eq!(
+        unsafe { matrix_to_cbuffer(2, 3, 3, &data, output.as_mut_ptr()) },
+        ERR_NONE
+    );
+    let bytes = output.to_vec().unwrap();
+    assert_eq!(&bytes[..12], &[2, 0, 0, 0, 3, 0, 0, 0, 3, 0, 0, 0]);
+    assert_eq!(&bytes[12..], &data[..]);
+
+    let matrix = unsafe { cbuffer_to_matrix(output.as_ptr()) }.unwrap();
+    assert_eq!(
+        matrix,
+        ByteMatrix {
+            rows: 2,
+            cols: 3,
+            elem_size: 3,
+            data: data.clone(),
+        }
+    );
+    assert_eq!(matrix.row(1), Some(&data[9..]));
+    assert_eq!(matrix.row(2), None);
+
+    let mut empty = OwnedCBuffer::with_capacity(16);
+    assert_eq!(
+        unsafe { matrix_to_cbuffer(0, 640, 4, &[], empty.as_mut_ptr()) },
+        ERR_NONE
+    );
+    let matrix = unsafe { cbuffer_to_matrix(empty.as_ptr()) }.unwrap();
+    assert_eq!((matrix.rows, matrix.cols, matrix.elem_size), (0, 640, 4));
+    assert_eq!(matrix.row(0), None);
+}
+
+#[test]
+fn writes_check_the_shape_against_the_data() {
+    let mut output = OwnedCBuffer::with_capacity(64);
+    for (rows, cols, elem_size, len) in [(2, 2, 2, 7), (2, 2, 0, 0), (usize::MAX, 2, 1, 0)] {
+        assert_eq!(
+            unsafe { matrix_to_cbuffer(rows, cols, elem_size, &vec![0; len], output.as_mut_ptr()) },
+            ERR_MALFORMED_PAYLOAD
+        );
+    }
+    // Dimensions past u32::MAX only exist on 64 bit targets
+    #[cfg(target_pointer_width = "64")]
+    assert_eq!(
+        unsafe { matrix_to_cbuffer(1 << 32, 0, 1, &[], output.as_mut_ptr()) },
+        ERR_BUFFER_TOO_LARGE
+    );
+}
+
+#[test]
+fn reads_check_the_shape_against_the_data() {
+    let header = |rows: u32, cols: u32, elem_size: u32| {
+        let mut bytes = Vec::new();
+        for value in [rows, cols, elem_size] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    };
+    let mut short = header(2, 2, 1);
+    short.extend_from_slice(&[1, 2, 3]);
+    let mut long = header(1, 1, 1);
+    long.extend_from_slice(&[1, 2]);
+    let overflowing = header(u32::MAX, u32::MAX, u32::MAX);
+    for payload in [short, long, overflowing, header(0, 0, 0), vec![1, 0, 0, 0]] {
+        let input = OwnedCBuffer::from_bytes(&payload);
+        assert_eq!(
+            unsafe { cbuffer_to_matrix(input.as_ptr()) },
+            Err(ERR_MALFORMED_PAYLOAD)
+        );
+    }
+    assert_eq!(
+        unsafe { cbuffer_to_matrix(std::ptr::null()) },
+        Err(ERR_NULL_PTR)
+    );
+}
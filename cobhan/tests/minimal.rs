@@ -0,0 +1,36 @@
+//! Builds without the `tempfile` feature have no default spill transport.
+
+#![cfg(not(feature = "tempfile"))]
+
+use std::os::raw::c_char;
+
+use cobhan::*;
+
+const HEADER_SIZE: usize = BUFFER_HEADER_SIZE as usize;
+
+fn with_capacity(capacity: usize) -> Vec<u64> {
+    let mut words = vec![0u64; (HEADER_SIZE + capacity).div_ceil(8)];
+    unsafe { *(words.as_mut_ptr() as *mut [u8; 4]) = encode_header_length(capacity as i32) };
+    words
+}
+
+#[test]
+fn payloads_that_fit_round_trip() {
+    let mut buffer = with_capacity(16);
+    let ptr = buffer.as_mut_ptr() as *mut c_char;
+    assert_eq!(unsafe { string_to_cbuffer("in place", ptr) }, ERR_NONE);
+    assert_eq!(
+        unsafe { cbuffer_to_string(ptr) },
+        Ok("in place".to_string())
+    );
+}
+
+#[test]
+fn payloads_that_do_not_fit_fail() {
+    let mut buffer = with_capacity(4);
+    let ptr = buffer.as_mut_ptr() as *mut c_char;
+    assert_eq!(
+        unsafe { string_to_cbuffer("does not fit", ptr) },
+        ERR_WRITE_TEMP_FILE_FAILED
+    );
+}
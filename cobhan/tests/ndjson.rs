@@ -0,0 +1,75 @@
+//! Newline-delimited JSON batches, encoded and decoded a line at a time.
+
+use cobhan::testing::OwnedCBuffer;
+use cobhan::*;
+use serde_json::{json, Value};
+
+fn lines(ndjson: &[u8]) -> Vec<Result<Value, i32>> {
+    let input = OwnedCBuffer::from_bytes(ndjson);
+    unsafe { cbuffer_to_ndjson_iter(input.as_ptr()) }.collect()
+}
+
+#[test]
+fn records_round_trip_one_per_line() {
+    let events = vec![
+        json!({"event": "login", "user": "a\nb"}),
+        json!([1, 2]),
+        json!("text"),
+    ];
+    let mut output = OwnedCBuffer::with_capacity(256);
+    assert_eq!(
+        unsafe { ndjson_to_cbuffer(events.iter(), output.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(
+        output.to_string().unwrap(),
+        "{\"event\":\"login\",\"user\":\"a\\nb\"}\n[1,2]\n\"text\"\n"
+    );
+    let decoded: Vec<Value> = unsafe { cbuffer_to_ndjson_iter(output.as_ptr()) }
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(decoded, events);
+
+    assert_eq!(
+        unsafe { ndjson_to_cbuffer(std::iter::empty::<&Value>(), output.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(lines(b""), vec![]);
+}
+
+#[test]
+fn blank_lines_crlf_and_a_missing_final_newline_are_accepted() {
+    assert_eq!(
+        lines(b"\n{\"a\": 1}\r\n  \n2\r\n\n3"),
+        vec![Ok(json!({"a": 1})), Ok(json!(2)), Ok(json!(3))]
+    );
+}
+
+#[test]
+fn a_bad_line_ends_the_iteration() {
+    assert_eq!(
+        lines(b"1\n{\"a\": \n3\n"),
+        vec![Ok(json!(1)), Err(ERR_JSON_DECODE_FAILED)]
+    );
+    assert_eq!(lines(b"1 2\n"), vec![Err(ERR_JSON_DECODE_FAILED)]);
+
+    let mut iter = unsafe { cbuffer_to_ndjson_iter(std::ptr::null()) };
+    assert_eq!(iter.next(), Some(Err(ERR_NULL_PTR)));
+    assert_eq!(iter.next(), None);
+}
+
+#[test]
+fn spilled_batches_are_streamed() {
+    let events: Vec<Value> = (0..5_000).map(|id| json!({"id": id})).collect();
+    let mut output = OwnedCBuffer::with_capacity(128);
+    assert_eq!(
+        unsafe { ndjson_to_cbuffer(events.iter(), output.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert!(output.is_spilled());
+    let count = unsafe { cbuffer_to_ndjson_iter(output.as_ptr()) }
+        .enumerate()
+        .map(|(id, event)| assert_eq!(event.unwrap()["id"], id))
+        .count();
+    assert_eq!(count, 5_000);
+}
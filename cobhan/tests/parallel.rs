@@ -0,0 +1,44 @@
+//! Batches of JSON documents decoded on the rayon thread pool.
+
+use cobhan::testing::{with_mock_transport, OwnedCBuffer};
+use cobhan::*;
+use serde_json::{json, Value};
+
+#[test]
+fn documents_are_decoded_in_order() {
+    let documents: Vec<Value> = (0..64)
+        .map(|i| json!({ "id": i, "tags": [i, i * 2] }))
+        .collect();
+    let buffers: Vec<_> = documents
+        .iter()
+        .map(|document| OwnedCBuffer::from_json(document).unwrap())
+        .collect();
+    let ptrs: Vec<_> = buffers.iter().map(OwnedCBuffer::as_ptr).collect();
+    let decoded = unsafe { cbuffers_to_json_parallel(&ptrs) };
+    assert_eq!(decoded, documents.into_iter().map(Ok).collect::<Vec<_>>());
+}
+
+#[test]
+fn failures_are_reported_per_document() {
+    let valid = OwnedCBuffer::from_bytes(b"[1, 2]");
+    let invalid = OwnedCBuffer::from_bytes(b"{");
+    let ptrs = [valid.as_ptr(), invalid.as_ptr(), std::ptr::null()];
+    let decoded = unsafe { cbuffers_to_json_parallel(&ptrs) };
+    assert_eq!(
+        decoded,
+        [
+            Ok(json!([1, 2])),
+            Err(ERR_JSON_DECODE_FAILED),
+            Err(ERR_NULL_PTR)
+        ]
+    );
+}
+
+#[test]
+fn spills_are_read_through_the_calling_threads_transport() {
+    with_mock_transport(|mock| {
+        let input = OwnedCBuffer::spilled(&mock.insert(br#"{"spilled": true}"#));
+        let decoded = unsafe { cbuffers_to_json_parallel(&[input.as_ptr()]) };
+        assert_eq!(decoded, [Ok(json!({ "spilled": true }))]);
+    });
+}
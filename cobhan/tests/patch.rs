@@ -0,0 +1,62 @@
+use cobhan::patch::{apply_patch, cbuffer_apply_patch, PatchWriter, OP_COPY};
+use cobhan::testing::{with_mock_transport, OwnedCBuffer};
+use cobhan::*;
+
+#[test]
+fn patches_rebuild_the_payload() {
+    let base = OwnedCBuffer::from_bytes(b"hello, old world");
+    let mut writer = PatchWriter::new();
+    writer.copy(0, 7).unwrap();
+    writer.insert(b"new").unwrap();
+    writer.copy(10, 6).unwrap();
+    let patch = OwnedCBuffer::from_bytes(writer.as_bytes());
+
+    let mut out = OwnedCBuffer::with_capacity(64);
+    assert_eq!(
+        unsafe { cbuffer_apply_patch(base.as_ptr(), patch.as_ptr(), out.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(out.to_vec().unwrap(), b"hello, new world");
+
+    assert_eq!(apply_patch(b"base", b"").unwrap(), b"");
+}
+
+#[test]
+fn malformed_patches_are_rejected() {
+    let mut writer = PatchWriter::new();
+    writer.copy(2, 3).unwrap();
+    assert_eq!(
+        apply_patch(b"four", writer.as_bytes()),
+        Err(ERR_MALFORMED_PAYLOAD)
+    );
+
+    let truncated = [OP_COPY, 0, 0, 0, 0, 1, 0];
+    assert_eq!(apply_patch(b"four", &truncated), Err(ERR_MALFORMED_PAYLOAD));
+    assert_eq!(apply_patch(b"four", &[0xff]), Err(ERR_MALFORMED_PAYLOAD));
+
+    let base = OwnedCBuffer::from_bytes(b"four");
+    let patch = OwnedCBuffer::from_bytes(&[0xff]);
+    let mut out = OwnedCBuffer::with_capacity(64);
+    assert_eq!(
+        unsafe { cbuffer_apply_patch(base.as_ptr(), patch.as_ptr(), out.as_mut_ptr()) },
+        ERR_MALFORMED_PAYLOAD
+    );
+}
+
+#[test]
+fn patched_payloads_spill_like_any_other() {
+    with_mock_transport(|_| {
+        let base = OwnedCBuffer::from_bytes(&[7u8; 1024]);
+        let mut writer = PatchWriter::new();
+        writer.copy(0, 1024).unwrap();
+        writer.copy(0, 1024).unwrap();
+        let patch = OwnedCBuffer::from_bytes(writer.as_bytes());
+        let mut out = OwnedCBuffer::with_capacity(16);
+        assert_eq!(
+            unsafe { cbuffer_apply_patch(base.as_ptr(), patch.as_ptr(), out.as_mut_ptr()) },
+            ERR_NONE
+        );
+        assert!(out.is_spilled());
+        assert_eq!(out.to_vec().unwrap(), vec![7u8; 2048]);
+    });
+}
@@ -0,0 +1,75 @@
+//! Pinned inputs and defensive copies for hosts with a moving garbage collector. The copy mode is
+//! process-wide configuration, so the tests take turns.
+
+use std::borrow::Cow;
+use std::ops::Range;
+use std::sync::MutexGuard;
+
+use cobhan::testing::{configured, OwnedCBuffer};
+use cobhan::*;
+
+/// Sets `defensive_copy_mode` until the returned guard is dropped.
+fn defensive_copies(enabled: bool) -> MutexGuard<'static, ()> {
+    configured(CobhanConfig {
+        defensive_copy_mode: enabled,
+        ..Default::default()
+    })
+}
+
+fn host_memory(buffer: &OwnedCBuffer) -> Range<usize> {
+    let start = buffer.as_ptr() as usize;
+    start..start + 64
+}
+
+fn chunk_addresses(buffer: &OwnedCBuffer) -> Vec<usize> {
+    let mut addresses = Vec::new();
+    let result = unsafe {
+        cbuffer_for_each_chunk(buffer.as_ptr(), 4, |chunk| {
+            addresses.push(chunk.as_ptr() as usize);
+            Ok(())
+        })
+    };
+    assert_eq!(result, Ok(()));
+    addresses
+}
+
+#[test]
+fn pinned_inputs_borrow_in_place_by_default() {
+    let _guard = defensive_copies(false);
+    let buffer = OwnedCBuffer::from_bytes(b"pinned by the host");
+    let input = unsafe { PinnedInput::new(buffer.as_ptr()) };
+    assert_eq!(input.as_ptr(), buffer.as_ptr());
+    let payload = input.payload().unwrap();
+    assert!(matches!(payload, Cow::Borrowed(_)));
+    assert!(host_memory(&buffer).contains(&(payload.as_ptr() as usize)));
+    assert_eq!(&*payload, b"pinned by the host");
+    assert_eq!(input.to_vec().unwrap(), b"pinned by the host");
+    assert_eq!(input.to_string().unwrap(), "pinned by the host");
+
+    for address in chunk_addresses(&buffer) {
+        assert!(host_memory(&buffer).contains(&address));
+    }
+
+    let input = unsafe { PinnedInput::new(std::ptr::null()) };
+    assert_eq!(input.payload(), Err(ERR_NULL_PTR));
+    assert_eq!(input.to_string(), Err(ERR_NULL_PTR));
+}
+
+#[test]
+fn defensive_copy_mode_never_borrows_host_memory() {
+    let _guard = defensive_copies(true);
+    let buffer = OwnedCBuffer::from_bytes(b"may move after the call");
+    let payload = unsafe { PinnedInput::new(buffer.as_ptr()) }
+        .payload()
+        .unwrap();
+    assert!(matches!(payload, Cow::Owned(_)));
+    assert_eq!(&*payload, b"may move after the call");
+
+    for address in chunk_addresses(&buffer) {
+        assert!(!host_memory(&buffer).contains(&address));
+    }
+
+    let json = OwnedCBuffer::from_bytes(br#"{"copied": true}"#);
+    let map = unsafe { cbuffer_to_hashmap_json(json.as_ptr()) }.unwrap();
+    assert_eq!(map["copied"], true);
+}
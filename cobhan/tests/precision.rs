@@ -0,0 +1,48 @@
+//! Numbers decoded with `arbitrary_precision` keep every digit.
+
+use cobhan::testing::OwnedCBuffer;
+use cobhan::*;
+
+#[test]
+fn numbers_round_trip_exactly() {
+    let json = r#"{"id":123456789012345678901234567890,"price":0.1000000000000000055511151231257827,"max":18446744073709551615,"neg":-9223372036854775809}"#;
+    let input = OwnedCBuffer::from_bytes(json.as_bytes());
+    let value = unsafe { cbuffer_to_json_value_precise(input.as_ptr()) }.unwrap();
+    assert_eq!(
+        value["price"].to_string(),
+        "0.1000000000000000055511151231257827"
+    );
+    assert_eq!(value["max"].as_u64(), Some(u64::MAX));
+
+    let mut output = OwnedCBuffer::with_capacity(256);
+    let map = value.as_object().unwrap().clone().into_iter().collect();
+    assert_eq!(
+        unsafe { hashmap_json_to_cbuffer(&map, output.as_mut_ptr()) },
+        ERR_NONE
+    );
+    let written = output.to_hashmap_json().unwrap();
+    assert_eq!(written["id"].to_string(), "123456789012345678901234567890");
+    assert_eq!(written["neg"].to_string(), "-9223372036854775809");
+}
+
+#[test]
+fn any_json_value_is_accepted() {
+    let input = OwnedCBuffer::from_bytes(b"[1.50, \"x\"]");
+    let value = unsafe { cbuffer_to_json_value_precise(input.as_ptr()) }.unwrap();
+    assert_eq!(value[0].to_string(), "1.50");
+
+    let input = OwnedCBuffer::from_bytes(b"[1,");
+    assert_eq!(
+        unsafe { cbuffer_to_json_value_precise(input.as_ptr()) },
+        Err(ERR_JSON_DECODE_FAILED)
+    );
+}
+
+#[test]
+fn strict_decode_keeps_every_digit() {
+    let json = r#"{"id":123456789012345678901234567890,"a":[{"price":1.50}]}"#;
+    let input = OwnedCBuffer::from_bytes(json.as_bytes());
+    let map = unsafe { cbuffer_to_hashmap_json_strict(input.as_ptr()) }.unwrap();
+    assert_eq!(map["id"].to_string(), "123456789012345678901234567890");
+    assert_eq!(map["a"][0]["price"].to_string(), "1.50");
+}
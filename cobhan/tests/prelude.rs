@@ -0,0 +1,124 @@
+//! One test per module, through `cobhan::prelude` only.
+
+use std::os::raw::c_char;
+
+use cobhan::prelude::*;
+
+const HEADER_SIZE: usize = BUFFER_HEADER_SIZE as usize;
+
+fn with_capacity(capacity: usize) -> Vec<u64> {
+    let mut words = vec![0u64; (HEADER_SIZE + capacity).div_ceil(8)];
+    unsafe { *(words.as_mut_ptr() as *mut [u8; 4]) = encode_header_length(capacity as i32) };
+    words
+}
+
+#[test]
+fn error_codes_are_distinct_and_negative() {
+    let codes = [
+        ERR_NULL_PTR,
+        ERR_BUFFER_TOO_LARGE,
+        ERR_BUFFER_TOO_SMALL,
+        ERR_COPY_FAILED,
+        ERR_JSON_DECODE_FAILED,
+        ERR_JSON_ENCODE_FAILED,
+        ERR_INVALID_UTF8,
+        ERR_READ_TEMP_FILE_FAILED,
+        ERR_WRITE_TEMP_FILE_FAILED,
+        ERR_INVALID_UTF16,
+        ERR_UNKNOWN_ENCODING,
+        ERR_INVALID_ENCODING,
+        ERR_BASE64_DECODE_FAILED,
+        ERR_HEX_DECODE_FAILED,
+        ERR_INVALID_TIMESTAMP,
+        ERR_INVALID_DECIMAL,
+        ERR_SCALAR_OUT_OF_RANGE,
+        ERR_MALFORMED_PAYLOAD,
+        ERR_INTERIOR_NUL,
+        ERR_INVALID_PATH,
+        ERR_INVALID_LENGTH,
+        ERR_LENGTH_OVERFLOW,
+        ERR_INVALID_CONFIG,
+        ERR_CAPTURE_FAILED,
+        ERR_OUT_OF_MEMORY,
+        ERR_CHECKSUM_MISMATCH,
+        ERR_JSON_DUPLICATE_KEY,
+        ERR_CSV_DECODE_FAILED,
+        ERR_CSV_ENCODE_FAILED,
+        ERR_COMPRESSION_FAILED,
+        ERR_PANIC,
+        ERR_WRONG_THREAD,
+        ERR_DEADLINE_EXCEEDED,
+        ERR_ARRAY_LENGTH_MISMATCH,
+        ERR_MISALIGNED_PAYLOAD,
+        ERR_UNKNOWN_METHOD,
+        ERR_UNSUPPORTED_VERSION,
+        ERR_INVALID_HANDLE,
+        ERR_UNKNOWN_TEST_VECTOR,
+        ERR_UNKNOWN_FORMAT,
+        ERR_JSON_LIMITS_EXCEEDED,
+    ];
+    for (i, code) in codes.iter().enumerate() {
+        assert_eq!(*code, -(i as i32) - 1);
+    }
+    assert_eq!(ERR_NONE, 0);
+}
+
+#[test]
+fn buffer_header_and_payload_round_trip() {
+    assert_eq!(decode_header_length(encode_header_length(-42)), -42);
+
+    let mut buffer = with_capacity(16);
+    let ptr = buffer.as_mut_ptr() as *mut c_char;
+    assert_eq!(unsafe { bytes_to_cbuffer(b"\x00\xffbytes", ptr) }, ERR_NONE);
+    assert_eq!(
+        unsafe { cbuffer_to_vector(ptr) },
+        Ok(b"\x00\xffbytes".to_vec())
+    );
+    assert_eq!(unsafe { string_to_cbuffer("text", ptr) }, ERR_NONE);
+    assert_eq!(unsafe { cbuffer_to_string(ptr) }, Ok("text".to_string()));
+    assert_eq!(
+        unsafe { cbuffer_to_vector(std::ptr::null()) },
+        Err(ERR_NULL_PTR)
+    );
+}
+
+#[cfg(feature = "tempfile")]
+#[test]
+fn temp_spills_and_reads_back() {
+    let mut buffer = with_capacity(256);
+    let ptr = buffer.as_mut_ptr() as *mut c_char;
+    let payload = "spilled ".repeat(64);
+    assert_eq!(unsafe { string_to_cbuffer(&payload, ptr) }, ERR_NONE);
+    let length = decode_header_length(unsafe { *(buffer.as_ptr() as *const [u8; 4]) });
+    assert!(length < 0, "payload should spill");
+    assert_eq!(unsafe { cbuffer_to_string(ptr) }, Ok(payload));
+
+    let path = unsafe {
+        std::slice::from_raw_parts(
+            (buffer.as_ptr() as *const u8).add(HEADER_SIZE),
+            length.unsigned_abs() as usize,
+        )
+    };
+    let _ = std::fs::remove_file(std::str::from_utf8(path).unwrap());
+}
+
+#[cfg(all(feature = "std", feature = "json"))]
+#[test]
+fn json_round_trip() {
+    use std::collections::HashMap;
+
+    let mut json = HashMap::new();
+    json.insert("name".to_string(), serde_json::json!("cobhan"));
+    json.insert("sizes".to_string(), serde_json::json!([1, 2, 3]));
+
+    let mut buffer = with_capacity(64);
+    let ptr = buffer.as_mut_ptr() as *mut c_char;
+    assert_eq!(unsafe { hashmap_json_to_cbuffer(&json, ptr) }, ERR_NONE);
+    assert_eq!(unsafe { cbuffer_to_hashmap_json(ptr) }, Ok(json));
+
+    assert_eq!(unsafe { string_to_cbuffer("{", ptr) }, ERR_NONE);
+    assert_eq!(
+        unsafe { cbuffer_to_hashmap_json(ptr) },
+        Err(ERR_JSON_DECODE_FAILED)
+    );
+}
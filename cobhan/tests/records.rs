@@ -0,0 +1,80 @@
+use cobhan::records::{cbuffer_for_each_record, RecordReader, RecordWriter};
+use cobhan::testing::{with_mock_transport, OwnedCBuffer};
+use cobhan::*;
+
+#[test]
+fn records_round_trip() {
+    let mut writer = RecordWriter::new();
+    assert!(writer.is_empty());
+    for record in [&b"first"[..], b"", b"third record"] {
+        writer.write_record(record).unwrap();
+    }
+    assert_eq!(writer.len(), 3);
+    assert_eq!(&writer.as_bytes()[..9], b"\x05\x00\x00\x00first");
+
+    let mut buffer = OwnedCBuffer::with_capacity(64);
+    assert_eq!(unsafe { writer.to_cbuffer(buffer.as_mut_ptr()) }, ERR_NONE);
+    let mut records = Vec::new();
+    let result = unsafe {
+        cbuffer_for_each_record(buffer.as_ptr(), |record| {
+            records.push(record.to_vec());
+            Ok(())
+        })
+    };
+    assert_eq!(result, Ok(()));
+    assert_eq!(records, [&b"first"[..], b"", b"third record"]);
+}
+
+#[test]
+fn reader_stops_at_truncated_records() {
+    let mut writer = RecordWriter::new();
+    writer.write_record(b"whole").unwrap();
+    writer.write_record(b"cut short").unwrap();
+    let bytes = writer.into_bytes();
+    let mut reader = RecordReader::new(&bytes[..bytes.len() - 1]);
+    assert_eq!(reader.next(), Some(Ok(&b"whole"[..])));
+    assert_eq!(reader.next(), Some(Err(ERR_MALFORMED_PAYLOAD)));
+    assert_eq!(reader.next(), None);
+
+    assert_eq!(RecordReader::new(b"").next(), None);
+}
+
+#[test]
+fn for_each_record_stops_at_the_first_error() {
+    let mut writer = RecordWriter::new();
+    writer.write_record(b"one").unwrap();
+    writer.write_record(b"two").unwrap();
+    let buffer = OwnedCBuffer::from_bytes(writer.as_bytes());
+    let mut seen = 0;
+    let result = unsafe {
+        cbuffer_for_each_record(buffer.as_ptr(), |_| {
+            seen += 1;
+            Err(ERR_MALFORMED_PAYLOAD)
+        })
+    };
+    assert_eq!(result, Err(ERR_MALFORMED_PAYLOAD));
+    assert_eq!(seen, 1);
+}
+
+#[test]
+fn spilled_records_are_read_back() {
+    with_mock_transport(|_| {
+        let mut writer = RecordWriter::new();
+        for i in 0..100u32 {
+            writer.write_record(&i.to_le_bytes()).unwrap();
+        }
+        let mut buffer = OwnedCBuffer::with_capacity(16);
+        assert_eq!(unsafe { writer.to_cbuffer(buffer.as_mut_ptr()) }, ERR_NONE);
+        assert!(buffer.is_spilled());
+        let mut next = 0u32;
+        let result = unsafe {
+            cbuffer_for_each_record(buffer.as_ptr(), |record| {
+                assert_eq!(record, next.to_le_bytes());
+                next += 1;
+                Ok(())
+            })
+        };
+        assert_eq!(result, Ok(()));
+        assert_eq!(next, 100);
+    });
+}
@@ -0,0 +1,99 @@
+//! Payload content redacted before it reaches diagnostics. The hook is process-wide, so the
+//! tests take turns.
+
+use std::borrow::Cow;
+use std::sync::{Mutex, MutexGuard};
+
+use cobhan::capture::{read_capture, start_capture, stop_capture};
+use cobhan::rpc::{handle_rpc, Router};
+use cobhan::testing::{configured, OwnedCBuffer};
+use cobhan::*;
+
+fn mask_secrets(bytes: &[u8]) -> Cow<'_, [u8]> {
+    if bytes.windows(6).any(|w| w == b"secret") {
+        Cow::Borrowed(b"<redacted>")
+    } else {
+        Cow::Borrowed(bytes)
+    }
+}
+
+/// Redacts with `mask_secrets` until the returned guard is dropped.
+fn redacting() -> MutexGuard<'static, ()> {
+    let guard = configured(CobhanConfig::default());
+    set_redaction_hook(mask_secrets);
+    guard
+}
+
+#[test]
+fn last_errors_are_redacted() {
+    let _guard = redacting();
+    set_last_error("bad token secret-123");
+    assert_eq!(last_error().as_deref(), Some("<redacted>"));
+    set_last_error("bad token");
+    assert_eq!(last_error().as_deref(), Some("bad token"));
+
+    clear_redaction_hook();
+    set_last_error("bad token secret-123");
+    assert_eq!(last_error().as_deref(), Some("bad token secret-123"));
+    clear_last_error();
+}
+
+#[test]
+fn captured_payloads_are_redacted_but_not_the_payloads_themselves() {
+    let _guard = redacting();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("calls.cbncap");
+    start_capture(&path, Default::default()).unwrap();
+    let mut output = OwnedCBuffer::with_capacity(64);
+    assert_eq!(
+        unsafe { string_to_cbuffer("the secret", output.as_mut_ptr()) },
+        ERR_NONE
+    );
+    stop_capture();
+    clear_redaction_hook();
+
+    assert_eq!(read_capture(&path).unwrap()[0].bytes, b"<redacted>");
+    assert_eq!(output.to_vec().unwrap(), b"the secret");
+}
+
+#[test]
+fn rpc_error_messages_are_redacted() {
+    let _guard = redacting();
+    let request = OwnedCBuffer::from_bytes(br#"{"method": "secret-method"}"#);
+    let mut response = OwnedCBuffer::with_capacity(256);
+    assert_eq!(
+        unsafe { handle_rpc(request.as_ptr(), response.as_mut_ptr(), &Router::new()) },
+        ERR_NONE
+    );
+    clear_redaction_hook();
+    let response = response.to_string().unwrap();
+    assert!(response.contains("<redacted>"), "{}", response);
+    assert!(!response.contains("secret-method"), "{}", response);
+}
+
+#[cfg(feature = "cobhan_debug")]
+#[test]
+fn debug_output_is_redacted() {
+    use std::sync::Arc;
+
+    let _guard = redacting();
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let sink = lines.clone();
+    configure(CobhanConfig {
+        debug_sink: DebugSink::Callback(Arc::new(move |line: &str| {
+            sink.lock().unwrap().push(line.to_string())
+        })),
+        ..Default::default()
+    });
+    let settings = OwnedCBuffer::from_bytes(br#"{"temp_dir": 7, "password": "secret"}"#);
+    assert_eq!(
+        unsafe { cobhan_configure(settings.as_ptr()) },
+        ERR_INVALID_CONFIG
+    );
+    configure(CobhanConfig::default());
+    clear_redaction_hook();
+
+    let lines = lines.lock().unwrap();
+    assert!(lines.iter().any(|line| line.contains("<redacted>")));
+    assert!(!lines.iter().any(|line| line.contains("secret")));
+}
@@ -0,0 +1,88 @@
+//! Grow and retry: writes that fail with `ERR_BUFFER_TOO_SMALL` leave the capacity to retry with
+//! in the reserved field. The buffer format is process-wide configuration, so the tests take
+//! turns.
+
+use cobhan::testing::{configured, with_mock_transport, OwnedCBuffer};
+use cobhan::*;
+
+/// The loop a host writes once for every function using `write_with_retry`.
+fn grow_and_retry(payload: &[u8], policy: RetryPolicy) -> (OwnedCBuffer, usize) {
+    let mut output = OwnedCBuffer::with_capacity(8);
+    let mut attempts = 1;
+    let mut result = unsafe { write_with_retry(payload, output.as_mut_ptr(), policy) };
+    while result == ERR_BUFFER_TOO_SMALL {
+        let capacity = unsafe { cbuffer_required_size(output.as_ptr()) }.unwrap();
+        output = OwnedCBuffer::with_capacity(capacity);
+        attempts += 1;
+        result = unsafe { write_with_retry(payload, output.as_mut_ptr(), policy) };
+    }
+    assert_eq!(result, ERR_NONE);
+    (output, attempts)
+}
+
+#[test]
+fn hosts_retry_with_the_hinted_capacity() {
+    let _guard = configured(CobhanConfig::default());
+    let (output, attempts) = grow_and_retry(&[5; 100], RetryPolicy::Exact);
+    assert_eq!(attempts, 2);
+    assert_eq!(output.capacity(), 100);
+    assert_eq!(output.to_vec(), Ok(vec![5; 100]));
+
+    let (output, attempts) = grow_and_retry(&[5; 100], RetryPolicy::NextPowerOfTwo);
+    assert_eq!(attempts, 2);
+    assert_eq!(output.capacity(), 128);
+    assert_eq!(output.to_vec(), Ok(vec![5; 100]));
+
+    let (output, attempts) = grow_and_retry(b"fits", RetryPolicy::Exact);
+    assert_eq!(attempts, 1);
+    assert_eq!(output.to_vec(), Ok(b"fits".to_vec()));
+}
+
+#[test]
+fn hints_stay_within_max_buffer_len() {
+    let _guard = configured(CobhanConfig {
+        max_buffer_len: Some(150),
+        ..Default::default()
+    });
+    let mut output = OwnedCBuffer::with_capacity(8);
+    assert_eq!(
+        unsafe { write_with_retry([0; 130], output.as_mut_ptr(), RetryPolicy::NextPowerOfTwo) },
+        ERR_BUFFER_TOO_SMALL
+    );
+    assert_eq!(unsafe { cbuffer_required_size(output.as_ptr()) }, Ok(150));
+    assert_eq!(
+        unsafe { write_with_retry([0; 200], output.as_mut_ptr(), RetryPolicy::Exact) },
+        ERR_BUFFER_TOO_LARGE
+    );
+}
+
+#[test]
+fn the_spill_policy_spills() {
+    let _guard = configured(CobhanConfig::default());
+    with_mock_transport(|transport| {
+        let mut output = OwnedCBuffer::with_capacity(32);
+        assert_eq!(
+            unsafe { write_with_retry([1; 100], output.as_mut_ptr(), RetryPolicy::Spill) },
+            ERR_NONE
+        );
+        assert!(output.is_spilled());
+        assert_eq!(transport.spilled_len(), 100);
+    });
+}
+
+#[test]
+fn hints_need_format_v1() {
+    let _guard = configured(CobhanConfig {
+        buffer_format: BufferFormat::V2,
+        ..Default::default()
+    });
+    let mut output = OwnedCBuffer::with_capacity(8);
+    assert_eq!(
+        unsafe { write_with_retry([0; 100], output.as_mut_ptr(), RetryPolicy::Exact) },
+        ERR_INVALID_CONFIG
+    );
+    assert_eq!(
+        unsafe { cbuffer_required_size(std::ptr::null()) },
+        Err(ERR_NULL_PTR)
+    );
+}
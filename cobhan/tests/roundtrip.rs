@@ -0,0 +1,123 @@
+//! Round-trip properties: for any value and any output capacity, writing the value into a
+//! Cobhan Buffer and reading it back returns the original value. Capacities smaller than the
+//! payload force the temp file path.
+
+use std::collections::HashMap;
+
+use cobhan::testing::OwnedCBuffer;
+use cobhan::*;
+use proptest::prelude::*;
+use serde_json::Value;
+
+/// Large enough to hold any temp file path, so spills always succeed.
+const PATH_CAPACITY: usize = 4096;
+
+/// Checks the outcome of a write of `payload_len` bytes into a buffer of `capacity` bytes.
+///
+/// Returns `true` if the buffer holds a payload that should be read back.
+fn check_write(result: i32, output: &OwnedCBuffer, capacity: usize, payload_len: usize) -> bool {
+    if capacity == 0 {
+        assert_eq!(result, ERR_BUFFER_TOO_SMALL);
+        return false;
+    }
+    if capacity >= payload_len {
+        assert_eq!(result, ERR_NONE);
+        assert_eq!(output.length_field(), payload_len as i32);
+        return true;
+    }
+    if result == ERR_BUFFER_TOO_SMALL {
+        // Only acceptable when the temp file path itself does not fit
+        assert!(capacity < PATH_CAPACITY);
+        return false;
+    }
+    assert_eq!(result, ERR_NONE);
+    assert!(output.length_field() < 0);
+    true
+}
+
+fn capacity_for(len: usize) -> impl Strategy<Value = usize> {
+    prop_oneof![
+        0..=len + 8,
+        Just(len),
+        Just(len.saturating_sub(1)),
+        Just(PATH_CAPACITY),
+    ]
+}
+
+fn json_value() -> impl Strategy<Value = Value> {
+    let leaf = prop_oneof![
+        Just(Value::Null),
+        any::<bool>().prop_map(Value::from),
+        any::<i64>().prop_map(Value::from),
+        any::<u64>().prop_map(Value::from),
+        ".*".prop_map(Value::from),
+    ];
+    leaf.prop_recursive(4, 64, 8, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..8).prop_map(Value::from),
+            prop::collection::hash_map(".*", inner, 0..8)
+                .prop_map(|m| Value::Object(m.into_iter().collect())),
+        ]
+    })
+}
+
+proptest! {
+    #[test]
+    fn bytes_round_trip(
+        (bytes, capacity) in prop::collection::vec(any::<u8>(), 0..2048)
+            .prop_flat_map(|b| { let len = b.len(); (Just(b), capacity_for(len)) })
+    ) {
+        let mut output = OwnedCBuffer::with_capacity(capacity);
+        let result = unsafe { bytes_to_cbuffer(&bytes, output.as_mut_ptr()) };
+        if check_write(result, &output, capacity, bytes.len()) {
+            let decoded = unsafe { cbuffer_to_vector(output.as_ptr()) };
+            prop_assert_eq!(decoded, Ok(bytes));
+        }
+    }
+
+    #[cfg(feature = "smallvec")]
+    #[test]
+    fn smallvec_round_trip(
+        (bytes, capacity) in prop::collection::vec(any::<u8>(), 0..256)
+            .prop_flat_map(|b| { let len = b.len(); (Just(b), capacity_for(len)) })
+    ) {
+        let mut output = OwnedCBuffer::with_capacity(capacity);
+        let result = unsafe { bytes_to_cbuffer(&bytes, output.as_mut_ptr()) };
+        if check_write(result, &output, capacity, bytes.len()) {
+            let decoded = unsafe { cbuffer_to_smallvec::<64>(output.as_ptr()) };
+            let decoded = decoded.unwrap();
+            prop_assert_eq!(decoded.spilled(), bytes.len() > 64);
+            prop_assert_eq!(decoded.as_slice(), &bytes[..]);
+        }
+    }
+
+    #[test]
+    fn string_round_trip(
+        (string, capacity) in ".{0,512}"
+            .prop_flat_map(|s: String| { let len = s.len(); (Just(s), capacity_for(len)) })
+    ) {
+        let mut output = OwnedCBuffer::with_capacity(capacity);
+        let result = unsafe { string_to_cbuffer(&string, output.as_mut_ptr()) };
+        if check_write(result, &output, capacity, string.len()) {
+            let decoded = unsafe { cbuffer_to_string(output.as_ptr()) };
+            prop_assert_eq!(decoded, Ok(string));
+        }
+    }
+
+    #[test]
+    fn json_map_round_trip(
+        (map, capacity) in prop::collection::hash_map(".*", json_value(), 0..16)
+            .prop_flat_map(|m: HashMap<String, Value>| {
+                let len = serde_json::to_vec(&m).unwrap().len();
+                (Just(m), capacity_for(len))
+            })
+    ) {
+        let encoded_len = serde_json::to_vec(&map).unwrap().len();
+        let mut output = OwnedCBuffer::with_capacity(capacity);
+        let result = unsafe { hashmap_json_to_cbuffer(&map, output.as_mut_ptr()) };
+        if check_write(result, &output, capacity, encoded_len) {
+            let decoded = unsafe { cbuffer_to_hashmap_json(output.as_ptr()) };
+            prop_assert_eq!(decoded, Ok(map));
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! Request/response RPC envelopes dispatched through a router.
+
+use cobhan::rpc::{handle_rpc, Router, RpcError, RpcRequest, RpcResponse};
+use cobhan::testing::OwnedCBuffer;
+use cobhan::*;
+use serde_json::{json, Value};
+
+fn router() -> Router {
+    let mut router = Router::new();
+    router
+        .register("echo", Ok)
+        .register("area", |payload| {
+            let side = |name| {
+                payload[name]
+                    .as_u64()
+                    .ok_or_else(|| RpcError::new(-1001, "bad side"))
+            };
+            Ok(json!({ "area": side("width")? * side("height")? }))
+        })
+        .register("fail", |_| Err(ERR_INVALID_UTF8.into()));
+    router
+}
+
+fn call(router: &Router, request: &[u8]) -> Value {
+    let input = OwnedCBuffer::from_bytes(request);
+    let mut output = OwnedCBuffer::with_capacity(256);
+    assert_eq!(
+        unsafe { handle_rpc(input.as_ptr(), output.as_mut_ptr(), router) },
+        ERR_NONE
+    );
+    serde_json::from_slice(&output.to_vec().unwrap()).unwrap()
+}
+
+#[test]
+fn requests_are_dispatched_by_method() {
+    let router = router();
+    assert_eq!(
+        call(
+            &router,
+            br#"{"method": "area", "payload": {"width": 4, "height": 5}}"#
+        ),
+        json!({"status": 0, "payload": {"area": 20}})
+    );
+    assert_eq!(
+        call(&router, br#"{"method": "echo", "payload": [1, "two"]}"#),
+        json!({"status": 0, "payload": [1, "two"]})
+    );
+    // A missing payload is null
+    assert_eq!(
+        call(&router, br#"{"method": "echo"}"#),
+        json!({"status": 0, "payload": null})
+    );
+}
+
+#[test]
+fn handler_errors_carry_status_and_error_object() {
+    let router = router();
+    assert_eq!(
+        call(&router, br#"{"method": "area", "payload": {"width": 4}}"#),
+        json!({"status": -1001, "error": {"message": "bad side"}})
+    );
+    assert_eq!(
+        call(&router, br#"{"method": "fail"}"#),
+        json!({"status": ERR_INVALID_UTF8, "error": {"message": "cobhan error -7"}})
+    );
+    let response = call(&router, br#"{"method": "rotate"}"#);
+    assert_eq!(response["status"], ERR_UNKNOWN_METHOD);
+    assert_eq!(
+        response["error"]["message"],
+        r#"no handler for method "rotate""#
+    );
+}
+
+#[test]
+fn invalid_envelopes_get_an_error_response() {
+    let router = router();
+    for request in [
+        &b"not json"[..],
+        br#"["echo"]"#,
+        br#"{"payload": 1}"#,
+        br#"{"method": 7}"#,
+    ] {
+        let response = call(&router, request);
+        assert_eq!(response["status"], ERR_JSON_DECODE_FAILED, "{:?}", response);
+        assert!(response["error"]["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("invalid request envelope: "));
+        assert_eq!(response.get("payload"), None);
+    }
+
+    let mut output = OwnedCBuffer::with_capacity(256);
+    assert_eq!(
+        unsafe { handle_rpc(std::ptr::null(), output.as_mut_ptr(), &router) },
+        ERR_NONE
+    );
+    let response: Value = serde_json::from_slice(&output.to_vec().unwrap()).unwrap();
+    assert_eq!(response["status"], ERR_NULL_PTR);
+}
+
+#[test]
+fn write_failures_are_returned() {
+    let input = OwnedCBuffer::from_bytes(br#"{"method": "echo"}"#);
+    assert_eq!(
+        unsafe { handle_rpc(input.as_ptr(), std::ptr::null_mut(), &router()) },
+        ERR_NULL_PTR
+    );
+}
+
+#[test]
+fn envelopes_convert_to_and_from_json() {
+    let request = RpcRequest::from_json(json!({"method": "m", "payload": 1, "id": 9})).unwrap();
+    assert_eq!(
+        request,
+        RpcRequest {
+            method: "m".to_owned(),
+            payload: json!(1),
+        }
+    );
+    assert_eq!(
+        RpcResponse::success(json!("ok")).to_json(),
+        json!({"status": 0, "payload": "ok"})
+    );
+    assert_eq!(
+        RpcResponse::failure(RpcError::new(-2000, "nope")).to_json(),
+        json!({"status": -2000, "error": {"message": "nope"}})
+    );
+    assert_eq!(
+        format!("{:?}", router()),
+        r#"Router { methods: ["area", "echo", "fail"] }"#
+    );
+}
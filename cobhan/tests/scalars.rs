@@ -0,0 +1,68 @@
+//! 64 and 128 bit values passed as i64 (hi, lo) pairs, for hosts without wider integers.
+
+use cobhan::*;
+
+#[test]
+fn u64_values_round_trip_through_pairs() {
+    for value in [
+        0,
+        1,
+        u32::MAX as u64,
+        u32::MAX as u64 + 1,
+        u64::MAX - 1,
+        u64::MAX,
+    ] {
+        let (hi, lo) = u64_to_i64_pair(value);
+        assert!((0..=u32::MAX as i64).contains(&hi));
+        assert!((0..=u32::MAX as i64).contains(&lo));
+        assert_eq!(i64_pair_to_u64(hi, lo), Ok(value));
+    }
+    assert_eq!(u64_to_i64_pair(0), (0, 0));
+    assert_eq!(
+        u64_to_i64_pair(u64::MAX),
+        (u32::MAX as i64, u32::MAX as i64)
+    );
+}
+
+#[test]
+fn out_of_range_halves_are_rejected() {
+    let max = u32::MAX as i64;
+    for (hi, lo) in [
+        (-1, 0),
+        (0, -1),
+        (max + 1, 0),
+        (0, max + 1),
+        (i64::MIN, i64::MAX),
+    ] {
+        assert_eq!(i64_pair_to_u64(hi, lo), Err(ERR_SCALAR_OUT_OF_RANGE));
+    }
+}
+
+#[test]
+fn i128_values_round_trip_through_pairs() {
+    for value in [
+        0,
+        1,
+        -1,
+        i64::MIN as i128,
+        u64::MAX as i128,
+        i128::MIN,
+        i128::MAX,
+    ] {
+        let (hi, lo) = i128_to_i64_pair(value);
+        assert_eq!(i64_pair_to_i128(hi, lo), value);
+    }
+    assert_eq!(i128_to_i64_pair(-1), (-1, -1));
+    assert_eq!(i128_to_i64_pair(i128::MIN), (i64::MIN, 0));
+    assert_eq!(i128_to_i64_pair(i128::MAX), (i64::MAX, -1));
+}
+
+#[test]
+fn u128_values_round_trip_through_pairs() {
+    for value in [0, 1, u64::MAX as u128, u64::MAX as u128 + 1, u128::MAX] {
+        let (hi, lo) = u128_to_i64_pair(value);
+        assert_eq!(i64_pair_to_u128(hi, lo), value);
+    }
+    assert_eq!(u128_to_i64_pair(u128::MAX), (-1, -1));
+    assert_eq!(u128_to_i64_pair(u64::MAX as u128 + 1), (1, 0));
+}
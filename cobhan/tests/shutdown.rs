@@ -0,0 +1,49 @@
+//! Shutdown waits for process-wide helper threads and removes process-wide spill files, so the
+//! tests take turns.
+
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use cobhan::testing::{configured, OwnedCBuffer};
+use cobhan::*;
+
+#[test]
+fn removes_live_spill_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let _guard = configured(CobhanConfig {
+        temp_dir: Some(dir.path().to_path_buf()),
+        ..Default::default()
+    });
+    let mut kept = OwnedCBuffer::with_capacity(256);
+    let mut read = OwnedCBuffer::with_capacity(256);
+    unsafe {
+        assert_eq!(bytes_to_cbuffer([1; 1024], kept.as_mut_ptr()), ERR_NONE);
+        assert_eq!(bytes_to_cbuffer([2; 1024], read.as_mut_ptr()), ERR_NONE);
+    }
+    let kept_file = kept.spill_reference().unwrap();
+    // The host reads and removes the spill files of the outputs it consumes
+    fs::remove_file(read.spill_reference().unwrap()).unwrap();
+    let input_file = dir.path().join("input");
+    fs::write(&input_file, b"written by the host").unwrap();
+
+    assert_eq!(cobhan_shutdown(0), ERR_NONE);
+    assert!(!Path::new(&kept_file).exists());
+    assert!(input_file.exists());
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    configure(CobhanConfig::default());
+}
+
+#[test]
+fn waits_for_helper_threads() {
+    let _guard = configured(CobhanConfig::default());
+    let run = Deadline::after(Duration::from_millis(10))
+        .run(|| thread::sleep(Duration::from_millis(300)));
+    assert_eq!(run, Err(ERR_DEADLINE_EXCEEDED));
+
+    assert_eq!(cobhan_shutdown(0), ERR_DEADLINE_EXCEEDED);
+    assert_eq!(cobhan_shutdown(-1), ERR_DEADLINE_EXCEEDED);
+    assert_eq!(cobhan_shutdown(10_000), ERR_NONE);
+    assert_eq!(cobhan_shutdown(0), ERR_NONE);
+}
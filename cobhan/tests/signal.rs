@@ -0,0 +1,63 @@
+use cobhan::testing::OwnedCBuffer;
+use cobhan::*;
+
+#[test]
+fn copy_payload_copies_header_and_payload() {
+    let mut source = OwnedCBuffer::from_bytes(b"diagnostics");
+    assert_eq!(
+        unsafe { set_cbuffer_flags(source.as_mut_ptr(), BufferFlags::empty().with_app_bits(7)) },
+        ERR_NONE
+    );
+    let mut destination = OwnedCBuffer::with_capacity(64);
+    let result = unsafe { cbuffer_copy_payload(source.as_ptr(), destination.as_mut_ptr()) };
+    assert_eq!(result, ERR_NONE);
+    assert_eq!(destination.to_vec().unwrap(), b"diagnostics");
+    assert_eq!(destination.flags().app_bits(), 7);
+
+    // Exactly full is enough
+    let mut exact = OwnedCBuffer::with_capacity(11);
+    let result = unsafe { cobhan_copy_payload(source.as_ptr(), exact.as_mut_ptr()) };
+    assert_eq!(result, ERR_NONE);
+    assert_eq!(exact.to_string().unwrap(), "diagnostics");
+}
+
+#[test]
+fn copy_payload_copies_spill_references() {
+    let source = OwnedCBuffer::spilled("/tmp/.tmp0123456789abcdef");
+    let mut destination = OwnedCBuffer::with_capacity(64);
+    let result = unsafe { cbuffer_copy_payload(source.as_ptr(), destination.as_mut_ptr()) };
+    assert_eq!(result, ERR_NONE);
+    assert_eq!(destination.length_field(), source.length_field());
+    assert_eq!(
+        destination.spill_reference().as_deref(),
+        Some("/tmp/.tmp0123456789abcdef")
+    );
+}
+
+#[test]
+fn copy_payload_rejects_what_does_not_fit() {
+    let source = OwnedCBuffer::from_bytes(b"diagnostics");
+    let mut destination = OwnedCBuffer::from_bytes(b"untouched!");
+    let result = unsafe { cbuffer_copy_payload(source.as_ptr(), destination.as_mut_ptr()) };
+    assert_eq!(result, ERR_BUFFER_TOO_SMALL);
+    assert_eq!(destination.to_vec().unwrap(), b"untouched!");
+
+    let mut destination = OwnedCBuffer::with_capacity(64);
+    let null = std::ptr::null_mut();
+    assert_eq!(
+        unsafe { cbuffer_copy_payload(std::ptr::null(), destination.as_mut_ptr()) },
+        ERR_NULL_PTR
+    );
+    assert_eq!(
+        unsafe { cbuffer_copy_payload(source.as_ptr(), null) },
+        ERR_NULL_PTR
+    );
+}
+
+#[test]
+fn copy_payload_within_one_buffer() {
+    let mut buffer = OwnedCBuffer::from_bytes(b"in place");
+    let result = unsafe { cbuffer_copy_payload(buffer.as_ptr(), buffer.as_mut_ptr()) };
+    assert_eq!(result, ERR_NONE);
+    assert_eq!(buffer.to_vec().unwrap(), b"in place");
+}
@@ -0,0 +1,160 @@
+//! Thread spill files, rewritten by each spill once the host released the last one, and the
+//! release of spills. The setting is process-wide configuration, so the tests take turns.
+
+use std::fs;
+use std::sync::MutexGuard;
+
+use cobhan::testing::{configured, OwnedCBuffer};
+use cobhan::*;
+
+fn reuse_spill_files() -> MutexGuard<'static, ()> {
+    configured(CobhanConfig {
+        reuse_spill_files: true,
+        ..Default::default()
+    })
+}
+
+fn spill(payload: &[u8]) -> OwnedCBuffer {
+    let mut buffer = OwnedCBuffer::with_capacity(256);
+    assert_eq!(
+        unsafe { bytes_to_cbuffer(payload, buffer.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert!(buffer.is_spilled());
+    buffer
+}
+
+#[test]
+fn released_spill_files_are_rewritten() {
+    let _guard = reuse_spill_files();
+    let mut first = spill(&[1; 1000]);
+    let path = first.temp_file_path().unwrap();
+    assert_eq!(
+        unsafe { cobhan_buffer_release(first.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(first.length_field(), 0);
+    assert!(path.exists());
+
+    let second = spill(&[2; 500]);
+    assert_eq!(second.temp_file_path().unwrap(), path);
+    assert_eq!(fs::read(&path).unwrap(), vec![2; 500]);
+    assert_eq!(second.to_vec(), Ok(vec![2; 500]));
+
+    assert_eq!(cobhan_shutdown(0), ERR_NONE);
+    assert!(!path.exists());
+}
+
+#[test]
+fn unreleased_spills_go_to_new_files() {
+    let _guard = reuse_spill_files();
+    let mut first = spill(&[1; 1000]);
+    let second = spill(&[2; 1000]);
+    let path = first.temp_file_path().unwrap();
+    assert_ne!(second.temp_file_path().unwrap(), path);
+    assert_eq!(first.to_vec(), Ok(vec![1; 1000]));
+    assert_eq!(second.to_vec(), Ok(vec![2; 1000]));
+
+    assert_eq!(
+        unsafe { cobhan_buffer_release(first.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(spill(&[3; 1000]).temp_file_path().unwrap(), path);
+}
+
+#[test]
+fn spills_to_new_files_are_removed_on_shutdown() {
+    let _guard = reuse_spill_files();
+    let _first = spill(&[1; 1000]);
+    let second = spill(&[2; 1000]);
+    let path = second.temp_file_path().unwrap();
+    assert_eq!(cobhan_shutdown(0), ERR_NONE);
+    assert!(!path.exists());
+}
+
+#[test]
+fn removed_spill_files_are_replaced() {
+    let _guard = reuse_spill_files();
+    let mut first = spill(&[1; 1000]);
+    let path = first.temp_file_path().unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(
+        unsafe { cobhan_buffer_release(first.as_mut_ptr()) },
+        ERR_NONE
+    );
+
+    let second = spill(&[2; 1000]);
+    assert_eq!(second.to_vec(), Ok(vec![2; 1000]));
+}
+
+#[test]
+fn spill_files_being_read_are_not_rewritten() {
+    let _guard = reuse_spill_files();
+    let mut first = spill(&[1; 1000]);
+    let path = first.temp_file_path().unwrap();
+    let ptr = first.as_mut_ptr();
+    let mut read = Vec::new();
+    let mut rewritten = None;
+    let result = unsafe {
+        cbuffer_for_each_chunk(ptr, 100, |chunk| {
+            if rewritten.is_none() {
+                // The host releases the spill while it is still being streamed
+                assert_eq!(cobhan_buffer_release(ptr), ERR_NONE);
+                rewritten = Some(spill(&[2; 1000]));
+            }
+            read.extend_from_slice(chunk);
+            Ok(())
+        })
+    };
+    assert_eq!(result, Ok(()));
+    assert_eq!(read, vec![1; 1000]);
+    assert_ne!(rewritten.unwrap().temp_file_path().unwrap(), path);
+}
+
+#[test]
+fn released_references_to_other_files_are_ignored() {
+    let _guard = reuse_spill_files();
+    let dir = tempfile::tempdir().unwrap();
+    let host_file = dir.path().join("host");
+    fs::write(&host_file, b"written by the host").unwrap();
+    let mut buffer = OwnedCBuffer::spilled(host_file.to_str().unwrap());
+    assert_eq!(
+        unsafe { cobhan_buffer_release(buffer.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(buffer.length_field(), 0);
+    assert!(host_file.exists());
+
+    // A spill to a new file is removed once, and ignored after that
+    let _first = spill(&[1; 1000]);
+    let mut second = spill(&[2; 1000]);
+    let path = second.temp_file_path().unwrap();
+    let mut again = OwnedCBuffer::spilled(path.to_str().unwrap());
+    assert_eq!(
+        unsafe { cobhan_buffer_release(second.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert!(!path.exists());
+    fs::write(&path, b"written by the host").unwrap();
+    assert_eq!(
+        unsafe { cobhan_buffer_release(again.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert!(path.exists());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn released_buffers_that_did_not_spill_are_reset() {
+    let _guard = reuse_spill_files();
+    let mut buffer = OwnedCBuffer::from_bytes(b"inline");
+    assert_eq!(
+        unsafe { cobhan_buffer_release(buffer.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(buffer.to_vec(), Ok(Vec::new()));
+    assert_eq!(
+        unsafe { cobhan_buffer_release(std::ptr::null_mut()) },
+        ERR_NULL_PTR
+    );
+}
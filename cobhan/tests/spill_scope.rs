@@ -0,0 +1,89 @@
+//! Spills tracked by a call scope and discarded when the host ends the call.
+
+use cobhan::testing::{with_mock_transport, OwnedCBuffer};
+use cobhan::*;
+
+/// An exported function writing `outputs.len()` spilled outputs in a scope.
+unsafe fn call(outputs: &mut [OwnedCBuffer]) -> i64 {
+    let spills = SpillScope::begin();
+    for (i, output) in outputs.iter_mut().enumerate() {
+        assert_eq!(
+            bytes_to_cbuffer(vec![i as u8; 100], output.as_mut_ptr()),
+            ERR_NONE
+        );
+    }
+    spills.id()
+}
+
+fn outputs(count: usize) -> Vec<OwnedCBuffer> {
+    (0..count)
+        .map(|_| OwnedCBuffer::with_capacity(32))
+        .collect()
+}
+
+#[test]
+fn ending_a_call_discards_its_spills() {
+    with_mock_transport(|transport| {
+        let mut outputs = outputs(3);
+        let scope = unsafe { call(&mut outputs) };
+        let references: Vec<String> = outputs
+            .iter()
+            .map(|output| output.spill_reference().unwrap())
+            .collect();
+        // The spills outlive the guard, for the host to read
+        for (i, reference) in references.iter().enumerate() {
+            assert_eq!(transport.payload(reference), Some(vec![i as u8; 100]));
+        }
+
+        assert_eq!(cobhan_end_call(scope), ERR_NONE);
+        for reference in &references {
+            assert_eq!(transport.payload(reference), None);
+        }
+        assert_eq!(cobhan_end_call(scope), ERR_INVALID_HANDLE);
+    });
+}
+
+#[test]
+fn spills_outside_a_scope_are_not_tracked() {
+    with_mock_transport(|transport| {
+        let mut scoped = outputs(1);
+        let scope = unsafe { call(&mut scoped) };
+        let mut unscoped = OwnedCBuffer::with_capacity(32);
+        assert_eq!(
+            unsafe { bytes_to_cbuffer([9; 100], unscoped.as_mut_ptr()) },
+            ERR_NONE
+        );
+
+        assert_eq!(cobhan_end_call(scope), ERR_NONE);
+        let reference = unscoped.spill_reference().unwrap();
+        assert_eq!(transport.payload(&reference), Some(vec![9; 100]));
+    });
+}
+
+#[test]
+fn nested_scopes_track_their_own_spills() {
+    with_mock_transport(|transport| {
+        let mut outer_output = OwnedCBuffer::with_capacity(32);
+        let mut inner_outputs = outputs(1);
+        let outer = SpillScope::begin();
+        let inner = unsafe { call(&mut inner_outputs) };
+        assert_eq!(
+            unsafe { bytes_to_cbuffer([7; 100], outer_output.as_mut_ptr()) },
+            ERR_NONE
+        );
+        let outer_id = outer.id();
+        drop(outer);
+
+        assert_eq!(cobhan_end_call(inner), ERR_NONE);
+        let reference = outer_output.spill_reference().unwrap();
+        assert_eq!(transport.payload(&reference), Some(vec![7; 100]));
+        assert_eq!(cobhan_end_call(outer_id), ERR_NONE);
+        assert_eq!(transport.payload(&reference), None);
+    });
+}
+
+#[test]
+fn unknown_scopes_are_rejected() {
+    assert_eq!(cobhan_end_call(0), ERR_INVALID_HANDLE);
+    assert_eq!(cobhan_end_call(-1), ERR_INVALID_HANDLE);
+}
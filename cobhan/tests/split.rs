@@ -0,0 +1,69 @@
+//! Lines and delimited records iterated over in place.
+
+use std::borrow::Cow;
+
+use cobhan::testing::{with_mock_transport, OwnedCBuffer};
+use cobhan::*;
+
+fn segments(buffer: &OwnedCBuffer, delimiter: u8) -> Vec<Vec<u8>> {
+    unsafe { cbuffer_split(buffer.as_ptr(), delimiter) }
+        .map(|segment| segment.unwrap().into_owned())
+        .collect()
+}
+
+fn lines(buffer: &OwnedCBuffer) -> Vec<String> {
+    unsafe { cbuffer_lines(buffer.as_ptr()) }
+        .map(|line| line.unwrap().into_owned())
+        .collect()
+}
+
+#[test]
+fn segments_split_like_slices() {
+    for payload in [&b"a,bc,,d"[..], b"a,", b",", b""] {
+        let buffer = OwnedCBuffer::from_bytes(payload);
+        let expected: Vec<Vec<u8>> = payload.split(|b| *b == b',').map(<[u8]>::to_vec).collect();
+        assert_eq!(segments(&buffer, b','), expected, "{:?}", payload);
+    }
+
+    let buffer = OwnedCBuffer::from_bytes(b"key\0value");
+    assert!(unsafe { cbuffer_split(buffer.as_ptr(), 0) }
+        .all(|segment| matches!(segment, Ok(Cow::Borrowed(_)))));
+}
+
+#[test]
+fn lines_split_like_str_lines() {
+    for payload in ["one\ntwo\r\n\nthree", "one\n", "\n", "", "one\r\ntwo\r\n"] {
+        let buffer = OwnedCBuffer::from_bytes(payload.as_bytes());
+        let expected: Vec<String> = payload.lines().map(str::to_owned).collect();
+        assert_eq!(lines(&buffer), expected, "{:?}", payload);
+    }
+}
+
+#[test]
+fn spilled_payloads_are_streamed() {
+    with_mock_transport(|mock| {
+        let payload = (0..1000)
+            .map(|i| format!("line {}\n", i))
+            .collect::<String>();
+        let input = OwnedCBuffer::spilled(&mock.insert(payload.as_bytes()));
+        let read = lines(&input);
+        assert_eq!(read.len(), 1000);
+        assert_eq!(read[999], "line 999");
+
+        let input = OwnedCBuffer::spilled(&mock.insert(b"a|b|"));
+        assert_eq!(segments(&input, b'|'), [&b"a"[..], b"b", b""]);
+    });
+}
+
+#[test]
+fn errors_end_the_iteration() {
+    let mut iter = unsafe { cbuffer_lines(std::ptr::null()) };
+    assert_eq!(iter.next(), Some(Err(ERR_NULL_PTR)));
+    assert_eq!(iter.next(), None);
+
+    let buffer = OwnedCBuffer::from_bytes(b"ok\n\xff\nafter");
+    let mut iter = unsafe { cbuffer_lines(buffer.as_ptr()) };
+    assert_eq!(iter.next(), Some(Ok(Cow::Borrowed("ok"))));
+    assert_eq!(iter.next(), Some(Err(ERR_INVALID_UTF8)));
+    assert_eq!(iter.next(), None);
+}
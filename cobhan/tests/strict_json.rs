@@ -0,0 +1,50 @@
+//! Strict JSON decoding rejects repeated keys, which the lenient decode resolves silently.
+
+use cobhan::testing::OwnedCBuffer;
+use cobhan::*;
+use serde_json::json;
+
+unsafe fn strict(json: &str) -> Result<std::collections::HashMap<String, serde_json::Value>, i32> {
+    let input = OwnedCBuffer::from_bytes(json.as_bytes());
+    cbuffer_to_hashmap_json_strict(input.as_ptr())
+}
+
+#[test]
+fn unique_keys_decode_like_the_lenient_decode() {
+    let json = r#"{"a": 1, "b": [{"a": 2.5}, {"a": null}], "c": {"d": "e", "f": true}}"#;
+    let input = OwnedCBuffer::from_bytes(json.as_bytes());
+    let decoded = unsafe { strict(json) }.unwrap();
+    assert_eq!(decoded, input.to_hashmap_json().unwrap());
+    assert_eq!(decoded["b"], json!([{"a": 2.5}, {"a": null}]));
+}
+
+#[test]
+fn repeated_keys_are_rejected_at_any_depth() {
+    for json in [
+        r#"{"role": "user", "role": "admin"}"#,
+        r#"{"a": {"b": 1, "b": 1}}"#,
+        r#"{"a": [{"x": 1}, {"y": 1, "y": 2}]}"#,
+        r#"{"a": 1, "a": 2}"#,
+    ] {
+        assert_eq!(
+            unsafe { strict(json) },
+            Err(ERR_JSON_DUPLICATE_KEY),
+            "{}",
+            json
+        );
+    }
+
+    // The lenient decode keeps the last value
+    let input = OwnedCBuffer::from_bytes(br#"{"role": "user", "role": "admin"}"#);
+    assert_eq!(input.to_hashmap_json().unwrap()["role"], "admin");
+}
+
+#[test]
+fn malformed_payloads_fail_to_decode() {
+    assert_eq!(unsafe { strict("[1, 2]") }, Err(ERR_JSON_DECODE_FAILED));
+    assert_eq!(unsafe { strict(r#"{"a": "#) }, Err(ERR_JSON_DECODE_FAILED));
+    assert_eq!(
+        unsafe { strict(r#"{"a": 1} 2"#) },
+        Err(ERR_JSON_DECODE_FAILED)
+    );
+}
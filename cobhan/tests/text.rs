@@ -0,0 +1,70 @@
+//! Strings trimmed or lowercased while they are decoded.
+
+use std::borrow::Cow;
+
+use cobhan::testing::{with_mock_transport, OwnedCBuffer};
+use cobhan::*;
+
+fn trimmed(payload: &[u8]) -> Result<String, i32> {
+    let buffer = OwnedCBuffer::from_bytes(payload);
+    unsafe { cbuffer_to_string_trimmed(buffer.as_ptr()) }.map(Cow::into_owned)
+}
+
+fn lowercase(payload: &[u8]) -> Result<String, i32> {
+    let buffer = OwnedCBuffer::from_bytes(payload);
+    unsafe { cbuffer_to_string_lowercase(buffer.as_ptr()) }.map(Cow::into_owned)
+}
+
+#[test]
+fn strings_are_trimmed() {
+    assert_eq!(trimmed(b"  tenant-1\r\n").unwrap(), "tenant-1");
+    assert_eq!(trimmed(b"\t a b \t").unwrap(), "a b");
+    assert_eq!(trimmed(b" \n ").unwrap(), "");
+    assert_eq!(trimmed(b"").unwrap(), "");
+
+    let buffer = OwnedCBuffer::from_bytes(b" id ");
+    assert!(matches!(
+        unsafe { cbuffer_to_string_trimmed(buffer.as_ptr()) },
+        Ok(Cow::Borrowed("id"))
+    ));
+}
+
+#[test]
+fn strings_are_lowercased() {
+    assert_eq!(lowercase(b"Tenant-ID").unwrap(), "tenant-id");
+    assert_eq!(lowercase("ÅSA ΟΔΟΣ".as_bytes()).unwrap(), "åsa οδος");
+
+    for already in ["tenant-id", "", "123 ß"] {
+        let buffer = OwnedCBuffer::from_bytes(already.as_bytes());
+        assert_eq!(
+            unsafe { cbuffer_to_string_lowercase(buffer.as_ptr()) },
+            Ok(Cow::Borrowed(already))
+        );
+    }
+}
+
+#[test]
+fn spilled_strings_are_converted() {
+    with_mock_transport(|mock| {
+        let payload = format!("  {}  ", "ID".repeat(50));
+        let input = OwnedCBuffer::spilled(&mock.insert(payload.as_bytes()));
+        assert_eq!(
+            unsafe { cbuffer_to_string_trimmed(input.as_ptr()) }.unwrap(),
+            "ID".repeat(50)
+        );
+        assert_eq!(
+            unsafe { cbuffer_to_string_lowercase(input.as_ptr()) }.unwrap(),
+            format!("  {}  ", "id".repeat(50))
+        );
+    });
+}
+
+#[test]
+fn invalid_strings_are_rejected() {
+    assert_eq!(trimmed(b" \xff "), Err(ERR_INVALID_UTF8));
+    assert_eq!(lowercase(b"\xff"), Err(ERR_INVALID_UTF8));
+    assert_eq!(
+        unsafe { cbuffer_to_string_lowercase(std::ptr::null()) },
+        Err(ERR_NULL_PTR)
+    );
+}
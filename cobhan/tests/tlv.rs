@@ -0,0 +1,46 @@
+//! Decoding of TLV payloads, which come from the host and cannot be trusted.
+
+use cobhan::tlv::*;
+use cobhan::ERR_MALFORMED_PAYLOAD;
+
+// A payload of `depth` records, each nesting the next, around an i64.
+fn nested(depth: usize) -> Vec<u8> {
+    let mut writer = TlvWriter::new();
+    writer.write_i64(7).unwrap();
+    for _ in 0..depth {
+        let mut outer = TlvWriter::new();
+        outer.write_nested(&writer).unwrap();
+        writer = outer;
+    }
+    writer.into_bytes()
+}
+
+#[test]
+fn nested_payloads_decode_up_to_the_maximum_depth() {
+    let mut value = decode_all(&nested(MAX_NESTING_DEPTH)).unwrap();
+    for _ in 0..MAX_NESTING_DEPTH {
+        value = match value.pop() {
+            Some(TlvValue::Nested(inner)) => inner,
+            other => panic!("expected a nested value, got {:?}", other),
+        };
+    }
+    assert_eq!(value, vec![TlvValue::I64(7)]);
+}
+
+#[test]
+fn deeper_payloads_are_malformed() {
+    assert_eq!(
+        decode_all(&nested(MAX_NESTING_DEPTH + 1)),
+        Err(ERR_MALFORMED_PAYLOAD)
+    );
+
+    // Built by hand, since each level only costs a 5 byte record header
+    let depth = 1_000_000;
+    let mut bytes = Vec::with_capacity(depth * 5);
+    for level in 0..depth {
+        let remaining = ((depth - level - 1) * 5) as u32;
+        bytes.push(TAG_NESTED);
+        bytes.extend_from_slice(&remaining.to_le_bytes());
+    }
+    assert_eq!(decode_all(&bytes), Err(ERR_MALFORMED_PAYLOAD));
+}
@@ -0,0 +1,95 @@
+use cobhan::testing::{with_mock_transport, OwnedCBuffer};
+use cobhan::*;
+
+fn xor(bytes: &mut [u8]) -> Result<usize, i32> {
+    bytes.iter_mut().for_each(|byte| *byte ^= 0x5A);
+    Ok(bytes.len())
+}
+
+#[test]
+fn transform_changes_inline_payloads_in_place() {
+    let mut buffer = OwnedCBuffer::from_bytes(b"secret");
+    let payload = unsafe { buffer.as_ptr().offset(BUFFER_HEADER_SIZE) } as usize;
+    let result = unsafe {
+        cbuffer_transform_in_place(buffer.as_mut_ptr(), |bytes| {
+            assert_eq!(bytes.as_ptr() as usize, payload);
+            xor(bytes)
+        })
+    };
+    assert_eq!(result, ERR_NONE);
+    assert_ne!(buffer.to_vec().unwrap(), b"secret");
+    assert_eq!(
+        unsafe { cbuffer_transform_in_place(buffer.as_mut_ptr(), xor) },
+        ERR_NONE
+    );
+    assert_eq!(buffer.to_vec().unwrap(), b"secret");
+}
+
+#[test]
+fn transform_can_shorten_the_payload() {
+    let mut buffer = OwnedCBuffer::from_bytes(b"  padded  ");
+    let result = unsafe {
+        cbuffer_transform_in_place(buffer.as_mut_ptr(), |bytes| {
+            let start = bytes
+                .iter()
+                .position(|byte| *byte != b' ')
+                .unwrap_or(bytes.len());
+            let end = bytes
+                .iter()
+                .rposition(|byte| *byte != b' ')
+                .map_or(start, |end| end + 1);
+            bytes.copy_within(start..end, 0);
+            Ok(end - start)
+        })
+    };
+    assert_eq!(result, ERR_NONE);
+    assert_eq!(buffer.to_string().unwrap(), "padded");
+}
+
+#[test]
+fn transform_failures_keep_the_length() {
+    let mut buffer = OwnedCBuffer::from_bytes(b"four");
+    let result = unsafe { cbuffer_transform_in_place(buffer.as_mut_ptr(), |_| Ok(5)) };
+    assert_eq!(result, ERR_INVALID_LENGTH);
+    assert_eq!(buffer.length_field(), 4);
+
+    let result =
+        unsafe { cbuffer_transform_in_place(buffer.as_mut_ptr(), |_| Err(ERR_MALFORMED_PAYLOAD)) };
+    assert_eq!(result, ERR_MALFORMED_PAYLOAD);
+    assert_eq!(buffer.to_vec().unwrap(), b"four");
+
+    let result = unsafe { cbuffer_transform_in_place(std::ptr::null_mut(), xor) };
+    assert_eq!(result, ERR_NULL_PTR);
+}
+
+#[test]
+fn transform_rewrites_spilled_payloads() {
+    with_mock_transport(|transport| {
+        let mut buffer = OwnedCBuffer::with_capacity(16);
+        let payload = vec![b'a'; 100];
+        assert_eq!(
+            unsafe { bytes_to_cbuffer(&payload, buffer.as_mut_ptr()) },
+            ERR_NONE
+        );
+        let reference = buffer.spill_reference().unwrap();
+
+        // Still too long to fit, so spilled again
+        let result = unsafe {
+            cbuffer_transform_in_place(buffer.as_mut_ptr(), |bytes| {
+                bytes.make_ascii_uppercase();
+                Ok(50)
+            })
+        };
+        assert_eq!(result, ERR_NONE);
+        assert!(transport.payload(&reference).is_none());
+        assert_eq!(buffer.to_vec().unwrap(), vec![b'A'; 50]);
+
+        // Short enough to fit where the reference was
+        let reference = buffer.spill_reference().unwrap();
+        let result = unsafe { cbuffer_transform_in_place(buffer.as_mut_ptr(), |_| Ok(2)) };
+        assert_eq!(result, ERR_NONE);
+        assert!(!buffer.is_spilled());
+        assert!(transport.payload(&reference).is_none());
+        assert_eq!(buffer.to_vec().unwrap(), b"AA");
+    });
+}
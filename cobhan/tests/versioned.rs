@@ -0,0 +1,65 @@
+//! Payloads prefixed with a format version byte.
+
+use std::borrow::Cow;
+
+use cobhan::testing::{with_mock_transport, OwnedCBuffer};
+use cobhan::*;
+
+#[test]
+fn versions_round_trip_with_their_body() {
+    let mut output = OwnedCBuffer::with_capacity(16);
+    assert_eq!(
+        unsafe { write_versioned(2, b"body", output.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(output.to_vec().unwrap(), b"\x02body");
+    let (version, body) = unsafe { read_versioned(output.as_ptr()) }.unwrap();
+    assert_eq!(version, 2);
+    assert!(matches!(body, Cow::Borrowed(b"body")));
+
+    assert_eq!(
+        unsafe { write_versioned(0, b"", output.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(
+        unsafe { read_versioned(output.as_ptr()) }.unwrap(),
+        (0, Cow::Borrowed(&b""[..]))
+    );
+}
+
+#[test]
+fn spilled_bodies_are_read_back() {
+    with_mock_transport(|_| {
+        let body = vec![b'v'; 100];
+        let mut output = OwnedCBuffer::with_capacity(32);
+        assert_eq!(
+            unsafe { write_versioned(255, &body, output.as_mut_ptr()) },
+            ERR_NONE
+        );
+        assert!(output.is_spilled());
+        let (version, read) = unsafe { read_versioned(output.as_ptr()) }.unwrap();
+        assert_eq!(version, 255);
+        assert_eq!(read, body);
+    });
+}
+
+#[test]
+fn payloads_without_a_version_are_rejected() {
+    let empty = OwnedCBuffer::from_bytes(b"");
+    assert_eq!(
+        unsafe { read_versioned(empty.as_ptr()) },
+        Err(ERR_MALFORMED_PAYLOAD)
+    );
+    assert_eq!(
+        unsafe { read_versioned(std::ptr::null()) },
+        Err(ERR_NULL_PTR)
+    );
+}
+
+#[test]
+fn readers_check_supported_versions() {
+    assert_eq!(require_version(1, 1..=3), Ok(1));
+    assert_eq!(require_version(3, 1..=3), Ok(3));
+    assert_eq!(require_version(0, 1..=3), Err(ERR_UNSUPPORTED_VERSION));
+    assert_eq!(require_version(4, 1..=3), Err(ERR_UNSUPPORTED_VERSION));
+}
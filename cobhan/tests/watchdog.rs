@@ -0,0 +1,132 @@
+//! The slow-call watchdog is process-wide, so the tests take turns.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use cobhan::stats::*;
+use cobhan::testing::{configured, with_mock_transport, OwnedCBuffer};
+use cobhan::*;
+
+fn serialized() -> MutexGuard<'static, ()> {
+    let guard = configured(CobhanConfig::default());
+    set_slow_call_threshold(None);
+    set_slow_call_callback(None);
+    reset_slow_calls();
+    guard
+}
+
+#[test]
+fn nothing_is_timed_without_a_threshold() {
+    let _guard = serialized();
+    assert_eq!(slow_call_threshold(), None);
+    let input = OwnedCBuffer::from_bytes(b"payload");
+    unsafe { cbuffer_to_vector(input.as_ptr()) }.unwrap();
+    assert!(slow_calls().is_empty());
+}
+
+#[test]
+fn calls_under_the_threshold_are_not_recorded() {
+    let _guard = serialized();
+    set_slow_call_threshold(Some(Duration::from_secs(3600)));
+    assert_eq!(slow_call_threshold(), Some(Duration::from_secs(3600)));
+    let input = OwnedCBuffer::from_bytes(b"payload");
+    unsafe { cbuffer_to_string(input.as_ptr()) }.unwrap();
+    assert!(slow_calls().is_empty());
+}
+
+#[test]
+fn records_inline_conversions() {
+    let _guard = serialized();
+    set_slow_call_threshold(Some(Duration::ZERO));
+    let input = OwnedCBuffer::from_bytes(b"payload");
+    let mut output = OwnedCBuffer::with_capacity(16);
+    unsafe {
+        cbuffer_to_vector(input.as_ptr()).unwrap();
+        assert_eq!(bytes_to_cbuffer(b"written", output.as_mut_ptr()), ERR_NONE);
+    }
+
+    let calls = slow_calls();
+    let operations: Vec<_> = calls.iter().map(|call| call.operation).collect();
+    assert_eq!(operations, ["cbuffer_to_vector", "bytes_to_cbuffer"]);
+    assert!(calls.iter().all(|call| call.bytes == 7 && !call.spilled));
+}
+
+#[test]
+fn spilled_conversions_are_marked() {
+    let _guard = serialized();
+    set_slow_call_threshold(Some(Duration::ZERO));
+    with_mock_transport(|transport| unsafe {
+        let mut output = OwnedCBuffer::with_capacity(32);
+        let payload = vec![b'x'; 100];
+        assert_eq!(bytes_to_cbuffer(&payload, output.as_mut_ptr()), ERR_NONE);
+        assert_eq!(transport.spills().len(), 1);
+        assert_eq!(cbuffer_to_vector(output.as_ptr()).unwrap(), payload);
+    });
+
+    let calls = slow_calls();
+    assert_eq!(calls.len(), 2);
+    assert!(calls.iter().all(|call| call.bytes == 100 && call.spilled));
+}
+
+#[test]
+fn failed_conversions_are_not_recorded() {
+    let _guard = serialized();
+    set_slow_call_threshold(Some(Duration::ZERO));
+    let input = OwnedCBuffer::from_bytes(&[0xff, 0xfe]);
+    assert_eq!(
+        unsafe { cbuffer_to_string(input.as_ptr()) },
+        Err(ERR_INVALID_UTF8)
+    );
+    assert!(slow_calls().is_empty());
+}
+
+#[test]
+fn guarded_calls_tell_whether_they_spilled() {
+    let _guard = serialized();
+    set_slow_call_threshold(Some(Duration::ZERO));
+    with_mock_transport(|_| {
+        let mut output = OwnedCBuffer::with_capacity(32);
+        let result = ffi_guard(|| unsafe { bytes_to_cbuffer([0; 100], output.as_mut_ptr()) });
+        assert_eq!(result, ERR_NONE);
+        assert_eq!(ffi_guard(|| ERR_NONE), ERR_NONE);
+    });
+
+    let guarded: Vec<_> = slow_calls()
+        .into_iter()
+        .filter(|call| call.operation == "ffi_guard")
+        .map(|call| (call.bytes, call.spilled))
+        .collect();
+    assert_eq!(guarded, [(0, true), (0, false)]);
+}
+
+#[test]
+fn the_callback_sees_each_slow_call() {
+    let _guard = serialized();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&seen);
+    set_slow_call_callback(Some(Arc::new(move |call: &SlowCall| {
+        sink.lock().unwrap().push(call.clone());
+    })));
+    set_slow_call_threshold(Some(Duration::ZERO));
+    let input = OwnedCBuffer::from_bytes(b"payload");
+    unsafe { cbuffer_to_string(input.as_ptr()) }.unwrap();
+    set_slow_call_callback(None);
+
+    assert_eq!(*seen.lock().unwrap(), slow_calls());
+    assert_eq!(slow_calls()[0].operation, "cbuffer_to_string");
+}
+
+#[test]
+fn only_the_latest_calls_are_kept() {
+    let _guard = serialized();
+    set_slow_call_threshold(Some(Duration::ZERO));
+    for i in 0..SLOW_CALLS_KEPT + 10 {
+        let input = OwnedCBuffer::from_bytes(&vec![0; i]);
+        unsafe { cbuffer_to_vector(input.as_ptr()) }.unwrap();
+    }
+
+    let calls = slow_calls();
+    assert_eq!(calls.len(), SLOW_CALLS_KEPT);
+    assert_eq!(calls[0].bytes, 10);
+    assert_eq!(calls[SLOW_CALLS_KEPT - 1].bytes, SLOW_CALLS_KEPT + 9);
+}
@@ -0,0 +1,135 @@
+//! Writers take anything that derefs to the payload, so callers holding a `String`, `Vec<u8>`
+//! or `Cow` pass it as is, and payloads produced in chunks are written as they come.
+
+use std::borrow::Cow;
+
+use cobhan::testing::OwnedCBuffer;
+use cobhan::*;
+
+#[test]
+fn owned_and_borrowed_strings_are_written() {
+    let mut buffer = OwnedCBuffer::with_capacity(16);
+    let owned = String::from("owned");
+    assert_eq!(
+        unsafe { string_to_cbuffer(&owned, buffer.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(buffer.to_string(), Ok("owned".to_string()));
+
+    buffer.reset();
+    assert_eq!(
+        unsafe { string_to_cbuffer(owned, buffer.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(buffer.to_string(), Ok("owned".to_string()));
+
+    for cow in [Cow::Borrowed("borrowed"), Cow::Owned("cow".to_string())] {
+        let expected = cow.to_string();
+        buffer.reset();
+        assert_eq!(
+            unsafe { string_to_cbuffer(cow, buffer.as_mut_ptr()) },
+            ERR_NONE
+        );
+        assert_eq!(buffer.to_string(), Ok(expected));
+    }
+
+    buffer.reset();
+    assert_eq!(
+        unsafe { string_to_cbuffer_or_size(String::from("sized"), buffer.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(buffer.to_string(), Ok("sized".to_string()));
+}
+
+#[test]
+fn owned_and_borrowed_bytes_are_written() {
+    let mut buffer = OwnedCBuffer::with_capacity(16);
+    assert_eq!(
+        unsafe { bytes_to_cbuffer(vec![1u8, 2, 3], buffer.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(buffer.to_vec(), Ok(vec![1, 2, 3]));
+
+    buffer.reset();
+    assert_eq!(
+        unsafe { bytes_to_cbuffer(b"array", buffer.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(buffer.to_vec(), Ok(b"array".to_vec()));
+
+    buffer.reset();
+    let cow: Cow<[u8]> = Cow::Owned(b"cow".to_vec());
+    assert_eq!(
+        unsafe { bytes_to_cbuffer(&cow, buffer.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(buffer.to_vec(), Ok(b"cow".to_vec()));
+
+    buffer.reset();
+    let outcome = unsafe { bytes_to_cbuffer_truncating(vec![7u8; 20], buffer.as_mut_ptr()) };
+    assert_eq!(
+        outcome,
+        Ok(WriteOutcome {
+            written: 16,
+            total: 20
+        })
+    );
+}
+
+#[test]
+fn chunks_that_fit_are_written_in_place() {
+    let mut buffer = OwnedCBuffer::with_capacity(17);
+    let chunks = ["compressed", " ", "chunks"].iter();
+    assert_eq!(
+        unsafe { chunks_to_cbuffer(chunks, buffer.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(buffer.to_string(), Ok("compressed chunks".to_string()));
+    assert!(!buffer.is_spilled());
+
+    buffer.reset();
+    let chunks = (0u8..4).map(|i| vec![i; 4]).chain([vec![4]]);
+    assert_eq!(
+        unsafe { chunks_to_cbuffer(chunks, buffer.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(buffer.to_vec().unwrap().len(), 17);
+    assert!(!buffer.is_spilled());
+
+    buffer.reset();
+    let empty: [&[u8]; 0] = [];
+    assert_eq!(
+        unsafe { chunks_to_cbuffer(empty, buffer.as_mut_ptr()) },
+        ERR_NONE
+    );
+    assert_eq!(buffer.to_vec(), Ok(Vec::new()));
+}
+
+#[test]
+fn chunks_that_outgrow_the_buffer_spill() {
+    testing::with_mock_transport(|transport| {
+        let mut buffer = OwnedCBuffer::with_capacity(64);
+        let chunks: Vec<Vec<u8>> = (0u8..10).map(|i| vec![i; 10]).collect();
+        assert_eq!(
+            unsafe { chunks_to_cbuffer(&chunks, buffer.as_mut_ptr()) },
+            ERR_NONE
+        );
+        assert!(buffer.is_spilled());
+        assert_eq!(transport.spilled_len(), 100);
+        assert_eq!(buffer.to_vec(), Ok(chunks.concat()));
+    });
+}
+
+#[test]
+fn chunk_writes_check_the_buffer() {
+    let chunks = [b"x"];
+    assert_eq!(
+        unsafe { chunks_to_cbuffer(chunks, std::ptr::null_mut()) },
+        ERR_NULL_PTR
+    );
+    let mut buffer = OwnedCBuffer::with_capacity(0);
+    assert_eq!(
+        unsafe { chunks_to_cbuffer(chunks, buffer.as_mut_ptr()) },
+        ERR_BUFFER_TOO_SMALL
+    );
+}
@@ -0,0 +1,172 @@
+//! # cobhan-host-simulator
+//!
+//! Drives a cobhan shared library the way a foreign host does: the library is loaded with
+//! `dlopen` (via libloading) and every buffer is a heap-allocated byte array whose header is
+//! packed by hand, without any help from the `cobhan` crate.
+
+use std::fs;
+use std::mem::ManuallyDrop;
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use std::str;
+
+use libloading::{Library, Symbol};
+
+/// Size of the little-endian length + reserved header in front of every payload
+pub const HEADER_SIZE: usize = 8;
+
+/// A host-owned cobhan buffer.
+///
+/// Backed by `u64` words so the header is 8 byte aligned, as host allocators guarantee.
+pub struct HostBuffer {
+    words: Vec<u64>,
+    capacity: usize,
+}
+
+impl HostBuffer {
+    /// Allocates an output buffer with room for `capacity` payload bytes.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut buffer = HostBuffer {
+            words: vec![0u64; (HEADER_SIZE + capacity).div_ceil(8)],
+            capacity,
+        };
+        buffer.set_length_field(capacity as i32);
+        buffer
+    }
+
+    /// Allocates an input buffer holding `payload`.
+    pub fn from_bytes(payload: &[u8]) -> Self {
+        let mut buffer = HostBuffer::with_capacity(payload.len());
+        buffer.bytes_mut()[HEADER_SIZE..HEADER_SIZE + payload.len()].copy_from_slice(payload);
+        buffer
+    }
+
+    pub fn from_text(payload: &str) -> Self {
+        HostBuffer::from_bytes(payload.as_bytes())
+    }
+
+    pub fn from_json(value: &serde_json::Value) -> Self {
+        HostBuffer::from_bytes(&serde_json::to_vec(value).unwrap())
+    }
+
+    fn bytes(&self) -> &[u8] {
+        let len = HEADER_SIZE + self.capacity;
+        unsafe { std::slice::from_raw_parts(self.words.as_ptr() as *const u8, len) }
+    }
+
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        let len = HEADER_SIZE + self.capacity;
+        unsafe { std::slice::from_raw_parts_mut(self.words.as_mut_ptr() as *mut u8, len) }
+    }
+
+    fn set_length_field(&mut self, length: i32) {
+        self.bytes_mut()[..4].copy_from_slice(&length.to_le_bytes());
+    }
+
+    /// The raw i32 length field: payload length, or negated temp file path length.
+    pub fn length_field(&self) -> i32 {
+        let b = self.bytes();
+        i32::from_le_bytes([b[0], b[1], b[2], b[3]])
+    }
+
+    /// The raw i32 reserved field.
+    pub fn reserved_field(&self) -> i32 {
+        let b = self.bytes();
+        i32::from_le_bytes([b[4], b[5], b[6], b[7]])
+    }
+
+    pub fn as_ptr(&self) -> *const c_char {
+        self.words.as_ptr() as *const c_char
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut c_char {
+        self.words.as_mut_ptr() as *mut c_char
+    }
+
+    /// Path of the temp file holding the payload, if the library spilled it.
+    pub fn temp_file_path(&self) -> Option<PathBuf> {
+        let length = self.length_field();
+        if length >= 0 {
+            return None;
+        }
+        let path_len = length.unsigned_abs() as usize;
+        let path = str::from_utf8(&self.bytes()[HEADER_SIZE..HEADER_SIZE + path_len])
+            .expect("temp file path is not utf-8");
+        Some(PathBuf::from(path))
+    }
+
+    /// Reads the payload, following and then deleting the temp file as a host would.
+    pub fn payload(&self) -> Vec<u8> {
+        match self.temp_file_path() {
+            Some(path) => {
+                let bytes = fs::read(&path).expect("failed to read temp file");
+                let _ = fs::remove_file(&path);
+                bytes
+            }
+            None => {
+                let length = self.length_field() as usize;
+                assert!(length <= self.capacity, "length field exceeds capacity");
+                self.bytes()[HEADER_SIZE..HEADER_SIZE + length].to_vec()
+            }
+        }
+    }
+
+    pub fn payload_string(&self) -> String {
+        String::from_utf8(self.payload()).expect("payload is not utf-8")
+    }
+
+    pub fn payload_json(&self) -> serde_json::Value {
+        serde_json::from_slice(&self.payload()).expect("payload is not JSON")
+    }
+}
+
+/// A loaded shared library.
+///
+/// The library is not unloaded when dropped, matching real hosts. Hosts that reload libraries
+/// call `cobhan_deinit` and then [`unload`](HostLibrary::unload); the dynamic loader keeps the
+/// library mapped while threads still have thread-local destructors registered inside it.
+pub struct HostLibrary {
+    library: ManuallyDrop<Library>,
+}
+
+impl HostLibrary {
+    /// Loads the shared library at `path`.
+    pub fn load(path: &Path) -> Result<Self, libloading::Error> {
+        let library = unsafe { Library::new(path)? };
+        Ok(HostLibrary {
+            library: ManuallyDrop::new(library),
+        })
+    }
+
+    /// Looks up an exported function.
+    ///
+    /// ## Safety
+    ///
+    /// `T` must match the exported function's actual signature.
+    pub unsafe fn function<T>(&self, name: &str) -> Symbol<'_, T> {
+        self.library
+            .get(name.as_bytes())
+            .unwrap_or_else(|e| panic!("missing export {}: {}", name, e))
+    }
+
+    /// Unloads the library with `dlclose`.
+    ///
+    /// ## Safety
+    ///
+    /// No function looked up from the library may be called afterwards.
+    pub unsafe fn unload(self) -> Result<(), libloading::Error> {
+        ManuallyDrop::into_inner(self.library).close()
+    }
+}
+
+/// Locates the libcobhandemo cdylib that cargo builds alongside the current test binary.
+pub fn demo_library_path() -> PathBuf {
+    let exe = std::env::current_exe().expect("no current executable");
+    let deps = exe.parent().expect("test binary has no parent directory");
+    let file_name = libloading::library_filename("cobhandemo");
+    [deps, deps.parent().unwrap_or(deps)]
+        .iter()
+        .map(|dir| dir.join(&file_name))
+        .find(|candidate| candidate.exists())
+        .unwrap_or_else(|| panic!("{:?} not found near {:?}", file_name, deps))
+}
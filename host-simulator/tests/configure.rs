@@ -0,0 +1,34 @@
+//! `cobhan_configure` changes process-wide state, so it runs in its own test binary.
+
+use std::os::raw::c_char;
+
+use cobhan_host_simulator::{demo_library_path, HostBuffer, HostLibrary};
+use serde_json::json;
+
+type Configure = unsafe extern "C" fn(*const c_char) -> i32;
+type Unary = unsafe extern "C" fn(*const c_char, *mut c_char) -> i32;
+
+#[test]
+fn configure_limits_buffer_length() {
+    let lib = HostLibrary::load(&demo_library_path()).expect("failed to load libcobhandemo");
+    unsafe {
+        let configure = lib.function::<Configure>("cobhan_configure");
+        let to_upper = lib.function::<Unary>("toUpper");
+
+        let settings = HostBuffer::from_json(&json!({ "max_buffer_len": 4 }));
+        assert_eq!(configure(settings.as_ptr()), 0);
+
+        let input = HostBuffer::from_text("Initial value");
+        let mut output = HostBuffer::with_capacity(64);
+        assert_eq!(to_upper(input.as_ptr(), output.as_mut_ptr()), -2);
+
+        let settings = HostBuffer::from_json(&json!({ "max_buffer_len": null }));
+        assert_eq!(configure(settings.as_ptr()), 0);
+        let mut output = HostBuffer::with_capacity(64);
+        assert_eq!(to_upper(input.as_ptr(), output.as_mut_ptr()), 0);
+        assert_eq!(output.payload_string(), "INITIAL VALUE");
+
+        let settings = HostBuffer::from_json(&json!({ "spill_policy": "never" }));
+        assert_eq!(configure(settings.as_ptr()), -23);
+    }
+}
@@ -0,0 +1,454 @@
+use std::os::raw::{c_char, c_void};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use cobhan_host_simulator::{demo_library_path, HostBuffer, HostLibrary};
+use serde_json::json;
+
+type Unary = unsafe extern "C" fn(*const c_char, *mut c_char) -> i32;
+type Binary = unsafe extern "C" fn(*const c_char, *const c_char, *mut c_char) -> i32;
+type Ternary =
+    unsafe extern "C" fn(*const c_char, *const c_char, *const c_char, *mut c_char) -> i32;
+type Output = unsafe extern "C" fn(*mut c_char) -> i32;
+type Handle = unsafe extern "C" fn() -> i64;
+
+fn load() -> HostLibrary {
+    HostLibrary::load(&demo_library_path()).expect("failed to load libcobhandemo")
+}
+
+#[test]
+fn scalars() {
+    let lib = load();
+    unsafe {
+        let add_int32 = lib.function::<unsafe extern "C" fn(i32, i32) -> i32>("addInt32");
+        assert_eq!(add_int32(1, 1), 2);
+        assert_eq!(add_int32(i32::MAX, 1), i32::MAX);
+
+        let add_int64 = lib.function::<unsafe extern "C" fn(i64, i64) -> i64>("addInt64");
+        assert_eq!(add_int64(i64::MIN, -1), i64::MIN);
+
+        let add_double = lib.function::<unsafe extern "C" fn(f64, f64) -> f64>("addDouble");
+        assert_eq!(add_double(2.5, 0.25), 2.75);
+
+        let sleep_test = lib.function::<unsafe extern "C" fn(i32)>("sleepTest");
+        sleep_test(0);
+    }
+}
+
+#[test]
+fn counter_thread() {
+    let lib = load();
+    unsafe {
+        let read_counter = lib.function::<unsafe extern "C" fn() -> i32>("readCounter");
+        let spawn_thread = lib.function::<unsafe extern "C" fn()>("spawnThread");
+        let before = read_counter();
+        spawn_thread();
+        thread::sleep(Duration::from_millis(100));
+        assert!(read_counter() > before);
+    }
+}
+
+#[test]
+fn to_upper() {
+    let lib = load();
+    unsafe {
+        let to_upper = lib.function::<Unary>("toUpper");
+        let input = HostBuffer::from_text("Initial value");
+        let mut output = HostBuffer::with_capacity(13);
+        assert_eq!(to_upper(input.as_ptr(), output.as_mut_ptr()), 0);
+        assert_eq!(output.payload_string(), "INITIAL VALUE");
+
+        // Zero capacity output buffers are rejected rather than spilled
+        let mut empty = HostBuffer::with_capacity(0);
+        assert_eq!(to_upper(input.as_ptr(), empty.as_mut_ptr()), -3);
+
+        assert_eq!(to_upper(std::ptr::null(), output.as_mut_ptr()), -1);
+    }
+}
+
+#[test]
+fn filter_json() {
+    let lib = load();
+    unsafe {
+        let filter_json = lib.function::<Binary>("filterJson");
+        let input = HostBuffer::from_json(&json!({"test": "foo", "test2": "kittens"}));
+        let disallowed = HostBuffer::from_text("foo");
+        let mut output = HostBuffer::with_capacity(64);
+        assert_eq!(
+            filter_json(input.as_ptr(), disallowed.as_ptr(), output.as_mut_ptr()),
+            0
+        );
+        assert_eq!(output.payload_json(), json!({"test2": "kittens"}));
+
+        let invalid = HostBuffer::from_text("{not json");
+        assert_eq!(
+            filter_json(invalid.as_ptr(), disallowed.as_ptr(), output.as_mut_ptr()),
+            -5
+        );
+
+        let filter_json_fields = lib.function::<Binary>("filterJsonFields");
+        let input = HostBuffer::from_json(&json!({"id": 7, "name": "kittens", "secret": "x"}));
+        let allowed = HostBuffer::from_json(&json!(["id", "name"]));
+        let mut output = HostBuffer::with_capacity(64);
+        assert_eq!(
+            filter_json_fields(input.as_ptr(), allowed.as_ptr(), output.as_mut_ptr()),
+            0
+        );
+        assert_eq!(output.payload_json(), json!({"id": 7, "name": "kittens"}));
+    }
+}
+
+#[test]
+fn base64_encode() {
+    let lib = load();
+    unsafe {
+        let base64_encode = lib.function::<Unary>("base64Encode");
+        let input = HostBuffer::from_text("Test");
+        let mut output = HostBuffer::with_capacity(8);
+        assert_eq!(base64_encode(input.as_ptr(), output.as_mut_ptr()), 0);
+        assert_eq!(output.payload_string(), "VGVzdA==");
+    }
+}
+
+#[test]
+fn generate_random_spills() {
+    let lib = load();
+    unsafe {
+        let generate_random = lib.function::<Output>("generateRandom");
+        let mut output = HostBuffer::with_capacity(256);
+        assert_eq!(generate_random(output.as_mut_ptr()), 0);
+        let spilled = output.temp_file_path().is_some();
+        let payload = output.payload();
+        assert_eq!(spilled, payload.len() > 256);
+    }
+}
+
+#[test]
+fn encrypt_decrypt() {
+    let lib = load();
+    unsafe {
+        let encrypt = lib.function::<Ternary>("encrypt");
+        let decrypt = lib.function::<Ternary>("decrypt");
+        let key = HostBuffer::from_bytes(&[7u8; 32]);
+        let nonce = HostBuffer::from_bytes(&[1u8; 12]);
+        let plaintext = HostBuffer::from_text("Secret message");
+
+        let mut sealed = HostBuffer::with_capacity(64);
+        assert_eq!(
+            encrypt(
+                key.as_ptr(),
+                nonce.as_ptr(),
+                plaintext.as_ptr(),
+                sealed.as_mut_ptr()
+            ),
+            0
+        );
+        let mut ciphertext = sealed.payload();
+        assert_eq!(ciphertext.len(), 14 + 16);
+
+        let input = HostBuffer::from_bytes(&ciphertext);
+        let mut opened = HostBuffer::with_capacity(64);
+        assert_eq!(
+            decrypt(
+                key.as_ptr(),
+                nonce.as_ptr(),
+                input.as_ptr(),
+                opened.as_mut_ptr()
+            ),
+            0
+        );
+        assert_eq!(opened.payload_string(), "Secret message");
+
+        ciphertext[0] ^= 0xFF;
+        let tampered = HostBuffer::from_bytes(&ciphertext);
+        assert_eq!(
+            decrypt(
+                key.as_ptr(),
+                nonce.as_ptr(),
+                tampered.as_ptr(),
+                opened.as_mut_ptr()
+            ),
+            -1004
+        );
+
+        let short_key = HostBuffer::from_bytes(&[7u8; 5]);
+        assert_eq!(
+            encrypt(
+                short_key.as_ptr(),
+                nonce.as_ptr(),
+                plaintext.as_ptr(),
+                sealed.as_mut_ptr()
+            ),
+            -1001
+        );
+        let short_nonce = HostBuffer::from_bytes(&[1u8; 5]);
+        assert_eq!(
+            encrypt(
+                key.as_ptr(),
+                short_nonce.as_ptr(),
+                plaintext.as_ptr(),
+                sealed.as_mut_ptr()
+            ),
+            -1002
+        );
+    }
+}
+
+#[test]
+fn sha256_handles() {
+    let lib = load();
+    unsafe {
+        let init = lib.function::<Handle>("sha256Init");
+        let update =
+            lib.function::<unsafe extern "C" fn(i64, *const c_char) -> i32>("sha256Update");
+        let finalize =
+            lib.function::<unsafe extern "C" fn(i64, *mut c_char) -> i32>("sha256Finalize");
+
+        let handle = init();
+        assert!(handle > 0);
+        assert_eq!(update(handle, HostBuffer::from_text("hello ").as_ptr()), 0);
+        assert_eq!(update(handle, HostBuffer::from_text("world").as_ptr()), 0);
+        let mut digest = HostBuffer::with_capacity(32);
+        assert_eq!(finalize(handle, digest.as_mut_ptr()), 0);
+        let digest = digest.payload();
+        assert_eq!(
+            &digest[..4],
+            &[0xb9, 0x4d, 0x27, 0xb9],
+            "sha256(\"hello world\") prefix"
+        );
+
+        assert_eq!(
+            update(handle, HostBuffer::from_text("late").as_ptr()),
+            -1005
+        );
+    }
+}
+
+#[test]
+fn compress_decompress_spills() {
+    let lib = load();
+    unsafe {
+        let compress = lib.function::<Unary>("compress");
+        let decompress = lib.function::<Unary>("decompress");
+        let original = b"cobhan ".repeat(100_000);
+
+        let input = HostBuffer::from_bytes(&original);
+        let mut compressed = HostBuffer::with_capacity(original.len());
+        assert_eq!(compress(input.as_ptr(), compressed.as_mut_ptr()), 0);
+        let compressed = compressed.payload();
+        assert!(compressed.len() < original.len());
+
+        let input = HostBuffer::from_bytes(&compressed);
+        let mut output = HostBuffer::with_capacity(compressed.len());
+        assert_eq!(decompress(input.as_ptr(), output.as_mut_ptr()), 0);
+        assert!(output.temp_file_path().is_some());
+        assert!(output.payload() == original);
+
+        let garbage = HostBuffer::from_text("not gzip");
+        let mut output = HostBuffer::with_capacity(64);
+        assert_eq!(decompress(garbage.as_ptr(), output.as_mut_ptr()), -1007);
+    }
+}
+
+static CALLBACK_RESULT: AtomicI32 = AtomicI32::new(i32::MIN);
+
+extern "C" fn on_sleep_done(ctx: *mut c_void, result: i32) {
+    assert_eq!(ctx as usize, 0x1234);
+    CALLBACK_RESULT.store(result, Ordering::SeqCst);
+}
+
+#[test]
+fn sleep_callback_and_job() {
+    let lib = load();
+    unsafe {
+        type Callback = extern "C" fn(*mut c_void, i32);
+        let sleep_then_callback =
+            lib.function::<unsafe extern "C" fn(i64, Option<Callback>, *mut c_void) -> i32>(
+                "sleepThenCallback",
+            );
+        assert_eq!(
+            sleep_then_callback(10, Some(on_sleep_done), 0x1234 as *mut c_void),
+            0
+        );
+        assert_eq!(sleep_then_callback(10, None, std::ptr::null_mut()), -1);
+
+        let start = lib.function::<unsafe extern "C" fn(i64) -> i64>("startSleepJob");
+        let poll = lib.function::<unsafe extern "C" fn(i64) -> i32>("pollSleepJob");
+        let job = start(10);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            match poll(job) {
+                0 => break,
+                1 => assert!(Instant::now() < deadline, "sleep job never finished"),
+                other => panic!("pollSleepJob returned {}", other),
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(poll(job), -1005);
+
+        while CALLBACK_RESULT.load(Ordering::SeqCst) == i32::MIN {
+            assert!(Instant::now() < deadline, "callback never ran");
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(CALLBACK_RESULT.load(Ordering::SeqCst), 0);
+    }
+}
+
+#[test]
+fn random_bytes_and_uuid() {
+    let lib = load();
+    unsafe {
+        let random_bytes =
+            lib.function::<unsafe extern "C" fn(i32, *mut c_char) -> i32>("randomBytes");
+        let mut output = HostBuffer::with_capacity(64);
+        assert_eq!(random_bytes(64, output.as_mut_ptr()), 0);
+        assert_eq!(output.payload().len(), 64);
+        assert_eq!(random_bytes(-1, output.as_mut_ptr()), -1008);
+
+        let generate_uuid = lib.function::<Output>("generateUuid");
+        let mut output = HostBuffer::with_capacity(36);
+        assert_eq!(generate_uuid(output.as_mut_ptr()), 0);
+        let uuid = output.payload_string();
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+    }
+}
+
+#[test]
+fn kv_store() {
+    let lib = load();
+    unsafe {
+        let open = lib.function::<Handle>("kvOpen");
+        let put =
+            lib.function::<unsafe extern "C" fn(i64, *const c_char, *const c_char) -> i32>("kvPut");
+        let get =
+            lib.function::<unsafe extern "C" fn(i64, *const c_char, *mut c_char) -> i32>("kvGet");
+        let close = lib.function::<unsafe extern "C" fn(i64) -> i32>("kvClose");
+
+        let store = open();
+        let key = HostBuffer::from_text("greeting");
+        assert_eq!(
+            put(store, key.as_ptr(), HostBuffer::from_text("hello").as_ptr()),
+            0
+        );
+        let mut output = HostBuffer::with_capacity(16);
+        assert_eq!(get(store, key.as_ptr(), output.as_mut_ptr()), 0);
+        assert_eq!(output.payload_string(), "hello");
+        assert_eq!(
+            get(
+                store,
+                HostBuffer::from_text("missing").as_ptr(),
+                output.as_mut_ptr()
+            ),
+            -1009
+        );
+
+        assert_eq!(close(store), 0);
+        assert_eq!(get(store, key.as_ptr(), output.as_mut_ptr()), -1005);
+        assert_eq!(close(store), -1005);
+    }
+}
+
+type KeyCallback = extern "C" fn(*mut c_void, *const c_char) -> i32;
+type KvPut = unsafe extern "C" fn(i64, *const c_char, *const c_char) -> i32;
+
+struct Reentry {
+    store: i64,
+    put: KvPut,
+    keys: Vec<String>,
+    put_result: i32,
+}
+
+extern "C" fn collect_key(ctx: *mut c_void, key: *const c_char) -> i32 {
+    let reentry = unsafe { &mut *(ctx as *mut Reentry) };
+    // Keys are short enough to always be passed inline
+    let key = unsafe {
+        let length = i32::from_le_bytes(*(key as *const [u8; 4]));
+        std::slice::from_raw_parts(key.add(8) as *const u8, length as usize)
+    };
+    reentry.keys.push(String::from_utf8(key.to_vec()).unwrap());
+    0
+}
+
+extern "C" fn put_from_callback(ctx: *mut c_void, key: *const c_char) -> i32 {
+    let reentry = unsafe { &mut *(ctx as *mut Reentry) };
+    let value = HostBuffer::from_text("changed");
+    reentry.put_result = unsafe { (reentry.put)(reentry.store, key, value.as_ptr()) };
+    reentry.put_result
+}
+
+#[test]
+fn kv_callbacks_cannot_reenter_the_store() {
+    let lib = load();
+    unsafe {
+        let open = lib.function::<Handle>("kvOpen");
+        let put = lib.function::<KvPut>("kvPut");
+        let for_each_key = lib
+            .function::<unsafe extern "C" fn(i64, Option<KeyCallback>, *mut c_void) -> i32>(
+                "kvForEachKey",
+            );
+        let close = lib.function::<unsafe extern "C" fn(i64) -> i32>("kvClose");
+
+        let store = open();
+        let key = HostBuffer::from_text("greeting");
+        assert_eq!(
+            put(store, key.as_ptr(), HostBuffer::from_text("hello").as_ptr()),
+            0
+        );
+        let mut reentry = Reentry {
+            store,
+            put: *put,
+            keys: Vec::new(),
+            put_result: 0,
+        };
+        let ctx = &mut reentry as *mut Reentry as *mut c_void;
+        assert_eq!(for_each_key(store, Some(collect_key), ctx), 0);
+        assert_eq!(reentry.keys, ["greeting"]);
+
+        // Re-entering the locked store fails cleanly instead of deadlocking
+        assert_eq!(for_each_key(store, Some(put_from_callback), ctx), -1012);
+        assert_eq!(reentry.put_result, -1012);
+        // and leaves it usable
+        assert_eq!(
+            put(store, key.as_ptr(), HostBuffer::from_text("again").as_ptr()),
+            0
+        );
+
+        assert_eq!(for_each_key(store, None, ctx), -1);
+        assert_eq!(close(store), 0);
+        assert_eq!(for_each_key(store, Some(collect_key), ctx), -1005);
+    }
+}
+
+#[test]
+fn error_fixtures() {
+    let lib = load();
+    unsafe {
+        let trigger_error = lib.function::<unsafe extern "C" fn(i32) -> i32>("triggerError");
+        for code in 0..=20 {
+            assert_eq!(trigger_error(-code), -code);
+        }
+
+        let trigger_panic = lib.function::<unsafe extern "C" fn() -> i32>("triggerPanic");
+        assert_eq!(trigger_panic(), -1010);
+
+        let invalid_utf8_output = lib.function::<Output>("invalidUtf8Output");
+        let mut output = HostBuffer::with_capacity(16);
+        assert_eq!(invalid_utf8_output(output.as_mut_ptr()), 0);
+        assert!(String::from_utf8(output.payload()).is_err());
+    }
+}
+
+#[test]
+fn self_test() {
+    let lib = load();
+    unsafe {
+        let self_test = lib.function::<Output>("cobhan_self_test");
+        let mut output = HostBuffer::with_capacity(4096);
+        assert_eq!(self_test(output.as_mut_ptr()), 0);
+        let report = output.payload_json();
+        assert_eq!(report["passed"], json!(true), "{}", report);
+        assert_eq!(report["header_size"], json!(8));
+    }
+}
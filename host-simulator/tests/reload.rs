@@ -0,0 +1,72 @@
+//! Unloading and loading the library again changes process-wide state, so it runs in its own
+//! test binary.
+
+use std::os::raw::c_char;
+use std::sync::mpsc;
+use std::thread;
+
+use cobhan_host_simulator::{demo_library_path, HostBuffer, HostLibrary};
+use serde_json::json;
+
+type Configure = unsafe extern "C" fn(*const c_char) -> i32;
+type Deinit = unsafe extern "C" fn();
+type Unary = unsafe extern "C" fn(*const c_char, *mut c_char) -> i32;
+
+fn load() -> HostLibrary {
+    HostLibrary::load(&demo_library_path()).expect("failed to load libcobhandemo")
+}
+
+unsafe fn to_upper(lib: &HostLibrary, capacity: usize) -> (i32, HostBuffer) {
+    let to_upper = lib.function::<Unary>("toUpper");
+    let input = HostBuffer::from_text("Initial value");
+    let mut output = HostBuffer::with_capacity(capacity);
+    (to_upper(input.as_ptr(), output.as_mut_ptr()), output)
+}
+
+#[test]
+fn deinit_and_reload() {
+    let lib = load();
+    unsafe {
+        let configure = lib.function::<Configure>("cobhan_configure");
+        let settings = HostBuffer::from_json(&json!({ "max_buffer_len": 4 }));
+        assert_eq!(configure(settings.as_ptr()), 0);
+        assert_eq!(to_upper(&lib, 64).0, -2);
+
+        // The settings are dropped, so the library behaves as if freshly loaded
+        lib.function::<Deinit>("cobhan_deinit")();
+        let (result, output) = to_upper(&lib, 64);
+        assert_eq!(result, 0);
+        assert_eq!(output.payload_string(), "INITIAL VALUE");
+
+        // A thread that spilled is still running when the library is unloaded
+        let to_upper_fn = *lib.function::<Unary>("toUpper");
+        let (unloaded, wait_for_unload) = mpsc::channel::<()>();
+        let (spilled, wait_for_spill) = mpsc::channel();
+        let spiller = thread::spawn(move || {
+            let input = HostBuffer::from_text(&"a".repeat(1024));
+            let mut output = HostBuffer::with_capacity(256);
+            spilled
+                .send(to_upper_fn(input.as_ptr(), output.as_mut_ptr()))
+                .unwrap();
+            assert!(output.temp_file_path().is_some());
+            assert_eq!(output.payload_string(), "A".repeat(1024));
+            wait_for_unload.recv().unwrap();
+        });
+        assert_eq!(wait_for_spill.recv().unwrap(), 0);
+
+        lib.function::<Deinit>("cobhan_deinit")();
+        lib.unload().expect("failed to unload libcobhandemo");
+        unloaded.send(()).unwrap();
+        spiller.join().unwrap();
+
+        let lib = load();
+        let configure = lib.function::<Configure>("cobhan_configure");
+        let (result, output) = to_upper(&lib, 64);
+        assert_eq!(result, 0);
+        assert_eq!(output.payload_string(), "INITIAL VALUE");
+        assert_eq!(configure(settings.as_ptr()), 0);
+        assert_eq!(to_upper(&lib, 64).0, -2);
+        lib.function::<Deinit>("cobhan_deinit")();
+        lib.unload().expect("failed to unload libcobhandemo");
+    }
+}
@@ -3,12 +3,35 @@ from cobhan.cobhan import Cobhan
 class CobhanDemoLib(Cobhan):
     CDEFINES = """
         void sleepTest(int32_t seconds);
+        int32_t sleepThenCallback(int64_t millis, void (*callback)(void *ctx, int32_t result), void *ctx);
+        int64_t startSleepJob(int64_t millis);
+        int32_t pollSleepJob(int64_t handle);
         int32_t addInt32(int32_t x, int32_t y);
         int64_t addInt64(int64_t x, int64_t y);
         double addDouble(double x, double y);
         int32_t toUpper(void *input, void *output);
         int32_t filterJson(void *input, void *disallowedValue, void *output);
+        int32_t filterJsonFields(void *input, void *allowedFields, void *output);
         int32_t base64Encode(void *input, void *output);
+        int32_t encrypt(void *key, void *nonce, void *plaintext, void *output);
+        int32_t decrypt(void *key, void *nonce, void *ciphertext, void *output);
+        int64_t sha256Init();
+        int32_t sha256Update(int64_t handle, void *input);
+        int32_t sha256Finalize(int64_t handle, void *output);
+        int32_t compress(void *input, void *output);
+        int32_t decompress(void *input, void *output);
+        int32_t compressZstd(void *input, void *output);
+        int32_t decompressZstd(void *input, void *output);
+        int32_t randomBytes(int32_t count, void *output);
+        int32_t generateUuid(void *output);
+        int64_t kvOpen();
+        int32_t kvPut(int64_t handle, void *key, void *value);
+        int32_t kvGet(int64_t handle, void *key, void *output);
+        int32_t kvClose(int64_t handle);
+        int32_t triggerError(int32_t code);
+        int32_t triggerPanic();
+        int32_t invalidUtf8Output(void *output);
+        int32_t cobhan_self_test(void *output);
         void spawnThread();
         int32_t readCounter();
     """
@@ -44,6 +67,29 @@ class CobhanDemoLib(Cobhan):
     def sleep_test(self, seconds):
         self._lib.sleepTest(seconds)
 
+    def sleep_then_callback(self, millis, on_done):
+        # Keep the cffi callback alive until Rust has invoked it
+        holder = {}
+
+        def trampoline(ctx, result):
+            holder.clear()
+            on_done(result)
+
+        holder['callback'] = self._ffi.callback("void(void *, int32_t)", trampoline)
+
+        result = self._lib.sleepThenCallback(millis, holder['callback'], self._ffi.NULL)
+        if result < 0:
+            raise Exception(f"sleepThenCallback failed {result}")
+
+    def start_sleep_job(self, millis):
+        return self._lib.startSleepJob(millis)
+
+    def poll_sleep_job(self, handle):
+        result = self._lib.pollSleepJob(handle)
+        if result < 0:
+            raise Exception(f"pollSleepJob failed {result}")
+        return result == 0
+
     def add_int32(self, x, y):
         return self._lib.addInt32(x, y)
 
@@ -67,6 +113,20 @@ class CobhanDemoLib(Cobhan):
 
         return self.from_json_buf(output_buf)
 
+    def filterJsonFields(self, input, allowed_fields):
+        input_buf = self.to_json_buf(input)
+
+        allowed_buf = self.to_json_buf(allowed_fields)
+
+        output_len = int(len(input_buf) * 1.5) # Allow extra space for reformatting
+        output_buf = self.allocate_buf(output_len)
+
+        result = self._lib.filterJsonFields(input_buf, allowed_buf, output_buf)
+        if result < 0:
+            raise Exception(f"filterJsonFields failed {result}")
+
+        return self.from_json_buf(output_buf)
+
     def base64Encode(self, input):
         input_buf = self.str_to_buf(input)
         output_len = int((4 * len(input_buf) / 3) + 3) & ~3
@@ -77,3 +137,161 @@ class CobhanDemoLib(Cobhan):
             raise Exception(f"base64Encode failed {result}")
 
         return self.buf_to_str(output_buf)
+
+    def encrypt(self, key, nonce, plaintext):
+        key_buf = self.bytearray_to_buf(key)
+        nonce_buf = self.bytearray_to_buf(nonce)
+        plaintext_buf = self.bytearray_to_buf(plaintext)
+        output_buf = self.allocate_buf(len(plaintext) + 16) # AES-GCM appends a 16 byte tag
+
+        result = self._lib.encrypt(key_buf, nonce_buf, plaintext_buf, output_buf)
+        if result < 0:
+            raise Exception(f"encrypt failed {result}")
+
+        return self.buf_to_bytearray(output_buf)
+
+    def decrypt(self, key, nonce, ciphertext):
+        key_buf = self.bytearray_to_buf(key)
+        nonce_buf = self.bytearray_to_buf(nonce)
+        ciphertext_buf = self.bytearray_to_buf(ciphertext)
+        output_buf = self.allocate_buf(len(ciphertext))
+
+        result = self._lib.decrypt(key_buf, nonce_buf, ciphertext_buf, output_buf)
+        if result < 0:
+            raise Exception(f"decrypt failed {result}")
+
+        return self.buf_to_bytearray(output_buf)
+
+    def sha256_init(self):
+        return self._lib.sha256Init()
+
+    def sha256_update(self, handle, data):
+        data_buf = self.bytearray_to_buf(data)
+
+        result = self._lib.sha256Update(handle, data_buf)
+        if result < 0:
+            raise Exception(f"sha256Update failed {result}")
+
+    def sha256_finalize(self, handle):
+        output_buf = self.allocate_buf(32)
+
+        result = self._lib.sha256Finalize(handle, output_buf)
+        if result < 0:
+            raise Exception(f"sha256Finalize failed {result}")
+
+        return self.buf_to_bytearray(output_buf)
+
+    def compress(self, data):
+        input_buf = self.bytearray_to_buf(data)
+        output_buf = self.allocate_buf(len(data) + 64) # Allow for gzip header and incompressible data
+
+        result = self._lib.compress(input_buf, output_buf)
+        if result < 0:
+            raise Exception(f"compress failed {result}")
+
+        return self.buf_to_bytearray(output_buf)
+
+    def decompress(self, data, output_len=None):
+        input_buf = self.bytearray_to_buf(data)
+        # Output larger than the buffer is returned via a temp file
+        output_buf = self.allocate_buf(output_len if output_len is not None else len(data))
+
+        result = self._lib.decompress(input_buf, output_buf)
+        if result < 0:
+            raise Exception(f"decompress failed {result}")
+
+        return self.buf_to_bytearray(output_buf)
+
+    def compress_zstd(self, data):
+        input_buf = self.bytearray_to_buf(data)
+        output_buf = self.allocate_buf(len(data) + 64) # Allow for zstd frame header and incompressible data
+
+        result = self._lib.compressZstd(input_buf, output_buf)
+        if result < 0:
+            raise Exception(f"compressZstd failed {result}")
+
+        return self.buf_to_bytearray(output_buf)
+
+    def decompress_zstd(self, data, output_len=None):
+        input_buf = self.bytearray_to_buf(data)
+        # Output larger than the buffer is returned via a temp file
+        output_buf = self.allocate_buf(output_len if output_len is not None else len(data))
+
+        result = self._lib.decompressZstd(input_buf, output_buf)
+        if result < 0:
+            raise Exception(f"decompressZstd failed {result}")
+
+        return self.buf_to_bytearray(output_buf)
+
+    def random_bytes(self, count):
+        output_buf = self.allocate_buf(count)
+
+        result = self._lib.randomBytes(count, output_buf)
+        if result < 0:
+            raise Exception(f"randomBytes failed {result}")
+
+        return self.buf_to_bytearray(output_buf)
+
+    def generate_uuid(self):
+        output_buf = self.allocate_buf(36)
+
+        result = self._lib.generateUuid(output_buf)
+        if result < 0:
+            raise Exception(f"generateUuid failed {result}")
+
+        return self.buf_to_str(output_buf)
+
+    KV_NOT_FOUND = -1009
+
+    def kv_open(self):
+        return self._lib.kvOpen()
+
+    def kv_put(self, handle, key, value):
+        key_buf = self.str_to_buf(key)
+        value_buf = self.bytearray_to_buf(value)
+
+        result = self._lib.kvPut(handle, key_buf, value_buf)
+        if result < 0:
+            raise Exception(f"kvPut failed {result}")
+
+    def kv_get(self, handle, key, capacity=1024):
+        key_buf = self.str_to_buf(key)
+        output_buf = self.allocate_buf(capacity)
+
+        result = self._lib.kvGet(handle, key_buf, output_buf)
+        if result == CobhanDemoLib.KV_NOT_FOUND:
+            return None
+        if result < 0:
+            raise Exception(f"kvGet failed {result}")
+
+        return self.buf_to_bytearray(output_buf)
+
+    def kv_close(self, handle):
+        result = self._lib.kvClose(handle)
+        if result < 0:
+            raise Exception(f"kvClose failed {result}")
+
+    def trigger_error(self, code):
+        return self._lib.triggerError(code)
+
+    def trigger_panic(self):
+        return self._lib.triggerPanic()
+
+    def invalid_utf8_output(self):
+        output_buf = self.allocate_buf(16)
+
+        result = self._lib.invalidUtf8Output(output_buf)
+        if result < 0:
+            raise Exception(f"invalidUtf8Output failed {result}")
+
+        return self.buf_to_str(output_buf)
+
+    def self_test(self):
+        output_buf = self.allocate_buf(4096)
+
+        result = self._lib.cobhan_self_test(output_buf)
+        report = self.from_json_buf(output_buf)
+        if result < 0:
+            raise Exception(f"cobhan_self_test failed {result}: {report}")
+
+        return report
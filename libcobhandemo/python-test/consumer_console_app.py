@@ -1,5 +1,9 @@
+import hashlib
 import os
 import sys
+import threading
+import time
+import uuid
 from cobhan_demo_lib.cobhan_demo import CobhanDemoLib
 
 lib_file = sys.argv[1]
@@ -17,6 +21,9 @@ print(f"Loaded: {lib_file}")
 counter = lib.read_counter()
 print(f"Counter: {counter}")
 
+report = lib.self_test()
+print(f"Self test: {report}")
+
 print("Spawning thread")
 lib.spawn_thread()
 
@@ -40,6 +47,115 @@ if result4["test2"] != "kittens":
     print("filterJson test failed")
     sys.exit(255)
 
+key = bytearray(range(32))
+nonce = bytearray(range(12))
+ciphertext = lib.encrypt(key, nonce, bytearray(b"Secret message"))
+if lib.decrypt(key, nonce, ciphertext) != bytearray(b"Secret message"):
+    print("encrypt/decrypt test failed")
+    sys.exit(255)
+
+ciphertext[0] ^= 0xFF
+try:
+    lib.decrypt(key, nonce, ciphertext)
+    print("decrypt of tampered ciphertext did not fail")
+    sys.exit(255)
+except Exception:
+    pass
+
+handle = lib.sha256_init()
+lib.sha256_update(handle, bytearray(b"hello "))
+lib.sha256_update(handle, bytearray(b"world"))
+digest = lib.sha256_finalize(handle)
+if bytes(digest) != hashlib.sha256(b"hello world").digest():
+    print("sha256 streaming test failed")
+    sys.exit(255)
+
+try:
+    lib.sha256_update(handle, bytearray(b"after finalize"))
+    print("sha256_update on a finalized handle did not fail")
+    sys.exit(255)
+except Exception:
+    pass
+
+original = bytearray(b"cobhan " * 100000)
+compressed = lib.compress(original)
+if len(compressed) >= len(original):
+    print("compress test failed")
+    sys.exit(255)
+
+# Output buffer is sized for the compressed input, so the result spills to a temp file
+if lib.decompress(compressed) != original:
+    print("decompress test failed")
+    sys.exit(255)
+
+compressed = lib.compress_zstd(original)
+if len(compressed) >= len(original):
+    print("compressZstd test failed")
+    sys.exit(255)
+
+if lib.decompress_zstd(compressed) != original:
+    print("decompressZstd test failed")
+    sys.exit(255)
+
+result5 = lib.filterJsonFields({'id': 7, 'name': 'kittens', 'secret': 'hunter2'}, ['id', 'name'])
+if result5 != {'id': 7, 'name': 'kittens'}:
+    print("filterJsonFields test failed")
+    sys.exit(255)
+
+callback_done = threading.Event()
+lib.sleep_then_callback(100, lambda result: callback_done.set())
+if not callback_done.wait(5):
+    print("sleep_then_callback test failed")
+    sys.exit(255)
+
+job = lib.start_sleep_job(100)
+deadline = time.time() + 5
+while not lib.poll_sleep_job(job):
+    if time.time() > deadline:
+        print("sleep job test failed")
+        sys.exit(255)
+    time.sleep(0.01)
+
+random = lib.random_bytes(64)
+if len(random) != 64 or random == lib.random_bytes(64):
+    print("random_bytes test failed")
+    sys.exit(255)
+
+generated = uuid.UUID(lib.generate_uuid())
+if generated.version != 4 or generated.variant != uuid.RFC_4122:
+    print("generate_uuid test failed")
+    sys.exit(255)
+
+store = lib.kv_open()
+lib.kv_put(store, "greeting", bytearray(b"hello"))
+if lib.kv_get(store, "greeting") != bytearray(b"hello") or lib.kv_get(store, "missing") is not None:
+    print("kv store test failed")
+    sys.exit(255)
+
+lib.kv_close(store)
+try:
+    lib.kv_get(store, "greeting")
+    print("kv_get on a closed handle did not fail")
+    sys.exit(255)
+except Exception:
+    pass
+
+for code in range(0, -21, -1):
+    if lib.trigger_error(code) != code:
+        print(f"trigger_error({code}) test failed")
+        sys.exit(255)
+
+if lib.trigger_panic() != -1010:
+    print("trigger_panic test failed")
+    sys.exit(255)
+
+try:
+    lib.invalid_utf8_output()
+    print("invalid_utf8_output did not fail to decode")
+    sys.exit(255)
+except UnicodeDecodeError:
+    pass
+
 print("Testing sleep_test(3)")
 lib.sleep_test(2)
 
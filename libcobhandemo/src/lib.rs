@@ -1,16 +1,153 @@
 #![allow(clippy::missing_safety_doc)]
 
-use std::collections::HashMap;
-use std::os::raw::c_char;
-use std::sync::atomic::{AtomicI32, Ordering};
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Read, Write};
+use std::os::raw::{c_char, c_void};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::{thread, time};
 
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes128Gcm, Aes256Gcm, Nonce};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use rand::Rng;
 use rand::RngCore;
 use serde_json::Value;
+use sha2::{Digest, Sha256};
 
 static COUNTER: AtomicI32 = AtomicI32::new(0);
 
+// Application error codes, kept clear of the cobhan::ERR_* range
+
+/// Key is not 16 or 32 bytes
+const ERR_INVALID_KEY: i32 = -1001;
+
+/// Nonce is not 12 bytes
+const ERR_INVALID_NONCE: i32 = -1002;
+
+/// Encryption failed
+const ERR_ENCRYPT_FAILED: i32 = -1003;
+
+/// Decryption failed (wrong key, nonce, or tampered ciphertext)
+const ERR_DECRYPT_FAILED: i32 = -1004;
+
+/// Handle is unknown or has already been finalized / closed
+const ERR_INVALID_HANDLE: i32 = -1005;
+
+/// Compression failed
+const ERR_COMPRESS_FAILED: i32 = -1006;
+
+/// Input is not valid gzip / zstd data
+const ERR_DECOMPRESS_FAILED: i32 = -1007;
+
+/// Requested count is negative
+const ERR_INVALID_COUNT: i32 = -1008;
+
+/// Key is not present in the store
+const ERR_NOT_FOUND: i32 = -1009;
+
+/// A panic was caught before it could unwind into the host
+const ERR_PANIC: i32 = -1010;
+
+/// At least one self test check failed, see the report for details
+const ERR_SELF_TEST_FAILED: i32 = -1011;
+
+/// A host callback re-entered a registry that was locked for the call that invoked it
+const ERR_REENTRANT_CALL: i32 = -1012;
+
+/// Input decompresses to more than MAX_DECOMPRESSED_LEN bytes
+const ERR_OUTPUT_TOO_LARGE: i32 = -1013;
+
+/// Largest output decompress will produce, a few bytes of input can expand to gigabytes
+const MAX_DECOMPRESSED_LEN: u64 = 64 * 1024 * 1024;
+
+// Handle registry: hosts hold an opaque i64, the state lives here until finalized
+static NEXT_HANDLE: AtomicI64 = AtomicI64::new(1);
+static SHA256_HANDLES: Registry<Sha256> = Registry::new("sha256", 1 << 0);
+static SLEEP_JOBS: Registry<Arc<AtomicBool>> = Registry::new("sleep job", 1 << 1);
+static KV_STORES: Registry<HashMap<String, Vec<u8>>> = Registry::new("kv store", 1 << 2);
+
+/// Returned by pollSleepJob while the job is still running
+const JOB_PENDING: i32 = 1;
+
+fn next_handle() -> i64 {
+    NEXT_HANDLE.fetch_add(1, Ordering::Relaxed)
+}
+
+thread_local! {
+    // Bits of the registries locked on this thread, re-entering one from a host callback would
+    // deadlock. A Cell<u8> registers no destructor on host threads
+    static ENTERED: Cell<u8> = const { Cell::new(0) };
+}
+
+struct Registry<T> {
+    // Only reported by lock_diagnostics
+    #[cfg_attr(not(feature = "lock_diagnostics"), allow(dead_code))]
+    name: &'static str,
+    // This registry's bit in ENTERED
+    bit: u8,
+    handles: Mutex<BTreeMap<i64, T>>,
+}
+
+impl<T> Registry<T> {
+    const fn new(name: &'static str, bit: u8) -> Self {
+        Registry {
+            name,
+            bit,
+            handles: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn insert(&self, value: T) -> Result<i64, i32> {
+        self.with(|handles| {
+            let handle = next_handle();
+            handles.insert(handle, value);
+            handle
+        })
+    }
+
+    // Runs f with the registry locked, or fails with ERR_REENTRANT_CALL if this thread already
+    // holds the lock. With lock_diagnostics, reports how long the lock was held
+    fn with<R>(&self, f: impl FnOnce(&mut BTreeMap<i64, T>) -> R) -> Result<R, i32> {
+        let _entered = Entered::enter(self.bit)?;
+        let mut handles = self.handles.lock().unwrap();
+        #[cfg(feature = "lock_diagnostics")]
+        let locked = time::Instant::now();
+        let result = f(&mut handles);
+        #[cfg(feature = "lock_diagnostics")]
+        eprintln!(
+            "cobhandemo: {} registry locked for {:?}",
+            self.name,
+            locked.elapsed()
+        );
+        Ok(result)
+    }
+}
+
+// Marks a registry as entered on this thread until dropped, even if the operation panics
+struct Entered(u8);
+
+impl Entered {
+    fn enter(bit: u8) -> Result<Self, i32> {
+        ENTERED.with(|entered| {
+            if entered.get() & bit != 0 {
+                return Err(ERR_REENTRANT_CALL);
+            }
+            entered.set(entered.get() | bit);
+            Ok(Entered(bit))
+        })
+    }
+}
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        ENTERED.with(|entered| entered.set(entered.get() & !self.0));
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn spawnThread() {
     std::thread::spawn(move || loop {
@@ -29,6 +166,69 @@ pub unsafe extern "C" fn sleepTest(seconds: i32) {
     thread::sleep(time::Duration::from_secs(seconds as u64))
 }
 
+/// Host callback invoked with the host's context pointer and a result code
+pub type SleepCallback = extern "C" fn(ctx: *mut c_void, result: i32);
+
+// The context pointer is opaque to us; the host guarantees it stays valid until the callback runs
+struct HostContext(*mut c_void);
+unsafe impl Send for HostContext {}
+
+#[no_mangle]
+pub unsafe extern "C" fn sleepThenCallback(
+    millis: i64,
+    callback: Option<SleepCallback>,
+    ctx: *mut c_void,
+) -> i32 {
+    let callback = match callback {
+        Some(c) => c,
+        None => return cobhan::ERR_NULL_PTR,
+    };
+    let ctx = HostContext(ctx);
+
+    // The callback runs on a Rust thread, hosts must marshal back to their own thread if needed
+    thread::spawn(move || {
+        thread::sleep(time::Duration::from_millis(millis.max(0) as u64));
+        callback(ctx.0, cobhan::ERR_NONE);
+    });
+
+    cobhan::ERR_NONE
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn startSleepJob(millis: i64) -> i64 {
+    let done = Arc::new(AtomicBool::new(false));
+    let handle = match SLEEP_JOBS.insert(done.clone()) {
+        Ok(handle) => handle,
+        Err(e) => return e.into(),
+    };
+
+    thread::spawn(move || {
+        thread::sleep(time::Duration::from_millis(millis.max(0) as u64));
+        done.store(true, Ordering::Release);
+    });
+
+    handle
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn pollSleepJob(handle: i64) -> i32 {
+    SLEEP_JOBS
+        .with(|jobs| {
+            let finished = match jobs.get(&handle) {
+                Some(done) => done.load(Ordering::Acquire),
+                None => return ERR_INVALID_HANDLE,
+            };
+            if !finished {
+                return JOB_PENDING;
+            }
+
+            // Reporting completion releases the handle
+            jobs.remove(&handle);
+            cobhan::ERR_NONE
+        })
+        .unwrap_or_else(|e| e)
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn addInt32(x: i32, y: i32) -> i32 {
     x.saturating_add(y)
@@ -80,7 +280,38 @@ pub unsafe extern "C" fn filterJson(
 
 // Example of a safe function
 pub fn filter_json(json: &mut HashMap<String, Value>, disallowed: &str) {
-    json.retain(|_key, value| matches!(value, Value::String(s) if !s.contains(&disallowed)));
+    json.retain(|_key, value| matches!(value, Value::String(s) if !s.contains(disallowed)));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn filterJsonFields(
+    input: *const c_char,
+    allowed_fields: *const c_char,
+    output: *mut c_char,
+) -> i32 {
+    let mut json = match cobhan::cbuffer_to_hashmap_json(input) {
+        Ok(input_json) => input_json,
+        Err(e) => return e,
+    };
+
+    // The allow list is a JSON array of field names, e.g. ["id", "name"]
+    let allowed_fields_str = match cobhan::cbuffer_to_string(allowed_fields) {
+        Ok(allowed) => allowed,
+        Err(e) => return e,
+    };
+    let allowed: Vec<String> = match serde_json::from_str(&allowed_fields_str) {
+        Ok(allowed) => allowed,
+        Err(_) => return cobhan::ERR_JSON_DECODE_FAILED,
+    };
+
+    retain_json_fields(&mut json, &allowed);
+
+    cobhan::hashmap_json_to_cbuffer(&json, output)
+}
+
+// Example of a safe function
+pub fn retain_json_fields(json: &mut HashMap<String, Value>, allowed: &[String]) {
+    json.retain(|key, _value| allowed.contains(key));
 }
 
 #[no_mangle]
@@ -103,3 +334,541 @@ pub unsafe extern "C" fn generateRandom(output: *mut c_char) -> i32 {
     rng.fill_bytes(&mut bytes);
     cobhan::bytes_to_cbuffer(&bytes, output)
 }
+
+#[no_mangle]
+pub unsafe extern "C" fn encrypt(
+    key: *const c_char,
+    nonce: *const c_char,
+    plaintext: *const c_char,
+    output: *mut c_char,
+) -> i32 {
+    let key_bytes = match cobhan::cbuffer_to_vector(key) {
+        Ok(k) => k,
+        Err(e) => return e,
+    };
+    let nonce_bytes = match cobhan::cbuffer_to_vector(nonce) {
+        Ok(n) => n,
+        Err(e) => return e,
+    };
+    let plaintext_bytes = match cobhan::cbuffer_to_vector(plaintext) {
+        Ok(p) => p,
+        Err(e) => return e,
+    };
+
+    match aes_gcm_seal(&key_bytes, &nonce_bytes, &plaintext_bytes) {
+        Ok(ciphertext) => cobhan::bytes_to_cbuffer(&ciphertext, output),
+        Err(e) => e,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn decrypt(
+    key: *const c_char,
+    nonce: *const c_char,
+    ciphertext: *const c_char,
+    output: *mut c_char,
+) -> i32 {
+    let key_bytes = match cobhan::cbuffer_to_vector(key) {
+        Ok(k) => k,
+        Err(e) => return e,
+    };
+    let nonce_bytes = match cobhan::cbuffer_to_vector(nonce) {
+        Ok(n) => n,
+        Err(e) => return e,
+    };
+    let ciphertext_bytes = match cobhan::cbuffer_to_vector(ciphertext) {
+        Ok(c) => c,
+        Err(e) => return e,
+    };
+
+    match aes_gcm_open(&key_bytes, &nonce_bytes, &ciphertext_bytes) {
+        Ok(plaintext) => cobhan::bytes_to_cbuffer(&plaintext, output),
+        Err(e) => e,
+    }
+}
+
+// Example of safe functions mapping a library's errors onto i32 codes
+pub fn aes_gcm_seal(key: &[u8], nonce: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, i32> {
+    if nonce.len() != 12 {
+        return Err(ERR_INVALID_NONCE);
+    }
+    let nonce = Nonce::from_slice(nonce);
+    let sealed = match key.len() {
+        16 => Aes128Gcm::new_from_slice(key).map(|c| c.encrypt(nonce, plaintext)),
+        32 => Aes256Gcm::new_from_slice(key).map(|c| c.encrypt(nonce, plaintext)),
+        _ => return Err(ERR_INVALID_KEY),
+    };
+    match sealed {
+        Ok(Ok(ciphertext)) => Ok(ciphertext),
+        Ok(Err(_)) => Err(ERR_ENCRYPT_FAILED),
+        Err(_) => Err(ERR_INVALID_KEY),
+    }
+}
+
+pub fn aes_gcm_open(key: &[u8], nonce: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, i32> {
+    if nonce.len() != 12 {
+        return Err(ERR_INVALID_NONCE);
+    }
+    let nonce = Nonce::from_slice(nonce);
+    let opened = match key.len() {
+        16 => Aes128Gcm::new_from_slice(key).map(|c| c.decrypt(nonce, ciphertext)),
+        32 => Aes256Gcm::new_from_slice(key).map(|c| c.decrypt(nonce, ciphertext)),
+        _ => return Err(ERR_INVALID_KEY),
+    };
+    match opened {
+        Ok(Ok(plaintext)) => Ok(plaintext),
+        Ok(Err(_)) => Err(ERR_DECRYPT_FAILED),
+        Err(_) => Err(ERR_INVALID_KEY),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sha256Init() -> i64 {
+    SHA256_HANDLES
+        .insert(Sha256::new())
+        .unwrap_or_else(i64::from)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sha256Update(handle: i64, input: *const c_char) -> i32 {
+    let bytes = match cobhan::cbuffer_to_vector(input) {
+        Ok(b) => b,
+        Err(e) => return e,
+    };
+
+    SHA256_HANDLES
+        .with(|handles| match handles.get_mut(&handle) {
+            Some(hasher) => {
+                hasher.update(&bytes);
+                cobhan::ERR_NONE
+            }
+            None => ERR_INVALID_HANDLE,
+        })
+        .unwrap_or_else(|e| e)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sha256Finalize(handle: i64, output: *mut c_char) -> i32 {
+    // Finalizing always releases the handle, even if the digest doesn't fit in the output
+    let hasher = match SHA256_HANDLES.with(|handles| handles.remove(&handle)) {
+        Ok(Some(h)) => h,
+        Ok(None) => return ERR_INVALID_HANDLE,
+        Err(e) => return e,
+    };
+
+    cobhan::bytes_to_cbuffer(hasher.finalize(), output)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn compress(input: *const c_char, output: *mut c_char) -> i32 {
+    let bytes = match cobhan::cbuffer_to_vector(input) {
+        Ok(b) => b,
+        Err(e) => return e,
+    };
+
+    match gzip_compress(&bytes) {
+        Ok(compressed) => cobhan::bytes_to_cbuffer(&compressed, output),
+        Err(e) => e,
+    }
+}
+
+// Decompressed output is usually larger than the input, so hosts that size the output
+// buffer from the input exercise the temp file path
+#[no_mangle]
+pub unsafe extern "C" fn decompress(input: *const c_char, output: *mut c_char) -> i32 {
+    let bytes = match cobhan::cbuffer_to_vector(input) {
+        Ok(b) => b,
+        Err(e) => return e,
+    };
+
+    match gzip_decompress(&bytes) {
+        Ok(decompressed) => cobhan::bytes_to_cbuffer(&decompressed, output),
+        Err(e) => e,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn compressZstd(input: *const c_char, output: *mut c_char) -> i32 {
+    let bytes = match cobhan::cbuffer_to_vector(input) {
+        Ok(b) => b,
+        Err(e) => return e,
+    };
+
+    match zstd_compress(&bytes) {
+        Ok(compressed) => cobhan::bytes_to_cbuffer(&compressed, output),
+        Err(e) => e,
+    }
+}
+
+// Same temp file path as decompress, for zstd frames
+#[no_mangle]
+pub unsafe extern "C" fn decompressZstd(input: *const c_char, output: *mut c_char) -> i32 {
+    let bytes = match cobhan::cbuffer_to_vector(input) {
+        Ok(b) => b,
+        Err(e) => return e,
+    };
+
+    match zstd_decompress(&bytes) {
+        Ok(decompressed) => cobhan::bytes_to_cbuffer(&decompressed, output),
+        Err(e) => e,
+    }
+}
+
+pub fn gzip_compress(bytes: &[u8]) -> Result<Vec<u8>, i32> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(bytes)
+        .and_then(|_| encoder.finish())
+        .map_err(|_| ERR_COMPRESS_FAILED)
+}
+
+pub fn gzip_decompress(bytes: &[u8]) -> Result<Vec<u8>, i32> {
+    read_decompressed(GzDecoder::new(bytes))
+}
+
+pub fn zstd_compress(bytes: &[u8]) -> Result<Vec<u8>, i32> {
+    zstd::encode_all(bytes, 0).map_err(|_| ERR_COMPRESS_FAILED)
+}
+
+pub fn zstd_decompress(bytes: &[u8]) -> Result<Vec<u8>, i32> {
+    let decoder = zstd::Decoder::new(bytes).map_err(|_| ERR_DECOMPRESS_FAILED)?;
+    read_decompressed(decoder)
+}
+
+// Reads a decoder to the end, failing once the output passes MAX_DECOMPRESSED_LEN instead of
+// inflating without bound
+fn read_decompressed(decoder: impl Read) -> Result<Vec<u8>, i32> {
+    let mut decompressed = Vec::new();
+    decoder
+        .take(MAX_DECOMPRESSED_LEN + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(|_| ERR_DECOMPRESS_FAILED)?;
+    if decompressed.len() as u64 > MAX_DECOMPRESSED_LEN {
+        return Err(ERR_OUTPUT_TOO_LARGE);
+    }
+    Ok(decompressed)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn randomBytes(count: i32, output: *mut c_char) -> i32 {
+    if count < 0 {
+        return ERR_INVALID_COUNT;
+    }
+    let mut bytes: Vec<u8> = vec![0; count as usize];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    cobhan::bytes_to_cbuffer(&bytes, output)
+}
+
+// Output is always 36 bytes, e.g. 0c9f3d4e-8a1b-4c2d-9e3f-5a6b7c8d9e0f
+#[no_mangle]
+pub unsafe extern "C" fn generateUuid(output: *mut c_char) -> i32 {
+    cobhan::string_to_cbuffer(uuid_v4(), output)
+}
+
+// Example of a safe function
+pub fn uuid_v4() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes[6] = (bytes[6] & 0x0F) | 0x40; // Version 4
+    bytes[8] = (bytes[8] & 0x3F) | 0x80; // RFC 4122 variant
+
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn kvOpen() -> i64 {
+    KV_STORES.insert(HashMap::new()).unwrap_or_else(i64::from)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn kvPut(handle: i64, key: *const c_char, value: *const c_char) -> i32 {
+    let key_str = match cobhan::cbuffer_to_string(key) {
+        Ok(k) => k,
+        Err(e) => return e,
+    };
+    let value_bytes = match cobhan::cbuffer_to_vector(value) {
+        Ok(v) => v,
+        Err(e) => return e,
+    };
+
+    KV_STORES
+        .with(|stores| match stores.get_mut(&handle) {
+            Some(store) => {
+                store.insert(key_str, value_bytes);
+                cobhan::ERR_NONE
+            }
+            None => ERR_INVALID_HANDLE,
+        })
+        .unwrap_or_else(|e| e)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn kvGet(handle: i64, key: *const c_char, output: *mut c_char) -> i32 {
+    let key_str = match cobhan::cbuffer_to_string(key) {
+        Ok(k) => k,
+        Err(e) => return e,
+    };
+
+    KV_STORES
+        .with(|stores| {
+            let store = match stores.get(&handle) {
+                Some(s) => s,
+                None => return ERR_INVALID_HANDLE,
+            };
+            match store.get(&key_str) {
+                Some(value) => cobhan::bytes_to_cbuffer(value, output),
+                None => ERR_NOT_FOUND,
+            }
+        })
+        .unwrap_or_else(|e| e)
+}
+
+/// Host callback invoked with the host's context pointer and a key buffer, a non-zero result
+/// stops the iteration
+pub type KeyCallback = extern "C" fn(ctx: *mut c_void, key: *const c_char) -> i32;
+
+// The store stays locked while the callback runs, so a callback calling back into the kv
+// exports gets ERR_REENTRANT_CALL
+#[no_mangle]
+pub unsafe extern "C" fn kvForEachKey(
+    handle: i64,
+    callback: Option<KeyCallback>,
+    ctx: *mut c_void,
+) -> i32 {
+    let callback = match callback {
+        Some(c) => c,
+        None => return cobhan::ERR_NULL_PTR,
+    };
+
+    KV_STORES
+        .with(|stores| {
+            let store = match stores.get(&handle) {
+                Some(s) => s,
+                None => return ERR_INVALID_HANDLE,
+            };
+            for key in store.keys() {
+                let mut buffer = SelfTestBuffer::with_capacity(key.len());
+                let result = cobhan::string_to_cbuffer(key, buffer.as_mut_ptr());
+                if result != cobhan::ERR_NONE {
+                    return result;
+                }
+                let result = callback(ctx, buffer.as_ptr());
+                if result != cobhan::ERR_NONE {
+                    return result;
+                }
+            }
+            cobhan::ERR_NONE
+        })
+        .unwrap_or_else(|e| e)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn kvClose(handle: i64) -> i32 {
+    match KV_STORES.with(|stores| stores.remove(&handle)) {
+        Ok(Some(_)) => cobhan::ERR_NONE,
+        Ok(None) => ERR_INVALID_HANDLE,
+        Err(e) => e,
+    }
+}
+
+// Error path fixtures: these exist only so host bindings can exercise their error handling
+
+#[no_mangle]
+pub unsafe extern "C" fn triggerError(code: i32) -> i32 {
+    code
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn triggerPanic() -> i32 {
+    // Unwinding across extern "C" aborts the host process, so catch the panic and map it to a code
+    match std::panic::catch_unwind(|| panic!("triggerPanic called")) {
+        Ok(()) => cobhan::ERR_NONE,
+        Err(_) => ERR_PANIC,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn invalidUtf8Output(output: *mut c_char) -> i32 {
+    // Lone continuation byte and bytes that never appear in UTF-8
+    cobhan::bytes_to_cbuffer([b'o', b'k', 0x80, 0xFE, 0xFF], output)
+}
+
+// Single call ABI check for binding authors: runs every conversion against buffers laid out
+// exactly as a host would lay them out, then writes a JSON report of the results
+#[no_mangle]
+pub unsafe extern "C" fn cobhan_self_test(output: *mut c_char) -> i32 {
+    let checks = self_test_checks();
+    let passed = checks.iter().all(|(_, result)| result.is_ok());
+
+    let mut report = HashMap::new();
+    report.insert("passed".to_string(), Value::Bool(passed));
+    report.insert(
+        "header_size".to_string(),
+        Value::from(cobhan::BUFFER_HEADER_SIZE),
+    );
+    let results = checks
+        .into_iter()
+        .map(|(name, result)| {
+            let outcome = match result {
+                Ok(()) => Value::String("ok".to_string()),
+                Err(code) => Value::from(code),
+            };
+            (name.to_string(), outcome)
+        })
+        .collect();
+    report.insert("checks".to_string(), Value::Object(results));
+
+    let result = cobhan::hashmap_json_to_cbuffer(&report, output);
+    if result != cobhan::ERR_NONE {
+        return result;
+    }
+
+    if passed {
+        cobhan::ERR_NONE
+    } else {
+        ERR_SELF_TEST_FAILED
+    }
+}
+
+// Host style buffer, also used to pass keys to host callbacks: u64 backing keeps the header 8 byte aligned, capacity in the length field
+struct SelfTestBuffer(Vec<u64>);
+
+impl SelfTestBuffer {
+    fn with_capacity(capacity: usize) -> Self {
+        let header = cobhan::BUFFER_HEADER_SIZE as usize;
+        let mut words = vec![0u64; (header + capacity).div_ceil(8)];
+        unsafe {
+            *(words.as_mut_ptr() as *mut [u8; 4]) = cobhan::encode_header_length(capacity as i32)
+        };
+        SelfTestBuffer(words)
+    }
+
+    fn as_ptr(&self) -> *const c_char {
+        self.0.as_ptr() as *const c_char
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut c_char {
+        self.0.as_mut_ptr() as *mut c_char
+    }
+
+    fn length_field(&self) -> i32 {
+        cobhan::decode_header_length(unsafe { *(self.0.as_ptr() as *const [u8; 4]) })
+    }
+
+    // Hosts own temp files once they've read them, so the self test cleans up its own
+    fn remove_temp_file(&self) {
+        let length = self.length_field();
+        if length < 0 {
+            let header = cobhan::BUFFER_HEADER_SIZE as usize;
+            let bytes = unsafe {
+                std::slice::from_raw_parts(self.as_ptr() as *const u8, header + (-length) as usize)
+            };
+            if let Ok(path) = std::str::from_utf8(&bytes[header..]) {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+}
+
+fn check(result: i32) -> Result<(), i32> {
+    if result == cobhan::ERR_NONE {
+        Ok(())
+    } else {
+        Err(result)
+    }
+}
+
+fn expect<T: PartialEq>(actual: T, expected: &T) -> Result<(), i32> {
+    if actual == *expected {
+        Ok(())
+    } else {
+        Err(ERR_SELF_TEST_FAILED)
+    }
+}
+
+fn self_test_checks() -> Vec<(&'static str, Result<(), i32>)> {
+    let text = "Cobhan self test \u{1F980}";
+    let binary: Vec<u8> = (0..=255).collect();
+
+    vec![
+        ("string", unsafe {
+            let mut buf = SelfTestBuffer::with_capacity(64);
+            check(cobhan::string_to_cbuffer(text, buf.as_mut_ptr()))
+                .and_then(|_| cobhan::cbuffer_to_string(buf.as_ptr()))
+                .and_then(|s| expect(s, &text.to_string()))
+        }),
+        ("bytes", unsafe {
+            let mut buf = SelfTestBuffer::with_capacity(256);
+            check(cobhan::bytes_to_cbuffer(&binary, buf.as_mut_ptr()))
+                .and_then(|_| cobhan::cbuffer_to_vector(buf.as_ptr()))
+                .and_then(|b| expect(b, &binary))
+        }),
+        ("json", unsafe {
+            let mut json = HashMap::new();
+            json.insert("name".to_string(), Value::String(text.to_string()));
+            json.insert("count".to_string(), Value::from(42));
+            let mut buf = SelfTestBuffer::with_capacity(128);
+            check(cobhan::hashmap_json_to_cbuffer(&json, buf.as_mut_ptr()))
+                .and_then(|_| cobhan::cbuffer_to_hashmap_json(buf.as_ptr()))
+                .and_then(|j| expect(j, &json))
+        }),
+        ("utf16le", unsafe {
+            let mut buf = SelfTestBuffer::with_capacity(128);
+            check(cobhan::string_to_cbuffer_utf16le(text, buf.as_mut_ptr()))
+                .and_then(|_| cobhan::cbuffer_utf16le_to_string(buf.as_ptr()))
+                .and_then(|s| expect(s, &text.to_string()))
+        }),
+        ("base64", unsafe {
+            let mut buf = SelfTestBuffer::with_capacity(512);
+            check(cobhan::bytes_to_cbuffer_base64(&binary, buf.as_mut_ptr()))
+                .and_then(|_| cobhan::cbuffer_base64_decode_to_vec(buf.as_ptr()))
+                .and_then(|b| expect(b, &binary))
+        }),
+        ("hex", unsafe {
+            let mut buf = SelfTestBuffer::with_capacity(512);
+            check(cobhan::bytes_to_cbuffer_hex(&binary, buf.as_mut_ptr()))
+                .and_then(|_| cobhan::cbuffer_hex_decode_to_vec(buf.as_ptr()))
+                .and_then(|b| expect(b, &binary))
+        }),
+        ("packed_buffers", unsafe {
+            let payloads: [&[u8]; 3] = [b"first", b"", &binary];
+            let mut buf = SelfTestBuffer::with_capacity(512);
+            check(cobhan::pack_buffers(&payloads, buf.as_mut_ptr()))
+                .and_then(|_| cobhan::unpack_buffers(buf.as_ptr()))
+                .and_then(|p| expect(p, &payloads.iter().map(|p| p.to_vec()).collect()))
+        }),
+        ("temp_file_spill", unsafe {
+            // Capacity only fits the temp file path, forcing the payload out to a temp file
+            let large: Vec<u8> = (0..1_000_000u32).map(|i| i as u8).collect();
+            let mut buf = SelfTestBuffer::with_capacity(256);
+            check(cobhan::bytes_to_cbuffer(&large, buf.as_mut_ptr()))
+                .and_then(|_| {
+                    if buf.length_field() < 0 {
+                        Ok(())
+                    } else {
+                        Err(ERR_SELF_TEST_FAILED)
+                    }
+                })
+                .and_then(|_| {
+                    let read = cobhan::cbuffer_to_vector(buf.as_ptr());
+                    buf.remove_temp_file();
+                    read
+                })
+                .and_then(|b| expect(b, &large))
+        }),
+        ("null_pointer", unsafe {
+            match cobhan::cbuffer_to_vector(std::ptr::null()) {
+                Err(cobhan::ERR_NULL_PTR) => Ok(()),
+                _ => Err(ERR_SELF_TEST_FAILED),
+            }
+        }),
+    ]
+}